@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::HeaderMap, Json};
+
+use crate::api::middleware::require_admin;
+use crate::api::models::{CacheClearResponse, CacheStatsResponse};
+use crate::api::state::AppState;
+use crate::error::Result;
+
+/// Response header telling whether a cache served the result (`HIT` or `MISS`)
+pub const X_CACHE_HEADER: &str = "x-cache";
+
+/// `X-Cache` header for a response, to pair with the response body as `(headers, body)`
+pub fn x_cache(hit: bool) -> [(&'static str, &'static str); 1] {
+    [(X_CACHE_HEADER, if hit { "HIT" } else { "MISS" })]
+}
+
+/// Entry counts and hit/miss ratios for the geocoding, location search, map data and preview caches
+pub async fn cache_stats(State(state): State<Arc<AppState>>) -> Json<CacheStatsResponse> {
+    Json(CacheStatsResponse {
+        geocoding: state.geocoding_cache.stats(),
+        location_search: state.location_search_cache.stats(),
+        map_data: state.map_data_cache_stats(),
+        preview: state.preview_cache.stats(),
+    })
+}
+
+/// Flush all caches (admin only)
+///
+/// Completed jobs can no longer be re-rendered once their map data is flushed.
+pub async fn clear_caches(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<CacheClearResponse>> {
+    require_admin(&state.config, &headers)?;
+
+    let response = CacheClearResponse {
+        geocoding: state.geocoding_cache.clear(),
+        location_search: state.location_search_cache.clear(),
+        map_data: state.clear_map_data_cache(),
+        preview: state.preview_cache.clear(),
+    };
+    tracing::info!(
+        "Cleared caches: {} geocoding, {} location search, {} map data, {} preview entries",
+        response.geocoding,
+        response.location_search,
+        response.map_data,
+        response.preview
+    );
+    Ok(Json(response))
+}
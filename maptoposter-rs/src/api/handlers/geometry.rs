@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::api::state::{AppState, CachedMapData};
+use crate::config::Settings;
+use crate::core::osm_client::AreaFeature;
+use crate::error::{AppError, Result};
+
+/// Credit required by the ODbL for anything derived from OpenStreetMap data
+const OSM_ATTRIBUTION: &str = "© OpenStreetMap contributors";
+const OSM_LICENSE: &str = "ODbL-1.0";
+const OSM_COPYRIGHT_URL: &str = "https://www.openstreetmap.org/copyright";
+
+/// Query parameters for geometry export
+#[derive(Debug, Deserialize)]
+pub struct GeometryQuery {
+    /// Include the original OSM way ids as `osm_id` feature properties
+    #[serde(default)]
+    pub include_ids: bool,
+}
+
+/// Export the cached map geometry of a job as a GeoJSON FeatureCollection
+///
+/// A top-level `metadata` member carries the OpenStreetMap attribution and license, so the
+/// credit travels with the exported data.
+pub async fn export_geometry(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+    Query(query): Query<GeometryQuery>,
+) -> Result<Json<Value>> {
+    let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
+
+    let cached_data = state.get_cached_map_data(uuid).ok_or_else(|| AppError::MapDataExpired(job_id.clone()))?;
+
+    let mut geojson = to_geojson(&cached_data, query.include_ids);
+    geojson["metadata"] = geometry_metadata(&cached_data, &state.config);
+    Ok(Json(geojson))
+}
+
+/// Attribution, data timestamp, extent and feature counts for an export
+fn geometry_metadata(data: &CachedMapData, config: &Settings) -> Value {
+    json!({
+        "attribution": OSM_ATTRIBUTION,
+        "license": OSM_LICENSE,
+        "copyright_url": OSM_COPYRIGHT_URL,
+        "source": if config.offline_mode { "offline" } else { "overpass" },
+        // When the OSM data was last updated on the server that answered (null if it didn't say)
+        "osm_timestamp": data.stats.osm_timestamp(),
+        "city": data.city,
+        "country": data.country,
+        "center": [data.lon, data.lat],
+        "distance": data.distance,
+        "bbox": geometry_bbox(data),
+        "counts": {
+            "roads": data.streets.len(),
+            "water": data.water.len(),
+            "parks": data.parks.len(),
+            "landuse": data.landuse.len(),
+            "details": data.details.len(),
+            "points": data.points.len(),
+        },
+    })
+}
+
+/// `[west, south, east, north]` around every exported feature, or None when there are none
+fn geometry_bbox(data: &CachedMapData) -> Option<[f64; 4]> {
+    let lines = data.streets.iter().map(|s| &s.points);
+    let areas = [&data.water, &data.parks, &data.landuse, &data.details].into_iter().flatten().map(|a| &a.points);
+    let points = data.points.iter().map(|p| (p.lat, p.lon));
+
+    lines
+        .chain(areas)
+        .flatten()
+        .copied()
+        .chain(points)
+        .fold(None, |bbox, (lat, lon)| {
+            let [west, south, east, north] = bbox.unwrap_or([lon, lat, lon, lat]);
+            Some([west.min(lon), south.min(lat), east.max(lon), north.max(lat)])
+        })
+}
+
+/// Convert cached map data into a GeoJSON FeatureCollection
+fn to_geojson(data: &CachedMapData, include_ids: bool) -> Value {
+    let mut features = Vec::new();
+
+    for segment in &data.streets {
+        let mut properties = Map::new();
+        properties.insert("layer".to_string(), json!("road"));
+        properties.insert("highway".to_string(), json!(segment.highway_type.as_str()));
+        if segment.oneway {
+            properties.insert("oneway".to_string(), json!(true));
+        }
+        if let Some(lanes) = segment.lanes {
+            properties.insert("lanes".to_string(), json!(lanes));
+        }
+        if let Some(maxspeed) = segment.maxspeed {
+            properties.insert("maxspeed".to_string(), json!(maxspeed));
+        }
+        if include_ids {
+            properties.insert("osm_id".to_string(), json!(segment.id));
+        }
+
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": lon_lat(&segment.points),
+            },
+            "properties": properties,
+        }));
+    }
+
+    for (layer, areas) in [("water", &data.water), ("park", &data.parks), ("landuse", &data.landuse), ("detail", &data.details)] {
+        features.extend(areas.iter().map(|area| area_feature(layer, area, include_ids)));
+    }
+
+    for point in &data.points {
+        let mut properties = Map::new();
+        properties.insert("layer".to_string(), json!("point"));
+        properties.insert("kind".to_string(), json!(point.kind.as_str()));
+        if let Some(name) = &point.name {
+            properties.insert("name".to_string(), json!(name));
+        }
+
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [point.lon, point.lat],
+            },
+            "properties": properties,
+        }));
+    }
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+/// Build a GeoJSON polygon feature for an area
+fn area_feature(layer: &str, area: &AreaFeature, include_ids: bool) -> Value {
+    let mut ring = lon_lat(&area.points);
+    // GeoJSON rings must be closed
+    if area.points.first() != area.points.last() {
+        if let Some(first) = ring.first().cloned() {
+            ring.push(first);
+        }
+    }
+
+    let mut properties = Map::new();
+    properties.insert("layer".to_string(), json!(layer));
+    properties.insert("feature_type".to_string(), json!(area.feature_type));
+    if include_ids {
+        properties.insert("osm_id".to_string(), json!(area.id));
+    }
+
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Polygon",
+            "coordinates": [ring],
+        },
+        "properties": properties,
+    })
+}
+
+/// Convert (lat, lon) points to GeoJSON [lon, lat] positions
+fn lon_lat(points: &[(f64, f64)]) -> Vec<Value> {
+    points.iter().map(|(lat, lon)| json!([lon, lat])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::osm_client::{HighwayType, RoadSegment};
+
+    fn sample_data() -> CachedMapData {
+        CachedMapData {
+            city: "Venice".to_string(),
+            country: "Italy".to_string(),
+            lat: 45.44,
+            lon: 12.33,
+            distance: 4000,
+            streets: vec![RoadSegment {
+                id: 42,
+                points: vec![(45.0, 12.0), (45.1, 12.1)],
+                highway_type: HighwayType::Primary,
+                oneway: false,
+                lanes: None,
+                maxspeed: None,
+            }],
+            water: vec![AreaFeature {
+                id: 7,
+                points: vec![(45.0, 12.0), (45.1, 12.0), (45.1, 12.1)],
+                feature_type: "water".to_string(),
+            }],
+            parks: Vec::new(),
+            landuse: Vec::new(),
+            details: Vec::new(),
+            points: Vec::new(),
+            country_outline: Vec::new(),
+            stats: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_geojson_ids_are_optional() {
+        let data = sample_data();
+
+        let without_ids = to_geojson(&data, false);
+        assert_eq!(without_ids["features"].as_array().unwrap().len(), 2);
+        assert!(without_ids["features"][0]["properties"].get("osm_id").is_none());
+
+        let with_ids = to_geojson(&data, true);
+        assert_eq!(with_ids["features"][0]["properties"]["osm_id"], 42);
+        assert_eq!(with_ids["features"][1]["properties"]["osm_id"], 7);
+    }
+
+    #[test]
+    fn test_geometry_metadata() {
+        let mut data = sample_data();
+        let timestamp = "2024-05-17T13:45:00Z".parse().unwrap();
+        data.stats.streets.osm_timestamp = Some(timestamp);
+        let metadata = geometry_metadata(&data, &Settings::defaults());
+
+        assert_eq!(metadata["attribution"], OSM_ATTRIBUTION);
+        assert_eq!(metadata["license"], "ODbL-1.0");
+        assert_eq!(metadata["osm_timestamp"], "2024-05-17T13:45:00Z");
+        assert_eq!(metadata["bbox"], json!([12.0, 45.0, 12.1, 45.1]));
+        assert_eq!(metadata["counts"]["roads"], 1);
+        assert_eq!(metadata["counts"]["water"], 1);
+
+        data.streets.clear();
+        data.water.clear();
+        assert!(geometry_metadata(&data, &Settings::defaults())["bbox"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_export_after_map_data_expired_is_gone() {
+        let state = Arc::new(AppState::new(Settings::defaults()));
+        let job_id = Uuid::new_v4().to_string();
+        let query = GeometryQuery { include_ids: false };
+        let error = export_geometry(State(state), Path(job_id.clone()), Query(query)).await.unwrap_err();
+        assert!(matches!(error, AppError::MapDataExpired(ref id) if *id == job_id), "{:?}", error);
+    }
+
+    #[test]
+    fn test_geojson_closes_polygon_rings() {
+        let geojson = to_geojson(&sample_data(), false);
+        let ring = geojson["features"][1]["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.first(), ring.last());
+        assert_eq!(ring[0], json!([12.0, 45.0]));
+    }
+}
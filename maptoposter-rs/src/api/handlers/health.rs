@@ -1,9 +1,23 @@
+use std::future::Future;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
 
-use crate::api::models::{HealthResponse, ReadinessChecks, ReadinessResponse};
+use crate::api::middleware::require_admin;
+use crate::api::models::{
+    ConfigResponse, HealthResponse, JobStatsResponse, ReadinessChecks, ReadinessResponse, ScheduleResponse, StorageResponse,
+    VersionResponse, WarmupQuery, WarmupResponse, WarmupStep,
+};
 use crate::api::state::AppState;
+use crate::core::geocoding::probe_nominatim;
+use crate::core::osm_client::probe_overpass;
+use crate::error::{AppError, Result};
 use crate::themes::loader::load_themes;
 
 /// Basic health check endpoint
@@ -14,6 +28,29 @@ pub async fn health_check() -> Json<HealthResponse> {
     })
 }
 
+/// Build information captured by build.rs
+pub async fn version_info() -> Json<VersionResponse> {
+    let build_timestamp = env!("BUILD_TIMESTAMP")
+        .parse::<i64>()
+        .ok()
+        .filter(|&secs| secs > 0)
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|t| t.to_rfc3339());
+
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("BUILD_GIT_COMMIT").to_string(),
+        build_timestamp,
+        rustc_version: env!("BUILD_RUSTC_VERSION").to_string(),
+    })
+}
+
+/// Effective settings after env, `.env` and config file layering (admin only)
+pub async fn effective_config(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Result<Json<ConfigResponse>> {
+    require_admin(&state.config, &headers)?;
+    Ok(Json(ConfigResponse::from(&state.config)))
+}
+
 /// Readiness check that verifies themes, fonts, and directories exist
 pub async fn readiness_check(State(state): State<Arc<AppState>>) -> Json<ReadinessResponse> {
     let themes_ok = !load_themes(&state.config.themes_dir).is_empty();
@@ -31,3 +68,196 @@ pub async fn readiness_check(State(state): State<Arc<AppState>>) -> Json<Readine
         },
     })
 }
+
+/// Fill the font and theme caches ahead of the first poster, timing each step
+///
+/// With `?probe=true` (admin only) it also sends a tiny query to Overpass and
+/// Nominatim, so a readiness gate can wait until the first real request will be fast.
+pub async fn warmup(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WarmupQuery>,
+    headers: HeaderMap,
+) -> Result<Json<WarmupResponse>> {
+    if query.probe {
+        require_admin(&state.config, &headers)?;
+    }
+
+    let config = &state.config;
+    let started = Instant::now();
+    let fonts_state = state.clone();
+    let themes_state = state.clone();
+    let mut steps = vec![
+        timed_step("fonts", blocking(move || fonts_state.fonts().map(|_| "default weights loaded".to_string())))
+            .await,
+        timed_step(
+            "themes",
+            blocking(move || match themes_state.themes.warm(&themes_state.config.themes_dir) {
+                0 => Err(format!("no themes in {}", themes_state.config.themes_dir.display())),
+                count => Ok(format!("{} themes parsed", count)),
+            }),
+        )
+        .await,
+    ];
+    if query.probe {
+        steps.push(
+            timed_step("overpass", async {
+                state.rate_limiters.overpass.wait("overpass").await;
+                probe_overpass(config).await.map(|_| "reachable")
+            })
+            .await,
+        );
+        // Spaced from other Nominatim requests by the geocoding client
+        steps.push(timed_step("nominatim", async { probe_nominatim(config).await.map(|_| "reachable") }).await);
+    }
+
+    let all_ok = steps.iter().all(|step| step.ok);
+    tracing::info!("Warmup finished in {:?} ({})", started.elapsed(), if all_ok { "ready" } else { "not ready" });
+
+    Ok(Json(WarmupResponse {
+        status: if all_ok { "ready" } else { "not_ready" }.to_string(),
+        total_ms: started.elapsed().as_millis() as u64,
+        steps,
+    }))
+}
+
+/// Run a blocking warmup step on the blocking thread pool
+async fn blocking<T, E>(step: impl FnOnce() -> std::result::Result<T, E> + Send + 'static) -> std::result::Result<T, String>
+where
+    T: Send + 'static,
+    E: ToString + Send + 'static,
+{
+    match tokio::task::spawn_blocking(step).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("Warmup task failed: {}", e)),
+    }
+}
+
+/// Run one warmup step and record its outcome and duration
+async fn timed_step<T, E>(name: &str, step: impl Future<Output = std::result::Result<T, E>>) -> WarmupStep
+where
+    T: ToString,
+    E: ToString,
+{
+    let started = Instant::now();
+    let (ok, detail) = match step.await {
+        Ok(detail) => (true, detail.to_string()),
+        Err(e) => {
+            let e = e.to_string();
+            tracing::warn!("Warmup step {} failed: {}", name, e);
+            (false, e)
+        }
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+    WarmupStep { name: name.to_string(), ok, duration_ms, detail }
+}
+
+/// Report disk usage of the static output directory
+pub async fn storage_check(State(state): State<Arc<AppState>>) -> Result<Json<StorageResponse>> {
+    let static_dir = state.config.static_dir.clone();
+    let (total_bytes, file_count) = tokio::task::spawn_blocking(move || directory_usage(&static_dir))
+        .await
+        .map_err(|e| AppError::Internal(format!("Storage scan task failed: {}", e)))?;
+    let threshold_bytes = state.config.storage_warn_mb * 1024 * 1024;
+
+    if total_bytes > threshold_bytes {
+        tracing::warn!(
+            "Static directory uses {} bytes, above threshold of {} bytes",
+            total_bytes,
+            threshold_bytes
+        );
+    }
+
+    Ok(Json(StorageResponse {
+        status: if total_bytes > threshold_bytes { "warning" } else { "ok" }.to_string(),
+        total_bytes,
+        file_count,
+        threshold_bytes,
+    }))
+}
+
+/// Lifetime job counters for dashboards
+pub async fn job_stats(State(state): State<Arc<AppState>>) -> Json<JobStatsResponse> {
+    Json(state.job_stats())
+}
+
+/// Recurring job schedule and the last run of each recipe
+pub async fn schedule_status(State(state): State<Arc<AppState>>) -> Json<ScheduleResponse> {
+    Json(state.schedule_status())
+}
+
+/// Total size in bytes and number of files under a directory (recursive)
+fn directory_usage(dir: &Path) -> (u64, u64) {
+    let mut total_bytes = 0;
+    let mut file_count = 0;
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let entries = match std::fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => pending.push(entry.path()),
+                Ok(metadata) => {
+                    total_bytes += metadata.len();
+                    file_count += 1;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    (total_bytes, file_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::middleware::ADMIN_TOKEN_HEADER;
+    use crate::config::Settings;
+    use crate::error::AppError;
+
+    #[tokio::test]
+    async fn test_effective_config_requires_admin_token() {
+        let mut settings = Settings::defaults();
+        settings.admin_token = Some("secret".to_string());
+        let state = Arc::new(AppState::new(settings));
+
+        let denied = effective_config(State(state.clone()), HeaderMap::new()).await;
+        assert!(matches!(denied, Err(AppError::Forbidden(_))));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ADMIN_TOKEN_HEADER, "secret".parse().unwrap());
+        let Json(config) = effective_config(State(state.clone()), headers).await.unwrap();
+        assert_eq!(config.port, state.config.port);
+        assert!(!serde_json::to_string(&config).unwrap().contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_warmup_times_fonts_and_themes() {
+        let repo = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let mut settings = Settings::defaults();
+        settings.fonts_dir = repo.join("fonts");
+        settings.themes_dir = repo.join("themes");
+        let state = Arc::new(AppState::new(settings));
+
+        let Json(response) = warmup(State(state.clone()), Query(WarmupQuery::default()), HeaderMap::new()).await.unwrap();
+        assert_eq!(response.status, "ready");
+        let names: Vec<&str> = response.steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(names, ["fonts", "themes"]);
+        assert!(state.themes.load(&state.config.themes_dir, "noir").is_some());
+
+        // Probing reaches external services, so it needs the admin token
+        let probe = warmup(State(state.clone()), Query(WarmupQuery { probe: true }), HeaderMap::new()).await;
+        assert!(matches!(probe, Err(AppError::Forbidden(_))));
+
+        let mut settings = Settings::defaults();
+        settings.fonts_dir = repo.join("missing");
+        let state = Arc::new(AppState::new(settings));
+        let Json(response) = warmup(State(state), Query(WarmupQuery::default()), HeaderMap::new()).await.unwrap();
+        assert_eq!(response.status, "not_ready");
+        assert!(!response.steps[0].ok);
+    }
+}
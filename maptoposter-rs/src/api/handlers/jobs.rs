@@ -2,22 +2,47 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::HeaderMap,
     response::sse::{Event, Sse},
 };
 use futures::stream::Stream;
+use serde::Deserialize;
 use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::StreamExt;
 use uuid::Uuid;
 
-use crate::api::models::{JobStatus, ProgressUpdate};
-use crate::api::state::AppState;
+use crate::api::models::{JobStatus, ProgressUpdate, StepTransition};
+use crate::api::state::{AppState, JobState};
 use crate::error::{AppError, Result};
 
+/// Query parameters for the progress stream
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Id of the last event the client saw, for clients that can't send `Last-Event-ID`
+    #[serde(default)]
+    pub last_event_id: Option<u64>,
+}
+
 /// Stream job progress updates via Server-Sent Events
+///
+/// The job is polled every `SSE_POLL_MS`, with a keep-alive comment every `SSE_KEEPALIVE_SECS`.
+/// Each event carries the job's revision as its id, and an event is only sent when the
+/// job has changed since the last one, or when a queued job's position in the queue
+/// moved since the last event on this stream. A reconnecting client that sends `Last-Event-ID`
+/// (or `?last_event_id=`) skips updates it already has, and always gets a finished job's
+/// terminal event straight away. At most `MAX_STREAMS_PER_JOB` streams can be open for
+/// one job; further ones get 429.
+///
+/// Whenever the job's step differs from the last one this stream reported, a `step` event
+/// naming the new and previous steps precedes the usual event, so a client can tick off
+/// stages. Steps are sampled on each poll, so one that starts and ends between polls is
+/// never reported.
 pub async fn stream_progress(
     State(state): State<Arc<AppState>>,
     Path(job_id): Path<String>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
 ) -> Result<Sse<impl Stream<Item = std::result::Result<Event, std::convert::Infallible>>>> {
     let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
 
@@ -26,63 +51,203 @@ pub async fn stream_progress(
         return Err(AppError::JobNotFound(job_id));
     }
 
-    // Create an interval stream that polls job status
-    let interval = tokio::time::interval(Duration::from_millis(500));
+    // Held by the stream so the slot is released when the client disconnects
+    let slot = state.open_stream(uuid).ok_or_else(|| {
+        AppError::TooManyRequests(format!(
+            "Job '{}' already has {} progress streams open",
+            job_id, state.config.max_streams_per_job
+        ))
+    })?;
+
+    let mut last_sent = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .or(query.last_event_id);
+    let mut first_tick = true;
+    let mut reported_missing = false;
+    let mut last_step: Option<String> = None;
+    let mut last_position: Option<Option<usize>> = None;
+
+    // Create an interval stream that polls job status (the first tick fires immediately)
+    let interval = tokio::time::interval(Duration::from_millis(state.config.sse_poll_ms.max(1)));
     let stream = IntervalStream::new(interval);
 
     let state_clone = state.clone();
     let stream = stream
-        .map(move |_| {
-            let job = state_clone.get_job(uuid);
-
-            match job {
-                Some(job) => {
-                    // Include download_url only when job is completed
-                    let download_url = if job.status == JobStatus::Completed {
-                        Some(format!("/api/posters/{}/download", job.id))
-                    } else {
-                        None
-                    };
-
-                    let update = ProgressUpdate {
-                        job_id: job.id.to_string(),
-                        status: job.status,
-                        percent: (job.progress * 100.0) as u32,
-                        step: job.current_step.unwrap_or_default(),
-                        message: job.message.unwrap_or_default(),
-                        download_url,
-                    };
-
-                    let data = serde_json::to_string(&update).unwrap_or_default();
-
-                    if job.status == JobStatus::Completed {
-                        // Successful completion
-                        Event::default().data(data).event("completed")
-                    } else if job.status == JobStatus::Failed {
-                        // Failed job - send error event with error message
-                        let error_msg = job.error.clone().unwrap_or_else(|| "Generation failed".to_string());
-                        let error_data = format!(r#"{{"message": "{}"}}"#, error_msg.replace('"', "\\\""));
-                        Event::default().data(error_data).event("error")
-                    } else {
-                        // In progress
-                        Event::default().data(data).event("progress")
-                    }
+        .filter_map(move |_| {
+            let _slot = &slot;
+            let is_first = std::mem::take(&mut first_tick);
+
+            let Some(job) = state_clone.get_job(uuid) else {
+                // Job no longer exists
+                if std::mem::replace(&mut reported_missing, true) {
+                    return None;
                 }
-                None => {
-                    // Job no longer exists
+                return Some(vec![
                     Event::default()
                         .data("{\"error\": \"Job not found\"}")
-                        .event("error")
-                }
+                        .event("error"),
+                ]);
+            };
+
+            // Jobs ahead finishing don't touch this job's revision, only its position
+            let terminal = matches!(job.status, JobStatus::Completed | JobStatus::Failed);
+            let position = state_clone.queue_position(job.id);
+            let moved = position.is_some() && last_position != Some(position);
+            if !should_emit(job.revision, last_sent, terminal, is_first) && !moved {
+                return None;
+            }
+
+            last_sent = Some(job.revision);
+            last_position = Some(position);
+            let mut events = Vec::with_capacity(2);
+            if let Some(transition) = step_transition(&job, &mut last_step) {
+                events.push(
+                    Event::default()
+                        .id(job.revision.to_string())
+                        .data(serde_json::to_string(&transition).unwrap_or_default())
+                        .event("step"),
+                );
             }
-        })
-        // Note: SSE will continue until client disconnects
-        // The "complete" event tells client to close connection
-        .map(Ok);
+            events.push(job_event(job, position));
+            Some(events)
+        });
+    // Note: SSE will continue until client disconnects
+    // The "complete" event tells client to close connection
+    let stream = futures::StreamExt::flat_map(stream, |events| futures::stream::iter(events.into_iter().map(Ok)));
 
     Ok(Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(15))
+            .interval(Duration::from_secs(state.config.sse_keepalive_secs.max(1)))
             .text("keep-alive"),
     ))
 }
+
+/// Whether a job revision needs an event, given the last revision the client has seen
+///
+/// Terminal states are always re-sent on the first tick so a reconnecting client
+/// learns the outcome even if it already saw that revision.
+fn should_emit(revision: u64, last_sent: Option<u64>, terminal: bool, first_tick: bool) -> bool {
+    let changed = !matches!(last_sent, Some(seen) if revision <= seen);
+    changed || (terminal && first_tick)
+}
+
+/// The job's step, if it differs from the last one reported on this stream (which it
+/// then becomes)
+fn step_transition(job: &JobState, last_step: &mut Option<String>) -> Option<StepTransition> {
+    let step = job.current_step.as_deref().filter(|step| !step.is_empty())?;
+    if last_step.as_deref() == Some(step) {
+        return None;
+    }
+    Some(StepTransition {
+        job_id: job.id.to_string(),
+        step: step.to_string(),
+        previous_step: last_step.replace(step.to_string()),
+    })
+}
+
+/// SSE event for the current state of a job, with its revision as the event id
+fn job_event(job: JobState, queue_position: Option<usize>) -> Event {
+    let event = Event::default().id(job.revision.to_string());
+
+    if job.status == JobStatus::Failed {
+        // Failed job - send error event with error message
+        let error_msg = job.error.clone().unwrap_or_else(|| "Generation failed".to_string());
+        let error_data = format!(r#"{{"message": "{}"}}"#, error_msg.replace('"', "\\\""));
+        return event.data(error_data).event("error");
+    }
+
+    // Include download_url only when job is completed
+    let download_url = if job.status == JobStatus::Completed {
+        Some(format!("/api/posters/{}/download", job.id))
+    } else {
+        None
+    };
+
+    let update = ProgressUpdate {
+        job_id: job.id.to_string(),
+        status: job.status,
+        percent: (job.progress * 100.0) as u32,
+        step: job.current_step.unwrap_or_default(),
+        message: job.message.unwrap_or_default(),
+        download_url,
+        queue_position,
+    };
+
+    let data = serde_json::to_string(&update).unwrap_or_default();
+
+    if job.status == JobStatus::Completed {
+        // Successful completion
+        event.data(data).event("completed")
+    } else {
+        // In progress
+        event.data(data).event("progress")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::progress::{STEP_FETCHING_STREETS, STEP_GEOCODING};
+
+    #[test]
+    fn test_should_emit_resumes_from_last_event_id() {
+        // Fresh connection gets the current state
+        assert!(should_emit(3, None, false, true));
+        // Reconnect after seeing revision 3: nothing new while the job is unchanged
+        assert!(!should_emit(3, Some(3), false, true));
+        assert!(should_emit(4, Some(3), false, false));
+        // A finished job is reported at once on reconnect, then not repeated
+        assert!(should_emit(5, Some(5), true, true));
+        assert!(!should_emit(5, Some(5), true, false));
+    }
+
+    #[tokio::test]
+    async fn test_queued_job_stream_reports_queue_moving() {
+        let mut config = crate::config::Settings::defaults();
+        config.sse_poll_ms = 10;
+        let state = Arc::new(AppState::new(config));
+        let ahead = state.create_job(crate::api::state::JobRequest::default()).id;
+        let waiting = state.create_job(crate::api::state::JobRequest::default()).id;
+        state.jobs.write().get_mut(&ahead).unwrap().created_at -= chrono::Duration::seconds(1);
+
+        let sse = stream_progress(State(state.clone()), Path(waiting.to_string()), Query(StreamQuery { last_event_id: None }), HeaderMap::new())
+            .await
+            .unwrap();
+        let mut body = axum::response::IntoResponse::into_response(sse).into_body().into_data_stream();
+        let mut received = String::new();
+        read_until(&mut body, &mut received, r#""queue_position":1"#).await;
+        // The job ahead starts; the waiting job itself is unchanged but is now first in line
+        state.update_job_status(ahead, JobStatus::Processing);
+        read_until(&mut body, &mut received, r#""queue_position":0"#).await;
+    }
+
+    /// Append SSE output to `received` until it contains `expected`, failing after 2s without data
+    async fn read_until(body: &mut axum::body::BodyDataStream, received: &mut String, expected: &str) {
+        while !received.contains(expected) {
+            let chunk = tokio::time::timeout(Duration::from_secs(2), body.next()).await.unwrap().unwrap().unwrap();
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    }
+
+    #[test]
+    fn test_step_transitions_are_not_repeated() {
+        let state = AppState::new(crate::config::Settings::defaults());
+        let job_id = state.create_job(crate::api::state::JobRequest::default()).id;
+        let mut last_step = None;
+        let mut poll = |step: &str, progress: f32| {
+            state.update_job_progress(job_id, progress, Some(step.to_string()), None);
+            step_transition(&state.get_job(job_id).unwrap(), &mut last_step)
+        };
+
+        let first = poll(STEP_GEOCODING, 0.05).unwrap();
+        assert_eq!((first.step.as_str(), first.previous_step), (STEP_GEOCODING, None));
+        // Progress within a step is not a transition
+        assert_eq!(poll(STEP_GEOCODING, 0.08), None);
+        let next = poll(STEP_FETCHING_STREETS, 0.1).unwrap();
+        assert_eq!(next.step, STEP_FETCHING_STREETS);
+        assert_eq!(next.previous_step.as_deref(), Some(STEP_GEOCODING));
+        assert_eq!(poll(STEP_FETCHING_STREETS, 0.3), None);
+    }
+}
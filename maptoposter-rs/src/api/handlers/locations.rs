@@ -2,14 +2,23 @@ use std::sync::Arc;
 
 use axum::{
     extract::{Query, State},
+    response::IntoResponse,
     Json,
 };
 use serde::Deserialize;
 
-use crate::api::models::{LocationResult, LocationSearchResponse};
+use crate::api::handlers::cache::x_cache;
+use crate::api::handlers::posters::validate_location;
+use crate::api::models::{
+    BatchLocationQuery, BatchLocationResponse, BatchLocationResult, BatchLocationStatus, LocationResult,
+    LocationSearchResponse,
+};
 use crate::api::state::AppState;
-use crate::core::geocoding::search_nominatim;
-use crate::error::Result;
+use crate::core::geocoding::{geocode_location, reverse_geocode, search_nominatim, LocationData};
+use crate::error::{AppError, Result};
+
+/// Most cities accepted by one batch geocoding request (about a minute of Nominatim lookups)
+pub const MAX_BATCH_LOCATIONS: usize = 50;
 
 /// Query parameters for location search
 #[derive(Debug, Deserialize)]
@@ -24,26 +33,155 @@ fn default_limit() -> u32 {
 }
 
 /// Search for locations using Nominatim
+///
+/// The requested limit is clamped to `MAX_SEARCH_LIMIT`. Results are cached per query and
+/// limit; the `X-Cache` header tells whether they were.
 pub async fn search_locations(
     State(state): State<Arc<AppState>>,
     Query(query): Query<LocationSearchQuery>,
-) -> Result<Json<LocationSearchResponse>> {
-    let results = search_nominatim(&query.q, query.limit, state.config.nominatim_timeout).await?;
+) -> Result<impl IntoResponse> {
+    let limit = state.config.search_limit(query.limit);
+    let cache_key = format!("{}:{}", limit, query.q.trim().to_lowercase());
+    let cached = state.location_search_cache.get(&cache_key);
+    let hit = cached.is_some();
+    let results = match cached {
+        Some(results) => results,
+        None => {
+            let results = search_nominatim(&query.q, limit, None, &state.config).await?;
+            state.location_search_cache.insert(cache_key, results.clone());
+            results
+        }
+    };
+
+    let locations: Vec<LocationResult> = results.into_iter().map(LocationResult::from).collect();
+
+    let count = locations.len();
+    Ok((
+        x_cache(hit),
+        Json(LocationSearchResponse {
+            results: locations,
+            count,
+            limit,
+        }),
+    ))
+}
+
+/// Query parameters for reverse geocoding
+#[derive(Debug, Deserialize)]
+pub struct ReverseGeocodeQuery {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Resolve a dropped pin to the nearest city/country
+pub async fn reverse_geocode_location(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ReverseGeocodeQuery>,
+) -> Result<impl IntoResponse> {
+    if !(-90.0..=90.0).contains(&query.lat) || !(-180.0..=180.0).contains(&query.lon) {
+        return Err(AppError::InvalidRequest(format!(
+            "Coordinates out of range: {}, {}",
+            query.lat, query.lon
+        )));
+    }
+
+    // ~11m precision, so pins dropped on the same spot share an entry
+    let cache_key = format!("reverse:{:.4},{:.4}", query.lat, query.lon);
+    if let Some(location) = state.geocoding_cache.get(&cache_key) {
+        return Ok((x_cache(true), Json(LocationResult::from(location))));
+    }
+
+    let location = reverse_geocode(query.lat, query.lon, &state.config).await?;
+    state.geocoding_cache.insert(cache_key, location.clone());
+
+    Ok((x_cache(false), Json(LocationResult::from(location))))
+}
 
-    let locations: Vec<LocationResult> = results
-        .into_iter()
-        .map(|r| LocationResult {
+/// Resolve a list of cities to coordinates, reporting each one as found, not found or failed
+///
+/// Lookups run one after another, spaced by the Nominatim rate limiter. Results are cached,
+/// so a city repeated within the batch (or looked up by an earlier batch) costs one request.
+pub async fn batch_geocode_locations(
+    State(state): State<Arc<AppState>>,
+    Json(locations): Json<Vec<BatchLocationQuery>>,
+) -> Result<Json<BatchLocationResponse>> {
+    if locations.is_empty() {
+        return Err(AppError::InvalidRequest("Batch must contain at least one location".to_string()));
+    }
+    if locations.len() > MAX_BATCH_LOCATIONS {
+        return Err(AppError::InvalidRequest(format!(
+            "Batch has {} locations, the maximum is {}",
+            locations.len(),
+            MAX_BATCH_LOCATIONS
+        )));
+    }
+
+    let mut results = Vec::with_capacity(locations.len());
+    for query in locations {
+        let outcome = match validate_location(&query.city, &query.country) {
+            Ok(()) => batch_lookup(&state, &query).await,
+            Err(e) => Err(e),
+        };
+        let (status, location, error) = match outcome {
+            Ok(location) => (BatchLocationStatus::Found, Some(LocationResult::from(location)), None),
+            Err(AppError::Geocoding(e)) => (BatchLocationStatus::NotFound, None, Some(e)),
+            Err(e) => (BatchLocationStatus::Error, None, Some(e.to_string())),
+        };
+        results.push(BatchLocationResult {
+            city: query.city,
+            country: query.country,
+            status,
+            location,
+            error,
+        });
+    }
+
+    let found = results.iter().filter(|r| r.status == BatchLocationStatus::Found).count();
+    Ok(Json(BatchLocationResponse {
+        count: results.len(),
+        found,
+        results,
+    }))
+}
+
+async fn batch_lookup(state: &AppState, query: &BatchLocationQuery) -> Result<LocationData> {
+    let cache_key = batch_cache_key(&query.city, &query.country);
+    if let Some(location) = state.geocoding_cache.get(&cache_key) {
+        return Ok(location);
+    }
+
+    let location = geocode_location(query.city.trim(), query.country.trim(), None, &state.config).await?;
+    state.geocoding_cache.insert(cache_key, location.clone());
+    Ok(location)
+}
+
+/// Cache key for a city lookup, ignoring case and surrounding whitespace
+fn batch_cache_key(city: &str, country: &str) -> String {
+    format!("city:{},{}", city.trim().to_lowercase(), country.trim().to_lowercase())
+}
+
+impl From<LocationData> for LocationResult {
+    fn from(r: LocationData) -> Self {
+        Self {
             display_name: r.display_name,
             lat: r.lat,
             lon: r.lon,
             city: r.city,
             country: r.country,
-        })
-        .collect();
+            class: r.class,
+            place_type: r.place_type,
+            importance: r.importance,
+        }
+    }
+}
 
-    let count = locations.len();
-    Ok(Json(LocationSearchResponse {
-        results: locations,
-        count,
-    }))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_cache_key_ignores_case_and_whitespace() {
+        assert_eq!(batch_cache_key(" Paris", "FRANCE "), batch_cache_key("paris", "France"));
+        assert_ne!(batch_cache_key("Paris", "France"), batch_cache_key("Paris", "United States"));
+    }
 }
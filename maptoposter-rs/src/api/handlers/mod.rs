@@ -1,3 +1,5 @@
+pub mod cache;
+pub mod geometry;
 pub mod health;
 pub mod jobs;
 pub mod locations;
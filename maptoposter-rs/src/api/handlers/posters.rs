@@ -1,60 +1,146 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 
 use axum::{
     body::Body,
-    extract::{Path, State},
-    http::{header, StatusCode},
-    response::Response,
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use futures::FutureExt;
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::ExtendedColorType;
+use futures::{AsyncWriteExt, FutureExt};
 use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
 use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
-use crate::api::models::{JobStatus, JobStatusResponse, PosterCreateRequest, PosterCreateResponse, ReRenderRequest};
-use crate::api::state::{AppState, CachedMapData, JobRequest};
-use crate::config::Settings;
-use crate::core::poster_generator::{PosterGenerator, PosterRequest};
+use crate::api::handlers::cache::x_cache;
+use crate::api::models::{
+    BatchManifest, BatchManifestEntry, ColorProfile, CompareThemesRequest, ConfirmRequest, DataUriResponse, DownloadQuery, JobStatus, JobStatusResponse, MultiThemeRequest, MultiThemeResponse, PosterCreateRequest,
+    PosterCreateResponse, ReRenderRequest, RenderOptionsRequest, ResponseFormat, TemplateRequest, ThemeJob,
+};
+use crate::api::state::{cmyk_file_name, thumbnail_file_name, AppState, CachedMapData, JobRequest, JobState};
+use crate::config::{render_filename_template, Settings};
+use crate::core::geocoding::validate_country_codes;
+use crate::core::osm_client::{check_overpass_available, AreaTags, HighwayType, FETCHED_HIGHWAY_TYPES};
+use crate::core::poster_generator::{
+    png_dimensions, png_is_complete, write_poster, MapData, PosterGenerator, PosterRequest, PosterTemplate, RenderOptions, ZoomLevel, MAX_ZOOM,
+    MIN_ZOOM,
+};
 use crate::core::progress::GenerationProgress;
 use crate::error::{AppError, Result};
-use crate::themes::loader::load_theme;
+use crate::rendering::canvas::{paper_size_pixels, MAX_CANVAS_DIMENSION};
+use crate::rendering::cmyk::encode_cmyk_tiff;
+use crate::rendering::compare::split_compare;
+use crate::rendering::resample::downscale_lanczos;
+use crate::themes::loader::{get_theme_color, invert_theme, is_valid_theme_name, load_theme, parse_hex_color, validate_theme_name};
 
 /// Create a new poster generation job
 pub async fn create_poster(
     State(state): State<Arc<AppState>>,
     Json(request): Json<PosterCreateRequest>,
 ) -> Result<Json<PosterCreateResponse>> {
-    // Validate distance
-    state.config.validate_distance(request.distance).map_err(AppError::InvalidDistance)?;
+    let job_request = poster_job_request(&state.config, &request)?;
+    check_overpass_available(&state.config)?;
+    let job_id = state.create_job(job_request.clone()).id;
+    let job_timeout = state.config.job_timeout(request.timeout_seconds);
+    let distance = job_request.distance;
+    let zoom = job_request.zoom;
+    spawn_poster_job(state, job_id, job_request, job_timeout);
+
+    Ok(Json(PosterCreateResponse {
+        job_id: job_id.to_string(),
+        status: "queued".to_string(),
+        estimated_time: estimate_generation_time(distance),
+        timeout_seconds: job_timeout.as_secs(),
+        distance: zoom.is_none().then_some(distance),
+    }))
+}
+
+/// Validate a poster request and build the job it describes
+pub fn poster_job_request(config: &Settings, request: &PosterCreateRequest) -> Result<JobRequest> {
+    // Validate location
+    validate_location(&request.city, &request.country)?;
+    validate_country_code(request.country_code.as_deref())?;
+    let extra_water_tags = parse_extra_tags("extra_water_tags", &request.extra_water_tags)?;
+    let extra_park_tags = parse_extra_tags("extra_park_tags", &request.extra_park_tags)?;
 
     // Validate theme exists
-    if load_theme(&state.config.themes_dir, &request.theme).is_none() {
-        return Err(AppError::ThemeNotFound(request.theme.clone()));
+    validate_theme_name(&request.theme)?;
+    let theme = load_theme(&config.themes_dir, &request.theme)
+        .ok_or_else(|| AppError::ThemeNotFound(request.theme.clone()))?;
+
+    // Validate distance (a zoom level is checked once it has been resolved to one)
+    let distance = request.distance.unwrap_or_else(|| theme_default_distance(config, &request.theme, &theme));
+    validate_zoom(request.zoom)?;
+    if request.zoom.is_none() {
+        config.validate_distance(distance).map_err(AppError::InvalidDistance)?;
     }
 
-    // Create job
-    let job_request = JobRequest {
-        city: request.city.clone(),
-        country: request.country.clone(),
-        theme: request.theme.clone(),
-        distance: request.distance,
+    let options = RenderOptions {
+        points: request.points.clone(),
+        detail_areas: request.detail_areas.clone(),
+        ..render_options(config, &request.render, RenderOptions::default())?
     };
 
-    let job = state.create_job(job_request.clone());
-    let job_id = job.id;
+    Ok(JobRequest {
+        city: request.city.trim().to_string(),
+        country: request.country.trim().to_string(),
+        theme: request.theme.clone(),
+        distance,
+        country_code: request.country_code.clone(),
+        strict: request.strict,
+        preview: request.preview,
+        invert: request.invert,
+        zoom: request.zoom,
+        extra_water_tags,
+        extra_park_tags,
+        options,
+    })
+}
 
-    // Spawn background task for poster generation with timeout and panic handling
-    let state_clone = state.clone();
-    let job_timeout = std::time::Duration::from_secs(180); // 3 minute timeout for entire job
+/// Distance for a request that doesn't set one: the theme's `default_distance` when it is
+/// within the server's bounds, otherwise `DEFAULT_DISTANCE`
+fn theme_default_distance(config: &Settings, name: &str, theme: &serde_json::Value) -> u32 {
+    let Some(value) = theme.get("default_distance") else {
+        return config.default_distance;
+    };
+    let distance = value.as_u64().and_then(|d| u32::try_from(d).ok());
+    match distance.map(|d| config.validate_distance(d)) {
+        Some(Ok(distance)) => distance,
+        _ => {
+            tracing::warn!("Theme '{}' has an invalid default_distance {}, using {}m", name, value, config.default_distance);
+            config.default_distance
+        }
+    }
+}
 
+/// Run a created poster job in the background once a job slot frees up
+pub fn spawn_poster_job(state: Arc<AppState>, job_id: Uuid, job_request: JobRequest, job_timeout: std::time::Duration) {
     tokio::spawn(async move {
+        // Wait for a free job slot; the job stays queued until then
+        let _permit = match state.job_slots.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => {
+                state.fail_job(job_id, "Job queue is shut down".to_string());
+                return;
+            }
+        };
+
         // Wrap job processing with timeout
         let job_result = tokio::time::timeout(
             job_timeout,
             AssertUnwindSafe(process_poster_job(
-                state_clone.clone(),
+                state.clone(),
                 job_id,
                 job_request,
             ))
@@ -67,43 +153,273 @@ pub async fn create_poster(
             }
             Ok(Err(_panic)) => {
                 tracing::error!("Job {} panicked during processing", job_id);
-                state_clone.fail_job(job_id, "Internal error: job processing crashed".to_string());
+                state.fail_job(job_id, "Internal error: job processing crashed".to_string());
             }
             Err(_timeout) => {
                 tracing::error!("Job {} timed out after {:?}", job_id, job_timeout);
-                state_clone.fail_job(job_id, "Generation timed out - try a smaller area".to_string());
+                state.fail_job(job_id, "Generation timed out - try a smaller area".to_string());
             }
         }
     });
+}
 
-    Ok(Json(PosterCreateResponse {
-        job_id: job_id.to_string(),
+/// Maximum number of themes accepted by a single multi-theme request
+const MAX_MULTI_THEMES: usize = 10;
+
+/// Largest accepted print bleed; print shops ask for 3-5mm
+const MAX_BLEED_MM: f32 = 10.0;
+
+/// Create one poster job per theme for a location, sharing a single map data fetch
+pub async fn create_multi_theme_posters(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<MultiThemeRequest>,
+) -> Result<Json<MultiThemeResponse>> {
+    validate_location(&request.city, &request.country)?;
+    validate_country_code(request.country_code.as_deref())?;
+    let extra_water_tags = parse_extra_tags("extra_water_tags", &request.extra_water_tags)?;
+    let extra_park_tags = parse_extra_tags("extra_park_tags", &request.extra_park_tags)?;
+
+    // Deduplicate while keeping the requested order
+    let mut themes: Vec<String> = Vec::new();
+    for theme in &request.themes {
+        if !themes.contains(theme) {
+            themes.push(theme.clone());
+        }
+    }
+
+    if themes.is_empty() || themes.len() > MAX_MULTI_THEMES {
+        return Err(AppError::InvalidRequest(format!(
+            "Between 1 and {} themes must be requested",
+            MAX_MULTI_THEMES
+        )));
+    }
+
+    let mut loaded = Vec::with_capacity(themes.len());
+    for theme in &themes {
+        validate_theme_name(theme)?;
+        loaded.push(load_theme(&state.config.themes_dir, theme).ok_or_else(|| AppError::ThemeNotFound(theme.clone()))?);
+    }
+
+    // The batch shares one fetch, so the first theme's default distance applies to all
+    let distance = request.distance.unwrap_or_else(|| theme_default_distance(&state.config, &themes[0], &loaded[0]));
+    validate_zoom(request.zoom)?;
+    if request.zoom.is_none() {
+        state.config.validate_distance(distance).map_err(AppError::InvalidDistance)?;
+    }
+
+    let options = RenderOptions {
+        points: request.points.clone(),
+        detail_areas: request.detail_areas.clone(),
+        ..render_options(&state.config, &request.render, RenderOptions::default())?
+    };
+    check_overpass_available(&state.config)?;
+
+    // Create one job per theme
+    let jobs: Vec<(Uuid, JobRequest)> = themes
+        .iter()
+        .map(|theme| {
+            let job_request = JobRequest {
+                city: request.city.trim().to_string(),
+                country: request.country.trim().to_string(),
+                theme: theme.clone(),
+                distance,
+                country_code: request.country_code.clone(),
+                strict: request.strict,
+                preview: false,
+                invert: request.invert,
+                zoom: request.zoom,
+                extra_water_tags: extra_water_tags.clone(),
+                extra_park_tags: extra_park_tags.clone(),
+                options: options.clone(),
+            };
+            (state.create_job(job_request.clone()).id, job_request)
+        })
+        .collect();
+
+    let batch_id = state.create_batch(jobs.iter().map(|(id, _)| *id).collect());
+
+    let response_jobs = jobs
+        .iter()
+        .map(|(id, job_request)| ThemeJob {
+            theme: job_request.theme.clone(),
+            job_id: id.to_string(),
+        })
+        .collect();
+
+    // One job timeout per theme (the fetch is shared), within MAX_JOB_TIMEOUT_SECS
+    let state_clone = state.clone();
+    let job_timeout = state.config.job_timeout(Some(state.config.job_timeout_secs * jobs.len() as u64));
+
+    tokio::spawn(async move {
+        let _permit = match state_clone.job_slots.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => {
+                for (job_id, _) in &jobs {
+                    state_clone.fail_job(*job_id, "Job queue is shut down".to_string());
+                }
+                return;
+            }
+        };
+
+        let job_ids: Vec<Uuid> = jobs.iter().map(|(id, _)| *id).collect();
+        let job_result = tokio::time::timeout(
+            job_timeout,
+            AssertUnwindSafe(process_multi_theme_job(state_clone.clone(), jobs))
+                .catch_unwind(),
+        )
+        .await;
+
+        let failure = match job_result {
+            Ok(Ok(())) => None,
+            Ok(Err(_panic)) => {
+                tracing::error!("Multi-theme job panicked during processing");
+                Some("Internal error: job processing crashed")
+            }
+            Err(_timeout) => {
+                tracing::error!("Multi-theme job timed out after {:?}", job_timeout);
+                Some("Generation timed out - try a smaller area")
+            }
+        };
+
+        if let Some(message) = failure {
+            for job_id in job_ids {
+                let finished = state_clone
+                    .get_job(job_id)
+                    .map(|j| j.status == JobStatus::Completed)
+                    .unwrap_or(true);
+                if !finished {
+                    state_clone.fail_job(job_id, message.to_string());
+                }
+            }
+        }
+    });
+
+    Ok(Json(MultiThemeResponse {
+        batch_id: batch_id.to_string(),
+        jobs: response_jobs,
         status: "queued".to_string(),
-        estimated_time: estimate_generation_time(request.distance),
+        estimated_time: estimate_generation_time(distance) + 5 * themes.len() as u32,
     }))
 }
 
+/// Fetch map data once, then render it with each theme of a multi-theme request
+async fn process_multi_theme_job(state: Arc<AppState>, jobs: Vec<(Uuid, JobRequest)>) {
+    let Some((_, first)) = jobs.first() else {
+        return;
+    };
+
+    for (job_id, _) in &jobs {
+        state.update_job_status(*job_id, JobStatus::Processing);
+    }
+
+    let fail_all = |message: String| {
+        for (job_id, _) in &jobs {
+            state.fail_job(*job_id, message.clone());
+        }
+    };
+
+    // Any theme will do for fetching, the data is theme-independent
+    let generator = match job_generator(&state, first) {
+        Ok(g) => g,
+        Err(e) => {
+            fail_all(e);
+            return;
+        }
+    };
+
+    let poster_request = PosterRequest {
+        city: first.city.clone(),
+        country: first.country.clone(),
+        distance: first.distance,
+        country_code: first.country_code.clone(),
+        strict: first.strict,
+        zoom: zoom_level(&state.config, first),
+        extra_water_tags: first.extra_water_tags.clone(),
+        extra_park_tags: first.extra_park_tags.clone(),
+        options: first.options.clone(),
+    };
+
+    // Fetch progress is shared by every job
+    let state_clone = state.clone();
+    let job_ids: Vec<Uuid> = jobs.iter().map(|(id, _)| *id).collect();
+    let fetch_callback: crate::core::progress::ProgressCallback = Box::new(move |progress: GenerationProgress| {
+        for job_id in &job_ids {
+            state_clone.update_job_progress(
+                *job_id,
+                progress.progress,
+                Some(progress.step.clone()),
+                Some(progress.message.clone()),
+            );
+        }
+    });
+
+    state.rate_limiters.overpass.wait("overpass").await;
+    let map_data = match generator.fetch_map_data(&poster_request, Some(&fetch_callback)).await {
+        Ok(data) => data,
+        Err(e) => {
+            fail_all(e.to_string());
+            return;
+        }
+    };
+
+    // One cached copy is shared by every job of the batch
+    let cached_data = Arc::new(CachedMapData::from(map_data.clone()));
+
+    // Render each theme from the in-memory data (no further network requests)
+    for (job_id, job_request) in &jobs {
+        let job_id = *job_id;
+        let generator = match job_generator(&state, job_request) {
+            Ok(g) => g,
+            Err(e) => {
+                state.fail_job(job_id, e);
+                continue;
+            }
+        };
+
+        let output_path = state.config.static_dir.join(format!("{}.png", job_id));
+        let state_clone = state.clone();
+        let progress_callback = Box::new(move |progress: GenerationProgress| {
+            state_clone.update_job_progress(
+                job_id,
+                progress.progress,
+                Some(progress.step),
+                Some(progress.message),
+            );
+        });
+
+        match generator.render_from_data(
+            &map_data,
+            &output_path,
+            &job_request.options,
+            Some(progress_callback),
+        )
+        .await
+        {
+            Ok(()) => {
+                state.cache_map_data(job_id, cached_data.clone());
+                state.complete_job(job_id, output_path.to_string_lossy().to_string());
+            }
+            Err(e) => state.fail_job(job_id, e.to_string()),
+        }
+    }
+}
+
 /// Process a poster generation job
 async fn process_poster_job(state: Arc<AppState>, job_id: Uuid, request: JobRequest) {
     // Update status to processing
     state.update_job_status(job_id, JobStatus::Processing);
 
-    // Load theme
-    let theme = match load_theme(&state.config.themes_dir, &request.theme) {
-        Some(t) => t,
-        None => {
-            state.fail_job(job_id, format!("Theme '{}' not found", request.theme));
+    // Load theme, falling back to the default if it disappeared since the request
+    let theme = match load_job_theme(&state, &request.theme, request.invert) {
+        Ok(t) => t,
+        Err(e) => {
+            state.fail_job(job_id, e);
             return;
         }
     };
 
     // Create generator
-    let generator = match PosterGenerator::new(
-        theme,
-        &state.config.fonts_dir,
-        state.config.nominatim_timeout,
-        state.config.osm_timeout,
-    ) {
+    let generator = match state.poster_generator(theme) {
         Ok(g) => g,
         Err(e) => {
             state.fail_job(job_id, format!("Failed to create generator: {}", e));
@@ -115,9 +431,13 @@ async fn process_poster_job(state: Arc<AppState>, job_id: Uuid, request: JobRequ
     let poster_request = PosterRequest {
         city: request.city.clone(),
         country: request.country.clone(),
-        theme_name: request.theme.clone(),
         distance: request.distance,
-        dpi: state.config.output_dpi,
+        country_code: request.country_code.clone(),
+        strict: request.strict,
+        zoom: zoom_level(&state.config, &request),
+        extra_water_tags: request.extra_water_tags.clone(),
+        extra_park_tags: request.extra_park_tags.clone(),
+        options: request.render_options(&state.config),
     };
 
     // Output path
@@ -135,23 +455,14 @@ async fn process_poster_job(state: Arc<AppState>, job_id: Uuid, request: JobRequ
     });
 
     // Generate poster and cache map data
+    state.rate_limiters.overpass.wait("overpass").await;
     match generator
         .generate_with_cache(&poster_request, &output_path, Some(progress_callback))
         .await
     {
         Ok(map_data) => {
             // Cache map data for re-rendering
-            let cached_data = CachedMapData {
-                city: map_data.city,
-                country: map_data.country,
-                lat: map_data.lat,
-                lon: map_data.lon,
-                distance: map_data.distance,
-                streets: map_data.streets,
-                water: map_data.water,
-                parks: map_data.parks,
-            };
-            state.cache_map_data(job_id, cached_data);
+            state.cache_map_data(job_id, Arc::new(CachedMapData::from(map_data)));
             state.complete_job(job_id, output_path.to_string_lossy().to_string());
         }
         Err(e) => {
@@ -167,52 +478,101 @@ pub async fn get_poster_status(
 ) -> Result<Json<JobStatusResponse>> {
     let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
 
-    let job = state
-        .get_job(uuid)
+    let response = state
+        .job_response(uuid)
         .ok_or_else(|| AppError::JobNotFound(job_id))?;
 
-    Ok(Json(job.to_response()))
+    Ok(Json(response))
 }
 
-/// Download a completed poster
+/// Largest poster returned by `?response_format=data_uri`; previews fit, full posters don't
+const MAX_DATA_URI_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Download a completed poster (also serves HEAD and conditional requests)
+///
+/// `?color_profile=cmyk` converts the stored PNG to a CMYK TIFF for print shops, kept next
+/// to the PNG for later downloads, and `?response_format=data_uri` returns a small PNG
+/// inline as JSON.
 pub async fn download_poster(
     State(state): State<Arc<AppState>>,
     Path(job_id): Path<String>,
+    query: std::result::Result<Query<DownloadQuery>, QueryRejection>,
+    method: Method,
+    headers: HeaderMap,
 ) -> Result<Response> {
+    let Query(query) = query.map_err(|e| AppError::InvalidRequest(e.body_text()))?;
     let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
 
     let job = state
         .get_job(uuid)
         .ok_or_else(|| AppError::JobNotFound(job_id.clone()))?;
 
-    if job.status != JobStatus::Completed {
-        return Err(AppError::Internal(format!(
-            "Job {} is not completed (status: {})",
-            job_id, job.status
-        )));
-    }
+    require_completed(&job)?;
 
     let output_path = job
         .output_path
+        .clone()
         .ok_or_else(|| AppError::Internal("No output path for completed job".to_string()))?;
+    ensure_poster_file(&state, &job, &output_path).await?;
 
-    let file = File::open(&output_path)
+    let metadata = tokio::fs::metadata(&output_path)
         .await
-        .map_err(|e| AppError::Io(e))?;
+        .map_err(AppError::Io)?;
+    if query.response_format == ResponseFormat::DataUri {
+        if query.color_profile != ColorProfile::Srgb {
+            return Err(AppError::InvalidRequest("Data URIs are only available as PNG".to_string()));
+        }
+        check_data_uri_size(metadata.len())?;
+        let png = tokio::fs::read(&output_path).await.map_err(AppError::Io)?;
+        return Ok(Json(DataUriResponse { image: png_data_uri(&png) }).into_response());
+    }
+    let mut etag = poster_etag(uuid, &metadata);
+    if query.color_profile == ColorProfile::Cmyk {
+        etag.insert_str(etag.len() - 1, "-cmyk");
+    }
+
+    // Let caches revalidate without re-downloading the file
+    if if_none_match(&headers, &etag) {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, &etag)
+            .body(Body::empty())
+            .unwrap());
+    }
 
-    let stream = ReaderStream::new(file);
-    let body = Body::from_stream(stream);
+    let (path, content_length) = match query.color_profile {
+        ColorProfile::Srgb => (std::path::PathBuf::from(&output_path), Some(metadata.len())),
+        ColorProfile::Cmyk => {
+            let tiff_path = state.config.static_dir.join(cmyk_file_name(uuid));
+            // HEAD doesn't convert; the length is only known once a GET has
+            let length = if method == Method::HEAD {
+                current_cmyk_tiff(&metadata, &tiff_path).await
+            } else {
+                Some(cmyk_tiff(&output_path, &metadata, &tiff_path).await?)
+            };
+            (tiff_path, length)
+        }
+    };
+    let body = if method == Method::HEAD {
+        Body::empty()
+    } else {
+        let file = File::open(&path).await.map_err(AppError::Io)?;
+        Body::from_stream(ReaderStream::new(file))
+    };
 
-    // Generate filename
     let filename = format!(
-        "{}_{}.png",
-        Settings::sanitize_filename(&job.request.city),
-        Settings::sanitize_filename(&job.request.theme)
+        "{}.{}",
+        poster_filename_stem(&state.config, &job)?,
+        query.color_profile.extension()
     );
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "image/png")
+    let mut response = Response::builder().status(StatusCode::OK);
+    if let Some(content_length) = content_length {
+        response = response.header(header::CONTENT_LENGTH, content_length);
+    }
+    Ok(response
+        .header(header::CONTENT_TYPE, query.color_profile.content_type())
+        .header(header::ETAG, &etag)
         .header(
             header::CONTENT_DISPOSITION,
             format!("attachment; filename=\"{}\"", filename),
@@ -221,51 +581,565 @@ pub async fn download_poster(
         .unwrap())
 }
 
+/// Reject posters too large to inline as a data URI
+fn check_data_uri_size(size: u64) -> Result<()> {
+    if size > MAX_DATA_URI_BYTES {
+        return Err(AppError::InvalidRequest(format!(
+            "Poster is {} bytes, but data URIs are limited to {} bytes; request a preview or download the file",
+            size, MAX_DATA_URI_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn png_data_uri(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+/// Download filename (without extension) for a job, from the configured template
+fn poster_filename_stem(config: &Settings, job: &JobState) -> Result<String> {
+    let date = job.created_at.format("%Y-%m-%d").to_string();
+    let job_id = job.id.to_string();
+    let values = [
+        ("city", job.request.city.as_str()),
+        ("country", job.request.country.as_str()),
+        ("theme", job.request.theme.as_str()),
+        ("date", date.as_str()),
+        ("job_id", job_id.as_str()),
+    ];
+    render_filename_template(&config.filename_template, &values)
+        .map_err(|e| AppError::Internal(format!("Invalid FILENAME_TEMPLATE: {}", e)))
+}
+
+/// JPEG quality of poster thumbnails
+const THUMBNAIL_QUALITY: u8 = 85;
+
+/// Serve a small JPEG of a completed poster for galleries
+///
+/// The thumbnail is rendered on the first request, at most `THUMBNAIL_MAX_SIZE` pixels on
+/// its longest side, and kept next to the poster for later requests.
+pub async fn get_poster_thumbnail(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<Response> {
+    let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
+
+    let job = state
+        .get_job(uuid)
+        .ok_or_else(|| AppError::JobNotFound(job_id.clone()))?;
+
+    require_completed(&job)?;
+
+    let output_path = job
+        .output_path
+        .clone()
+        .ok_or_else(|| AppError::Internal("No output path for completed job".to_string()))?;
+
+    let max_size = state.config.thumbnail_max_size;
+    let thumbnail_path = state.config.static_dir.join(thumbnail_file_name(uuid, max_size));
+    let jpeg = match tokio::fs::read(&thumbnail_path).await {
+        Ok(jpeg) => jpeg,
+        Err(_) => {
+            ensure_poster_file(&state, &job, &output_path).await?;
+            let jpeg = tokio::task::spawn_blocking(move || render_thumbnail(&output_path, max_size))
+                .await
+                .map_err(|e| AppError::Internal(format!("Thumbnail task failed: {}", e)))??;
+            write_poster(&thumbnail_path, &jpeg).await?;
+            jpeg
+        }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/jpeg")
+        .header(header::CONTENT_LENGTH, jpeg.len())
+        // A job's poster never changes
+        .header(header::CACHE_CONTROL, "public, max-age=86400")
+        .body(Body::from(jpeg))
+        .unwrap())
+}
+
+/// Load a poster PNG and encode it as a JPEG no larger than `max_size` on either side
+fn render_thumbnail(path: &str, max_size: u32) -> Result<Vec<u8>> {
+    let pixmap = tiny_skia::Pixmap::load_png(path)
+        .map_err(|e| AppError::Rendering(format!("Failed to load poster PNG: {}", e)))?;
+    let (width, height) = thumbnail_size(pixmap.width(), pixmap.height(), max_size);
+    let thumbnail = if (width, height) == (pixmap.width(), pixmap.height()) {
+        pixmap
+    } else {
+        downscale_lanczos(&pixmap, width, height)?
+    };
+
+    // JPEG has no alpha, so transparent posters are flattened onto white (pixels are premultiplied)
+    let rgb: Vec<u8> = thumbnail
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let paper = 255 - pixel.alpha();
+            [pixel.red() + paper, pixel.green() + paper, pixel.blue() + paper]
+        })
+        .collect();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, THUMBNAIL_QUALITY)
+        .encode(&rgb, thumbnail.width(), thumbnail.height(), ExtendedColorType::Rgb8)
+        .map_err(|e| AppError::Rendering(format!("Failed to encode thumbnail JPEG: {}", e)))?;
+    Ok(jpeg)
+}
+
+/// Size that fits `max_size` on the longest side, keeping the aspect ratio and never enlarging
+fn thumbnail_size(width: u32, height: u32, max_size: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_size {
+        return (width, height);
+    }
+    let scale = |side: u32| ((side as u64 * max_size as u64 + longest as u64 / 2) / longest as u64).max(1) as u32;
+    (scale(width), scale(height))
+}
+
+/// Name of the manifest entry in batch archives
+const BATCH_MANIFEST_NAME: &str = "manifest.json";
+
+/// Bytes of a batch archive buffered ahead of the client
+const BATCH_ZIP_BUFFER: usize = 256 * 1024;
+
+/// Download every completed poster of a batch as one ZIP archive
+///
+/// The archive is streamed: posters are read from disk chunk by chunk as the client
+/// downloads, so memory stays flat however large the batch is. `manifest.json` lists
+/// every job of the batch with its status, including those not in the archive yet.
+pub async fn download_batch(
+    State(state): State<Arc<AppState>>,
+    Path(batch_id): Path<String>,
+) -> Result<Response> {
+    let uuid = Uuid::parse_str(&batch_id).map_err(|_| AppError::BatchNotFound(batch_id.clone()))?;
+    let job_ids = state
+        .get_batch(uuid)
+        .ok_or_else(|| AppError::BatchNotFound(batch_id.clone()))?;
+
+    let mut files = Vec::new();
+    let mut posters = Vec::new();
+    let mut used_names = HashSet::new();
+    for job in job_ids.into_iter().filter_map(|id| state.get_job(id)) {
+        // A poster that can't be restored is listed with its error instead of archived
+        let mut error = job.error.clone();
+        let servable = match (&job.output_path, job.status) {
+            (Some(path), JobStatus::Completed) => match ensure_poster_file(&state, &job, path).await {
+                Ok(()) => true,
+                Err(e) => {
+                    error = Some(e.to_string());
+                    false
+                }
+            },
+            _ => false,
+        };
+        let file = match &job.output_path {
+            Some(path) if servable => {
+                let stem = poster_filename_stem(&state.config, &job)?;
+                let mut name = format!("{}.png", stem);
+                let mut n = 2;
+                while !used_names.insert(name.clone()) {
+                    name = format!("{}_{}.png", stem, n);
+                    n += 1;
+                }
+                files.push(BatchFile {
+                    name: name.clone(),
+                    path: path.clone(),
+                    modified: job.updated_at,
+                });
+                Some(name)
+            }
+            _ => None,
+        };
+
+        posters.push(BatchManifestEntry {
+            job_id: job.id.to_string(),
+            theme: job.request.theme.clone(),
+            status: job.status,
+            file,
+            error,
+        });
+    }
+
+    if files.is_empty() {
+        return Err(AppError::InvalidRequest(format!(
+            "Batch {} has no completed posters yet",
+            batch_id
+        )));
+    }
+
+    let manifest = serde_json::to_vec_pretty(&BatchManifest {
+        batch_id: batch_id.clone(),
+        posters,
+    })?;
+
+    // A small pipe keeps at most a few chunks buffered ahead of the client; a failure
+    // after the pipe drains ends the body with an error rather than a truncated archive
+    let (writer, reader) = tokio::io::duplex(BATCH_ZIP_BUFFER);
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let result = write_batch_zip(writer, files, manifest).await.map_err(std::io::Error::other);
+        if let Err(e) = &result {
+            tracing::warn!("Batch {} download aborted: {}", uuid, e);
+        }
+        let _ = done_tx.send(result);
+    });
+    let failure = futures::stream::once(done_rx).filter_map(|result| match result {
+        Ok(Err(e)) => Some(Err(e)),
+        _ => None,
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"posters_{}.zip\"", batch_id),
+        )
+        .body(Body::from_stream(ReaderStream::new(reader).chain(failure)))
+        .unwrap())
+}
+
+/// A finished poster to include in a batch archive
+struct BatchFile {
+    name: String,
+    path: String,
+    modified: chrono::DateTime<chrono::Utc>,
+}
+
+/// Write a batch archive into `out`: each poster, then the manifest
+///
+/// Entries are stored uncompressed (posters are PNGs, which don't deflate further) and
+/// streamed, each file read once; ZIP64 records are added once the archive needs them.
+async fn write_batch_zip(
+    out: impl tokio::io::AsyncWrite + Unpin,
+    files: Vec<BatchFile>,
+    manifest: Vec<u8>,
+) -> async_zip::error::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(out);
+    let mut buf = vec![0u8; 64 * 1024];
+    for file in files {
+        let entry = ZipEntryBuilder::new(file.name.into(), Compression::Stored)
+            .last_modification_date(ZipDateTime::from_chrono(&file.modified));
+        let mut poster = File::open(&file.path).await?;
+        let mut entry_writer = zip.write_entry_stream(entry).await?;
+        loop {
+            let n = poster.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            entry_writer.write_all(&buf[..n]).await?;
+        }
+        entry_writer.close().await?;
+    }
+
+    let entry = ZipEntryBuilder::new(BATCH_MANIFEST_NAME.into(), Compression::Stored)
+        .last_modification_date(ZipDateTime::from_chrono(&chrono::Utc::now()));
+    zip.write_entry_whole(entry, &manifest).await?;
+    zip.close().await?;
+    Ok(())
+}
+
+/// Refuse to serve the poster of a job that is still running (409) or has failed (404)
+fn require_completed(job: &JobState) -> Result<()> {
+    match job.status {
+        JobStatus::Completed => Ok(()),
+        JobStatus::Failed => Err(AppError::PosterNotFound(format!("Job {} failed and has no poster", job.id))),
+        status => Err(AppError::JobNotCompleted(format!("Job {} is not completed (status: {})", job.id, status))),
+    }
+}
+
+/// Make sure a completed job's poster file is whole before it is served
+///
+/// A missing or truncated file (e.g. from a crash while it was written) is rendered again
+/// from the job's cached map data when that is still available. The render takes a job
+/// slot like any other, and concurrent requests for the same poster wait for one render.
+async fn ensure_poster_file(state: &Arc<AppState>, job: &JobState, output_path: &str) -> Result<()> {
+    let path = std::path::PathBuf::from(output_path);
+    if poster_file_is_complete(&path).await? {
+        return Ok(());
+    }
+
+    let repair = state.poster_repair(job.id);
+    let _repairing = repair.lock().await;
+    // Another request may have rendered it while this one waited
+    if poster_file_is_complete(&path).await? {
+        return Ok(());
+    }
+
+    tracing::warn!("Poster file for job {} is missing or incomplete, rendering it again", job.id);
+    let cached = state
+        .get_cached_map_data(job.id)
+        .ok_or_else(|| AppError::MapDataExpired(job.id.to_string()))?;
+    let _permit = state
+        .job_slots
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|_| AppError::Internal("Job queue is shut down".to_string()))?;
+    let generator = job_generator(state, &job.request).map_err(AppError::Rendering)?;
+    let map_data = MapData::from(Arc::unwrap_or_clone(cached));
+    generator
+        .render_from_data(&map_data, &path, &job.request.render_options(&state.config), None)
+        .await
+}
+
+/// Check a poster PNG for a missing or truncated file off the async runtime
+async fn poster_file_is_complete(path: &std::path::Path) -> Result<bool> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || png_is_complete(&path))
+        .await
+        .map_err(|e| AppError::Internal(format!("Poster check task failed: {}", e)))
+}
+
+/// Make sure the CMYK TIFF of a poster exists at `tiff_path` and return its size
+///
+/// Converted once and reused, unless the PNG was written again after the TIFF.
+async fn cmyk_tiff(png_path: &str, png: &std::fs::Metadata, tiff_path: &std::path::Path) -> Result<u64> {
+    if let Some(length) = current_cmyk_tiff(png, tiff_path).await {
+        return Ok(length);
+    }
+
+    let tiff = convert_to_cmyk_tiff(png_path.to_string()).await?;
+    write_poster(tiff_path, &tiff).await?;
+    Ok(tiff.len() as u64)
+}
+
+/// Size of the CMYK TIFF at `tiff_path`, unless it is missing or older than the PNG
+async fn current_cmyk_tiff(png: &std::fs::Metadata, tiff_path: &std::path::Path) -> Option<u64> {
+    let tiff = tokio::fs::metadata(tiff_path).await.ok()?;
+    match (tiff.modified(), png.modified()) {
+        (Ok(tiff_modified), Ok(png_modified)) if tiff_modified >= png_modified => Some(tiff.len()),
+        _ => None,
+    }
+}
+
+/// Decode a stored poster PNG and re-encode it as a CMYK TIFF off the async runtime
+async fn convert_to_cmyk_tiff(path: String) -> Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        let pixmap = tiny_skia::Pixmap::load_png(&path)
+            .map_err(|e| AppError::Rendering(format!("Failed to load poster PNG: {}", e)))?;
+        encode_cmyk_tiff(&pixmap)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("CMYK conversion task failed: {}", e)))?
+}
+
+/// Build a strong ETag for a poster file from its job id, size and modification time
+fn poster_etag(job_id: Uuid, metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+
+    format!("\"{}-{:x}-{:x}\"", job_id.simple(), metadata.len(), modified)
+}
+
+/// Check whether an `If-None-Match` header matches the given ETag
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().trim_start_matches("W/"))
+        .any(|v| v == "*" || v == etag)
+}
+
 /// Re-render a poster with a different theme using cached map data
 pub async fn rerender_poster(
     State(state): State<Arc<AppState>>,
     Path(job_id): Path<String>,
     Json(request): Json<ReRenderRequest>,
-) -> Result<Json<PosterCreateResponse>> {
+) -> Result<Response> {
     let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
 
-    // Get cached map data
-    let cached_data = state
-        .get_cached_map_data(uuid)
-        .ok_or_else(|| AppError::Internal("No cached data available for this job".to_string()))?;
+    // Get cached map data; the X-Cache header tells a missing entry apart from other failures
+    let Some(cached_data) = state.get_cached_map_data(uuid) else {
+        return Ok((x_cache(false), AppError::MapDataExpired(job_id)).into_response());
+    };
 
     // Validate theme exists
+    validate_theme_name(&request.theme)?;
     if load_theme(&state.config.themes_dir, &request.theme).is_none() {
         return Err(AppError::ThemeNotFound(request.theme.clone()));
     }
 
+    // Resolve the target canvas size (defaults to the standard poster, or the template's rectangle)
+    if request.render.template.is_some() && (request.paper_size.is_some() || request.width.is_some() || request.height.is_some()) {
+        return Err(AppError::InvalidRequest(
+            "A template sets the canvas size; omit paper_size, width and height".to_string(),
+        ));
+    }
+    let canvas = resolve_render_options(
+        &state.config,
+        request.paper_size.as_deref(),
+        request.width,
+        request.height,
+    )?;
+    let options = render_options(&state.config, &request.render, canvas)?;
+
     // Create new job for re-render
     let job_request = JobRequest {
         city: cached_data.city.clone(),
         country: cached_data.country.clone(),
         theme: request.theme.clone(),
         distance: cached_data.distance,
+        preview: request.preview,
+        invert: request.invert,
+        options,
+        ..Default::default()
     };
 
-    let new_job = state.create_job(job_request);
+    Ok((x_cache(true), Json(spawn_rerender_job(&state, job_request, cached_data))).into_response())
+}
+
+/// Render a job's cached map data with two themes, left half A and right half B, as one PNG
+///
+/// Both halves are drawn at the job's preview size so a theme picker gets its answer quickly.
+pub async fn compare_themes(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+    Json(request): Json<CompareThemesRequest>,
+) -> Result<Response> {
+    let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
+
+    let Some(cached_data) = state.get_cached_map_data(uuid) else {
+        return Ok((x_cache(false), AppError::MapDataExpired(job_id)).into_response());
+    };
+
+    let mut themes = Vec::with_capacity(2);
+    for name in [&request.theme_a, &request.theme_b] {
+        validate_theme_name(name)?;
+        let theme = state.load_theme(name).ok_or_else(|| AppError::ThemeNotFound(name.clone()))?;
+        themes.push(if request.invert { invert_theme(&theme) } else { theme });
+    }
+
+    // Keep the original job's canvas and layout options when it is still known
+    let config = &state.config;
+    let options = match state.get_job(uuid) {
+        Some(job) => job.request.options,
+        None => resolve_render_options(config, None, None, None)?,
+    }
+    .into_preview(config.output_dpi, config.preview_dpi, config.preview_scale);
+
+    let map_data = MapData::from(Arc::unwrap_or_clone(cached_data));
+    let divider = parse_hex_color(&get_theme_color(&themes[0], "text", "#000000")).unwrap_or((0, 0, 0));
+
+    // Two full renders, so wait for a job slot like any other render
+    let _permit = state
+        .job_slots
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|_| AppError::Internal("Job queue is shut down".to_string()))?;
+    let state = state.clone();
+    let png = tokio::task::spawn_blocking(move || {
+        let mut halves = Vec::with_capacity(2);
+        for theme in themes {
+            let generator = state.poster_generator(theme)?;
+            halves.push(generator.render_pixmap(&map_data, &options, None)?);
+        }
+        split_compare(&halves[0], &halves[1], divider)?
+            .encode_png()
+            .map_err(|e| AppError::Rendering(format!("Failed to encode comparison PNG: {}", e)))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Comparison rendering task failed: {}", e)))??;
+
+    Ok((
+        x_cache(true),
+        [(header::CONTENT_TYPE, "image/png")],
+        [(header::CONTENT_LENGTH, png.len().to_string())],
+        Body::from(png),
+    )
+        .into_response())
+}
+
+/// Turn a completed preview into a full-resolution poster from its cached map data
+pub async fn confirm_preview(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+    body: Option<Json<ConfirmRequest>>,
+) -> Result<Json<PosterCreateResponse>> {
+    let uuid = Uuid::parse_str(&job_id).map_err(|_| AppError::JobNotFound(job_id.clone()))?;
+
+    let preview = state
+        .get_job(uuid)
+        .ok_or_else(|| AppError::JobNotFound(job_id.clone()))?;
+    if !preview.request.preview {
+        return Err(AppError::InvalidRequest(format!("Job {} is not a preview", job_id)));
+    }
+    if preview.status != JobStatus::Completed {
+        return Err(AppError::InvalidRequest(format!(
+            "Preview {} is not completed (status: {})",
+            job_id, preview.status
+        )));
+    }
+
+    let cached_data = state.get_cached_map_data(uuid).ok_or_else(|| AppError::MapDataExpired(job_id.clone()))?;
+
+    // Keep the preview's theme unless the confirmation picks another one
+    let theme = body
+        .and_then(|Json(request)| request.theme)
+        .unwrap_or_else(|| preview.request.theme.clone());
+    validate_theme_name(&theme)?;
+    if load_theme(&state.config.themes_dir, &theme).is_none() {
+        return Err(AppError::ThemeNotFound(theme));
+    }
+
+    let job_request = JobRequest {
+        theme,
+        preview: false,
+        ..preview.request
+    };
+
+    Ok(Json(spawn_rerender_job(&state, job_request, cached_data)))
+}
+
+/// Create a job rendering cached map data and run it in the background
+fn spawn_rerender_job(
+    state: &Arc<AppState>,
+    job_request: JobRequest,
+    cached_data: Arc<CachedMapData>,
+) -> PosterCreateResponse {
+    let options = job_request.render_options(&state.config);
+    let theme_name = job_request.theme.clone();
+    let invert = job_request.invert;
+    let preview = job_request.preview;
+    let distance = cached_data.distance;
+
+    let new_job = state.create_rerender_job(job_request);
     let new_job_id = new_job.id;
 
-    // Copy cached data to new job
+    // Share the cached data with the new job
     state.cache_map_data(new_job_id, cached_data.clone());
 
     // Spawn background task for re-rendering with timeout
     let state_clone = state.clone();
-    let theme_name = request.theme.clone();
-    let rerender_timeout = std::time::Duration::from_secs(30); // 30 second timeout for re-render
+    let rerender_timeout = state.config.job_timeout(None);
 
     tokio::spawn(async move {
+        // Re-renders share the job slots with full generations
+        let _permit = match state_clone.job_slots.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(_) => {
+                state_clone.fail_job(new_job_id, "Job queue is shut down".to_string());
+                return;
+            }
+        };
+
         let job_result = tokio::time::timeout(
             rerender_timeout,
             AssertUnwindSafe(process_rerender_job(
                 state_clone.clone(),
                 new_job_id,
                 theme_name,
+                invert,
+                preview,
                 cached_data,
+                options,
             ))
             .catch_unwind()
         ).await;
@@ -283,40 +1157,56 @@ pub async fn rerender_poster(
         }
     });
 
-    Ok(Json(PosterCreateResponse {
+    PosterCreateResponse {
         job_id: new_job_id.to_string(),
         status: "queued".to_string(),
         estimated_time: 5, // Re-render is much faster
-    }))
+        timeout_seconds: rerender_timeout.as_secs(),
+        distance: Some(distance),
+    }
 }
 
 /// Process a re-render job using cached data
+///
+/// Previews are served from the preview cache when the same map data was already
+/// rendered with the same theme and options, since rendering is deterministic.
 async fn process_rerender_job(
     state: Arc<AppState>,
     job_id: Uuid,
     theme_name: String,
-    cached_data: CachedMapData,
+    invert: bool,
+    preview: bool,
+    cached_data: Arc<CachedMapData>,
+    options: RenderOptions,
 ) {
-    use crate::core::geocoding::format_coordinates;
-
     state.update_job_status(job_id, JobStatus::Processing);
 
-    // Load theme
-    let theme = match load_theme(&state.config.themes_dir, &theme_name) {
-        Some(t) => t,
-        None => {
-            state.fail_job(job_id, format!("Theme '{}' not found", theme_name));
+    // Load theme, falling back to the default if it disappeared since the request
+    let theme = match load_job_theme(&state, &theme_name, invert) {
+        Ok(t) => t,
+        Err(e) => {
+            state.fail_job(job_id, e);
             return;
         }
     };
 
+    // Output path
+    let output_path = state.config.static_dir.join(format!("{}.png", job_id));
+
+    let cache_key = preview.then(|| preview_cache_key(&cached_data, &theme, &options)).flatten();
+    if let Some(png) = cache_key.as_deref().and_then(|key| state.preview_cache.get(key)) {
+        match write_poster(&output_path, &png).await {
+            Ok(()) => {
+                tracing::debug!("Re-render preview {} served from the preview cache", job_id);
+                state.complete_job(job_id, output_path.to_string_lossy().to_string());
+                return;
+            }
+            Err(e) => tracing::warn!("Could not write cached preview for {}: {}", job_id, e),
+        }
+    }
+
     // Create generator
-    let generator = match PosterGenerator::new(
-        theme,
-        &state.config.fonts_dir,
-        state.config.nominatim_timeout,
-        state.config.osm_timeout,
-    ) {
+    let generator = match state.poster_generator(theme) {
         Ok(g) => g,
         Err(e) => {
             state.fail_job(job_id, format!("Failed to create generator: {}", e));
@@ -324,9 +1214,6 @@ async fn process_rerender_job(
         }
     };
 
-    // Output path
-    let output_path = state.config.static_dir.join(format!("{}.png", job_id));
-
     // Create progress callback
     let state_clone = state.clone();
     let progress_callback = Box::new(move |progress: crate::core::progress::GenerationProgress| {
@@ -339,22 +1226,24 @@ async fn process_rerender_job(
     });
 
     // Convert cached data to MapData for rendering
-    let map_data = crate::core::poster_generator::MapData {
-        city: cached_data.city,
-        country: cached_data.country,
-        lat: cached_data.lat,
-        lon: cached_data.lon,
-        distance: cached_data.distance,
-        streets: cached_data.streets,
-        water: cached_data.water,
-        parks: cached_data.parks,
-    };
-
-    let coordinates = format_coordinates(map_data.lat, map_data.lon);
+    let map_data = MapData::from(Arc::unwrap_or_clone(cached_data));
 
     // Render using cached data (no network requests!)
-    match generator.render_from_data(&map_data, &coordinates, &output_path, Some(progress_callback)) {
+    match generator.render_from_data(
+        &map_data,
+        &output_path,
+        &options,
+        Some(progress_callback),
+    )
+    .await
+    {
         Ok(()) => {
+            if let Some(key) = cache_key {
+                match tokio::fs::read(&output_path).await {
+                    Ok(png) => state.preview_cache.insert(key, png),
+                    Err(e) => tracing::warn!("Could not cache preview {}: {}", job_id, e),
+                }
+            }
             state.complete_job(job_id, output_path.to_string_lossy().to_string());
         }
         Err(e) => {
@@ -363,8 +1252,665 @@ async fn process_rerender_job(
     }
 }
 
+/// Preview cache key: the map data's fingerprint plus a hash of the theme and render options
+///
+/// The theme is hashed by content rather than name, so an edited theme file is never
+/// answered with a stale preview. The options include the canvas and output dimensions.
+/// Previews with embedded metadata aren't cached (None), since it records the creation time.
+fn preview_cache_key(data: &CachedMapData, theme: &serde_json::Value, options: &RenderOptions) -> Option<String> {
+    if options.embed_metadata {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    theme.to_string().hash(&mut hasher);
+    format!("{:?}", options).hash(&mut hasher);
+    let (width, height) = options.output_size.unwrap_or_else(|| options.canvas_size());
+    Some(format!("{:016x}-{}x{}-{:016x}", data.fingerprint(), width, height, hasher.finish()))
+}
+
+/// Create a generator for a job's theme, with the default-theme fallback
+fn job_generator(state: &AppState, request: &JobRequest) -> std::result::Result<PosterGenerator, String> {
+    let theme = load_job_theme(state, &request.theme, request.invert)?;
+    state.poster_generator(theme).map_err(|e| format!("Failed to create generator: {}", e))
+}
+
+/// Validate that a requested location has a non-blank city and country
+pub fn validate_location(city: &str, country: &str) -> Result<()> {
+    if city.trim().is_empty() {
+        return Err(AppError::InvalidRequest("City must not be empty".to_string()));
+    }
+    if country.trim().is_empty() {
+        return Err(AppError::InvalidRequest("Country must not be empty".to_string()));
+    }
+    Ok(())
+}
+
+/// Validate an optional geocoding country code filter
+pub fn validate_country_code(country_code: Option<&str>) -> Result<()> {
+    match country_code {
+        Some(codes) if !validate_country_codes(codes) => Err(AppError::InvalidRequest(format!(
+            "Invalid country code '{}': expected two-letter ISO codes, comma-separated",
+            codes
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Load a theme for a running job, inverted if asked, falling back to the configured default theme
+fn load_job_theme(state: &AppState, name: &str, invert: bool) -> std::result::Result<serde_json::Value, String> {
+    let theme = load_fallback_theme(state, name)?;
+    Ok(if invert { invert_theme(&theme) } else { theme })
+}
+
+/// Load a theme by name, or the configured default theme if it is missing
+fn load_fallback_theme(state: &AppState, name: &str) -> std::result::Result<serde_json::Value, String> {
+    if let Some(theme) = state.load_theme(name) {
+        return Ok(theme);
+    }
+
+    let config = &state.config;
+    tracing::warn!(
+        "Theme '{}' could not be loaded, falling back to default theme '{}'",
+        name,
+        config.default_theme
+    );
+
+    state.load_theme(&config.default_theme).ok_or_else(|| {
+        format!(
+            "Theme '{}' not found and default theme '{}' is also unavailable",
+            name, config.default_theme
+        )
+    })
+}
+
+/// Resolve canvas dimensions from an optional paper size and explicit pixel overrides
+fn resolve_render_options(
+    config: &Settings,
+    paper_size: Option<&str>,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<RenderOptions> {
+    let mut options = RenderOptions::default();
+
+    if let Some(name) = paper_size {
+        let (w, h) = paper_size_pixels(name, config.output_dpi)
+            .ok_or_else(|| AppError::InvalidRequest(format!("Unknown paper size '{}'", name)))?;
+        options.width = w;
+        options.height = h;
+    }
+
+    if let Some(w) = width {
+        options.width = w;
+    }
+    if let Some(h) = height {
+        options.height = h;
+    }
+
+    for (label, value) in [("width", options.width), ("height", options.height)] {
+        if value == 0 || value > MAX_CANVAS_DIMENSION {
+            return Err(AppError::InvalidRequest(format!(
+                "Canvas {} {} must be between 1 and {} pixels",
+                label, value, MAX_CANVAS_DIMENSION
+            )));
+        }
+    }
+
+    Ok(options)
+}
+
+/// Check a requested zoom level is within `MIN_ZOOM`..=`MAX_ZOOM`
+fn validate_zoom(zoom: Option<f64>) -> Result<()> {
+    match zoom {
+        Some(level) if !(MIN_ZOOM..=MAX_ZOOM).contains(&level) => Err(AppError::InvalidRequest(format!(
+            "zoom {} must be between {} and {}",
+            level, MIN_ZOOM, MAX_ZOOM
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Zoom framing for a job, sized by its full-resolution poster width
+fn zoom_level(config: &Settings, request: &JobRequest) -> Option<ZoomLevel> {
+    request.zoom.map(|level| ZoomLevel {
+        level,
+        poster_width_in: request.options.width as f64 / config.output_dpi.max(1) as f64,
+    })
+}
+
+/// Bleed in pixels at the output DPI for a requested `bleed_mm`, keeping the canvas within limits
+fn bleed_pixels(config: &Settings, bleed_mm: f32, options: &RenderOptions) -> Result<u32> {
+    if !(0.0..=MAX_BLEED_MM).contains(&bleed_mm) {
+        return Err(AppError::InvalidRequest(format!(
+            "bleed_mm must be between 0 and {}",
+            MAX_BLEED_MM
+        )));
+    }
+
+    let bleed = (bleed_mm / 25.4 * config.output_dpi as f32).round() as u32;
+    if options.width.max(options.height) + 2 * bleed > MAX_CANVAS_DIMENSION {
+        return Err(AppError::InvalidRequest(format!(
+            "Canvas with a {}mm bleed exceeds {} pixels",
+            bleed_mm, MAX_CANVAS_DIMENSION
+        )));
+    }
+    Ok(bleed)
+}
+
+fn validate_center_clear_radius(radius: Option<f64>) -> Result<Option<f64>> {
+    match radius {
+        Some(meters) if !(meters > 0.0 && meters.is_finite()) => Err(AppError::InvalidRequest(format!(
+            "center_clear_radius must be a positive number of meters, got {}",
+            meters
+        ))),
+        _ => Ok(radius),
+    }
+}
+
+/// Validate the rendering options of a create, multi-theme or re-render request and apply
+/// them to `canvas`, which sets the poster size when no template does
+fn render_options(config: &Settings, request: &RenderOptionsRequest, canvas: RenderOptions) -> Result<RenderOptions> {
+    let mut options = RenderOptions {
+        framing: request.framing,
+        fit_mode: request.fit_mode,
+        locator: request.locator,
+        width_curve: request.width_curve,
+        width_by: request.width_by,
+        coordinate_format: request.coordinate_format,
+        road_types: parse_road_types(request.road_types.as_deref())?,
+        flatten_palette: request.flatten_palette,
+        transparent_background: request.transparent_background,
+        embed_metadata: request.embed_metadata,
+        center_clear_radius: validate_center_clear_radius(request.center_clear_radius)?,
+        ..canvas
+    };
+    options.bleed = bleed_pixels(config, request.bleed_mm, &options)?;
+    apply_template(config, request.template.as_ref(), &mut options)?;
+    Ok(options)
+}
+
+/// Render into a template's rectangle, sizing the canvas to it after checking it fits the image
+fn apply_template(config: &Settings, template: Option<&TemplateRequest>, options: &mut RenderOptions) -> Result<()> {
+    let Some(template) = template else {
+        return Ok(());
+    };
+    let dir = config
+        .templates_dir
+        .as_ref()
+        .ok_or_else(|| AppError::InvalidRequest("Templates are not enabled on this server (TEMPLATES_DIR)".to_string()))?;
+    // Template names follow the theme name rules, so they can't leave the directory
+    if !is_valid_theme_name(&template.image) {
+        return Err(AppError::InvalidRequest(format!("Invalid template name: {:?}", template.image)));
+    }
+    if options.bleed > 0 {
+        return Err(AppError::InvalidRequest("bleed_mm can't be combined with a template".to_string()));
+    }
+
+    // Only the header is read here; the image is decoded when the poster is rendered
+    let path = dir.join(&template.image);
+    let (image_width, image_height) = png_dimensions(&path)
+        .ok_or_else(|| AppError::InvalidRequest(format!("Template '{}' not found or not a PNG", template.image)))?;
+    if image_width > MAX_CANVAS_DIMENSION || image_height > MAX_CANVAS_DIMENSION {
+        return Err(AppError::InvalidRequest(format!(
+            "Template '{}' is larger than {} pixels",
+            template.image, MAX_CANVAS_DIMENSION
+        )));
+    }
+    let fits = |start: u32, length: u32, limit: u32| length > 0 && start.checked_add(length).is_some_and(|end| end <= limit);
+    if !fits(template.x, template.width, image_width) || !fits(template.y, template.height, image_height) {
+        return Err(AppError::InvalidRequest(format!(
+            "Template rectangle {}x{} at ({}, {}) must lie within the {}x{} template",
+            template.width, template.height, template.x, template.y, image_width, image_height
+        )));
+    }
+
+    options.width = template.width;
+    options.height = template.height;
+    options.template = Some(PosterTemplate {
+        path,
+        x: template.x,
+        y: template.y,
+        width: template.width,
+        height: template.height,
+    });
+    Ok(())
+}
+
+/// Parse a requested subset of highway types (None keeps every fetched type)
+fn parse_road_types(names: Option<&[String]>) -> Result<Option<Vec<HighwayType>>> {
+    let Some(names) = names else {
+        return Ok(None);
+    };
+
+    let mut types = Vec::new();
+    for name in names {
+        let highway_type = HighwayType::from_tag(name.trim()).ok_or_else(|| {
+            let known: Vec<&str> = FETCHED_HIGHWAY_TYPES.iter().map(HighwayType::as_str).collect();
+            AppError::InvalidRequest(format!("Unknown road type '{}' (expected one of {})", name, known.join(", ")))
+        })?;
+        if !types.contains(&highway_type) {
+            types.push(highway_type);
+        }
+    }
+
+    if types.is_empty() {
+        return Err(AppError::InvalidRequest("road_types must list at least one road type".to_string()));
+    }
+    Ok(Some(types))
+}
+
+/// Most filters a request may add to one layer's query
+const MAX_EXTRA_TAGS: usize = 10;
+
+/// Parse a request's extra Overpass filters for one layer (None when there are none)
+///
+/// Filters are `key=value` pairs of plain OSM tokens, optionally prefixed with `way:` or
+/// `relation:`, so nothing else can reach the query.
+fn parse_extra_tags(field: &str, filters: &[String]) -> Result<Option<AreaTags>> {
+    if filters.is_empty() {
+        return Ok(None);
+    }
+    if filters.len() > MAX_EXTRA_TAGS {
+        return Err(AppError::InvalidRequest(format!(
+            "{} is limited to {} filters, got {}",
+            field,
+            MAX_EXTRA_TAGS,
+            filters.len()
+        )));
+    }
+    AreaTags::parse(filters)
+        .map(Some)
+        .map_err(|e| AppError::InvalidRequest(format!("{}: {}", field, e)))
+}
+
 /// Estimate generation time in seconds based on distance
 fn estimate_generation_time(distance: u32) -> u32 {
     // Rough estimate: 30 seconds base + 1 second per 1000m
     30 + distance / 1000
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rerender_without_cached_data_reports_a_cache_miss() {
+        let state = Arc::new(AppState::new(Settings::defaults()));
+        let request: ReRenderRequest = serde_json::from_str(r#"{"theme": "noir"}"#).unwrap();
+        let response = rerender_poster(State(state), Path(Uuid::new_v4().to_string()), Json(request))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GONE);
+        assert_eq!(response.headers()["x-cache"], "MISS");
+    }
+
+    #[tokio::test]
+    async fn test_missing_poster_is_rendered_again_in_a_job_slot() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-repair-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Settings::defaults();
+        config.static_dir = dir.clone();
+        config.max_concurrent_jobs = 1;
+        let state = Arc::new(AppState::new(config));
+        let job = state.create_job(JobRequest::default());
+        let output_path = dir.join(format!("{}.png", job.id)).to_string_lossy().to_string();
+
+        // Without map data to render from, the poster is gone for good
+        let error = ensure_poster_file(&state, &job, &output_path).await.unwrap_err();
+        assert!(matches!(error, AppError::MapDataExpired(_)), "{:?}", error);
+
+        // With it, the render waits for a free job slot
+        state.cache_map_data(job.id, Arc::new(CachedMapData::default()));
+        let busy = state.job_slots.clone().acquire_owned().await.unwrap();
+        let waiting = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            ensure_poster_file(&state, &job, &output_path),
+        )
+        .await;
+        assert!(waiting.is_err(), "rendered without a job slot");
+        drop(busy);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_confirm_after_map_data_expired_is_gone() {
+        let state = Arc::new(AppState::new(Settings::defaults()));
+        let preview = state.create_job(JobRequest { preview: true, ..Default::default() }).id;
+        state.complete_job(preview, "preview.png".to_string());
+
+        let error = confirm_preview(State(state), Path(preview.to_string()), None).await.unwrap_err();
+        assert!(matches!(error, AppError::MapDataExpired(ref id) if *id == preview.to_string()), "{:?}", error);
+        assert_eq!(error.into_response().status(), StatusCode::GONE);
+    }
+
+    #[tokio::test]
+    async fn test_batch_zip_stores_posters_and_manifest() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-batch-zip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let poster = dir.join("poster.png");
+        let png: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&poster, &png).unwrap();
+        let files = vec![BatchFile {
+            name: "paris_noir.png".to_string(),
+            path: poster.to_string_lossy().to_string(),
+            modified: chrono::Utc::now(),
+        }];
+
+        let mut archive = Vec::new();
+        write_batch_zip(&mut archive, files, b"{}".to_vec()).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let reader = async_zip::base::read::mem::ZipFileReader::new(archive).await.unwrap();
+        let names: Vec<&str> = reader.file().entries().iter().map(|e| e.filename().as_str().unwrap()).collect();
+        assert_eq!(names, ["paris_noir.png", BATCH_MANIFEST_NAME]);
+        assert!(reader.file().entries().iter().all(|e| e.compression() == Compression::Stored));
+
+        let mut stored = Vec::new();
+        reader.reader_with_entry(0).await.unwrap().read_to_end_checked(&mut stored).await.unwrap();
+        assert_eq!(stored, png);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_multi_theme_renders_every_theme_from_one_fetch() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-multi-theme-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let repo = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let mut config = Settings::defaults();
+        config.themes_dir = repo.join("themes");
+        config.fonts_dir = repo.join("fonts");
+        config.static_dir = dir.clone();
+        config.offline_data_dir = Some(dir.clone());
+        config.offline_mode = true;
+        config.default_distance = 3000;
+
+        // Recorded location and streets; the other layers are optional
+        let center = (45.43, 12.33);
+        std::fs::write(dir.join("geocode_venice_italy.json"), "[45.43, 12.33]").unwrap();
+        std::fs::write(
+            dir.join(crate::core::osm_client::offline_file_name("streets", center, 3000)),
+            include_str!("../../../tests/fixtures/overpass_areas.json"),
+        )
+        .unwrap();
+
+        let state = Arc::new(AppState::new(config));
+        let request: MultiThemeRequest = serde_json::from_str(
+            r#"{"city": "Venice", "country": "Italy", "themes": ["noir", "blueprint", "noir"]}"#,
+        )
+        .unwrap();
+        let response = create_multi_theme_posters(State(state.clone()), Json(request)).await.unwrap().0;
+        let themes: Vec<&str> = response.jobs.iter().map(|job| job.theme.as_str()).collect();
+        assert_eq!(themes, ["noir", "blueprint"]);
+
+        let job_ids: Vec<Uuid> = response.jobs.iter().map(|job| Uuid::parse_str(&job.job_id).unwrap()).collect();
+        for _ in 0..600 {
+            let finished = job_ids
+                .iter()
+                .all(|id| matches!(state.get_job(*id).unwrap().status, JobStatus::Completed | JobStatus::Failed));
+            if finished {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        for id in &job_ids {
+            let job = state.get_job(*id).unwrap();
+            assert_eq!(job.status, JobStatus::Completed, "{:?}", job.error);
+            // Without a distance, the themes' default (here DEFAULT_DISTANCE) is used
+            assert_eq!(job.request.distance, 3000);
+            assert!(std::path::Path::new(job.output_path.as_deref().unwrap()).exists());
+        }
+
+        // Every theme's job shares the one copy of the fetched data
+        let first = state.get_cached_map_data(job_ids[0]).unwrap();
+        let second = state.get_cached_map_data(job_ids[1]).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!first.streets.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cmyk_download_is_converted_once() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-cmyk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Settings::defaults();
+        config.static_dir = dir.clone();
+        let state = Arc::new(AppState::new(config));
+        let job_id = state.create_job(JobRequest::default()).id;
+        let poster = dir.join(format!("{}.png", job_id));
+        tiny_skia::Pixmap::new(8, 8).unwrap().save_png(&poster).unwrap();
+        state.complete_job(job_id, poster.to_string_lossy().to_string());
+
+        let download = |profile: &str, method: Method| {
+            let uri = format!("/api/posters/{}/download?color_profile={}", job_id, profile).parse().unwrap();
+            download_poster(State(state.clone()), Path(job_id.to_string()), Query::try_from_uri(&uri), method, HeaderMap::new())
+        };
+
+        // HEAD doesn't convert, so it can't tell the length yet
+        let head = download("cmyk", Method::HEAD).await.unwrap();
+        assert_eq!(head.status(), StatusCode::OK);
+        assert!(head.headers().get(header::CONTENT_LENGTH).is_none());
+        let tiff_path = dir.join(cmyk_file_name(job_id));
+        assert!(!tiff_path.exists());
+
+        // GET converts and keeps the TIFF, which later requests reuse
+        let get = download("cmyk", Method::GET).await.unwrap();
+        assert_eq!(get.headers()[header::CONTENT_TYPE], "image/tiff");
+        let body = axum::body::to_bytes(get.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, std::fs::read(&tiff_path).unwrap());
+        let converted = std::fs::metadata(&tiff_path).unwrap().modified().unwrap();
+
+        let head = download("cmyk", Method::HEAD).await.unwrap();
+        assert_eq!(head.headers()[header::CONTENT_LENGTH], body.len().to_string());
+        download("cmyk", Method::GET).await.unwrap();
+        assert_eq!(std::fs::metadata(&tiff_path).unwrap().modified().unwrap(), converted);
+
+        let invalid = download("rgb", Method::GET).await.unwrap_err();
+        assert!(matches!(invalid, AppError::InvalidRequest(ref message) if message.contains("`rgb`")), "{:?}", invalid);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_of_unfinished_or_failed_job() {
+        let state = Arc::new(AppState::new(Settings::defaults()));
+        let job_id = state.create_job(JobRequest::default()).id;
+        let download = || {
+            let uri = format!("/api/posters/{}/download", job_id).parse().unwrap();
+            download_poster(State(state.clone()), Path(job_id.to_string()), Query::try_from_uri(&uri), Method::GET, HeaderMap::new())
+        };
+
+        for status in [JobStatus::Queued, JobStatus::Processing] {
+            state.update_job_status(job_id, status);
+            let error = download().await.unwrap_err();
+            assert_eq!(error.into_response().status(), StatusCode::CONFLICT, "{}", status);
+        }
+
+        state.fail_job(job_id, "Rendering failed".to_string());
+        let error = download().await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+        let thumbnail = get_poster_thumbnail(State(state.clone()), Path(job_id.to_string())).await.unwrap_err();
+        assert!(matches!(thumbnail, AppError::PosterNotFound(_)), "{:?}", thumbnail);
+    }
+
+    #[tokio::test]
+    async fn test_compare_themes_without_cached_data_reports_a_cache_miss() {
+        let state = Arc::new(AppState::new(Settings::defaults()));
+        let request: CompareThemesRequest = serde_json::from_str(r#"{"theme_a": "noir", "theme_b": "blueprint"}"#).unwrap();
+        let response = compare_themes(State(state), Path(Uuid::new_v4().to_string()), Json(request))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GONE);
+        assert_eq!(response.headers()["x-cache"], "MISS");
+    }
+
+    #[test]
+    fn test_validate_location() {
+        assert!(validate_location("Venice", "Italy").is_ok());
+        assert!(validate_location("", "Italy").is_err());
+        assert!(validate_location("   ", "Italy").is_err());
+        assert!(validate_location("Venice", "\t").is_err());
+    }
+
+    #[test]
+    fn test_theme_default_distance() {
+        let mut config = Settings::defaults();
+        config.min_distance = 1000;
+        config.max_distance = 50000;
+        config.default_distance = 15000;
+        let theme = |value: serde_json::Value| serde_json::json!({ "name": "Test", "default_distance": value });
+
+        assert_eq!(theme_default_distance(&config, "test", &theme(serde_json::json!(5000))), 5000);
+        assert_eq!(theme_default_distance(&config, "test", &serde_json::json!({ "name": "Test" })), 15000);
+        // Out of bounds or not a whole number of meters
+        for value in [serde_json::json!(100), serde_json::json!(90000), serde_json::json!(-5), serde_json::json!("5km")] {
+            assert_eq!(theme_default_distance(&config, "test", &theme(value)), 15000);
+        }
+    }
+
+    #[test]
+    fn test_png_data_uri() {
+        assert_eq!(png_data_uri(b"\x89PNG"), "data:image/png;base64,iVBORw==");
+        assert!(check_data_uri_size(MAX_DATA_URI_BYTES).is_ok());
+        let err = check_data_uri_size(MAX_DATA_URI_BYTES + 1).unwrap_err().to_string();
+        assert!(err.contains("limited to"), "{}", err);
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = "\"abc-1-2\"";
+        let mut headers = HeaderMap::new();
+        assert!(!if_none_match(&headers, etag));
+
+        headers.insert(header::IF_NONE_MATCH, "\"other\", \"abc-1-2\"".parse().unwrap());
+        assert!(if_none_match(&headers, etag));
+
+        headers.insert(header::IF_NONE_MATCH, "W/\"abc-1-2\"".parse().unwrap());
+        assert!(if_none_match(&headers, etag));
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(if_none_match(&headers, etag));
+    }
+
+    #[test]
+    fn test_render_thumbnail() {
+        assert_eq!(thumbnail_size(3600, 4800, 400), (300, 400));
+        assert_eq!(thumbnail_size(4800, 3600, 400), (400, 300));
+        assert_eq!(thumbnail_size(200, 100, 400), (200, 100));
+        assert_eq!(thumbnail_size(10_000, 3, 400), (400, 1));
+
+        let path = std::env::temp_dir().join(format!("maptoposter-thumbnail-{}.png", std::process::id()));
+        let mut poster = tiny_skia::Pixmap::new(120, 160).unwrap();
+        poster.fill(tiny_skia::Color::from_rgba8(10, 20, 200, 255));
+        poster.save_png(&path).unwrap();
+
+        let jpeg = render_thumbnail(path.to_str().unwrap(), 40).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let decoded = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (30, 40));
+        let pixel = decoded.get_pixel(15, 20);
+        assert!(pixel[2] > 180 && pixel[0] < 30, "{:?}", pixel);
+    }
+
+    #[test]
+    fn test_render_options_are_read_alike_by_every_request() {
+        let config = Settings::defaults();
+        let render = r#""fit_mode": "cover", "road_types": ["primary"], "flatten_palette": true, "bleed_mm": 3"#;
+        let create: PosterCreateRequest =
+            serde_json::from_str(&format!(r#"{{"city": "Venice", "country": "Italy", {}}}"#, render)).unwrap();
+        let multi: MultiThemeRequest =
+            serde_json::from_str(&format!(r#"{{"city": "Venice", "country": "Italy", "themes": ["noir"], {}}}"#, render)).unwrap();
+        let rerender: ReRenderRequest = serde_json::from_str(&format!(r#"{{"theme": "noir", {}}}"#, render)).unwrap();
+
+        let options: Vec<RenderOptions> = [create.render, multi.render, rerender.render]
+            .iter()
+            .map(|request| render_options(&config, request, RenderOptions::default()).unwrap())
+            .collect();
+        assert_eq!(options[0].fit_mode, crate::rendering::canvas::FitMode::Cover);
+        assert_eq!(options[0].road_types, Some(vec![HighwayType::Primary]));
+        assert!(options[0].flatten_palette && options[0].bleed > 0);
+        for other in &options[1..] {
+            assert_eq!(format!("{:?}", other), format!("{:?}", options[0]));
+        }
+    }
+
+    #[test]
+    fn test_apply_template() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        tiny_skia::Pixmap::new(200, 300).unwrap().save_png(dir.join("frame.png")).unwrap();
+        let mut config = Settings::defaults();
+        let template = |image: &str, x, width| TemplateRequest { image: image.to_string(), x, y: 20, width, height: 200 };
+
+        let mut options = RenderOptions::default();
+        assert!(apply_template(&config, Some(&template("frame.png", 10, 180)), &mut options).is_err());
+        config.templates_dir = Some(dir.clone());
+
+        apply_template(&config, Some(&template("frame.png", 10, 180)), &mut options).unwrap();
+        assert_eq!((options.width, options.height), (180, 200));
+        assert_eq!(options.template.as_ref().map(|t| (t.x, t.y)), Some((10, 20)));
+
+        for (image, x, width) in [("frame.png", 30, 180), ("frame.png", 10, 0), ("missing.png", 10, 180), ("../frame.png", 10, 180)] {
+            assert!(apply_template(&config, Some(&template(image, x, width)), &mut RenderOptions::default()).is_err());
+        }
+        let mut with_bleed = RenderOptions { bleed: 35, ..Default::default() };
+        assert!(apply_template(&config, Some(&template("frame.png", 10, 180)), &mut with_bleed).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_road_types() {
+        assert_eq!(parse_road_types(None).unwrap(), None);
+
+        let names = vec!["motorway".to_string(), " primary ".to_string(), "motorway".to_string()];
+        assert_eq!(
+            parse_road_types(Some(&names)).unwrap(),
+            Some(vec![HighwayType::Motorway, HighwayType::Primary])
+        );
+
+        assert!(parse_road_types(Some(&["footway".to_string()])).is_err());
+        assert!(parse_road_types(Some(&[])).is_err());
+    }
+
+    #[test]
+    fn test_preview_cache_key() {
+        let config = Settings::defaults();
+        let options = resolve_render_options(&config, None, None, None)
+            .unwrap()
+            .into_preview(config.output_dpi, config.preview_dpi, config.preview_scale);
+        let data = CachedMapData { city: "Paris".to_string(), ..Default::default() };
+        let light = serde_json::json!({ "name": "Light", "bg": "#FFFFFF" });
+        let dark = serde_json::json!({ "name": "Dark", "bg": "#000000" });
+
+        let key = preview_cache_key(&data, &light, &options);
+        assert_eq!(key, preview_cache_key(&data.clone(), &light, &options));
+        assert_ne!(key, preview_cache_key(&data, &dark, &options));
+
+        let mut larger = options.clone();
+        larger.output_size = Some((1200, 1600));
+        assert_ne!(key, preview_cache_key(&data, &light, &larger));
+
+        let elsewhere = CachedMapData { city: "Lyon".to_string(), ..Default::default() };
+        assert_ne!(key, preview_cache_key(&elsewhere, &light, &options));
+
+        let mut with_metadata = options.clone();
+        with_metadata.embed_metadata = true;
+        assert!(key.is_some());
+        assert_eq!(preview_cache_key(&data, &light, &with_metadata), None);
+    }
+
+    #[test]
+    fn test_parse_extra_tags() {
+        assert!(parse_extra_tags("extra_water_tags", &[]).unwrap().is_none());
+
+        let filters = vec!["natural=wetland".to_string(), "way:waterway=canal".to_string()];
+        assert!(parse_extra_tags("extra_water_tags", &filters).unwrap().is_some());
+
+        let injected = vec![r#"natural=water"];out;"#.to_string()];
+        assert!(matches!(
+            parse_extra_tags("extra_water_tags", &injected),
+            Err(AppError::InvalidRequest(message)) if message.starts_with("extra_water_tags")
+        ));
+
+        let too_many = vec!["natural=wetland".to_string(); MAX_EXTRA_TAGS + 1];
+        assert!(parse_extra_tags("extra_park_tags", &too_many).is_err());
+    }
+}
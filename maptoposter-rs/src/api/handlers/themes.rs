@@ -1,14 +1,22 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
     Json,
 };
 
-use crate::api::models::{ThemeInfo, ThemeListResponse};
+use crate::api::middleware::require_admin;
+use crate::api::models::{ThemeInfo, ThemeListResponse, ThemeNormalizeResponse, ThemeQuery};
 use crate::api::state::AppState;
 use crate::error::{AppError, Result};
-use crate::themes::loader::{load_theme, load_themes};
+use crate::rendering::swatch::render_swatch;
+use crate::rendering::typography::FontSet;
+use crate::themes::loader::{
+    invert_theme, load_theme, load_themes, normalize_theme_file, road_color_warnings, validate_theme_name,
+};
 
 /// List all available themes
 pub async fn list_themes(State(state): State<Arc<AppState>>) -> Json<ThemeListResponse> {
@@ -29,6 +37,7 @@ pub async fn list_themes(State(state): State<Arc<AppState>>) -> Json<ThemeListRe
             road_motorway: theme.get("road_motorway").and_then(|v| v.as_str()).map(String::from),
             road_primary: theme.get("road_primary").and_then(|v| v.as_str()).map(String::from),
             road_default: theme.get("road_default").and_then(|v| v.as_str()).map(String::from),
+            warnings: road_color_warnings(&theme),
         })
         .collect();
 
@@ -39,13 +48,18 @@ pub async fn list_themes(State(state): State<Arc<AppState>>) -> Json<ThemeListRe
     Json(ThemeListResponse { themes, count })
 }
 
-/// Get a specific theme by name
+/// Get a specific theme by name (`?invert=true` for its lightness-inverted variant)
 pub async fn get_theme(
     State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
+    Query(query): Query<ThemeQuery>,
 ) -> Result<Json<ThemeInfo>> {
-    let theme = load_theme(&state.config.themes_dir, &name)
+    validate_theme_name(&name)?;
+    let mut theme = load_theme(&state.config.themes_dir, &name)
         .ok_or_else(|| AppError::ThemeNotFound(name.clone()))?;
+    if query.invert {
+        theme = invert_theme(&theme);
+    }
 
     Ok(Json(ThemeInfo {
         id: name.clone(),
@@ -59,5 +73,70 @@ pub async fn get_theme(
         road_motorway: theme.get("road_motorway").and_then(|v| v.as_str()).map(String::from),
         road_primary: theme.get("road_primary").and_then(|v| v.as_str()).map(String::from),
         road_default: theme.get("road_default").and_then(|v| v.as_str()).map(String::from),
+        warnings: road_color_warnings(&theme),
     }))
 }
+
+/// Get a theme's complete JSON as loaded, including keys `ThemeInfo` leaves out
+pub async fn get_theme_raw(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<ThemeQuery>,
+) -> Result<Json<serde_json::Value>> {
+    validate_theme_name(&name)?;
+    let theme = load_theme(&state.config.themes_dir, &name)
+        .ok_or_else(|| AppError::ThemeNotFound(name.clone()))?;
+
+    Ok(Json(if query.invert { invert_theme(&theme) } else { theme }))
+}
+
+/// Render a theme's colors as a small labelled PNG strip, for galleries and quick checks
+pub async fn get_theme_swatch(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<ThemeQuery>,
+) -> Result<Response> {
+    validate_theme_name(&name)?;
+    let mut theme = load_theme(&state.config.themes_dir, &name)
+        .ok_or_else(|| AppError::ThemeNotFound(name.clone()))?;
+    if query.invert {
+        theme = invert_theme(&theme);
+    }
+
+    let fonts_dir = state.config.fonts_dir.clone();
+    let png = tokio::task::spawn_blocking(move || {
+        let fonts = FontSet::load(&fonts_dir)?;
+        render_swatch(&theme, &fonts)?
+            .encode_png()
+            .map_err(|e| AppError::Rendering(format!("Failed to encode swatch PNG: {}", e)))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Swatch rendering task failed: {}", e)))??;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CONTENT_LENGTH, png.len())
+        .body(Body::from(png))
+        .unwrap())
+}
+
+/// Rewrite a theme file with every color as uppercase `#RRGGBB` (admin only)
+pub async fn normalize_theme(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ThemeNormalizeResponse>> {
+    require_admin(&state.config, &headers)?;
+
+    let themes_dir = state.config.themes_dir.clone();
+    let theme_name = name.clone();
+    let changed = tokio::task::spawn_blocking(move || normalize_theme_file(&themes_dir, &theme_name))
+        .await
+        .map_err(|e| AppError::Internal(format!("Theme normalization task failed: {}", e)))??;
+    if !changed.is_empty() {
+        tracing::info!("Normalized colors of theme '{}': {}", name, changed.join(", "));
+    }
+
+    Ok(Json(ThemeNormalizeResponse { name, changed }))
+}
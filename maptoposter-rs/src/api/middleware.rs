@@ -0,0 +1,90 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::{Frame, SizeHint};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::Settings;
+use crate::error::{AppError, Result as AppResult};
+
+/// Header carrying the admin token for operator-only endpoints
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Check the request's admin token against `ADMIN_TOKEN`
+///
+/// Admin endpoints are refused entirely when no token is configured.
+pub fn require_admin(config: &Settings, headers: &HeaderMap) -> AppResult<()> {
+    let Some(expected) = config.admin_token.as_deref() else {
+        return Err(AppError::Forbidden("Admin endpoints are disabled (ADMIN_TOKEN is not set)".to_string()));
+    };
+
+    let provided = headers
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden("Missing or invalid admin token".to_string()))
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Reject requests with 503 once too many are in flight.
+///
+/// The permit is held until the response body finishes, so long-lived SSE
+/// streams count against the limit for as long as they stay open.
+pub async fn shed_load(
+    State(slots): State<Arc<Semaphore>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let permit = match slots.try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            tracing::warn!("Shedding request to {}: too many requests in flight", request.uri().path());
+            return AppError::Overloaded.into_response();
+        }
+    };
+
+    let (parts, body) = next.run(request).await.into_parts();
+    Response::from_parts(parts, Body::new(PermitBody { inner: body, _permit: permit }))
+}
+
+/// Response body that keeps an in-flight permit alive until it is dropped
+struct PermitBody {
+    inner: Body,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl http_body::Body for PermitBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -1,3 +1,5 @@
 pub mod handlers;
+pub mod middleware;
 pub mod models;
+pub mod scheduler;
 pub mod state;
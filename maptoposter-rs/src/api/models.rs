@@ -1,5 +1,60 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
+use crate::config::Settings;
+use crate::core::geocoding::CoordinateFormat;
+use crate::core::osm_client::{DetailArea, PointKind};
+use crate::core::poster_generator::{DataStats, Framing, TrimBox};
+use crate::core::rate_limiter::CacheStats;
+use crate::rendering::canvas::FitMode;
+use crate::rendering::locator::LocatorCorner;
+use crate::rendering::road_styles::{WidthBy, WidthCurve};
+
+/// Rendering options accepted by create, multi-theme and re-render requests
+#[derive(Debug, Default, Deserialize)]
+pub struct RenderOptionsRequest {
+    /// Frame to the fetched roads (default) or exactly to the requested distance
+    #[serde(default)]
+    pub framing: Framing,
+    /// Letterbox the framed area into the poster (`contain`, default) or crop it to fill the poster (`cover`)
+    #[serde(default)]
+    pub fit_mode: FitMode,
+    /// Corner for a country locator inset (`top_left`, `top_right`, `bottom_left`, `bottom_right`)
+    #[serde(default)]
+    pub locator: Option<LocatorCorner>,
+    /// Road width scaling with distance (`sqrt`, `linear`, `log`), overriding the theme
+    #[serde(default)]
+    pub width_curve: Option<WidthCurve>,
+    /// What sets road widths: `type` (default), or the `lanes` or `maxspeed` tag
+    #[serde(default)]
+    pub width_by: WidthBy,
+    /// How the coordinates line is written: `decimal` (default), `dms` or `plain`
+    #[serde(default)]
+    pub coordinate_format: CoordinateFormat,
+    /// Highway tag values to include, e.g. `["motorway", "primary"]` (all when omitted)
+    #[serde(default)]
+    pub road_types: Option<Vec<String>>,
+    /// Limit the saved image to the theme's own colors (no anti-aliased shades), for screen printing
+    #[serde(default)]
+    pub flatten_palette: bool,
+    /// Leave the background transparent, so only the map's layers and text are drawn
+    #[serde(default)]
+    pub transparent_background: bool,
+    /// Record the city, coordinates, theme, distance and render time in the PNG's text chunks
+    #[serde(default)]
+    pub embed_metadata: bool,
+    /// Meters around the center kept free of roads (only roads lying wholly inside are left out)
+    #[serde(default)]
+    pub center_clear_radius: Option<f64>,
+    /// Render the map into a rectangle of a template image instead of the built-in layout
+    #[serde(default)]
+    pub template: Option<TemplateRequest>,
+    /// Print bleed in millimetres added beyond the trim line on every side (0 for none)
+    #[serde(default)]
+    pub bleed_mm: f32,
+}
+
 /// Request to create a new poster
 #[derive(Debug, Deserialize)]
 pub struct PosterCreateRequest {
@@ -7,30 +62,242 @@ pub struct PosterCreateRequest {
     pub country: String,
     #[serde(default = "default_theme")]
     pub theme: String,
-    #[serde(default = "default_distance")]
-    pub distance: u32,
+    /// Radius in meters; defaults to the theme's `default_distance`, then `DEFAULT_DISTANCE`
+    #[serde(default)]
+    pub distance: Option<u32>,
+    /// Web-map zoom level (10-16) to frame instead of `distance`, resolved for the location's latitude
+    #[serde(default)]
+    pub zoom: Option<f64>,
+    /// Restrict geocoding to ISO 3166-1 alpha-2 country codes (comma-separated)
+    #[serde(default)]
+    pub country_code: Option<String>,
+    /// Job timeout override in seconds, clamped to the server maximum
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>,
+    /// Point features to mark and label, e.g. `["peak", "place"]` (none when omitted)
+    #[serde(default)]
+    pub points: Vec<PointKind>,
+    /// Extra area categories for detailed prints, e.g. `["parking", "pitch"]` (none when omitted)
+    #[serde(default)]
+    pub detail_areas: Vec<DetailArea>,
+    /// Fail the job if water or park data can't be fetched
+    #[serde(default)]
+    pub strict: bool,
+    /// Overpass filters added to the water query for this request, e.g. `["natural=wetland"]`
+    #[serde(default)]
+    pub extra_water_tags: Vec<String>,
+    /// Overpass filters added to the park query for this request, e.g. `["leisure=garden"]`
+    #[serde(default)]
+    pub extra_park_tags: Vec<String>,
+    /// Render a small preview at `PREVIEW_DPI` instead of the full poster
+    #[serde(default)]
+    pub preview: bool,
+    /// Render with the theme's colors lightness-inverted (a dark variant of a light theme)
+    #[serde(default)]
+    pub invert: bool,
+    /// Canvas, framing and styling options
+    #[serde(flatten)]
+    pub render: RenderOptionsRequest,
 }
 
 fn default_theme() -> String {
     "feature_based".to_string()
 }
 
-fn default_distance() -> u32 {
-    15000
-}
-
 /// Response when a poster job is created
 #[derive(Debug, Serialize)]
 pub struct PosterCreateResponse {
     pub job_id: String,
     pub status: String,
     pub estimated_time: u32,
+    /// Effective job timeout in seconds
+    pub timeout_seconds: u64,
+    /// Radius in meters the poster will cover (left out when `zoom` frames it instead)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<u32>,
+}
+
+/// Request to render one location in several themes, fetching map data once
+#[derive(Debug, Deserialize)]
+pub struct MultiThemeRequest {
+    pub city: String,
+    pub country: String,
+    pub themes: Vec<String>,
+    /// Radius in meters; defaults to the first theme's `default_distance`, then `DEFAULT_DISTANCE`
+    #[serde(default)]
+    pub distance: Option<u32>,
+    /// Web-map zoom level (10-16) to frame instead of `distance`, resolved for the location's latitude
+    #[serde(default)]
+    pub zoom: Option<f64>,
+    /// Restrict geocoding to ISO 3166-1 alpha-2 country codes (comma-separated)
+    #[serde(default)]
+    pub country_code: Option<String>,
+    /// Point features to mark and label, e.g. `["peak", "place"]` (none when omitted)
+    #[serde(default)]
+    pub points: Vec<PointKind>,
+    /// Extra area categories for detailed prints, e.g. `["parking", "pitch"]` (none when omitted)
+    #[serde(default)]
+    pub detail_areas: Vec<DetailArea>,
+    /// Fail the job if water or park data can't be fetched
+    #[serde(default)]
+    pub strict: bool,
+    /// Overpass filters added to the water query for this request, e.g. `["natural=wetland"]`
+    #[serde(default)]
+    pub extra_water_tags: Vec<String>,
+    /// Overpass filters added to the park query for this request, e.g. `["leisure=garden"]`
+    #[serde(default)]
+    pub extra_park_tags: Vec<String>,
+    /// Render with the theme's colors lightness-inverted (a dark variant of a light theme)
+    #[serde(default)]
+    pub invert: bool,
+    /// Canvas, framing and styling options
+    #[serde(flatten)]
+    pub render: RenderOptionsRequest,
+}
+
+/// Job created for one theme of a multi-theme request
+#[derive(Debug, Serialize)]
+pub struct ThemeJob {
+    pub theme: String,
+    pub job_id: String,
+}
+
+/// Response when multi-theme poster jobs are created
+#[derive(Debug, Serialize)]
+pub struct MultiThemeResponse {
+    /// Id for downloading every poster at once from `/api/posters/batch/{batch_id}/download`
+    pub batch_id: String,
+    pub jobs: Vec<ThemeJob>,
+    pub status: String,
+    pub estimated_time: u32,
+}
+
+/// `manifest.json` included in a batch ZIP download
+#[derive(Debug, Serialize)]
+pub struct BatchManifest {
+    pub batch_id: String,
+    pub posters: Vec<BatchManifestEntry>,
+}
+
+/// One job of a batch and whether its poster is in the archive
+#[derive(Debug, Serialize)]
+pub struct BatchManifestEntry {
+    pub job_id: String,
+    pub theme: String,
+    pub status: JobStatus,
+    /// File name inside the archive (only for completed posters)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Request to re-render a poster with a different theme
 #[derive(Debug, Deserialize)]
 pub struct ReRenderRequest {
     pub theme: String,
+    /// Named paper size (e.g. "12x16", "square", "a4") rendered at the output DPI
+    #[serde(default)]
+    pub paper_size: Option<String>,
+    /// Explicit canvas width in pixels (overrides paper_size)
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Explicit canvas height in pixels (overrides paper_size)
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Render at preview size, e.g. to try themes before confirming
+    #[serde(default)]
+    pub preview: bool,
+    /// Render with the theme's colors lightness-inverted (a dark variant of a light theme)
+    #[serde(default)]
+    pub invert: bool,
+    /// Canvas, framing and styling options
+    #[serde(flatten)]
+    pub render: RenderOptionsRequest,
+}
+
+/// A template from `TEMPLATES_DIR` and the rectangle the map fills, in template pixels
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateRequest {
+    /// PNG file name inside `TEMPLATES_DIR`
+    pub image: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Request to render cached map data with two themes split down the middle
+#[derive(Debug, Deserialize)]
+pub struct CompareThemesRequest {
+    /// Theme for the left half
+    pub theme_a: String,
+    /// Theme for the right half
+    pub theme_b: String,
+    /// Render both halves with lightness-inverted colors
+    #[serde(default)]
+    pub invert: bool,
+}
+
+/// Optional body for confirming a preview
+#[derive(Debug, Deserialize)]
+pub struct ConfirmRequest {
+    /// Theme for the full render (defaults to the preview's theme)
+    #[serde(default)]
+    pub theme: Option<String>,
+}
+
+/// Query parameters for poster downloads
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadQuery {
+    #[serde(default)]
+    pub color_profile: ColorProfile,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+}
+
+/// How a downloaded poster is delivered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// The image file itself, as an attachment
+    #[default]
+    File,
+    /// JSON holding the PNG as a base64 `data:` URI, for small previews embedded in a page
+    DataUri,
+}
+
+/// Poster inlined as a data URI (`?response_format=data_uri`)
+#[derive(Debug, Serialize)]
+pub struct DataUriResponse {
+    pub image: String,
+}
+
+/// Color space of a downloaded poster
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorProfile {
+    /// The rendered PNG as stored
+    #[default]
+    Srgb,
+    /// Naive CMYK conversion delivered as TIFF (no ICC profile)
+    Cmyk,
+}
+
+impl ColorProfile {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Srgb => "image/png",
+            Self::Cmyk => "image/tiff",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Srgb => "png",
+            Self::Cmyk => "tif",
+        }
+    }
 }
 
 /// Job status response
@@ -47,6 +314,15 @@ pub struct JobStatusResponse {
     pub download_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Number of queued jobs ahead of this one (only while queued)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<usize>,
+    /// Features kept and dropped per layer (once map data has been fetched)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<DataStats>,
+    /// Trim line within the image (only for posters rendered with a bleed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim_box: Option<TrimBox>,
 }
 
 /// Job status enumeration
@@ -88,6 +364,25 @@ pub struct ThemeInfo {
     pub road_primary: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub road_default: Option<String>,
+    /// Road color problems that flatten or hide the road hierarchy
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Result of rewriting a theme file with canonical colors
+#[derive(Debug, Serialize)]
+pub struct ThemeNormalizeResponse {
+    pub name: String,
+    /// Top-level keys whose values were rewritten (empty if the file was already canonical)
+    pub changed: Vec<String>,
+}
+
+/// Query parameters for fetching a theme
+#[derive(Debug, Default, Deserialize)]
+pub struct ThemeQuery {
+    /// Return the lightness-inverted (dark/light swapped) variant of the theme
+    #[serde(default)]
+    pub invert: bool,
 }
 
 /// Theme list response
@@ -107,6 +402,15 @@ pub struct LocationResult {
     pub city: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub country: Option<String>,
+    /// OSM tag key of the match, e.g. "place" for settlements
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// OSM tag value of the match, e.g. "city", "town" or "residential"
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub place_type: Option<String>,
+    /// Nominatim's relevance score (0.0-1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub importance: Option<f64>,
 }
 
 /// Location search response
@@ -114,6 +418,46 @@ pub struct LocationResult {
 pub struct LocationSearchResponse {
     pub results: Vec<LocationResult>,
     pub count: usize,
+    /// Result limit applied, after clamping the requested one to the server maximum
+    pub limit: u32,
+}
+
+/// One city of a `POST /api/locations/batch` request
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchLocationQuery {
+    pub city: String,
+    pub country: String,
+}
+
+/// Outcome of geocoding one city of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchLocationStatus {
+    Found,
+    NotFound,
+    /// The lookup failed (invalid entry, Nominatim unavailable or throttling)
+    Error,
+}
+
+/// Geocoding result for one city of a batch, in request order
+#[derive(Debug, Serialize)]
+pub struct BatchLocationResult {
+    pub city: String,
+    pub country: String,
+    pub status: BatchLocationStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<LocationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Batch geocoding response
+#[derive(Debug, Serialize)]
+pub struct BatchLocationResponse {
+    pub results: Vec<BatchLocationResult>,
+    pub count: usize,
+    /// How many of the cities were resolved
+    pub found: usize,
 }
 
 /// Health check response
@@ -123,6 +467,78 @@ pub struct HealthResponse {
     pub version: String,
 }
 
+/// Build information response
+#[derive(Debug, Serialize)]
+pub struct VersionResponse {
+    pub version: String,
+    pub git_commit: String,
+    /// Build time as RFC 3339, if known
+    pub build_timestamp: Option<String>,
+    pub rustc_version: String,
+}
+
+/// Effective server settings, for checking which environment variables took effect
+///
+/// `admin_token` is never included.
+#[derive(Debug, Serialize)]
+pub struct ConfigResponse {
+    pub port: u16,
+    pub themes_dir: PathBuf,
+    pub fonts_dir: PathBuf,
+    pub static_dir: PathBuf,
+    pub frontend_dir: PathBuf,
+    pub default_theme: String,
+    pub default_distance: u32,
+    pub min_distance: u32,
+    pub max_distance: u32,
+    pub output_dpi: u32,
+    pub preview_dpi: u32,
+    pub preview_scale: f32,
+    pub nominatim_url: String,
+    pub nominatim_delay: f64,
+    pub nominatim_timeout: f64,
+    pub osm_delay: f64,
+    pub osm_timeout: f64,
+    pub offline_mode: bool,
+    pub max_concurrent_jobs: usize,
+    pub job_timeout_secs: u64,
+    pub max_job_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+    pub max_inflight_requests: usize,
+    pub user_agent: String,
+}
+
+impl From<&Settings> for ConfigResponse {
+    fn from(config: &Settings) -> Self {
+        Self {
+            port: config.port,
+            themes_dir: config.themes_dir.clone(),
+            fonts_dir: config.fonts_dir.clone(),
+            static_dir: config.static_dir.clone(),
+            frontend_dir: config.frontend_dir.clone(),
+            default_theme: config.default_theme.clone(),
+            default_distance: config.default_distance,
+            min_distance: config.min_distance,
+            max_distance: config.max_distance,
+            output_dpi: config.output_dpi,
+            preview_dpi: config.preview_dpi,
+            preview_scale: config.preview_scale,
+            nominatim_url: config.nominatim_url.clone(),
+            nominatim_delay: config.nominatim_delay,
+            nominatim_timeout: config.nominatim_timeout,
+            osm_delay: config.osm_delay,
+            osm_timeout: config.osm_timeout,
+            offline_mode: config.offline_mode,
+            max_concurrent_jobs: config.max_concurrent_jobs,
+            job_timeout_secs: config.job_timeout_secs,
+            max_job_timeout_secs: config.max_job_timeout_secs,
+            request_timeout_secs: config.request_timeout_secs,
+            max_inflight_requests: config.max_inflight_requests,
+            user_agent: config.user_agent_header(),
+        }
+    }
+}
+
 /// Readiness check response
 #[derive(Debug, Serialize)]
 pub struct ReadinessResponse {
@@ -138,6 +554,90 @@ pub struct ReadinessChecks {
     pub static_dir: bool,
 }
 
+/// Query parameters for the warmup endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct WarmupQuery {
+    /// Also send a tiny request to Overpass and Nominatim
+    #[serde(default)]
+    pub probe: bool,
+}
+
+/// Timings of the warmup steps
+#[derive(Debug, Serialize)]
+pub struct WarmupResponse {
+    /// "ready" when every step succeeded, "not_ready" otherwise
+    pub status: String,
+    pub total_ms: u64,
+    pub steps: Vec<WarmupStep>,
+}
+
+/// One warmup step: what was loaded or probed, and how long it took
+#[derive(Debug, Serialize)]
+pub struct WarmupStep {
+    /// "fonts", "themes", "overpass" or "nominatim"
+    pub name: String,
+    pub ok: bool,
+    pub duration_ms: u64,
+    /// What was loaded, or why the step failed
+    pub detail: String,
+}
+
+/// Storage usage of the generated posters directory
+#[derive(Debug, Serialize)]
+pub struct StorageResponse {
+    /// "ok" or "warning" when usage exceeds the threshold
+    pub status: String,
+    pub total_bytes: u64,
+    pub file_count: u64,
+    pub threshold_bytes: u64,
+}
+
+/// Job counters since startup; these keep counting after old jobs are pruned
+#[derive(Debug, Serialize)]
+pub struct JobStatsResponse {
+    pub jobs_created: u64,
+    pub jobs_completed: u64,
+    pub jobs_failed: u64,
+    /// Re-render and preview confirmation jobs, also included in `jobs_created`
+    pub rerenders: u64,
+    /// Jobs currently held in memory
+    pub jobs_retained: usize,
+}
+
+/// The recurring job schedule and the outcome of its latest run
+#[derive(Debug, Serialize)]
+pub struct ScheduleResponse {
+    /// False when no `SCHEDULE_FILE` is configured
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    pub runs: u64,
+    pub last_run_at: Option<String>,
+    pub next_run_at: Option<String>,
+    pub recipes: Vec<ScheduledRecipeStatus>,
+}
+
+/// A scheduled recipe and the latest job enqueued for it
+#[derive(Debug, Serialize)]
+pub struct ScheduledRecipeStatus {
+    pub city: String,
+    pub country: String,
+    pub theme: String,
+    pub last_job_id: Option<String>,
+    /// Status of the last job, or None if it hasn't run or has since been pruned
+    pub last_status: Option<JobStatus>,
+    pub last_error: Option<String>,
+}
+
+/// SSE `step` event sent when a job moves on to another step
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StepTransition {
+    pub job_id: String,
+    pub step: String,
+    /// Step reported before this one on the same stream (None on the stream's first)
+    pub previous_step: Option<String>,
+}
+
 /// Progress update for SSE streaming
 #[derive(Debug, Clone, Serialize)]
 pub struct ProgressUpdate {
@@ -150,4 +650,25 @@ pub struct ProgressUpdate {
     /// Download URL - included when job is completed
     #[serde(skip_serializing_if = "Option::is_none")]
     pub download_url: Option<String>,
+    /// Number of queued jobs ahead of this one (only while queued)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<usize>,
+}
+
+/// Statistics for the in-memory caches
+#[derive(Debug, Serialize)]
+pub struct CacheStatsResponse {
+    pub geocoding: CacheStats,
+    pub location_search: CacheStats,
+    pub map_data: CacheStats,
+    pub preview: CacheStats,
+}
+
+/// Number of entries removed from each cache by a flush
+#[derive(Debug, Serialize)]
+pub struct CacheClearResponse {
+    pub geocoding: usize,
+    pub location_search: usize,
+    pub map_data: usize,
+    pub preview: usize,
 }
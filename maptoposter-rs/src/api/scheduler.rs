@@ -0,0 +1,228 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::handlers::posters::{poster_job_request, spawn_poster_job};
+use crate::api::models::{JobStatus, PosterCreateRequest};
+use crate::api::state::AppState;
+use crate::config::Settings;
+
+/// Shortest accepted schedule interval, so a typo can't hammer Nominatim and Overpass
+pub const MIN_SCHEDULE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Recurring poster jobs loaded from `SCHEDULE_FILE`
+///
+/// The file is JSON: `{"interval": "@daily", "recipes": [...]}`, where each recipe is a
+/// `POST /api/posters` body.
+#[derive(Debug, Deserialize)]
+pub struct Schedule {
+    /// `@hourly`, `@daily`, `@weekly` or a count with a unit (`30m`, `6h`, `2d`)
+    pub interval: String,
+    pub recipes: Vec<PosterCreateRequest>,
+}
+
+/// Progress of the configured schedule, kept on `AppState` for `GET /api/schedule`
+#[derive(Debug, Clone)]
+pub struct ScheduleRuns {
+    pub interval: String,
+    pub runs: u64,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub recipes: Vec<RecipeRun>,
+}
+
+/// The latest job enqueued for one recipe
+#[derive(Debug, Clone)]
+pub struct RecipeRun {
+    pub city: String,
+    pub country: String,
+    pub theme: String,
+    pub last_job_id: Option<Uuid>,
+    /// Why the last run could not enqueue a job (e.g. its theme was removed)
+    pub last_error: Option<String>,
+}
+
+impl Schedule {
+    /// Read and check a schedule file, rejecting recipes the API would reject
+    pub fn load(path: &Path, config: &Settings) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read schedule file {:?}: {}", path, e))?;
+        let schedule: Schedule = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid schedule file {:?}: {}", path, e))?;
+        schedule.validate(config)?;
+        Ok(schedule)
+    }
+
+    fn validate(&self, config: &Settings) -> Result<(), String> {
+        let interval = self.period()?;
+        if interval < MIN_SCHEDULE_INTERVAL {
+            return Err(format!(
+                "Schedule interval '{}' is shorter than the minimum of {} minutes",
+                self.interval,
+                MIN_SCHEDULE_INTERVAL.as_secs() / 60
+            ));
+        }
+        if self.recipes.is_empty() {
+            return Err("Schedule has no recipes".to_string());
+        }
+        for (i, recipe) in self.recipes.iter().enumerate() {
+            poster_job_request(config, recipe)
+                .map_err(|e| format!("Schedule recipe {} ({}): {}", i, recipe.city, e))?;
+        }
+        Ok(())
+    }
+
+    /// Time between runs
+    pub fn period(&self) -> Result<Duration, String> {
+        parse_interval(&self.interval).ok_or_else(|| format!("Invalid schedule interval '{}'", self.interval))
+    }
+}
+
+/// Parse a cron-style shorthand (`@hourly`, `@daily`, `@weekly`) or a `<count><m|h|d>` interval
+pub fn parse_interval(spec: &str) -> Option<Duration> {
+    let spec = spec.trim().to_lowercase();
+    let hours = match spec.as_str() {
+        "@hourly" => Some(1),
+        "@daily" | "@midnight" => Some(24),
+        "@weekly" => Some(24 * 7),
+        _ => None,
+    };
+    if let Some(hours) = hours {
+        return Some(Duration::from_secs(hours * 3600));
+    }
+
+    let unit_secs = match spec.chars().last()? {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 24 * 3600,
+        _ => return None,
+    };
+    let count: u64 = spec[..spec.len() - 1].parse().ok()?;
+    count.checked_mul(unit_secs).filter(|&secs| secs > 0).map(Duration::from_secs)
+}
+
+/// Enqueue every recipe now and then once per interval, for as long as the server runs
+///
+/// Jobs go through the same queue and job slots as API requests, and are pruned like any
+/// other job. A recipe whose previous job is still queued or processing is skipped for
+/// that run rather than piling up behind itself.
+pub async fn run_schedule(state: Arc<AppState>, schedule: Schedule) {
+    let Ok(period) = schedule.period() else {
+        return;
+    };
+    *state.schedule_runs.write() = Some(ScheduleRuns {
+        interval: schedule.interval.clone(),
+        runs: 0,
+        last_run_at: None,
+        next_run_at: Some(Utc::now()),
+        recipes: schedule
+            .recipes
+            .iter()
+            .map(|recipe| RecipeRun {
+                city: recipe.city.clone(),
+                country: recipe.country.clone(),
+                theme: recipe.theme.clone(),
+                last_job_id: None,
+                last_error: None,
+            })
+            .collect(),
+    });
+    tracing::info!("Scheduled {} poster recipe(s) {}", schedule.recipes.len(), schedule.interval);
+
+    let mut ticks = tokio::time::interval(period);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        run_recipes(&state, &schedule.recipes, period);
+    }
+}
+
+/// Enqueue one run of the schedule
+fn run_recipes(state: &Arc<AppState>, recipes: &[PosterCreateRequest], period: Duration) {
+    let previous: Vec<Option<Uuid>> = match state.schedule_runs.read().as_ref() {
+        Some(runs) => runs.recipes.iter().map(|run| run.last_job_id).collect(),
+        None => return,
+    };
+
+    let mut outcomes = Vec::with_capacity(recipes.len());
+    for (recipe, previous) in recipes.iter().zip(previous) {
+        let still_running = previous
+            .and_then(|id| state.get_job(id))
+            .is_some_and(|job| matches!(job.status, JobStatus::Queued | JobStatus::Processing));
+        if still_running {
+            tracing::warn!("Scheduled poster for {} is still running, skipping this run", recipe.city);
+            outcomes.push(None);
+            continue;
+        }
+
+        // Themes can change after startup, so every run is validated again
+        let outcome = poster_job_request(&state.config, recipe).map(|job_request| {
+            let job_id = state.create_job(job_request.clone()).id;
+            spawn_poster_job(state.clone(), job_id, job_request, state.config.job_timeout(recipe.timeout_seconds));
+            job_id
+        });
+        if let Err(e) = &outcome {
+            tracing::error!("Scheduled poster for {} could not be enqueued: {}", recipe.city, e);
+        }
+        outcomes.push(Some(outcome.map_err(|e| e.to_string())));
+    }
+
+    let now = Utc::now();
+    if let Some(runs) = state.schedule_runs.write().as_mut() {
+        runs.runs += 1;
+        runs.last_run_at = Some(now);
+        runs.next_run_at = chrono::Duration::from_std(period).ok().map(|period| now + period);
+        for (run, outcome) in runs.recipes.iter_mut().zip(outcomes) {
+            match outcome {
+                Some(Ok(job_id)) => {
+                    run.last_job_id = Some(job_id);
+                    run.last_error = None;
+                }
+                Some(Err(e)) => run.last_error = Some(e),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("@daily"), Some(Duration::from_secs(86_400)));
+        assert_eq!(parse_interval(" @Hourly "), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_interval("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_interval("6h"), Some(Duration::from_secs(21_600)));
+        assert_eq!(parse_interval("2d"), Some(Duration::from_secs(172_800)));
+        for spec in ["", "h", "0h", "-1h", "1.5h", "10s", "0 0 * * *", "@yearly"] {
+            assert_eq!(parse_interval(spec), None, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn test_schedule_validation() {
+        let mut config = Settings::defaults();
+        config.themes_dir = std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../themes"));
+        let schedule = |json: &str| serde_json::from_str::<Schedule>(json).unwrap();
+
+        let recipe = r#"{"city": "Paris", "country": "France", "theme": "noir"}"#;
+        assert!(schedule(&format!(r#"{{"interval": "@daily", "recipes": [{}]}}"#, recipe)).validate(&config).is_ok());
+
+        let err = schedule(&format!(r#"{{"interval": "1m", "recipes": [{}]}}"#, recipe)).validate(&config).unwrap_err();
+        assert!(err.contains("shorter than the minimum"));
+        let err = schedule(r#"{"interval": "@daily", "recipes": []}"#).validate(&config).unwrap_err();
+        assert!(err.contains("no recipes"));
+
+        // Recipes are checked like API requests
+        let err = schedule(r#"{"interval": "@daily", "recipes": [{"city": "Paris", "country": "France", "theme": "missing"}]}"#)
+            .validate(&config)
+            .unwrap_err();
+        assert!(err.contains("recipe 0 (Paris)"));
+    }
+}
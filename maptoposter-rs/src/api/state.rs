@@ -1,17 +1,83 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
-use parking_lot::RwLock;
-use tokio::sync::mpsc;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::Semaphore;
 use uuid::Uuid;
 
-use crate::api::models::{JobStatus, JobStatusResponse};
+use crate::api::models::{JobStatsResponse, JobStatus, JobStatusResponse, ScheduleResponse, ScheduledRecipeStatus};
+use crate::api::scheduler::ScheduleRuns;
 use crate::config::Settings;
-use crate::core::osm_client::{AreaFeature, RoadSegment};
-use crate::core::rate_limiter::{ApiRateLimiters, Cache};
+use crate::core::geocoding::LocationData;
+use crate::core::osm_client::{AreaFeature, AreaTags, PointFeature, RoadSegment};
+use crate::core::poster_generator::{DataStats, MapData, PosterGenerator, RenderOptions};
+use crate::core::rate_limiter::{ApiRateLimiters, ByteCache, Cache, CacheCounters, CacheStats};
+use crate::error::Result;
+use crate::rendering::typography::FontSet;
+use crate::themes::loader::ThemeCache;
+
+/// File name of a job's thumbnail at `max_size` pixels, kept in `static_dir` next to its poster
+///
+/// Named by size, so changing `THUMBNAIL_MAX_SIZE` doesn't serve stale thumbnails.
+pub fn thumbnail_file_name(job_id: Uuid, max_size: u32) -> String {
+    format!("{}_thumb_{}.jpg", job_id, max_size)
+}
+
+/// File name of a job's CMYK TIFF, converted on the first download and kept in `static_dir`
+pub fn cmyk_file_name(job_id: Uuid) -> String {
+    format!("{}_cmyk.tif", job_id)
+}
+
+/// Delete the poster, thumbnails and CMYK TIFF of removed jobs, ignoring files that are already gone
+///
+/// Called from request handlers, so inside a runtime the deletion runs on the blocking
+/// pool instead of stalling the async worker.
+fn remove_job_files(static_dir: &std::path::Path, jobs: &[JobState]) {
+    let static_dir = static_dir.to_path_buf();
+    let outputs: Vec<std::path::PathBuf> = jobs
+        .iter()
+        .filter_map(|job| job.output_path.as_deref().map(std::path::PathBuf::from))
+        .chain(jobs.iter().map(|job| static_dir.join(cmyk_file_name(job.id))))
+        .collect();
+    let job_ids: Vec<Uuid> = jobs.iter().map(|job| job.id).collect();
+
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn_blocking(move || delete_job_files(&static_dir, outputs, &job_ids));
+        }
+        Err(_) => delete_job_files(&static_dir, outputs, &job_ids),
+    }
+}
+
+/// Delete job outputs and every thumbnail of the given jobs in `static_dir`
+fn delete_job_files(static_dir: &std::path::Path, mut paths: Vec<std::path::PathBuf>, job_ids: &[Uuid]) {
+    // Thumbnails of any size, including ones made before THUMBNAIL_MAX_SIZE changed
+    let prefixes: Vec<String> = job_ids.iter().map(|id| format!("{}_thumb_", id)).collect();
+    if let Ok(entries) = std::fs::read_dir(static_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())) {
+                paths.push(entry.path());
+            }
+        }
+    }
+
+    for path in paths {
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Could not remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
 
 /// Cached map data for re-rendering with different themes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct CachedMapData {
     pub city: String,
     pub country: String,
@@ -21,6 +87,87 @@ pub struct CachedMapData {
     pub streets: Vec<RoadSegment>,
     pub water: Vec<AreaFeature>,
     pub parks: Vec<AreaFeature>,
+    pub landuse: Vec<AreaFeature>,
+    pub details: Vec<AreaFeature>,
+    pub points: Vec<PointFeature>,
+    pub country_outline: Vec<Vec<(f64, f64)>>,
+    pub stats: DataStats,
+}
+
+impl CachedMapData {
+    /// Hash of the location and every feature's geometry, equal for copies of the same data
+    ///
+    /// Only meaningful within one process, which is all the preview cache needs.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let hash_points = |points: &[(f64, f64)], hasher: &mut DefaultHasher| {
+            points.len().hash(hasher);
+            for (lat, lon) in points {
+                lat.to_bits().hash(hasher);
+                lon.to_bits().hash(hasher);
+            }
+        };
+
+        (&self.city, &self.country, self.lat.to_bits(), self.lon.to_bits(), self.distance).hash(&mut hasher);
+        for road in &self.streets {
+            (road.id, road.highway_type, road.oneway, road.lanes, road.maxspeed).hash(&mut hasher);
+            hash_points(&road.points, &mut hasher);
+        }
+        for layer in [&self.water, &self.parks, &self.landuse, &self.details] {
+            layer.len().hash(&mut hasher);
+            for area in layer {
+                (area.id, &area.feature_type).hash(&mut hasher);
+                hash_points(&area.points, &mut hasher);
+            }
+        }
+        for point in &self.points {
+            (point.lat.to_bits(), point.lon.to_bits(), &point.name, point.kind).hash(&mut hasher);
+        }
+        for ring in &self.country_outline {
+            hash_points(ring, &mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+impl From<MapData> for CachedMapData {
+    fn from(data: MapData) -> Self {
+        Self {
+            city: data.city,
+            country: data.country,
+            lat: data.lat,
+            lon: data.lon,
+            distance: data.distance,
+            streets: data.streets,
+            water: data.water,
+            parks: data.parks,
+            landuse: data.landuse,
+            details: data.details,
+            points: data.points,
+            country_outline: data.country_outline,
+            stats: data.stats,
+        }
+    }
+}
+
+impl From<CachedMapData> for MapData {
+    fn from(data: CachedMapData) -> Self {
+        Self {
+            city: data.city,
+            country: data.country,
+            lat: data.lat,
+            lon: data.lon,
+            distance: data.distance,
+            streets: data.streets,
+            water: data.water,
+            parks: data.parks,
+            landuse: data.landuse,
+            details: data.details,
+            points: data.points,
+            country_outline: data.country_outline,
+            stats: data.stats,
+        }
+    }
 }
 
 /// Internal job state
@@ -35,16 +182,47 @@ pub struct JobState {
     pub error: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Incremented on every change; used as the SSE event id
+    pub revision: u64,
     pub request: JobRequest,
 }
 
 /// Job request data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct JobRequest {
     pub city: String,
     pub country: String,
     pub theme: String,
     pub distance: u32,
+    /// Optional country code filter for geocoding
+    pub country_code: Option<String>,
+    /// Fail instead of rendering without water/parks when their fetch fails
+    pub strict: bool,
+    /// Render at preview size (`options` keep the full size for a later confirm)
+    pub preview: bool,
+    /// Render with the theme's colors lightness-inverted
+    pub invert: bool,
+    /// Web-map zoom level framed instead of `distance`
+    pub zoom: Option<f64>,
+    /// Filters added to the configured water query for this job only
+    pub extra_water_tags: Option<AreaTags>,
+    /// Filters added to the configured park query for this job only
+    pub extra_park_tags: Option<AreaTags>,
+    /// Rendering options for the job's output
+    pub options: RenderOptions,
+}
+
+impl JobRequest {
+    /// Options the job actually renders with (scaled down for previews)
+    pub fn render_options(&self, config: &Settings) -> RenderOptions {
+        if self.preview {
+            self.options
+                .clone()
+                .into_preview(config.output_dpi, config.preview_dpi, config.preview_scale)
+        } else {
+            self.options.clone()
+        }
+    }
 }
 
 impl JobState {
@@ -60,10 +238,17 @@ impl JobState {
             error: None,
             created_at: now,
             updated_at: now,
+            revision: 0,
             request,
         }
     }
 
+    /// Record a change to the job
+    fn touch(&mut self) {
+        self.updated_at = Utc::now();
+        self.revision += 1;
+    }
+
     pub fn to_response(&self) -> JobStatusResponse {
         JobStatusResponse {
             job_id: self.id.to_string(),
@@ -75,68 +260,148 @@ impl JobState {
                 format!("/api/posters/{}/download", self.id)
             }),
             error: self.error.clone(),
+            queue_position: None,
+            stats: None,
+            trim_box: None,
         }
     }
 }
 
-/// Progress update message for job processing
-#[derive(Debug, Clone)]
-pub struct ProgressMessage {
-    pub job_id: Uuid,
-    pub status: JobStatus,
-    pub progress: f32,
-    pub step: String,
-    pub message: String,
-}
-
-/// Cached geocoding result
-#[derive(Debug, Clone)]
-pub struct GeocodingResult {
-    pub lat: f64,
-    pub lon: f64,
-}
-
 /// Application state shared across handlers
 pub struct AppState {
     pub config: Settings,
     pub jobs: RwLock<HashMap<Uuid, JobState>>,
-    pub job_sender: mpsc::Sender<JobRequest>,
-    job_receiver: RwLock<Option<mpsc::Receiver<JobRequest>>>,
     /// Rate limiters for external APIs
     pub rate_limiters: ApiRateLimiters,
-    /// Cache for geocoding results (city,country -> coordinates)
-    pub geocoding_cache: Cache<GeocodingResult>,
+    /// Cache for geocoding results (reverse lookups keyed by rounded coordinates, batch
+    /// lookups by city and country)
+    pub geocoding_cache: Cache<LocationData>,
+    /// Cache for location search results, keyed by query and limit
+    pub location_search_cache: Cache<Vec<LocationData>>,
     /// Cache for map data (job_id -> map data) for re-rendering
-    pub map_data_cache: RwLock<HashMap<Uuid, CachedMapData>>,
+    pub map_data_cache: RwLock<HashMap<Uuid, Arc<CachedMapData>>>,
+    /// Hit/miss counters for map data lookups
+    map_data_counters: CacheCounters,
+    /// PNG bytes of re-render previews, keyed by map data, theme and render options
+    pub preview_cache: ByteCache,
+    /// Limits how many jobs render at once; queued jobs wait for a permit
+    pub job_slots: Arc<Semaphore>,
+    /// Open progress streams per job
+    stream_counts: Mutex<HashMap<Uuid, usize>>,
+    /// Locks held while a job's missing poster file is rendered again, see `PosterRepair`
+    poster_repairs: Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>,
+    /// Jobs created together by a multi-theme request (batch_id -> job ids)
+    batches: RwLock<HashMap<Uuid, Vec<Uuid>>>,
+    /// Lifetime job counters, unaffected by TTL pruning
+    job_counters: JobCounters,
+    /// Runs of the recurring job schedule (None when no schedule is configured)
+    pub schedule_runs: RwLock<Option<ScheduleRuns>>,
+    /// Default font weights, loaded on first use or by warmup
+    fonts: Mutex<Option<FontSet>>,
+    /// Parsed themes, loaded on first use or by warmup
+    pub themes: ThemeCache,
+}
+
+/// Jobs processed since startup
+#[derive(Debug, Default)]
+struct JobCounters {
+    created: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    rerenders: AtomicU64,
+}
+
+/// An open progress stream for a job; releases its slot when dropped
+pub struct StreamSlot {
+    state: Arc<AppState>,
+    job_id: Uuid,
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut counts = self.state.stream_counts.lock();
+        if let Some(count) = counts.get_mut(&self.job_id) {
+            *count -= 1;
+            tracing::debug!("Job {} has {} progress stream(s) open", self.job_id, count);
+            if *count == 0 {
+                counts.remove(&self.job_id);
+            }
+        }
+    }
+}
+
+/// A request's share of a job's poster repair; forgets the job's lock when the last one drops
+pub struct PosterRepair {
+    state: Arc<AppState>,
+    job_id: Uuid,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl PosterRepair {
+    /// Wait until no other request is rendering the poster
+    pub async fn lock(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+}
+
+impl Drop for PosterRepair {
+    fn drop(&mut self) {
+        let mut repairs = self.state.poster_repairs.lock();
+        // Held by the map and by this request; clones are only taken under the map's lock
+        if Arc::strong_count(&self.lock) <= 2 {
+            repairs.remove(&self.job_id);
+        }
+    }
 }
 
 impl AppState {
     pub fn new(config: Settings) -> Self {
-        let (tx, rx) = mpsc::channel(100);
-
         // Create rate limiters with configured delays
-        let rate_limiters = ApiRateLimiters::new(
-            config.nominatim_delay,
-            config.osm_delay,
-        );
+        let rate_limiters = ApiRateLimiters::new(config.osm_delay);
 
         // Cache geocoding results for 24 hours, max 1000 entries
         let geocoding_cache = Cache::new(24 * 60 * 60, 1000);
+        let location_search_cache = Cache::new(24 * 60 * 60, 1000);
 
         Self {
-            config,
             jobs: RwLock::new(HashMap::new()),
-            job_sender: tx,
-            job_receiver: RwLock::new(Some(rx)),
             rate_limiters,
             geocoding_cache,
+            location_search_cache,
             map_data_cache: RwLock::new(HashMap::new()),
+            map_data_counters: CacheCounters::default(),
+            preview_cache: ByteCache::new((config.preview_cache_mb * 1024 * 1024) as usize),
+            job_slots: Arc::new(Semaphore::new(config.max_concurrent_jobs.max(1))),
+            stream_counts: Mutex::new(HashMap::new()),
+            poster_repairs: Mutex::new(HashMap::new()),
+            batches: RwLock::new(HashMap::new()),
+            job_counters: JobCounters::default(),
+            schedule_runs: RwLock::new(None),
+            fonts: Mutex::new(None),
+            themes: ThemeCache::default(),
+            config,
+        }
+    }
+
+    /// The default font weights, loaded from `fonts_dir` once and kept for later jobs
+    pub fn fonts(&self) -> Result<FontSet> {
+        let mut fonts = self.fonts.lock();
+        if let Some(fonts) = fonts.as_ref() {
+            return Ok(fonts.clone());
         }
+        let loaded = FontSet::load(&self.config.fonts_dir)?;
+        *fonts = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    /// Load a theme by name through the theme cache
+    pub fn load_theme(&self, name: &str) -> Option<serde_json::Value> {
+        self.themes.load(&self.config.themes_dir, name)
     }
 
-    /// Take the job receiver (can only be called once)
-    pub fn take_job_receiver(&self) -> Option<mpsc::Receiver<JobRequest>> {
-        self.job_receiver.write().take()
+    /// Create a poster generator for a theme with the cached fonts
+    pub fn poster_generator(&self, theme: serde_json::Value) -> Result<PosterGenerator> {
+        Ok(PosterGenerator::with_fonts(theme, self.fonts()?, &self.config))
     }
 
     /// Create a new job and return its state
@@ -144,19 +409,183 @@ impl AppState {
         let job = JobState::new(request);
         let id = job.id;
         self.jobs.write().insert(id, job.clone());
+        self.job_counters.created.fetch_add(1, Ordering::Relaxed);
+        self.evict_excess_jobs();
         job
     }
 
+    /// Evict the oldest finished jobs beyond `max_jobs_retained`, with their cached data and files
+    ///
+    /// Queued and processing jobs are never evicted, so the cap can be exceeded while they run.
+    fn evict_excess_jobs(&self) {
+        let evicted: Vec<JobState> = {
+            let mut jobs = self.jobs.write();
+            let excess = jobs.len().saturating_sub(self.config.max_jobs_retained);
+            if excess == 0 {
+                return;
+            }
+
+            let mut finished: Vec<(DateTime<Utc>, Uuid)> = jobs
+                .values()
+                .filter(|job| matches!(job.status, JobStatus::Completed | JobStatus::Failed))
+                .map(|job| (job.created_at, job.id))
+                .collect();
+            finished.sort_unstable();
+            finished
+                .into_iter()
+                .take(excess)
+                .filter_map(|(_, id)| jobs.remove(&id))
+                .collect()
+        };
+        if evicted.is_empty() {
+            tracing::warn!(
+                "{} jobs retained, above MAX_JOBS_RETAINED ({}), but none are finished",
+                self.jobs.read().len(),
+                self.config.max_jobs_retained
+            );
+            return;
+        }
+
+        {
+            let mut cache = self.map_data_cache.write();
+            for job in &evicted {
+                cache.remove(&job.id);
+            }
+        }
+        {
+            let jobs = self.jobs.read();
+            self.batches
+                .write()
+                .retain(|_, job_ids| job_ids.iter().any(|id| jobs.contains_key(id)));
+        }
+        remove_job_files(&self.config.static_dir, &evicted);
+        tracing::info!("Evicted {} finished job(s) over MAX_JOBS_RETAINED", evicted.len());
+    }
+
+    /// Create a job that re-renders cached map data; counted as both a job and a rerender
+    pub fn create_rerender_job(&self, request: JobRequest) -> JobState {
+        self.job_counters.rerenders.fetch_add(1, Ordering::Relaxed);
+        self.create_job(request)
+    }
+
+    /// Lifetime job counters plus the number of jobs currently retained
+    pub fn job_stats(&self) -> JobStatsResponse {
+        let counters = &self.job_counters;
+        JobStatsResponse {
+            jobs_created: counters.created.load(Ordering::Relaxed),
+            jobs_completed: counters.completed.load(Ordering::Relaxed),
+            jobs_failed: counters.failed.load(Ordering::Relaxed),
+            rerenders: counters.rerenders.load(Ordering::Relaxed),
+            jobs_retained: self.jobs.read().len(),
+        }
+    }
+
+    /// The schedule's latest run, with the live status of each recipe's last job
+    pub fn schedule_status(&self) -> ScheduleResponse {
+        let runs = self.schedule_runs.read().clone();
+        let Some(runs) = runs else {
+            return ScheduleResponse {
+                enabled: false,
+                interval: None,
+                runs: 0,
+                last_run_at: None,
+                next_run_at: None,
+                recipes: Vec::new(),
+            };
+        };
+
+        ScheduleResponse {
+            enabled: true,
+            interval: Some(runs.interval),
+            runs: runs.runs,
+            last_run_at: runs.last_run_at.map(|t| t.to_rfc3339()),
+            next_run_at: runs.next_run_at.map(|t| t.to_rfc3339()),
+            recipes: runs
+                .recipes
+                .into_iter()
+                .map(|run| ScheduledRecipeStatus {
+                    last_status: run.last_job_id.and_then(|id| self.get_job(id)).map(|job| job.status),
+                    last_job_id: run.last_job_id.map(|id| id.to_string()),
+                    city: run.city,
+                    country: run.country,
+                    theme: run.theme,
+                    last_error: run.last_error,
+                })
+                .collect(),
+        }
+    }
+
     /// Get a job by ID
     pub fn get_job(&self, id: Uuid) -> Option<JobState> {
         self.jobs.read().get(&id).cloned()
     }
 
+    /// Number of queued jobs created before the given job (None if it is not queued)
+    pub fn queue_position(&self, id: Uuid) -> Option<usize> {
+        let jobs = self.jobs.read();
+        let job = jobs.get(&id)?;
+        if job.status != JobStatus::Queued {
+            return None;
+        }
+
+        Some(
+            jobs.values()
+                .filter(|other| {
+                    other.status == JobStatus::Queued
+                        && other.id != id
+                        && other.created_at < job.created_at
+                })
+                .count(),
+        )
+    }
+
+    /// Build the status response for a job, including its queue position, feature stats and trim box
+    pub fn job_response(&self, id: Uuid) -> Option<JobStatusResponse> {
+        let job = self.get_job(id)?;
+        let mut response = job.to_response();
+        response.queue_position = self.queue_position(id);
+        response.trim_box = job.request.render_options(&self.config).trim_box();
+        response.stats = self.map_data_cache.read().get(&id).map(|data| data.stats);
+        Some(response)
+    }
+
+    /// Group jobs into a batch and return its id
+    pub fn create_batch(&self, job_ids: Vec<Uuid>) -> Uuid {
+        let batch_id = Uuid::new_v4();
+        self.batches.write().insert(batch_id, job_ids);
+        batch_id
+    }
+
+    /// Jobs of a batch, in the order they were created
+    pub fn get_batch(&self, batch_id: Uuid) -> Option<Vec<Uuid>> {
+        self.batches.read().get(&batch_id).cloned()
+    }
+
+    /// Claim a progress stream slot for a job, or None if `max_streams_per_job` are already open
+    pub fn open_stream(self: &Arc<Self>, job_id: Uuid) -> Option<StreamSlot> {
+        let mut counts = self.stream_counts.lock();
+        let count = counts.entry(job_id).or_insert(0);
+        if *count >= self.config.max_streams_per_job {
+            tracing::warn!("Job {} already has {} progress streams open, rejecting another", job_id, count);
+            return None;
+        }
+
+        *count += 1;
+        tracing::debug!("Job {} has {} progress stream(s) open", job_id, count);
+        Some(StreamSlot { state: self.clone(), job_id })
+    }
+
+    /// Join the requests rendering a job's missing poster file again
+    pub fn poster_repair(self: &Arc<Self>, job_id: Uuid) -> PosterRepair {
+        let lock = self.poster_repairs.lock().entry(job_id).or_default().clone();
+        PosterRepair { state: self.clone(), job_id, lock }
+    }
+
     /// Update job status
     pub fn update_job_status(&self, id: Uuid, status: JobStatus) {
         if let Some(job) = self.jobs.write().get_mut(&id) {
             job.status = status;
-            job.updated_at = Utc::now();
+            job.touch();
         }
     }
 
@@ -172,70 +601,295 @@ impl AppState {
             job.progress = progress;
             job.current_step = step;
             job.message = message;
-            job.updated_at = Utc::now();
+            job.touch();
         }
     }
 
     /// Mark job as completed
     pub fn complete_job(&self, id: Uuid, output_path: String) {
         if let Some(job) = self.jobs.write().get_mut(&id) {
+            if job.status != JobStatus::Completed {
+                self.job_counters.completed.fetch_add(1, Ordering::Relaxed);
+            }
             job.status = JobStatus::Completed;
             job.progress = 1.0;
             job.output_path = Some(output_path);
             job.current_step = Some("completed".to_string());
             job.message = Some("Poster generated successfully".to_string());
-            job.updated_at = Utc::now();
+            job.touch();
         }
     }
 
     /// Mark job as failed
     pub fn fail_job(&self, id: Uuid, error: String) {
         if let Some(job) = self.jobs.write().get_mut(&id) {
+            if job.status != JobStatus::Failed {
+                self.job_counters.failed.fetch_add(1, Ordering::Relaxed);
+            }
             job.status = JobStatus::Failed;
             job.error = Some(error);
             job.current_step = Some("failed".to_string());
-            job.updated_at = Utc::now();
+            job.touch();
         }
     }
 
-    /// Clean up old jobs based on TTL
+    /// Clean up jobs older than `job_ttl_hours`, with their cached data and files
     pub fn cleanup_old_jobs(&self) {
         let ttl_hours = self.config.job_ttl_hours as i64;
         let cutoff = Utc::now() - chrono::Duration::hours(ttl_hours);
 
-        // Collect IDs to remove
-        let removed_ids: Vec<Uuid> = {
-            let jobs = self.jobs.read();
-            jobs.iter()
-                .filter(|(_, job)| job.created_at <= cutoff)
-                .map(|(id, _)| *id)
-                .collect()
-        };
-
         // Remove jobs
-        {
+        let removed: Vec<JobState> = {
             let mut jobs = self.jobs.write();
-            for id in &removed_ids {
-                jobs.remove(id);
-            }
+            let expired: Vec<Uuid> = jobs
+                .values()
+                .filter(|job| job.created_at <= cutoff)
+                .map(|job| job.id)
+                .collect();
+            expired.iter().filter_map(|id| jobs.remove(id)).collect()
+        };
+        if removed.is_empty() {
+            return;
         }
 
         // Also clean up cached map data for removed jobs
         {
             let mut cache = self.map_data_cache.write();
-            for id in removed_ids {
-                cache.remove(&id);
+            for job in &removed {
+                cache.remove(&job.id);
             }
         }
+
+        // Drop batches with no jobs left
+        {
+            let jobs = self.jobs.read();
+            self.batches
+                .write()
+                .retain(|_, job_ids| job_ids.iter().any(|id| jobs.contains_key(id)));
+        }
+        remove_job_files(&self.config.static_dir, &removed);
+        tracing::info!("Removed {} job(s) older than JOB_TTL_HOURS", removed.len());
     }
 
-    /// Store cached map data for a job
-    pub fn cache_map_data(&self, job_id: Uuid, data: CachedMapData) {
+    /// Store cached map data for a job, possibly shared with other jobs of the same fetch
+    pub fn cache_map_data(&self, job_id: Uuid, data: Arc<CachedMapData>) {
         self.map_data_cache.write().insert(job_id, data);
     }
 
     /// Get cached map data for a job
-    pub fn get_cached_map_data(&self, job_id: Uuid) -> Option<CachedMapData> {
-        self.map_data_cache.read().get(&job_id).cloned()
+    pub fn get_cached_map_data(&self, job_id: Uuid) -> Option<Arc<CachedMapData>> {
+        let data = self.map_data_cache.read().get(&job_id).cloned();
+        self.map_data_counters.record(data.is_some());
+        data
+    }
+
+    /// Entry count and hit/miss counters for the map data cache
+    pub fn map_data_cache_stats(&self) -> CacheStats {
+        self.map_data_counters.stats(self.map_data_cache.read().len())
+    }
+
+    /// Drop all cached map data, returning how many entries were removed
+    pub fn clear_map_data_cache(&self) -> usize {
+        let mut cache = self.map_data_cache.write();
+        let removed = cache.len();
+        cache.clear();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle_completes() {
+        let state = AppState::new(Settings::defaults());
+        let job = state.create_job(JobRequest::default());
+        assert_eq!((job.status, job.progress, job.revision), (JobStatus::Queued, 0.0, 0));
+        assert_eq!(state.queue_position(job.id), Some(0));
+
+        state.update_job_status(job.id, JobStatus::Processing);
+        state.update_job_progress(job.id, 0.4, Some("fetching_parks".to_string()), Some("Fetching park features...".to_string()));
+        let processing = state.get_job(job.id).unwrap();
+        assert_eq!(processing.status, JobStatus::Processing);
+        assert_eq!(processing.progress, 0.4);
+        assert_eq!(processing.current_step.as_deref(), Some("fetching_parks"));
+        assert_eq!(processing.revision, 2);
+        assert!(processing.updated_at >= job.updated_at);
+        // Only queued jobs have a queue position
+        assert_eq!(state.queue_position(job.id), None);
+
+        state.complete_job(job.id, "poster.png".to_string());
+        // Completing twice is not counted twice
+        state.complete_job(job.id, "poster.png".to_string());
+        let completed = state.get_job(job.id).unwrap();
+        assert_eq!(completed.status, JobStatus::Completed);
+        assert_eq!(completed.progress, 1.0);
+        assert_eq!(completed.output_path.as_deref(), Some("poster.png"));
+        assert_eq!(completed.current_step.as_deref(), Some("completed"));
+        assert_eq!(completed.error, None);
+        let stats = state.job_stats();
+        assert_eq!((stats.jobs_created, stats.jobs_completed, stats.jobs_failed), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_job_lifecycle_fails() {
+        let state = AppState::new(Settings::defaults());
+        let id = state.create_job(JobRequest::default()).id;
+        state.update_job_status(id, JobStatus::Processing);
+        state.update_job_progress(id, 0.3, Some("fetching_water".to_string()), None);
+
+        state.fail_job(id, "Overpass timed out".to_string());
+        let failed = state.get_job(id).unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.error.as_deref(), Some("Overpass timed out"));
+        assert_eq!(failed.current_step.as_deref(), Some("failed"));
+        // Progress stays where the job stopped, and there is no poster
+        assert_eq!(failed.progress, 0.3);
+        assert_eq!(failed.output_path, None);
+        assert_eq!(state.job_stats().jobs_failed, 1);
+
+        // Updates for unknown jobs are ignored
+        let unknown = Uuid::new_v4();
+        state.update_job_status(unknown, JobStatus::Processing);
+        state.complete_job(unknown, "poster.png".to_string());
+        state.fail_job(unknown, "boom".to_string());
+        assert!(state.get_job(unknown).is_none());
+        assert_eq!(state.job_stats().jobs_completed, 0);
+    }
+
+    #[test]
+    fn test_cleanup_removes_expired_jobs_with_cached_data() {
+        let static_dir = std::env::temp_dir().join(format!("maptoposter-expire-{}", std::process::id()));
+        std::fs::create_dir_all(&static_dir).unwrap();
+        let mut config = Settings::defaults();
+        config.job_ttl_hours = 1;
+        config.static_dir = static_dir.clone();
+        let state = AppState::new(config);
+
+        let expired = state.create_job(JobRequest::default()).id;
+        let poster = static_dir.join(format!("{}.png", expired));
+        let thumbnail = static_dir.join(thumbnail_file_name(expired, 400));
+        let tiff = static_dir.join(cmyk_file_name(expired));
+        std::fs::write(&poster, b"png").unwrap();
+        std::fs::write(&thumbnail, b"jpg").unwrap();
+        std::fs::write(&tiff, b"tif").unwrap();
+        state.complete_job(expired, poster.to_string_lossy().to_string());
+        state.cache_map_data(expired, Arc::default());
+        let batch = state.create_batch(vec![expired]);
+        state.jobs.write().get_mut(&expired).unwrap().created_at = Utc::now() - chrono::Duration::hours(2);
+
+        let fresh = state.create_job(JobRequest::default()).id;
+        state.cache_map_data(fresh, Arc::default());
+
+        state.cleanup_old_jobs();
+        assert!(state.get_job(expired).is_none());
+        assert!(!state.map_data_cache.read().contains_key(&expired));
+        assert!(state.get_batch(batch).is_none());
+        assert!(state.get_job(fresh).is_some());
+        assert!(state.map_data_cache.read().contains_key(&fresh));
+        assert!(!poster.exists());
+        assert!(!thumbnail.exists());
+        assert!(!tiff.exists());
+        std::fs::remove_dir_all(&static_dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream_slots_are_capped_and_released() {
+        let mut config = Settings::defaults();
+        config.max_streams_per_job = 2;
+        let state = Arc::new(AppState::new(config));
+        let job_id = Uuid::new_v4();
+
+        let first = state.open_stream(job_id).unwrap();
+        let _second = state.open_stream(job_id).unwrap();
+        assert!(state.open_stream(job_id).is_none());
+        // The cap is per job
+        assert!(state.open_stream(Uuid::new_v4()).is_some());
+
+        drop(first);
+        assert!(state.open_stream(job_id).is_some());
+    }
+
+    #[test]
+    fn test_job_counters_survive_pruning() {
+        // Pruning deletes the jobs' files, so keep them out of the working directory
+        let static_dir = std::env::temp_dir().join(format!("maptoposter-counters-{}", std::process::id()));
+        std::fs::create_dir_all(&static_dir).unwrap();
+        let mut config = Settings::defaults();
+        config.job_ttl_hours = 0;
+        config.static_dir = static_dir.clone();
+        let state = AppState::new(config);
+        let request = JobRequest::default();
+
+        let done = state.create_job(request.clone()).id;
+        state.complete_job(done, static_dir.join("poster.png").to_string_lossy().to_string());
+        let failed = state.create_rerender_job(request).id;
+        state.fail_job(failed, "boom".to_string());
+        // Failing an already failed job is not counted twice
+        state.fail_job(failed, "boom again".to_string());
+
+        state.cleanup_old_jobs();
+        let stats = state.job_stats();
+        assert_eq!(stats.jobs_retained, 0);
+        assert_eq!(
+            (stats.jobs_created, stats.jobs_completed, stats.jobs_failed, stats.rerenders),
+            (2, 1, 1, 1)
+        );
+        std::fs::remove_dir_all(&static_dir).unwrap();
+    }
+
+    #[test]
+    fn test_job_cap_evicts_oldest_finished_jobs() {
+        let static_dir = std::env::temp_dir().join(format!("maptoposter-evict-{}", std::process::id()));
+        std::fs::create_dir_all(&static_dir).unwrap();
+        let mut config = Settings::defaults();
+        config.max_jobs_retained = 2;
+        config.static_dir = static_dir.clone();
+        let state = AppState::new(config);
+        let poster = static_dir.join("poster.png");
+        std::fs::write(&poster, b"png").unwrap();
+
+        let oldest = state.create_job(JobRequest::default()).id;
+        let thumbnail = static_dir.join(thumbnail_file_name(oldest, 400));
+        std::fs::write(&thumbnail, b"jpeg").unwrap();
+        state.complete_job(oldest, poster.to_string_lossy().to_string());
+        state.cache_map_data(oldest, Arc::default());
+        let failed = state.create_job(JobRequest::default()).id;
+        state.fail_job(failed, "boom".to_string());
+
+        // Over the cap: the oldest finished job goes, with its cached data and file
+        let queued = state.create_job(JobRequest::default()).id;
+        assert!(state.get_job(oldest).is_none());
+        assert!(state.get_cached_map_data(oldest).is_none());
+        assert!(!poster.exists());
+        assert!(!thumbnail.exists());
+        assert!(state.get_job(failed).is_some());
+
+        // Active jobs are kept even if that exceeds the cap
+        let second_queued = state.create_job(JobRequest::default()).id;
+        let third_queued = state.create_job(JobRequest::default()).id;
+        assert!(state.get_job(failed).is_none());
+        for id in [queued, second_queued, third_queued] {
+            assert!(state.get_job(id).is_some());
+        }
+        std::fs::remove_dir_all(&static_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_poster_repair_lock_is_shared_and_forgotten() {
+        let state = Arc::new(AppState::new(Settings::defaults()));
+        let id = Uuid::new_v4();
+        let first = state.poster_repair(id);
+        let second = state.poster_repair(id);
+
+        let repairing = first.lock().await;
+        assert!(second.lock.try_lock().is_err());
+        drop(repairing);
+
+        drop(first);
+        assert!(state.poster_repairs.lock().contains_key(&id));
+        drop(second);
+        assert!(state.poster_repairs.lock().is_empty());
     }
 }
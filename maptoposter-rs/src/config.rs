@@ -1,5 +1,30 @@
+use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::core::osm_client::FeatureTags;
+
+/// Public Nominatim instance, which requires a contact in the User-Agent
+pub const PRODUCTION_NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+
+/// Shortest job timeout a request may ask for, in seconds
+pub const MIN_JOB_TIMEOUT_SECS: u64 = 10;
+
+/// Default User-Agent sent to Nominatim and Overpass
+const DEFAULT_USER_AGENT: &str = "MapToPoster-RS/2.0 (https://github.com/maptoposter)";
+
+/// Default download filename (without extension) when `FILENAME_TEMPLATE` is unset
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{city}_{theme}";
+
+/// Placeholders accepted in `FILENAME_TEMPLATE`
+pub const FILENAME_PLACEHOLDERS: &[&str] = &["city", "country", "theme", "date", "job_id"];
+
+/// Default config file read by `Settings::load` (override with `CONFIG_FILE`)
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Largest accepted `THUMBNAIL_MAX_SIZE`; anything bigger is better served by a preview
+const MAX_THUMBNAIL_SIZE: u32 = 2048;
 
 /// Application configuration loaded from environment variables
 #[derive(Clone, Debug)]
@@ -26,11 +51,17 @@ pub struct Settings {
     pub output_dpi: u32,
     /// Preview DPI (lower quality for speed)
     pub preview_dpi: u32,
+    /// Fraction of the full poster size previews are rendered at before downscaling
+    pub preview_scale: f32,
+    /// Longest side of poster thumbnails in pixels
+    pub thumbnail_max_size: u32,
     /// Nominatim API delay in seconds
     pub nominatim_delay: f64,
     /// Nominatim API timeout in seconds
     pub nominatim_timeout: f64,
-    /// OSM API delay in seconds
+    /// Most results a location search may ask Nominatim for
+    pub max_search_limit: u32,
+    /// Seconds between jobs starting their Overpass fetches
     pub osm_delay: f64,
     /// OSM API timeout in seconds
     pub osm_timeout: f64,
@@ -38,78 +69,393 @@ pub struct Settings {
     pub max_concurrent_jobs: usize,
     /// Job time-to-live in hours
     pub job_ttl_hours: u32,
+    /// Most jobs kept in memory; the oldest finished ones are evicted beyond this
+    pub max_jobs_retained: usize,
+    /// Nominatim API base URL
+    pub nominatim_url: String,
+    /// User-Agent sent to upstream APIs (without contact)
+    pub user_agent: String,
+    /// Operator contact email appended to the User-Agent
+    pub contact_email: Option<String>,
+    /// Size of static_dir in megabytes above which storage health reports a warning
+    pub storage_warn_mb: u64,
+    /// Megabytes of rendered re-render previews kept in memory (0 disables the cache)
+    pub preview_cache_mb: u64,
+    /// Maximum number of HTTP requests (including open SSE streams) in flight
+    pub max_inflight_requests: usize,
+    /// Maximum number of progress streams open at once for a single job
+    pub max_streams_per_job: usize,
+    /// How often progress streams check the job for changes, in milliseconds
+    pub sse_poll_ms: u64,
+    /// Interval between keep-alive comments on idle progress streams, in seconds
+    pub sse_keepalive_secs: u64,
+    /// Seconds a request may take to produce its response before answering 408 (0 for no limit)
+    pub request_timeout_secs: u64,
+    /// Idle seconds before TCP keep-alive probes are sent on client connections (0 to disable)
+    pub tcp_keepalive_secs: u64,
+    /// Poster job timeout in seconds when the request doesn't set one
+    pub job_timeout_secs: u64,
+    /// Upper bound for a per-request job timeout in seconds
+    pub max_job_timeout_secs: u64,
+    /// Overpass queries in a row failing on every mirror before new fetches are refused (0 to never refuse)
+    pub overpass_breaker_threshold: u32,
+    /// How long new map fetches are refused once the Overpass breaker trips, in seconds
+    pub overpass_breaker_cooldown_secs: u64,
+    /// Blend text and gradient overlays in linear light instead of raw sRGB values
+    pub gamma_correct: bool,
+    /// Water, park and landuse polygons smaller than this many square pixels are not drawn
+    pub min_polygon_area_px: f32,
+    /// Download filename template without extension (see `FILENAME_PLACEHOLDERS`)
+    pub filename_template: String,
+    /// Token required in `X-Admin-Token` for admin endpoints (disabled when unset)
+    pub admin_token: Option<String>,
+    /// Directory of recorded Overpass and Nominatim responses (written when online, read in offline mode)
+    pub offline_data_dir: Option<PathBuf>,
+    /// Read map data and locations only from `offline_data_dir`, never from Overpass or Nominatim
+    pub offline_mode: bool,
+    /// Directory of PNG layout templates requests can render into (disabled when unset)
+    pub templates_dir: Option<PathBuf>,
+    /// JSON file of poster recipes to regenerate on an interval (see `api::scheduler::Schedule`)
+    pub schedule_file: Option<PathBuf>,
+    /// JSON file overriding the Overpass tag filters of the water, park, parking and pitch layers
+    pub feature_tags_file: Option<PathBuf>,
+    /// Tag filters for the water, park, parking and pitch layers (built-in unless `feature_tags_file` is set)
+    pub feature_tags: FeatureTags,
 }
 
 impl Settings {
     /// Load settings from environment variables with sensible defaults
     pub fn from_env() -> Self {
+        Self::from_lookup(|key| env::var(key).ok())
+    }
+
+    /// Load settings layered from files and the environment, then validate them
+    ///
+    /// Precedence (highest first): process environment, `.env` in the working directory,
+    /// the TOML file at `path`, built-in defaults. TOML keys are the environment variable
+    /// names in any case (`max_distance = 50000`). A missing file is skipped, but a
+    /// `FEATURE_TAGS_FILE` that can't be read or parsed is an error.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        // dotenvy never overrides variables that are already set
+        if let Err(e) = dotenvy::dotenv() {
+            if !e.not_found() {
+                return Err(format!("Failed to load .env: {}", e));
+            }
+        }
+
+        let file_values = match std::fs::read_to_string(path.as_ref()) {
+            Ok(contents) => parse_config_toml(&contents)
+                .map_err(|e| format!("Invalid config file {:?}: {}", path.as_ref(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(format!("Failed to read config file {:?}: {}", path.as_ref(), e)),
+        };
+
+        let mut settings = Self::from_lookup(|key| env::var(key).ok().or_else(|| file_values.get(key).cloned()));
+        if let Some(path) = &settings.feature_tags_file {
+            settings.feature_tags = FeatureTags::load(path)?;
+        }
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Check that the configuration is usable, reporting every problem found
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        if self.min_distance >= self.max_distance {
+            problems.push(format!(
+                "MIN_DISTANCE ({}) must be less than MAX_DISTANCE ({})",
+                self.min_distance, self.max_distance
+            ));
+        } else if self.validate_distance(self.default_distance).is_err() {
+            problems.push(format!(
+                "DEFAULT_DISTANCE ({}) must be between MIN_DISTANCE ({}) and MAX_DISTANCE ({})",
+                self.default_distance, self.min_distance, self.max_distance
+            ));
+        }
+
+        for (name, value) in [
+            ("NOMINATIM_TIMEOUT", self.nominatim_timeout),
+            ("OSM_TIMEOUT", self.osm_timeout),
+        ] {
+            if !(value > 0.0 && value.is_finite()) {
+                problems.push(format!("{} must be a positive number of seconds, got {}", name, value));
+            }
+        }
+        if !(self.preview_scale > 0.0 && self.preview_scale <= 1.0) {
+            problems.push(format!("PREVIEW_SCALE must be in (0, 1], got {}", self.preview_scale));
+        }
+        if self.preview_dpi == 0 || self.preview_dpi > self.output_dpi {
+            problems.push(format!(
+                "PREVIEW_DPI ({}) must be between 1 and OUTPUT_DPI ({})",
+                self.preview_dpi, self.output_dpi
+            ));
+        }
+        if !(16..=MAX_THUMBNAIL_SIZE).contains(&self.thumbnail_max_size) {
+            problems.push(format!(
+                "THUMBNAIL_MAX_SIZE must be between 16 and {}, got {}",
+                MAX_THUMBNAIL_SIZE, self.thumbnail_max_size
+            ));
+        }
+        let sample: Vec<(&str, &str)> = FILENAME_PLACEHOLDERS.iter().map(|&name| (name, "x")).collect();
+        if let Err(e) = render_filename_template(&self.filename_template, &sample) {
+            problems.push(format!("FILENAME_TEMPLATE is invalid: {}", e));
+        }
+        if !(self.min_polygon_area_px >= 0.0 && self.min_polygon_area_px.is_finite()) {
+            problems.push(format!(
+                "MIN_POLYGON_AREA_PX must be a non-negative number, got {}",
+                self.min_polygon_area_px
+            ));
+        }
+        if self.max_jobs_retained == 0 {
+            problems.push("MAX_JOBS_RETAINED must be positive".to_string());
+        }
+        if self.max_streams_per_job == 0 {
+            problems.push("MAX_STREAMS_PER_JOB must be positive".to_string());
+        }
+        if self.max_search_limit == 0 {
+            problems.push("MAX_SEARCH_LIMIT must be positive".to_string());
+        }
+        if self.sse_poll_ms == 0 {
+            problems.push("SSE_POLL_MS must be positive".to_string());
+        }
+        if self.sse_keepalive_secs == 0 {
+            problems.push("SSE_KEEPALIVE_SECS must be positive".to_string());
+        }
+        if self.job_timeout_secs == 0 {
+            problems.push("JOB_TIMEOUT_SECS must be positive".to_string());
+        }
+        if self.overpass_breaker_threshold > 0 && self.overpass_breaker_cooldown_secs == 0 {
+            problems.push("OVERPASS_BREAKER_COOLDOWN_SECS must be positive".to_string());
+        }
+        if self.max_job_timeout_secs < self.job_timeout_secs {
+            problems.push(format!(
+                "MAX_JOB_TIMEOUT_SECS ({}) must be at least JOB_TIMEOUT_SECS ({})",
+                self.max_job_timeout_secs, self.job_timeout_secs
+            ));
+        }
+
+        for (name, dir) in [
+            ("THEMES_DIR", &self.themes_dir),
+            ("FONTS_DIR", &self.fonts_dir),
+            ("STATIC_DIR", &self.static_dir),
+            ("FRONTEND_DIR", &self.frontend_dir),
+        ] {
+            if dir.as_os_str().is_empty() {
+                problems.push(format!("{} must not be empty", name));
+            }
+        }
+        if !self.fonts_dir.as_os_str().is_empty() && !self.fonts_dir.is_dir() {
+            problems.push(format!("FONTS_DIR {:?} does not exist", self.fonts_dir));
+        }
+
+        match &self.offline_data_dir {
+            Some(dir) if !dir.is_dir() => {
+                problems.push(format!("OFFLINE_DATA_DIR {:?} does not exist", dir));
+            }
+            None if self.offline_mode => problems.push("OFFLINE_MODE requires OFFLINE_DATA_DIR".to_string()),
+            _ => {}
+        }
+        if let Some(dir) = self.templates_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            problems.push(format!("TEMPLATES_DIR {:?} does not exist", dir));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    /// Built-in defaults, unaffected by the environment the tests run in
+    #[cfg(test)]
+    pub fn defaults() -> Self {
+        Self::from_lookup(|_| None)
+    }
+
+    /// Build settings from a lookup of environment-style keys
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
         Self {
-            port: env::var("PORT")
-                .or_else(|_| env::var("API_PORT"))
-                .ok()
+            port: lookup("PORT")
+                .or_else(|| lookup("API_PORT"))
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(8000),
-            themes_dir: env::var("THEMES_DIR")
+            themes_dir: lookup("THEMES_DIR")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| PathBuf::from("themes")),
-            fonts_dir: env::var("FONTS_DIR")
+                .unwrap_or_else(|| PathBuf::from("themes")),
+            fonts_dir: lookup("FONTS_DIR")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| PathBuf::from("fonts")),
-            static_dir: env::var("STATIC_DIR")
+                .unwrap_or_else(|| PathBuf::from("fonts")),
+            static_dir: lookup("STATIC_DIR")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| PathBuf::from("static")),
-            frontend_dir: env::var("FRONTEND_DIR")
+                .unwrap_or_else(|| PathBuf::from("static")),
+            frontend_dir: lookup("FRONTEND_DIR")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| PathBuf::from("frontend")),
-            default_theme: env::var("DEFAULT_THEME")
-                .unwrap_or_else(|_| "feature_based".to_string()),
-            default_distance: env::var("DEFAULT_DISTANCE")
-                .ok()
+                .unwrap_or_else(|| PathBuf::from("frontend")),
+            default_theme: lookup("DEFAULT_THEME")
+                .unwrap_or_else(|| "feature_based".to_string()),
+            default_distance: lookup("DEFAULT_DISTANCE")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(15000),
-            min_distance: env::var("MIN_DISTANCE")
-                .ok()
+            min_distance: lookup("MIN_DISTANCE")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(2000),
-            max_distance: env::var("MAX_DISTANCE")
-                .ok()
+            max_distance: lookup("MAX_DISTANCE")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(50000),
-            output_dpi: env::var("OUTPUT_DPI")
-                .ok()
+            output_dpi: lookup("OUTPUT_DPI")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(300),
-            preview_dpi: env::var("PREVIEW_DPI")
-                .ok()
+            preview_dpi: lookup("PREVIEW_DPI")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(72),
-            nominatim_delay: env::var("NOMINATIM_DELAY")
-                .ok()
+            preview_scale: lookup("PREVIEW_SCALE")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(1.0),
-            nominatim_timeout: env::var("NOMINATIM_TIMEOUT")
-                .ok()
+            thumbnail_max_size: lookup("THUMBNAIL_MAX_SIZE")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(400),
+            nominatim_delay: lookup("NOMINATIM_DELAY")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1.0),
+            nominatim_timeout: lookup("NOMINATIM_TIMEOUT")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(10.0),
-            osm_delay: env::var("OSM_DELAY")
-                .ok()
+            max_search_limit: lookup("MAX_SEARCH_LIMIT")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(50),
+            osm_delay: lookup("OSM_DELAY")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(0.5),
-            osm_timeout: env::var("OSM_TIMEOUT")
-                .ok()
+            osm_timeout: lookup("OSM_TIMEOUT")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(60.0),
-            max_concurrent_jobs: env::var("MAX_CONCURRENT_JOBS")
-                .ok()
+            max_concurrent_jobs: lookup("MAX_CONCURRENT_JOBS")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(5),
-            job_ttl_hours: env::var("JOB_TTL_HOURS")
-                .ok()
+            job_ttl_hours: lookup("JOB_TTL_HOURS")
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(24),
+            max_jobs_retained: lookup("MAX_JOBS_RETAINED")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1000),
+            nominatim_url: lookup("NOMINATIM_URL")
+                .map(|s| s.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| PRODUCTION_NOMINATIM_URL.to_string()),
+            user_agent: lookup("OSM_USER_AGENT")
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()),
+            contact_email: lookup("CONTACT_EMAIL")
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+            storage_warn_mb: lookup("STORAGE_WARN_MB")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(5120),
+            preview_cache_mb: lookup("PREVIEW_CACHE_MB")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(64),
+            max_inflight_requests: lookup("MAX_INFLIGHT_REQUESTS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1024),
+            max_streams_per_job: lookup("MAX_STREAMS_PER_JOB")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(8),
+            sse_poll_ms: lookup("SSE_POLL_MS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(500),
+            sse_keepalive_secs: lookup("SSE_KEEPALIVE_SECS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(15),
+            request_timeout_secs: lookup("REQUEST_TIMEOUT_SECS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(120),
+            tcp_keepalive_secs: lookup("TCP_KEEPALIVE_SECS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            job_timeout_secs: lookup("JOB_TIMEOUT_SECS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(180),
+            max_job_timeout_secs: lookup("MAX_JOB_TIMEOUT_SECS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(600),
+            overpass_breaker_threshold: lookup("OVERPASS_BREAKER_THRESHOLD")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3),
+            overpass_breaker_cooldown_secs: lookup("OVERPASS_BREAKER_COOLDOWN_SECS")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            gamma_correct: lookup("GAMMA_CORRECT")
+                .map(|s| matches!(s.to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .unwrap_or(false),
+            min_polygon_area_px: lookup("MIN_POLYGON_AREA_PX")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1.0),
+            filename_template: lookup("FILENAME_TEMPLATE")
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_FILENAME_TEMPLATE.to_string()),
+            admin_token: lookup("ADMIN_TOKEN")
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+            offline_data_dir: lookup("OFFLINE_DATA_DIR")
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            offline_mode: lookup("OFFLINE_MODE")
+                .map(|s| matches!(s.to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .unwrap_or(false),
+            templates_dir: lookup("TEMPLATES_DIR")
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            schedule_file: lookup("SCHEDULE_FILE")
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            feature_tags_file: lookup("FEATURE_TAGS_FILE")
+                .filter(|s| !s.trim().is_empty())
+                .map(PathBuf::from),
+            feature_tags: FeatureTags::default(),
         }
     }
 
+    /// Limit on producing a response for routes other than progress streams, if any
+    pub fn request_timeout(&self) -> Option<Duration> {
+        (self.request_timeout_secs > 0).then(|| Duration::from_secs(self.request_timeout_secs))
+    }
+
+    /// Idle time before keep-alive probes on client connections, if enabled
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        (self.tcp_keepalive_secs > 0).then(|| Duration::from_secs(self.tcp_keepalive_secs))
+    }
+
+    /// Result count for a location search, clamped to between 1 and `max_search_limit`
+    pub fn search_limit(&self, requested: u32) -> u32 {
+        requested.clamp(1, self.max_search_limit.max(1))
+    }
+
+    /// Effective job timeout for an optional per-request override, clamped to server bounds
+    pub fn job_timeout(&self, requested: Option<u64>) -> Duration {
+        let max = self.max_job_timeout_secs.max(MIN_JOB_TIMEOUT_SECS);
+        let secs = requested.unwrap_or(self.job_timeout_secs);
+        Duration::from_secs(secs.clamp(MIN_JOB_TIMEOUT_SECS, max))
+    }
+
+    /// Full User-Agent header value including the operator contact, if any
+    pub fn user_agent_header(&self) -> String {
+        match &self.contact_email {
+            Some(email) => format!("{} contact: {}", self.user_agent, email),
+            None => self.user_agent.clone(),
+        }
+    }
+
+    /// Ensure the Nominatim usage policy can be met (a contact is required for the public instance)
+    pub fn validate_contact(&self) -> Result<(), String> {
+        if self.nominatim_url == PRODUCTION_NOMINATIM_URL && self.contact_email.is_none() {
+            return Err(format!(
+                "CONTACT_EMAIL must be set when using {} (see https://operations.osmfoundation.org/policies/nominatim/)",
+                PRODUCTION_NOMINATIM_URL
+            ));
+        }
+        Ok(())
+    }
+
     /// Validate that a distance is within allowed bounds
     pub fn validate_distance(&self, distance: u32) -> Result<u32, String> {
         if distance < self.min_distance {
@@ -141,8 +487,180 @@ impl Settings {
     }
 }
 
+/// Substitute `{name}` placeholders in a filename template
+///
+/// Every substituted value and every literal part goes through `sanitize_filename`,
+/// so the result is always a safe single path component.
+pub fn render_filename_template(template: &str, values: &[(&str, &str)]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&Settings::sanitize_filename(&rest[..start]));
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed '{{' in '{}'", template))?;
+        let name = &rest[start + 1..start + end];
+        let value = values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| format!("unknown placeholder '{{{}}}' (expected one of {})", name, FILENAME_PLACEHOLDERS.join(", ")))?;
+        output.push_str(&Settings::sanitize_filename(value));
+        rest = &rest[start + end + 1..];
+    }
+    output.push_str(&Settings::sanitize_filename(rest));
+
+    if output.is_empty() {
+        return Err("template produces an empty filename".to_string());
+    }
+    Ok(output)
+}
+
+/// Flatten a TOML config file into environment-style keys (`port` -> `PORT`)
+fn parse_config_toml(contents: &str) -> Result<HashMap<String, String>, String> {
+    let table: toml::Table = contents.parse().map_err(|e: toml::de::Error| e.to_string())?;
+
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                other => return Err(format!("unsupported value for '{}': {}", key, other.type_str())),
+            };
+            Ok((key.to_uppercase(), value))
+        })
+        .collect()
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self::from_env()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_toml() {
+        let values = parse_config_toml("port = 9000\nmax_distance = 30000\nNOMINATIM_DELAY = 1.5\ncontact_email = \"ops@example.com\"").unwrap();
+        assert_eq!(values.get("PORT").map(String::as_str), Some("9000"));
+        assert_eq!(values.get("MAX_DISTANCE").map(String::as_str), Some("30000"));
+        assert_eq!(values.get("NOMINATIM_DELAY").map(String::as_str), Some("1.5"));
+        assert_eq!(values.get("CONTACT_EMAIL").map(String::as_str), Some("ops@example.com"));
+
+        assert!(parse_config_toml("[server]\nport = 9000").is_err());
+    }
+
+    #[test]
+    fn test_lookup_layering_and_validate() {
+        let file = parse_config_toml("min_distance = 1000\nmax_distance = 30000").unwrap();
+        let env: HashMap<String, String> = [
+            ("MAX_DISTANCE".to_string(), "40000".to_string()),
+            ("FONTS_DIR".to_string(), env::temp_dir().to_string_lossy().to_string()),
+        ]
+        .into();
+
+        let settings = Settings::from_lookup(|key| env.get(key).or_else(|| file.get(key)).cloned());
+        assert_eq!(settings.min_distance, 1000);
+        assert_eq!(settings.max_distance, 40000);
+        assert!(settings.validate().is_ok());
+
+        let inverted = Settings::from_lookup(|key| match key {
+            "MIN_DISTANCE" => Some("60000".to_string()),
+            "MAX_DISTANCE" => Some("50000".to_string()),
+            _ => None,
+        });
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn test_file_settings_are_validated() {
+        // The file layer as `load` reads it, without the process environment or `.env`
+        let file_values = parse_config_toml("min_distance = 60000\nmax_distance = 50000").unwrap();
+        let err = Settings::from_lookup(|key| file_values.get(key).cloned()).validate().unwrap_err();
+        assert!(err.contains("MIN_DISTANCE (60000) must be less than MAX_DISTANCE (50000)"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let fonts_dir = env::temp_dir().to_string_lossy().to_string();
+        let settings_with = |overrides: &[(&str, &str)]| {
+            let overrides: HashMap<String, String> = overrides
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .chain([("FONTS_DIR".to_string(), fonts_dir.clone())])
+                .collect();
+            Settings::from_lookup(|key| overrides.get(key).cloned())
+        };
+
+        assert!(settings_with(&[]).validate().is_ok());
+
+        let err = settings_with(&[("MIN_DISTANCE", "60000"), ("MAX_DISTANCE", "50000")])
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("MIN_DISTANCE (60000) must be less than MAX_DISTANCE (50000)"));
+
+        // Every problem is reported at once
+        let err = settings_with(&[("DEFAULT_DISTANCE", "100"), ("OSM_TIMEOUT", "0"), ("THEMES_DIR", "")])
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("DEFAULT_DISTANCE"));
+        assert!(err.contains("OSM_TIMEOUT"));
+        assert!(err.contains("THEMES_DIR"));
+
+        let missing_fonts = Settings::from_lookup(|key| {
+            (key == "FONTS_DIR").then(|| "/nonexistent/maptoposter-fonts".to_string())
+        });
+        assert!(missing_fonts.validate().unwrap_err().contains("FONTS_DIR"));
+
+        let err = settings_with(&[("FILENAME_TEMPLATE", "{city}_{zoom}")]).validate().unwrap_err();
+        assert!(err.contains("unknown placeholder '{zoom}'"));
+
+        let err = settings_with(&[("OFFLINE_MODE", "true")]).validate().unwrap_err();
+        assert!(err.contains("OFFLINE_MODE requires OFFLINE_DATA_DIR"));
+        assert!(settings_with(&[("OFFLINE_MODE", "true"), ("OFFLINE_DATA_DIR", &fonts_dir)]).validate().is_ok());
+
+        let err = settings_with(&[("TEMPLATES_DIR", "/nonexistent/maptoposter-templates")]).validate().unwrap_err();
+        assert!(err.contains("TEMPLATES_DIR"));
+
+        let settings = settings_with(&[("SSE_POLL_MS", "250"), ("SSE_KEEPALIVE_SECS", "30")]);
+        assert_eq!((settings.sse_poll_ms, settings.sse_keepalive_secs), (250, 30));
+        let err = settings_with(&[("SSE_POLL_MS", "0")]).validate().unwrap_err();
+        assert!(err.contains("SSE_POLL_MS"));
+
+        let err = settings_with(&[("THUMBNAIL_MAX_SIZE", "8")]).validate().unwrap_err();
+        assert!(err.contains("THUMBNAIL_MAX_SIZE"));
+
+        let err = settings_with(&[("OVERPASS_BREAKER_COOLDOWN_SECS", "0")]).validate().unwrap_err();
+        assert!(err.contains("OVERPASS_BREAKER_COOLDOWN_SECS"));
+        assert!(settings_with(&[("OVERPASS_BREAKER_THRESHOLD", "0"), ("OVERPASS_BREAKER_COOLDOWN_SECS", "0")]).validate().is_ok());
+
+        let settings = settings_with(&[("MAX_SEARCH_LIMIT", "20")]);
+        assert_eq!((settings.search_limit(5), settings.search_limit(1000), settings.search_limit(0)), (5, 20, 1));
+        let err = settings_with(&[("MAX_SEARCH_LIMIT", "0")]).validate().unwrap_err();
+        assert!(err.contains("MAX_SEARCH_LIMIT"));
+
+        // Zero turns the connection limits off rather than failing every request
+        let settings = settings_with(&[("REQUEST_TIMEOUT_SECS", "0"), ("TCP_KEEPALIVE_SECS", "0")]);
+        assert_eq!((settings.request_timeout(), settings.tcp_keepalive()), (None, None));
+        assert_eq!(settings_with(&[]).request_timeout(), Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn test_render_filename_template() {
+        let values = [("city", "São Paulo"), ("country", "Brazil"), ("theme", "noir"), ("date", "2026-01-08")];
+        assert_eq!(render_filename_template(DEFAULT_FILENAME_TEMPLATE, &values).unwrap(), "São_Paulo_noir");
+        assert_eq!(
+            render_filename_template("{date}-{country}/{city}", &values).unwrap(),
+            "2026-01-08-Brazil_São_Paulo"
+        );
+        assert!(render_filename_template("{city", &values).is_err());
+        assert!(render_filename_template("{}", &values).is_err());
+    }
+}
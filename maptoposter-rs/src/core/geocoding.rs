@@ -1,9 +1,24 @@
-use serde::Deserialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use reqwest::{header, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+use crate::core::poster_generator::write_file_atomically;
 use crate::error::{AppError, Result};
 
-const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
-const USER_AGENT: &str = "MapToPoster-RS/2.0 (https://github.com/maptoposter)";
+/// Longest `Retry-After` we wait out before retrying a throttled Nominatim request
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
+
+/// Wait before retrying a 429 that has no usable `Retry-After` (Nominatim allows 1 request per second)
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Earliest time the next Nominatim request may go out, shared by every caller
+static NEXT_NOMINATIM_REQUEST: Mutex<Option<Instant>> = parking_lot::const_mutex(None);
 
 /// Raw Nominatim search response
 #[derive(Debug, Deserialize)]
@@ -14,6 +29,14 @@ struct NominatimResult {
     lon: String,
     display_name: String,
     #[serde(default)]
+    importance: Option<f64>,
+    /// Main OSM tag key of the result, e.g. "place" or "highway"
+    #[serde(default)]
+    class: Option<String>,
+    /// Value of that tag, e.g. "city" or "residential"
+    #[serde(default, rename = "type")]
+    place_type: Option<String>,
+    #[serde(default)]
     address: Option<NominatimAddress>,
 }
 
@@ -34,76 +57,386 @@ pub struct LocationData {
     pub display_name: String,
     pub city: Option<String>,
     pub country: Option<String>,
+    /// Nominatim's relevance score (0.0-1.0), if provided
+    pub importance: Option<f64>,
+    /// OSM tag key the result was found by ("place", "boundary", "highway", ...)
+    pub class: Option<String>,
+    /// OSM tag value ("city", "administrative", "residential", ...)
+    pub place_type: Option<String>,
 }
 
+/// Number of candidates considered when geocoding a city
+const GEOCODE_CANDIDATES: u32 = 5;
+
 /// Geocode a city and country to coordinates
-pub async fn geocode(city: &str, country: &str, timeout_secs: f64) -> Result<(f64, f64)> {
+///
+/// Several candidates are requested so an ambiguous name ("Springfield") resolves
+/// to the one in the requested country rather than whichever Nominatim lists first.
+/// Recorded to and replayed from `OFFLINE_DATA_DIR` like Overpass responses.
+pub async fn geocode(
+    city: &str,
+    country: &str,
+    country_code: Option<&str>,
+    config: &Settings,
+) -> Result<(f64, f64)> {
+    let file_name = offline_file_name("geocode", &[city, country, country_code.unwrap_or_default()]);
+    recorded(&file_name, config, async {
+        geocode_location(city, country, country_code, config)
+            .await
+            .map(|r| (r.lat, r.lon))
+    })
+    .await
+}
+
+/// File name of a recorded Nominatim result in `OFFLINE_DATA_DIR`, e.g. `geocode_new-york_usa_us.json`
+fn offline_file_name(kind: &str, parts: &[&str]) -> String {
+    let slug: Vec<String> = parts
+        .iter()
+        .map(|part| part.trim().to_lowercase())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.chars().map(|c| if c.is_alphanumeric() { c } else { '-' }).collect())
+        .collect();
+    format!("{}_{}.json", kind, slug.join("_"))
+}
+
+/// Honor the offline data directory for a Nominatim lookup
+///
+/// With `OFFLINE_MODE` the result is read from `file_name` in `OFFLINE_DATA_DIR` and
+/// `fetch` never runs. Otherwise, if `OFFLINE_DATA_DIR` is set, the fetched result is
+/// also saved there so the location can later be rendered offline.
+async fn recorded<T: Serialize + DeserializeOwned>(
+    file_name: &str,
+    config: &Settings,
+    fetch: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(dir) = &config.offline_data_dir else {
+        return fetch.await;
+    };
+    let path = dir.join(file_name);
+
+    if config.offline_mode {
+        let body = tokio::fs::read(&path).await.map_err(|e| {
+            AppError::Geocoding(if e.kind() == std::io::ErrorKind::NotFound {
+                format!("Offline mode: no recorded lookup, expected {:?}", path)
+            } else {
+                format!("Offline mode: could not read {:?}: {}", path, e)
+            })
+        })?;
+        return serde_json::from_slice(&body)
+            .map_err(|e| AppError::Geocoding(format!("Offline data file {:?} is not valid: {}", path, e)));
+    }
+
+    let value = fetch.await?;
+    let saved = match serde_json::to_vec(&value) {
+        Ok(body) => write_file_atomically(&path, &body).await,
+        Err(e) => Err(AppError::Internal(e.to_string())),
+    };
+    if let Err(e) = saved {
+        tracing::warn!("Could not save offline data to {:?}: {}", path, e);
+    }
+    Ok(value)
+}
+
+/// Geocode a city and country to the best matching Nominatim result
+pub async fn geocode_location(
+    city: &str,
+    country: &str,
+    country_code: Option<&str>,
+    config: &Settings,
+) -> Result<LocationData> {
     let query = format!("{}, {}", city, country);
-    let results = search_nominatim(&query, 1, timeout_secs).await?;
+    let results = search_nominatim(&query, GEOCODE_CANDIDATES, country_code, config).await?;
 
-    results
-        .into_iter()
-        .next()
-        .map(|r| (r.lat, r.lon))
+    pick_best_candidate(&results, country)
+        .cloned()
         .ok_or_else(|| AppError::Geocoding(format!("Location not found: {}, {}", city, country)))
 }
 
-/// Search Nominatim for locations matching a query
-pub async fn search_nominatim(query: &str, limit: u32, timeout_secs: f64) -> Result<Vec<LocationData>> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs_f64(timeout_secs))
-        .user_agent(USER_AGENT)
-        .build()?;
+/// Pick the most plausible candidate: matching country first, then highest importance
+fn pick_best_candidate<'a>(candidates: &'a [LocationData], country: &str) -> Option<&'a LocationData> {
+    let country = country.trim().to_lowercase();
+    let matches_country = |c: &LocationData| {
+        c.country
+            .as_ref()
+            .map(|name| name.to_lowercase() == country)
+            .unwrap_or(false)
+    };
 
-    let url = format!(
+    let preferred: Vec<&LocationData> = candidates.iter().filter(|c| matches_country(c)).collect();
+    let pool: Vec<&LocationData> = if preferred.is_empty() {
+        candidates.iter().collect()
+    } else {
+        preferred
+    };
+
+    // Keep Nominatim's order on ties so the first result wins by default
+    pool.into_iter().reduce(|best, c| {
+        if c.importance.unwrap_or(0.0) > best.importance.unwrap_or(0.0) {
+            c
+        } else {
+            best
+        }
+    })
+}
+
+/// Validate a comma-separated list of ISO 3166-1 alpha-2 country codes
+pub fn validate_country_codes(codes: &str) -> bool {
+    !codes.is_empty()
+        && codes
+            .split(',')
+            .all(|c| c.len() == 2 && c.chars().all(|ch| ch.is_ascii_alphabetic()))
+}
+
+/// Search Nominatim for locations matching a query, optionally restricted to country codes
+pub async fn search_nominatim(
+    query: &str,
+    limit: u32,
+    country_code: Option<&str>,
+    config: &Settings,
+) -> Result<Vec<LocationData>> {
+    let client = nominatim_client(config)?;
+
+    let mut url = format!(
         "{}/search?q={}&format=json&limit={}&addressdetails=1",
-        NOMINATIM_URL,
+        config.nominatim_url,
         urlencoding::encode(query),
         limit
     );
+    if let Some(codes) = country_code {
+        url.push_str(&format!("&countrycodes={}", urlencoding::encode(&codes.to_lowercase())));
+    }
+
+    let response = send_nominatim(&client, &url, config).await?;
+
+    let results: Vec<NominatimResult> = response.json().await?;
+
+    Ok(results.into_iter().filter_map(into_location_data).collect())
+}
+
+/// Resolve coordinates to the nearest named place (city level)
+pub async fn reverse_geocode(lat: f64, lon: f64, config: &Settings) -> Result<LocationData> {
+    let client = nominatim_client(config)?;
+
+    // zoom=10 resolves to city/town level rather than the nearest building
+    let url = format!(
+        "{}/reverse?lat={}&lon={}&format=json&zoom=10&addressdetails=1",
+        config.nominatim_url, lat, lon
+    );
 
-    let response = client.get(&url).send().await?;
+    let response = send_nominatim(&client, &url, config).await?;
 
-    if !response.status().is_success() {
-        return Err(AppError::Geocoding(format!(
-            "Nominatim API error: {}",
-            response.status()
-        )));
+    // Nominatim reports "nothing here" (e.g. open ocean) as {"error": "..."} with 200
+    let body: serde_json::Value = response.json().await?;
+    if body.get("error").is_some() {
+        return Err(AppError::Geocoding(format!("No place found at {}, {}", lat, lon)));
     }
 
-    let results: Vec<NominatimResult> = response.json().await?;
+    serde_json::from_value(body)
+        .ok()
+        .and_then(into_location_data)
+        .ok_or_else(|| AppError::Geocoding(format!("No place found at {}, {}", lat, lon)))
+}
+
+/// Check that Nominatim answers, via its status endpoint
+///
+/// Passes without a request in offline mode.
+pub async fn probe_nominatim(config: &Settings) -> Result<()> {
+    if config.offline_mode {
+        return Ok(());
+    }
+    let client = nominatim_client(config)?;
+    send_nominatim(&client, &format!("{}/status", config.nominatim_url), config).await?;
+    Ok(())
+}
+
+/// Simplified country outline as (lat, lon) rings, for the locator inset
+///
+/// Recorded to and replayed from `OFFLINE_DATA_DIR` like `geocode`.
+pub async fn fetch_country_outline(country: &str, config: &Settings) -> Result<Vec<Vec<(f64, f64)>>> {
+    let file_name = offline_file_name("outline", &[country]);
+    recorded(&file_name, config, query_country_outline(country, config)).await
+}
+
+async fn query_country_outline(country: &str, config: &Settings) -> Result<Vec<Vec<(f64, f64)>>> {
+    let client = nominatim_client(config)?;
 
-    let locations = results
+    // polygon_threshold simplifies the geometry server-side (degrees of tolerance)
+    let url = format!(
+        "{}/search?country={}&format=json&limit=1&polygon_geojson=1&polygon_threshold=0.05",
+        config.nominatim_url,
+        urlencoding::encode(country)
+    );
+
+    let response = send_nominatim(&client, &url, config).await?;
+
+    let results: Vec<serde_json::Value> = response.json().await?;
+    let rings = results
+        .first()
+        .and_then(|r| r.get("geojson"))
+        .map(geojson_outer_rings)
+        .unwrap_or_default();
+
+    if rings.is_empty() {
+        return Err(AppError::Geocoding(format!("No boundary found for {}", country)));
+    }
+    Ok(rings)
+}
+
+/// Outer rings of a GeoJSON Polygon or MultiPolygon as (lat, lon) points
+fn geojson_outer_rings(geometry: &serde_json::Value) -> Vec<Vec<(f64, f64)>> {
+    let to_ring = |ring: &serde_json::Value| -> Option<Vec<(f64, f64)>> {
+        ring.as_array()?
+            .iter()
+            .map(|p| Some((p.get(1)?.as_f64()?, p.get(0)?.as_f64()?)))
+            .collect()
+    };
+    let Some(coordinates) = geometry.get("coordinates") else {
+        return Vec::new();
+    };
+
+    let polygons: Vec<&serde_json::Value> = match geometry.get("type").and_then(|t| t.as_str()) {
+        Some("Polygon") => vec![coordinates],
+        Some("MultiPolygon") => coordinates
+            .as_array()
+            .map(|polygons| polygons.iter().collect())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    polygons
         .into_iter()
-        .filter_map(|r| {
-            let lat = r.lat.parse::<f64>().ok()?;
-            let lon = r.lon.parse::<f64>().ok()?;
-
-            let city = r.address.as_ref().and_then(|a| {
-                a.city
-                    .clone()
-                    .or_else(|| a.town.clone())
-                    .or_else(|| a.village.clone())
-                    .or_else(|| a.municipality.clone())
-            });
-
-            let country = r.address.as_ref().and_then(|a| a.country.clone());
-
-            Some(LocationData {
-                lat,
-                lon,
-                display_name: r.display_name,
-                city,
-                country,
-            })
-        })
-        .collect();
+        .filter_map(|polygon| polygon.get(0).and_then(to_ring))
+        .collect()
+}
+
+/// Send a Nominatim request, retrying once after the `Retry-After` delay if it is throttled
+///
+/// Every attempt first waits its turn, `NOMINATIM_DELAY` after the previous request from
+/// anywhere in the process. Fails with `AppError::GeocodingRateLimited` when the retry is
+/// throttled too or the server asks for a longer wait than `MAX_RETRY_AFTER`, and
+/// `AppError::Geocoding` for any other error status.
+async fn send_nominatim(client: &reqwest::Client, url: &str, config: &Settings) -> Result<reqwest::Response> {
+    let mut retried = false;
+    loop {
+        wait_for_nominatim(Duration::from_secs_f64(config.nominatim_delay)).await;
+        let response = client.get(url).send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status != StatusCode::TOO_MANY_REQUESTS {
+            return Err(AppError::Geocoding(format!("Nominatim API error: {}", status)));
+        }
 
-    Ok(locations)
+        let wait = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| parse_retry_after(v, Utc::now()));
+        let wait = match wait {
+            Some(Some(wait)) => wait,
+            Some(None) | None => DEFAULT_RETRY_AFTER,
+        };
+        if retried || wait > MAX_RETRY_AFTER {
+            return Err(AppError::GeocodingRateLimited(format!(
+                "Nominatim is throttling requests, retry in {}s",
+                wait.as_secs().max(1)
+            )));
+        }
+
+        tracing::warn!("Nominatim returned 429, retrying in {:?}", wait);
+        tokio::time::sleep(wait).await;
+        retried = true;
+    }
+}
+
+/// Wait until `delay` has passed since the previous Nominatim request
+///
+/// Turns are handed out under the lock, so concurrent callers queue up one `delay` apart
+/// instead of all finding the delay elapsed at once.
+async fn wait_for_nominatim(delay: Duration) {
+    let now = Instant::now();
+    let turn = {
+        let mut next = NEXT_NOMINATIM_REQUEST.lock();
+        let turn = next.map_or(now, |next| next.max(now));
+        *next = Some(turn + delay);
+        turn
+    };
+    if turn > now {
+        tracing::debug!("Rate limiting: waiting {:?} for nominatim", turn - now);
+        tokio::time::sleep(turn - now).await;
+    }
+}
+
+/// Delay from a `Retry-After` header, given as seconds or an HTTP date (None if unparseable)
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+    Some((at - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// HTTP client for Nominatim with the configured timeout and User-Agent
+fn nominatim_client(config: &Settings) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs_f64(config.nominatim_timeout))
+        .user_agent(config.user_agent_header())
+        .build()?)
+}
+
+/// Convert a raw Nominatim result, skipping it if the coordinates don't parse
+fn into_location_data(r: NominatimResult) -> Option<LocationData> {
+    let lat = r.lat.parse::<f64>().ok()?;
+    let lon = r.lon.parse::<f64>().ok()?;
+
+    let city = r.address.as_ref().and_then(|a| {
+        a.city
+            .clone()
+            .or_else(|| a.town.clone())
+            .or_else(|| a.village.clone())
+            .or_else(|| a.municipality.clone())
+    });
+
+    let country = r.address.as_ref().and_then(|a| a.country.clone());
+
+    Some(LocationData {
+        lat,
+        lon,
+        display_name: r.display_name,
+        city,
+        country,
+        importance: r.importance,
+        class: r.class,
+        place_type: r.place_type,
+    })
 }
 
-/// Format coordinates for display (e.g., "40.7128° N, 74.0060° W")
-pub fn format_coordinates(lat: f64, lon: f64) -> String {
+/// How coordinates are written on a poster
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordinateFormat {
+    /// Decimal degrees with hemispheres, e.g. `40.7128° N, 74.0060° W`
+    #[default]
+    Decimal,
+    /// Degrees, minutes and seconds, e.g. `40°42'46.1" N, 74°0'21.6" W`
+    Dms,
+    /// Signed decimal pair, e.g. `40.7128, -74.0060`
+    Plain,
+}
+
+/// Format coordinates for display in the given format
+pub fn format_coordinates(lat: f64, lon: f64, format: CoordinateFormat) -> String {
+    match format {
+        CoordinateFormat::Decimal => format_coordinates_decimal(lat, lon),
+        CoordinateFormat::Dms => format_coordinates_dms(lat, lon),
+        CoordinateFormat::Plain => format!("{:.4}, {:.4}", lat, lon),
+    }
+}
+
+/// Format coordinates as decimal degrees (e.g., "40.7128° N, 74.0060° W")
+pub fn format_coordinates_decimal(lat: f64, lon: f64) -> String {
     let lat_dir = if lat >= 0.0 { "N" } else { "S" };
     let lon_dir = if lon >= 0.0 { "E" } else { "W" };
 
@@ -116,6 +449,19 @@ pub fn format_coordinates(lat: f64, lon: f64) -> String {
     )
 }
 
+/// Format coordinates as degrees, minutes and seconds (e.g., "40°42'46.1\" N, 74°0'21.6\" W")
+pub fn format_coordinates_dms(lat: f64, lon: f64) -> String {
+    // Rounded to tenths of a second up front, so 59.96" carries into the next minute
+    let dms = |value: f64| {
+        let tenths = (value.abs() * 36_000.0).round() as u64;
+        format!("{}°{}'{:.1}\"", tenths / 36_000, tenths % 36_000 / 600, (tenths % 600) as f64 / 10.0)
+    };
+    let lat_dir = if lat >= 0.0 { "N" } else { "S" };
+    let lon_dir = if lon >= 0.0 { "E" } else { "W" };
+
+    format!("{} {}, {} {}", dms(lat), lat_dir, dms(lon), lon_dir)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -123,12 +469,147 @@ mod tests {
     #[test]
     fn test_format_coordinates() {
         assert_eq!(
-            format_coordinates(40.7128, -74.0060),
+            format_coordinates(40.7128, -74.0060, CoordinateFormat::Decimal),
             "40.7128° N, 74.0060° W"
         );
         assert_eq!(
-            format_coordinates(-33.8688, 151.2093),
+            format_coordinates(-33.8688, 151.2093, CoordinateFormat::Decimal),
             "33.8688° S, 151.2093° E"
         );
+        assert_eq!(format_coordinates(40.7128, -74.0060, CoordinateFormat::Plain), "40.7128, -74.0060");
+        assert_eq!(format_coordinates(-33.8688, 151.2093, CoordinateFormat::Plain), "-33.8688, 151.2093");
+    }
+
+    #[test]
+    fn test_format_coordinates_dms() {
+        assert_eq!(format_coordinates_dms(40.7128, -74.0060), "40°42'46.1\" N, 74°0'21.6\" W");
+        assert_eq!(format_coordinates_dms(-33.8688, 151.2093), "33°52'7.7\" S, 151°12'33.5\" E");
+        // Seconds that round up to 60 carry into the minutes and degrees
+        assert_eq!(format_coordinates_dms(10.99999, 0.0), "11°0'0.0\" N, 0°0'0.0\" E");
+        assert_eq!(format_coordinates(0.5, -0.5, CoordinateFormat::Dms), "0°30'0.0\" N, 0°30'0.0\" W");
+    }
+
+    fn candidate(country: &str, importance: f64) -> LocationData {
+        LocationData {
+            lat: importance,
+            lon: 0.0,
+            display_name: format!("Springfield, {}", country),
+            city: Some("Springfield".to_string()),
+            country: Some(country.to_string()),
+            importance: Some(importance),
+            class: Some("place".to_string()),
+            place_type: Some("city".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_pick_best_candidate() {
+        let candidates = vec![
+            candidate("United States", 0.7),
+            candidate("Australia", 0.4),
+            candidate("United States", 0.9),
+        ];
+
+        // Matching country wins over importance elsewhere
+        let best = pick_best_candidate(&candidates, "australia").unwrap();
+        assert_eq!(best.country.as_deref(), Some("Australia"));
+
+        // Among matches, the most important one wins
+        let best = pick_best_candidate(&candidates, "United States").unwrap();
+        assert_eq!(best.importance, Some(0.9));
+
+        // No country match falls back to the most important overall
+        let best = pick_best_candidate(&candidates, "Canada").unwrap();
+        assert_eq!(best.importance, Some(0.9));
+
+        assert!(pick_best_candidate(&[], "Canada").is_none());
+    }
+
+    #[test]
+    fn test_into_location_data_keeps_place_type() {
+        let raw: NominatimResult = serde_json::from_value(serde_json::json!({
+            "lat": "45.4371908",
+            "lon": "12.3345898",
+            "display_name": "Venezia, Veneto, Italia",
+            "class": "place",
+            "type": "city",
+            "importance": 0.78,
+            "address": { "city": "Venezia", "country": "Italia" }
+        }))
+        .unwrap();
+        let location = into_location_data(raw).unwrap();
+        assert_eq!(location.class.as_deref(), Some("place"));
+        assert_eq!(location.place_type.as_deref(), Some("city"));
+        assert_eq!(location.importance, Some(0.78));
+        assert_eq!(location.city.as_deref(), Some("Venezia"));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2024-05-17T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(parse_retry_after("3", now), Some(Duration::from_secs(3)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Fri, 17 May 2024 12:00:05 GMT", now), Some(Duration::from_secs(5)));
+        // Dates in the past mean "retry now"
+        assert_eq!(parse_retry_after("Fri, 17 May 2024 11:59:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_geojson_outer_rings() {
+        let polygon = serde_json::json!({
+            "type": "Polygon",
+            "coordinates": [[[10.0, 45.0], [11.0, 45.0], [11.0, 46.0]], [[10.2, 45.2], [10.4, 45.2], [10.4, 45.4]]]
+        });
+        assert_eq!(geojson_outer_rings(&polygon), vec![vec![(45.0, 10.0), (45.0, 11.0), (46.0, 11.0)]]);
+
+        let multi = serde_json::json!({
+            "type": "MultiPolygon",
+            "coordinates": [[[[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]], [[[7.0, 8.0], [9.0, 10.0], [11.0, 12.0]]]]
+        });
+        assert_eq!(geojson_outer_rings(&multi).len(), 2);
+
+        let point = serde_json::json!({ "type": "Point", "coordinates": [1.0, 2.0] });
+        assert!(geojson_outer_rings(&point).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_replays_recorded_geocoding() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-offline-geocode-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Settings::defaults();
+        config.offline_data_dir = Some(dir.clone());
+        config.offline_mode = true;
+
+        assert_eq!(offline_file_name("geocode", &["New York", "USA", ""]), "geocode_new-york_usa.json");
+        std::fs::write(dir.join("geocode_venice_italy.json"), "[45.4372, 12.3346]").unwrap();
+        assert_eq!(geocode("Venice", "Italy", None, &config).await.unwrap(), (45.4372, 12.3346));
+
+        // Nothing recorded: fails without asking Nominatim
+        let err = fetch_country_outline("Italy", &config).await.unwrap_err().to_string();
+        assert!(err.contains("Offline mode") && err.contains("outline_italy.json"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_nominatim_requests_are_spaced() {
+        let delay = Duration::from_millis(50);
+        wait_for_nominatim(delay).await;
+        let started = Instant::now();
+        let waits = (0..3).map(|_| wait_for_nominatim(delay));
+        futures::future::join_all(waits).await;
+        // Concurrent callers take successive turns
+        assert!(started.elapsed() >= delay * 3 - Duration::from_millis(5), "{:?}", started.elapsed());
+    }
+
+    #[test]
+    fn test_validate_country_codes() {
+        assert!(validate_country_codes("us"));
+        assert!(validate_country_codes("gb,IE"));
+        assert!(!validate_country_codes(""));
+        assert!(!validate_country_codes("usa"));
+        assert!(!validate_country_codes("u1"));
+        assert!(!validate_country_codes("us&q=x"));
     }
 }
@@ -1,8 +1,14 @@
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
 
 // geo types available for future use if needed
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::config::Settings;
+use crate::core::poster_generator::write_file_atomically;
 use crate::error::{AppError, Result};
 
 // Overpass API mirrors with fallback support (ordered by reliability/speed)
@@ -11,7 +17,6 @@ const OVERPASS_MIRRORS: &[&str] = &[
     "https://maps.mail.ru/osm/tools/overpass/api/interpreter", // Fast Russian mirror
     "https://overpass.kumi.systems/api/interpreter",     // Backup mirror
 ];
-const USER_AGENT: &str = "MapToPoster-RS/2.0 (https://github.com/maptoposter)";
 
 /// Highway types with their rendering priority
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -32,7 +37,29 @@ pub enum HighwayType {
     Default,
 }
 
+/// Highway types requested from Overpass, in tag order
+pub const FETCHED_HIGHWAY_TYPES: &[HighwayType] = &[
+    HighwayType::Motorway,
+    HighwayType::MotorwayLink,
+    HighwayType::Trunk,
+    HighwayType::Primary,
+    HighwayType::PrimaryLink,
+    HighwayType::Secondary,
+    HighwayType::SecondaryLink,
+    HighwayType::Tertiary,
+    HighwayType::TertiaryLink,
+    HighwayType::Residential,
+    HighwayType::LivingStreet,
+    HighwayType::Service,
+    HighwayType::Unclassified,
+];
+
 impl HighwayType {
+    /// Parse a fetched highway tag value, rejecting anything outside `FETCHED_HIGHWAY_TYPES`
+    pub fn from_tag(s: &str) -> Option<Self> {
+        FETCHED_HIGHWAY_TYPES.iter().copied().find(|t| t.as_str() == s)
+    }
+
     pub fn from_str(s: &str) -> Self {
         match s {
             "motorway" => Self::Motorway,
@@ -52,6 +79,26 @@ impl HighwayType {
         }
     }
 
+    /// Get the OSM tag value for this highway type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Motorway => "motorway",
+            Self::MotorwayLink => "motorway_link",
+            Self::Trunk => "trunk",
+            Self::Primary => "primary",
+            Self::PrimaryLink => "primary_link",
+            Self::Secondary => "secondary",
+            Self::SecondaryLink => "secondary_link",
+            Self::Tertiary => "tertiary",
+            Self::TertiaryLink => "tertiary_link",
+            Self::Residential => "residential",
+            Self::LivingStreet => "living_street",
+            Self::Service => "service",
+            Self::Unclassified => "unclassified",
+            Self::Default => "road",
+        }
+    }
+
     /// Get the line width for this highway type
     pub fn line_width(&self) -> f32 {
         match self {
@@ -80,24 +127,143 @@ impl HighwayType {
     }
 }
 
+/// Every theme key `HighwayType::theme_key` can return, from most to least important
+pub const ROAD_THEME_KEYS: [&str; 6] = [
+    "road_motorway",
+    "road_primary",
+    "road_secondary",
+    "road_tertiary",
+    "road_residential",
+    "road_default",
+];
+
 /// A road segment with coordinates and type
 #[derive(Debug, Clone)]
 pub struct RoadSegment {
+    /// OSM way id
+    pub id: i64,
     pub points: Vec<(f64, f64)>,
     pub highway_type: HighwayType,
+    /// One-way in the direction of `points` (from the `oneway` tag)
+    pub oneway: bool,
+    /// Number of lanes (from the `lanes` tag)
+    pub lanes: Option<u32>,
+    /// Speed limit in km/h (from the `maxspeed` tag, converted from mph when tagged so)
+    pub maxspeed: Option<u32>,
 }
 
 /// Water or park polygon feature
 #[derive(Debug, Clone)]
 pub struct AreaFeature {
+    /// OSM way id
+    pub id: i64,
     pub points: Vec<(f64, f64)>,
     pub feature_type: String,
 }
 
+/// Kind of a point feature, also the name accepted in a request's `points` list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PointKind {
+    /// Mountain summit (`natural=peak`)
+    Peak,
+    /// Named settlement or neighbourhood (`place`, see `PLACE_TYPES`)
+    Place,
+}
+
+impl PointKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Peak => "peak",
+            Self::Place => "place",
+        }
+    }
+}
+
+/// Optional area category for detailed neighbourhood prints, also the name accepted in a
+/// request's `detail_areas` list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetailArea {
+    /// Car parks (`amenity=parking`)
+    Parking,
+    /// Sports pitches and playgrounds (`leisure=pitch`, `leisure=playground`)
+    Pitch,
+}
+
+impl DetailArea {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Parking => "parking",
+            Self::Pitch => "pitch",
+        }
+    }
+}
+
+/// A single OSM node drawn as a marker, e.g. a peak
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointFeature {
+    pub lat: f64,
+    pub lon: f64,
+    /// The node's `name` tag, used as its label
+    pub name: Option<String>,
+    pub kind: PointKind,
+}
+
+/// Counts of OSM elements kept or skipped while parsing one layer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LayerStats {
+    /// Features that made it into the layer
+    pub kept: usize,
+    /// Ways dropped because too few of their nodes could be resolved
+    pub dropped_ways: usize,
+    /// Ways kept even though some of their nodes were missing
+    pub partial_ways: usize,
+    /// Relations skipped because multipolygons are not stitched
+    pub skipped_relations: usize,
+    /// The whole layer failed to fetch and was left empty
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub fetch_failed: bool,
+    /// When the OSM data the layer was fetched from was last updated (Overpass `timestamp_osm_base`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub osm_timestamp: Option<DateTime<Utc>>,
+}
+
+impl LayerStats {
+    /// Whether anything was dropped, partially resolved or failed
+    pub fn has_losses(&self) -> bool {
+        self.dropped_ways > 0 || self.partial_ways > 0 || self.skipped_relations > 0 || self.fetch_failed
+    }
+
+    /// Stats for a layer whose fetch failed entirely
+    pub fn failed() -> Self {
+        Self {
+            fetch_failed: true,
+            ..Default::default()
+        }
+    }
+}
+
 /// Overpass API response structures
 #[derive(Debug, Deserialize)]
 struct OverpassResponse {
     elements: Vec<OverpassElement>,
+    #[serde(default)]
+    osm3s: Option<OverpassMeta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OverpassMeta {
+    #[serde(default)]
+    timestamp_osm_base: Option<String>,
+}
+
+impl OverpassResponse {
+    /// Timestamp of the OSM database state the response was answered from
+    fn osm_timestamp(&self) -> Option<DateTime<Utc>> {
+        let timestamp = self.osm3s.as_ref()?.timestamp_osm_base.as_deref()?;
+        DateTime::parse_from_rfc3339(timestamp).ok().map(|t| t.with_timezone(&Utc))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -113,96 +279,607 @@ struct OverpassElement {
     nodes: Option<Vec<i64>>,
     #[serde(default)]
     tags: Option<HashMap<String, String>>,
-    #[serde(default)]
-    members: Option<Vec<OverpassMember>>,
-}
-
-#[derive(Debug, Deserialize)]
-struct OverpassMember {
-    #[serde(rename = "type")]
-    member_type: String,
-    #[serde(rename = "ref")]
-    reference: i64,
-    role: String,
 }
 
-/// Fetch street network from Overpass API
+/// Fetch street network from Overpass API, limited to `road_types` if given
 pub async fn fetch_streets(
     center: (f64, f64),
     distance: u32,
-    timeout_secs: f64,
-) -> Result<Vec<RoadSegment>> {
+    road_types: Option<&[HighwayType]>,
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<(Vec<RoadSegment>, LayerStats)> {
     let query = format!(
         r#"[out:json][timeout:90];
 (
-  way["highway"~"^(motorway|motorway_link|trunk|primary|primary_link|secondary|secondary_link|tertiary|tertiary_link|residential|living_street|service|unclassified)$"](around:{},{},{});
+  way["highway"~"^({})$"](around:{},{},{});
 );
 out body;
 >;
 out skel qt;"#,
+        highway_regex(road_types.unwrap_or(FETCHED_HIGHWAY_TYPES)),
         distance, center.0, center.1
     );
 
-    let response = execute_overpass_query(&query, timeout_secs).await?;
+    // A road subset is a different query, so it gets its own offline file
+    let layer = match road_types {
+        Some(types) => format!("streets-{}", highway_regex(types).replace('|', "-")),
+        None => "streets".to_string(),
+    };
+    let response = run_overpass_query(&query, &offline_file_name(&layer, center, distance), on_download, config).await?;
     parse_road_segments(&response)
 }
 
-/// Fetch water features from Overpass API
+/// Alternation of highway tag values for the street query
+fn highway_regex(road_types: &[HighwayType]) -> String {
+    road_types.iter().map(HighwayType::as_str).collect::<Vec<_>>().join("|")
+}
+
+/// Way tags (key, value) for water features
+const WATER_WAY_TAGS: &[(&str, &str)] = &[("natural", "water"), ("waterway", "riverbank")];
+
+/// Relation tags (key, value) for water features
+const WATER_RELATION_TAGS: &[(&str, &str)] = &[("natural", "water")];
+
+/// Way tags (key, value) for park features
+const PARK_WAY_TAGS: &[(&str, &str)] = &[("leisure", "park"), ("landuse", "grass"), ("landuse", "forest")];
+
+/// Relation tags (key, value) for park features
+const PARK_RELATION_TAGS: &[(&str, &str)] = &[("leisure", "park")];
+
+/// Way tags (key, value) for parking areas
+const PARKING_WAY_TAGS: &[(&str, &str)] = &[("amenity", "parking")];
+
+/// Relation tags (key, value) for parking areas
+const PARKING_RELATION_TAGS: &[(&str, &str)] = &[("amenity", "parking")];
+
+/// Way tags (key, value) for pitches and playgrounds
+const PITCH_WAY_TAGS: &[(&str, &str)] = &[("leisure", "pitch"), ("leisure", "playground")];
+
+/// Relation tags (key, value) for pitches and playgrounds
+const PITCH_RELATION_TAGS: &[(&str, &str)] = &[("leisure", "pitch")];
+
+/// Way and relation tag filters (key, value) for one area category
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaTags {
+    pub ways: Vec<(String, String)>,
+    pub relations: Vec<(String, String)>,
+}
+
+impl AreaTags {
+    fn from_tags(ways: &[(&str, &str)], relations: &[(&str, &str)]) -> Self {
+        let owned = |tags: &[(&str, &str)]| tags.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Self { ways: owned(ways), relations: owned(relations) }
+    }
+
+    /// Parse `key=value` filters, each matching ways and relations unless prefixed with
+    /// `way:` or `relation:`
+    pub fn parse(filters: &[String]) -> std::result::Result<Self, String> {
+        if filters.is_empty() {
+            return Err("at least one filter is required".to_string());
+        }
+        let mut tags = Self { ways: Vec::new(), relations: Vec::new() };
+        for filter in filters {
+            let (ways, relations, tag) = match filter.trim().split_once(':') {
+                Some(("way", tag)) => (true, false, tag),
+                Some(("relation", tag)) => (false, true, tag),
+                _ => (true, true, filter.trim()),
+            };
+            let (key, value) = tag
+                .split_once('=')
+                .filter(|(k, v)| is_tag_token(k) && is_tag_token(v))
+                .ok_or_else(|| format!("invalid filter '{}', expected key=value", filter))?;
+            let tag = (key.to_string(), value.to_string());
+            if ways && !tags.ways.contains(&tag) {
+                tags.ways.push(tag.clone());
+            }
+            if relations && !tags.relations.contains(&tag) {
+                tags.relations.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+
+    fn query(&self, center: (f64, f64), distance: u32) -> String {
+        area_query(center, distance, &borrow_tags(&self.ways), &borrow_tags(&self.relations))
+    }
+
+    /// These filters plus any `extra` ones not already present
+    pub fn merged(&self, extra: &AreaTags) -> Self {
+        let mut tags = self.clone();
+        for tag in &extra.ways {
+            if !tags.ways.contains(tag) {
+                tags.ways.push(tag.clone());
+            }
+        }
+        for tag in &extra.relations {
+            if !tags.relations.contains(tag) {
+                tags.relations.push(tag.clone());
+            }
+        }
+        tags
+    }
+
+    /// File-name-safe summary of the filters, e.g. `natural-wetland` for `natural=wetland`
+    fn slug(&self) -> String {
+        let mut tags: Vec<String> = self
+            .ways
+            .iter()
+            .chain(&self.relations)
+            .map(|(key, value)| format!("{}-{}", key, value).replace(':', "_"))
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags.join("-")
+    }
+
+    /// Whether a way carrying `tags` matches any of the way filters
+    fn matches_way(&self, tags: &HashMap<String, String>) -> bool {
+        self.ways.iter().any(|(key, value)| tags.get(key) == Some(value))
+    }
+}
+
+fn borrow_tags(tags: &[(String, String)]) -> Vec<(&str, &str)> {
+    tags.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+}
+
+/// OSM keys and values accepted in tag filters; anything else could break the query
+fn is_tag_token(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':'))
+}
+
+/// Tag filters for the configurable area categories (see `FEATURE_TAGS_FILE`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureTags {
+    pub water: AreaTags,
+    pub parks: AreaTags,
+    pub parking: AreaTags,
+    pub pitch: AreaTags,
+}
+
+impl Default for FeatureTags {
+    fn default() -> Self {
+        Self {
+            water: AreaTags::from_tags(WATER_WAY_TAGS, WATER_RELATION_TAGS),
+            parks: AreaTags::from_tags(PARK_WAY_TAGS, PARK_RELATION_TAGS),
+            parking: AreaTags::from_tags(PARKING_WAY_TAGS, PARKING_RELATION_TAGS),
+            pitch: AreaTags::from_tags(PITCH_WAY_TAGS, PITCH_RELATION_TAGS),
+        }
+    }
+}
+
+impl FeatureTags {
+    /// Filters of an optional detail area category
+    pub fn detail(&self, kind: DetailArea) -> &AreaTags {
+        match kind {
+            DetailArea::Parking => &self.parking,
+            DetailArea::Pitch => &self.pitch,
+        }
+    }
+
+    /// Read a JSON object mapping categories (`water`, `parks`, `parking`, `pitch`) to lists of filters
+    ///
+    /// Categories left out keep the built-in filters.
+    pub fn load(path: &std::path::Path) -> std::result::Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read feature tags file {:?}: {}", path, e))?;
+        Self::from_json(&contents).map_err(|e| format!("Invalid feature tags file {:?}: {}", path, e))
+    }
+
+    fn from_json(json: &str) -> std::result::Result<Self, String> {
+        let categories: HashMap<String, Vec<String>> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut tags = Self::default();
+        for (category, filters) in categories {
+            let parsed = AreaTags::parse(&filters).map_err(|e| format!("{}: {}", category, e))?;
+            match category.as_str() {
+                "water" => tags.water = parsed,
+                "parks" => tags.parks = parsed,
+                "parking" => tags.parking = parsed,
+                "pitch" => tags.pitch = parsed,
+                other => return Err(format!("unknown category '{}' (expected water, parks, parking or pitch)", other)),
+            }
+        }
+        Ok(tags)
+    }
+}
+
+/// Fetch water features from Overpass API, with a request's `extra` filters added to the configured ones
 pub async fn fetch_water(
     center: (f64, f64),
     distance: u32,
-    timeout_secs: f64,
-) -> Result<Vec<AreaFeature>> {
-    let query = format!(
-        r#"[out:json][timeout:60];
-(
-  way["natural"="water"](around:{},{},{});
-  way["waterway"="riverbank"](around:{},{},{});
-  relation["natural"="water"](around:{},{},{});
-);
-out body;
->;
-out skel qt;"#,
-        distance, center.0, center.1, distance, center.0, center.1, distance, center.0, center.1
-    );
-
-    let response = execute_overpass_query(&query, timeout_secs).await?;
+    extra: Option<&AreaTags>,
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
+    let (query, layer) = area_layer_query(&config.feature_tags.water, extra, "water", center, distance);
+    let response = run_overpass_query(&query, &offline_file_name(&layer, center, distance), on_download, config).await?;
     parse_area_features(&response, "water")
 }
 
-/// Fetch park features from Overpass API
+/// Fetch park features from Overpass API, with a request's `extra` filters added to the configured ones
 pub async fn fetch_parks(
     center: (f64, f64),
     distance: u32,
-    timeout_secs: f64,
-) -> Result<Vec<AreaFeature>> {
+    extra: Option<&AreaTags>,
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
+    let (query, layer) = area_layer_query(&config.feature_tags.parks, extra, "parks", center, distance);
+    let response = run_overpass_query(&query, &offline_file_name(&layer, center, distance), on_download, config).await?;
+    parse_area_features(&response, "park")
+}
+
+/// Query for an area layer and its offline layer name
+///
+/// Extra filters make a different query, so they get their own offline file.
+fn area_layer_query(
+    tags: &AreaTags,
+    extra: Option<&AreaTags>,
+    layer: &str,
+    center: (f64, f64),
+    distance: u32,
+) -> (String, String) {
+    match extra {
+        Some(extra) => (
+            tags.merged(extra).query(center, distance),
+            format!("{}-{}", layer, extra.slug()),
+        ),
+        None => (tags.query(center, distance), layer.to_string()),
+    }
+}
+
+/// Fetch the requested detail areas in one query, each feature typed by its category
+///
+/// A way matching several categories' filters takes the first requested one.
+pub async fn fetch_detail_areas(
+    center: (f64, f64),
+    distance: u32,
+    kinds: &[DetailArea],
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
+    let query = detail_area_query(&config.feature_tags, kinds, center, distance);
+
+    // Each combination of categories is a different query, so it gets its own offline file
+    let layer = std::iter::once("details")
+        .chain(kinds.iter().map(DetailArea::as_str))
+        .collect::<Vec<_>>()
+        .join("-");
+    let response = run_overpass_query(&query, &offline_file_name(&layer, center, distance), on_download, config).await?;
+    parse_area_features_with(&response, |element| {
+        let tags = element.tags.as_ref()?;
+        kinds
+            .iter()
+            .find(|kind| config.feature_tags.detail(**kind).matches_way(tags))
+            .map(|kind| kind.as_str().to_string())
+    })
+}
+
+/// One area query for all the requested detail categories, each shared filter asked once
+fn detail_area_query(tags: &FeatureTags, kinds: &[DetailArea], center: (f64, f64), distance: u32) -> String {
+    let (mut way_tags, mut relation_tags) = (Vec::new(), Vec::new());
+    for kind in kinds {
+        let tags = tags.detail(*kind);
+        way_tags.extend(borrow_tags(&tags.ways));
+        relation_tags.extend(borrow_tags(&tags.relations));
+    }
+    way_tags.sort_unstable();
+    way_tags.dedup();
+    relation_tags.sort_unstable();
+    relation_tags.dedup();
+    area_query(center, distance, &way_tags, &relation_tags)
+}
+
+/// Build an Overpass query for ways and relations carrying any of the given tags
+///
+/// The `around:` radius is evaluated once into a `.searchArea` set, prefiltered by a
+/// key/value regex so it stays small, and each exact tag filter then narrows that set
+/// instead of repeating the radius search. Relations live in a different set, so they
+/// are matched against the radius directly.
+fn area_query(
+    center: (f64, f64),
+    distance: u32,
+    way_tags: &[(&str, &str)],
+    relation_tags: &[(&str, &str)],
+) -> String {
+    let around = format!("(around:{},{},{})", distance, center.0, center.1);
+    let alternatives = |values: Vec<&str>| {
+        let mut values = values;
+        values.sort_unstable();
+        values.dedup();
+        values.join("|")
+    };
+    let keys = alternatives(way_tags.iter().map(|(k, _)| *k).collect());
+    let values = alternatives(way_tags.iter().map(|(_, v)| *v).collect());
+
+    let mut query = String::from("[out:json][timeout:60];\n");
+    if !way_tags.is_empty() {
+        query.push_str(&format!("way[~\"^({})$\"~\"^({})$\"]{}->.searchArea;\n", keys, values, around));
+    }
+    query.push_str("(\n");
+    for (key, value) in way_tags {
+        query.push_str(&format!("  way.searchArea[\"{}\"=\"{}\"];\n", key, value));
+    }
+    for (key, value) in relation_tags {
+        query.push_str(&format!("  relation[\"{}\"=\"{}\"]{};\n", key, value, around));
+    }
+    query.push_str(");\nout body;\n>;\nout skel qt;");
+    query
+}
+
+/// `place` values fetched for `PointKind::Place` (cities are left out, the poster names one)
+pub const PLACE_TYPES: &[&str] = &["town", "village", "hamlet", "suburb"];
+
+/// Fetch point features of the given kinds from Overpass API
+pub async fn fetch_points(
+    center: (f64, f64),
+    distance: u32,
+    kinds: &[PointKind],
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<(Vec<PointFeature>, LayerStats)> {
+    let around = format!("(around:{},{},{})", distance, center.0, center.1);
+    let mut query = String::from("[out:json][timeout:60];\n(\n");
+    for kind in kinds {
+        match kind {
+            PointKind::Peak => query.push_str(&format!("  node[\"natural\"=\"peak\"]{};\n", around)),
+            PointKind::Place => {
+                query.push_str(&format!("  node[\"place\"~\"^({})$\"]{};\n", PLACE_TYPES.join("|"), around))
+            }
+        }
+    }
+    query.push_str(");\nout body;");
+
+    // Each combination of kinds is a different query, so it gets its own offline file
+    let layer = std::iter::once("points")
+        .chain(kinds.iter().map(PointKind::as_str))
+        .collect::<Vec<_>>()
+        .join("-");
+    let response = run_overpass_query(&query, &offline_file_name(&layer, center, distance), on_download, config).await?;
+    Ok(parse_point_features(&response))
+}
+
+/// Landuse values fetched by `fetch_landuse`
+pub const LANDUSE_TYPES: &[&str] = &["residential", "industrial", "commercial", "retail"];
+
+/// Fetch landuse areas from Overpass API, tagged by their landuse value
+pub async fn fetch_landuse(
+    center: (f64, f64),
+    distance: u32,
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
     let query = format!(
         r#"[out:json][timeout:60];
 (
-  way["leisure"="park"](around:{},{},{});
-  way["landuse"="grass"](around:{},{},{});
-  way["landuse"="forest"](around:{},{},{});
-  relation["leisure"="park"](around:{},{},{});
+  way["landuse"~"^({})$"](around:{},{},{});
 );
 out body;
 >;
 out skel qt;"#,
-        distance, center.0, center.1, distance, center.0, center.1, distance, center.0, center.1,
+        LANDUSE_TYPES.join("|"),
         distance, center.0, center.1
     );
 
-    let response = execute_overpass_query(&query, timeout_secs).await?;
-    parse_area_features(&response, "park")
+    let response = run_overpass_query(&query, &offline_file_name("landuse", center, distance), on_download, config).await?;
+    parse_tagged_area_features(&response, "landuse")
+}
+
+/// Receives the fraction (0.0-1.0) of an Overpass response downloaded so far
+pub type DownloadProgress<'a> = dyn Fn(f32) + Send + Sync + 'a;
+
+/// File name of a layer's recorded Overpass response in `OFFLINE_DATA_DIR`
+///
+/// Named by layer and the south, west, north and east edges of the requested area (4
+/// decimals, about 11m), e.g. `water_45.2575_12.0786_45.6169_12.5906.json` for 20km
+/// around Venice.
+pub fn offline_file_name(layer: &str, center: (f64, f64), distance: u32) -> String {
+    let ((south, west), (north, east)) = distance_bounds(center, distance as f64);
+    format!("{}_{:.4}_{:.4}_{:.4}_{:.4}.json", layer, south, west, north, east)
 }
 
-/// Execute an Overpass API query with fallback to multiple mirrors
-async fn execute_overpass_query(query: &str, timeout_secs: f64) -> Result<OverpassResponse> {
+/// Run an Overpass query, honoring the offline data directory
+///
+/// With `OFFLINE_MODE` the response is read from `file_name` in `OFFLINE_DATA_DIR` and the
+/// network is never used. Otherwise, if `OFFLINE_DATA_DIR` is set, each fetched response
+/// is also saved there so the area can later be rendered offline.
+async fn run_overpass_query(
+    query: &str,
+    file_name: &str,
+    on_download: Option<&DownloadProgress<'_>>,
+    config: &Settings,
+) -> Result<OverpassResponse> {
+    let Some(dir) = &config.offline_data_dir else {
+        return execute_overpass_query(query, on_download, None, config).await;
+    };
+    let path = dir.join(file_name);
+
+    if !config.offline_mode {
+        return execute_overpass_query(query, on_download, Some(&path), config).await;
+    }
+
+    let body = tokio::fs::read(&path).await.map_err(|e| {
+        AppError::DataFetch(if e.kind() == std::io::ErrorKind::NotFound {
+            format!("Offline mode: no data for this area, expected {:?}", path)
+        } else {
+            format!("Offline mode: could not read {:?}: {}", path, e)
+        })
+    })?;
+    serde_json::from_slice(&body)
+        .map_err(|e| AppError::DataFetch(format!("Offline data file {:?} is not valid Overpass JSON: {}", path, e)))
+}
+
+/// Counts Overpass queries that failed on every mirror, refusing new ones for a while
+/// once too many fail in a row
+///
+/// After the cooldown the breaker is half-open: one trial query is let through while
+/// the rest are refused for another cooldown. The count is only cleared by a success,
+/// so a failed trial trips the breaker straight back.
+#[derive(Debug)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    const fn new() -> Self {
+        Self {
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// Time left until queries are allowed again, while the breaker is open
+    fn retry_after(&self, now: Instant) -> Option<Duration> {
+        self.open_until.filter(|&until| now < until).map(|until| until - now)
+    }
+
+    /// Let a query through, or say how long until one may go
+    ///
+    /// The first query after the cooldown is the half-open trial; it holds the breaker
+    /// open for everyone else until it is recorded or another cooldown passes.
+    fn acquire(&mut self, cooldown: Duration, now: Instant) -> std::result::Result<(), Duration> {
+        match self.open_until {
+            Some(until) if now < until => Err(until - now),
+            Some(_) => {
+                self.open_until = Some(now + cooldown);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record a query's outcome; true when this failure tripped the breaker
+    fn record(&mut self, success: bool, threshold: u32, cooldown: Duration, now: Instant) -> bool {
+        if success {
+            self.consecutive_failures = 0;
+            self.open_until = None;
+            return false;
+        }
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        let tripped = threshold > 0 && self.consecutive_failures >= threshold;
+        if tripped {
+            self.open_until = Some(now + cooldown);
+        }
+        tripped
+    }
+}
+
+/// Shared by every job, since an outage affects them all alike
+static OVERPASS_BREAKER: Mutex<CircuitBreaker> = parking_lot::const_mutex(CircuitBreaker::new());
+
+/// Fail fast with `AppError::MapServiceUnavailable` while Overpass is known to be down
+///
+/// Always passes in offline mode, which never queries Overpass. Only looks at the
+/// breaker, so checking doesn't use up the half-open trial.
+pub fn check_overpass_available(config: &Settings) -> Result<()> {
+    if config.offline_mode || config.overpass_breaker_threshold == 0 {
+        return Ok(());
+    }
+    match OVERPASS_BREAKER.lock().retry_after(Instant::now()) {
+        Some(wait) => Err(overpass_unavailable(wait)),
+        None => Ok(()),
+    }
+}
+
+fn overpass_unavailable(wait: Duration) -> AppError {
+    AppError::MapServiceUnavailable(format!(
+        "every Overpass mirror is failing, try again in {} seconds",
+        wait.as_secs().max(1)
+    ))
+}
+
+/// Check that an Overpass mirror answers, with a query for an empty bounding box
+///
+/// Passes without a request in offline mode. Bypasses the circuit breaker, so a probe
+/// neither waits out an open breaker nor counts towards tripping it.
+pub async fn probe_overpass(config: &Settings) -> Result<()> {
+    if config.offline_mode {
+        return Ok(());
+    }
+    query_overpass_mirrors("[out:json][timeout:10];node(0,0,0,0);out;", None, None, config)
+        .await
+        .map(|_| ())
+        .map_err(AppError::from)
+}
+
+/// Execute an Overpass API query with fallback to multiple mirrors, through the breaker
+///
+/// Only outages count towards tripping the breaker; a query every mirror rejects, or
+/// whose response can't be parsed, says nothing about whether Overpass is up.
+async fn execute_overpass_query(
+    query: &str,
+    on_download: Option<&DownloadProgress<'_>>,
+    record_to: Option<&std::path::Path>,
+    config: &Settings,
+) -> Result<OverpassResponse> {
+    let breaker_enabled = !config.offline_mode && config.overpass_breaker_threshold > 0;
+    let cooldown = Duration::from_secs(config.overpass_breaker_cooldown_secs);
+    if breaker_enabled {
+        OVERPASS_BREAKER.lock().acquire(cooldown, Instant::now()).map_err(overpass_unavailable)?;
+    }
+    let result = query_overpass_mirrors(query, on_download, record_to, config).await;
+
+    let outage = matches!(&result, Err(e) if e.outage);
+    let tripped = OVERPASS_BREAKER
+        .lock()
+        .record(!outage, config.overpass_breaker_threshold, cooldown, Instant::now());
+    if tripped {
+        tracing::error!(
+            "{} Overpass queries in a row failed on every mirror, refusing map fetches for {} seconds",
+            config.overpass_breaker_threshold,
+            cooldown.as_secs()
+        );
+    }
+    result.map_err(AppError::from)
+}
+
+/// Why a query failed on every Overpass mirror
+#[derive(Debug)]
+struct MirrorsFailed {
+    message: String,
+    /// Every mirror was unreachable, timed out or answered 429 or 5xx
+    outage: bool,
+}
+
+impl From<MirrorsFailed> for AppError {
+    fn from(failed: MirrorsFailed) -> Self {
+        AppError::DataFetch(failed.message)
+    }
+}
+
+/// Whether an HTTP status means the mirror is overloaded or down, rather than
+/// rejecting the query
+fn is_outage_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Try each Overpass mirror in turn until one answers
+///
+/// The body is read chunk by chunk; when the mirror sends `Content-Length`, `on_download`
+/// is called each time another whole percent has arrived. A mirror retried after another
+/// failed mid-download only reports once it passes the furthest point already reported,
+/// so progress never goes backwards. A successful response is also written to `record_to`
+/// when given.
+async fn query_overpass_mirrors(
+    query: &str,
+    on_download: Option<&DownloadProgress<'_>>,
+    record_to: Option<&std::path::Path>,
+    config: &Settings,
+) -> std::result::Result<OverpassResponse, MirrorsFailed> {
     let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs_f64(timeout_secs))
-        .user_agent(USER_AGENT)
-        .build()?;
+        .timeout(std::time::Duration::from_secs_f64(config.osm_timeout))
+        .user_agent(config.user_agent_header())
+        .build()
+        .map_err(|e| MirrorsFailed {
+            message: format!("Failed to create HTTP client: {}", e),
+            outage: false,
+        })?;
 
     let mut last_error = None;
+    // Cleared as soon as any mirror answers, even if only to reject the query
+    let mut outage = true;
+    let mut reported = DownloadPercent::default();
 
     for (i, mirror) in OVERPASS_MIRRORS.iter().enumerate() {
         tracing::debug!("Trying Overpass mirror {}: {}", i + 1, mirror);
@@ -215,19 +892,34 @@ async fn execute_overpass_query(query: &str, timeout_secs: f64) -> Result<Overpa
         {
             Ok(response) => {
                 if response.status().is_success() {
-                    match response.json::<OverpassResponse>().await {
+                    let body = match read_body(response, on_download, &mut reported).await {
+                        Ok(body) => body,
+                        Err(e) => {
+                            last_error = Some(format!("Failed to read response from {}: {}", mirror, e));
+                            tracing::warn!("Mirror {} failed mid-download: {}", mirror, e);
+                            continue;
+                        }
+                    };
+                    match serde_json::from_slice::<OverpassResponse>(&body) {
                         Ok(data) => {
                             if i > 0 {
                                 tracing::info!("Successfully used fallback mirror: {}", mirror);
                             }
+                            if let Some(path) = record_to {
+                                if let Err(e) = write_file_atomically(path, &body).await {
+                                    tracing::warn!("Could not save offline data to {:?}: {}", path, e);
+                                }
+                            }
                             return Ok(data);
                         }
                         Err(e) => {
                             last_error = Some(format!("Failed to parse response from {}: {}", mirror, e));
+                            outage = false;
                         }
                     }
                 } else {
                     last_error = Some(format!("Overpass API error from {}: {}", mirror, response.status()));
+                    outage &= is_outage_status(response.status());
                 }
             }
             Err(e) => {
@@ -237,13 +929,51 @@ async fn execute_overpass_query(query: &str, timeout_secs: f64) -> Result<Overpa
         }
     }
 
-    Err(AppError::DataFetch(
-        last_error.unwrap_or_else(|| "All Overpass mirrors failed".to_string())
-    ))
+    Err(MirrorsFailed {
+        message: last_error.unwrap_or_else(|| "All Overpass mirrors failed".to_string()),
+        outage,
+    })
+}
+
+/// Read a response body, reporting download progress past `reported` when its length is known
+async fn read_body(
+    mut response: reqwest::Response,
+    on_download: Option<&DownloadProgress<'_>>,
+    reported: &mut DownloadPercent,
+) -> std::result::Result<Vec<u8>, reqwest::Error> {
+    let total = response.content_length().filter(|&len| len > 0);
+    let mut body = Vec::with_capacity(total.unwrap_or(0).min(64 * 1024 * 1024) as usize);
+
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if let (Some(cb), Some(total)) = (on_download, total) {
+            if let Some(fraction) = reported.advance(body.len() as u64, total) {
+                cb(fraction);
+            }
+        }
+    }
+
+    Ok(body)
+}
+
+/// Tracks the last whole percent reported so progress isn't sent for every chunk
+#[derive(Debug, Default)]
+struct DownloadPercent(u32);
+
+impl DownloadPercent {
+    /// The new fraction downloaded, if it crossed into a higher whole percent
+    fn advance(&mut self, received: u64, total: u64) -> Option<f32> {
+        let percent = (received.min(total) * 100 / total) as u32;
+        if percent <= self.0 {
+            return None;
+        }
+        self.0 = percent;
+        Some(percent as f32 / 100.0)
+    }
 }
 
 /// Parse road segments from Overpass response
-fn parse_road_segments(response: &OverpassResponse) -> Result<Vec<RoadSegment>> {
+fn parse_road_segments(response: &OverpassResponse) -> Result<(Vec<RoadSegment>, LayerStats)> {
     // Build node lookup table
     let mut nodes: HashMap<i64, (f64, f64)> = HashMap::new();
     for element in &response.elements {
@@ -256,36 +986,102 @@ fn parse_road_segments(response: &OverpassResponse) -> Result<Vec<RoadSegment>>
 
     // Parse ways into road segments
     let mut segments = Vec::new();
+    let mut stats = LayerStats {
+        osm_timestamp: response.osm_timestamp(),
+        ..Default::default()
+    };
     for element in &response.elements {
-        if element.element_type == "way" {
+        if element.element_type == "relation" {
+            stats.skipped_relations += 1;
+        } else if element.element_type == "way" {
             if let Some(node_ids) = &element.nodes {
                 let points: Vec<(f64, f64)> = node_ids
                     .iter()
                     .filter_map(|id| nodes.get(id).copied())
                     .collect();
 
-                if points.len() >= 2 {
-                    let highway_type = element
-                        .tags
-                        .as_ref()
+                if points.len() < 2 {
+                    stats.dropped_ways += 1;
+                } else {
+                    if points.len() < node_ids.len() {
+                        stats.partial_ways += 1;
+                    }
+
+                    let tags = element.tags.as_ref();
+                    let highway_type = tags
                         .and_then(|t| t.get("highway"))
                         .map(|s| HighwayType::from_str(s))
                         .unwrap_or(HighwayType::Default);
 
+                    // oneway=-1 means traffic runs against the way direction
+                    let mut points = points;
+                    let oneway = match tags.and_then(|t| t.get("oneway")).map(String::as_str) {
+                        Some("yes") | Some("true") | Some("1") => true,
+                        Some("-1") | Some("reverse") => {
+                            points.reverse();
+                            true
+                        }
+                        _ => false,
+                    };
+
                     segments.push(RoadSegment {
+                        id: element.id,
                         points,
                         highway_type,
+                        oneway,
+                        lanes: tags.and_then(|t| t.get("lanes")).and_then(|s| parse_lanes(s)),
+                        maxspeed: tags.and_then(|t| t.get("maxspeed")).and_then(|s| parse_maxspeed(s)),
                     });
                 }
             }
         }
     }
 
-    Ok(segments)
+    stats.kept = segments.len();
+    Ok((segments, stats))
+}
+
+/// Lane count from a `lanes` tag; for lists such as `2;3` the first value is used
+fn parse_lanes(value: &str) -> Option<u32> {
+    value.split(';').next()?.trim().parse().ok().filter(|&lanes| lanes > 0)
+}
+
+/// Speed limit in km/h from a `maxspeed` tag such as `50`, `30 mph` or `50;30`
+///
+/// Symbolic values (`none`, `walk`, `RU:urban`) have no number and are ignored.
+fn parse_maxspeed(value: &str) -> Option<u32> {
+    let value = value.split(';').next()?.trim();
+    let (number, mph) = match value.strip_suffix("mph") {
+        Some(number) => (number.trim(), true),
+        None => (value, false),
+    };
+    let speed: f64 = number.parse().ok().filter(|&speed: &f64| speed > 0.0)?;
+    Some(if mph { (speed * 1.609_344).round() as u32 } else { speed.round() as u32 })
 }
 
 /// Parse area features from Overpass response
-fn parse_area_features(response: &OverpassResponse, feature_type: &str) -> Result<Vec<AreaFeature>> {
+fn parse_area_features(
+    response: &OverpassResponse,
+    feature_type: &str,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
+    parse_area_features_with(response, |_| Some(feature_type.to_string()))
+}
+
+/// Parse area features, using the value of `tag_key` on each way as its feature type
+fn parse_tagged_area_features(
+    response: &OverpassResponse,
+    tag_key: &str,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
+    parse_area_features_with(response, |element| {
+        element.tags.as_ref().and_then(|t| t.get(tag_key)).cloned()
+    })
+}
+
+/// Parse closed ways into area features, classifying each with `feature_type_of`
+fn parse_area_features_with(
+    response: &OverpassResponse,
+    feature_type_of: impl Fn(&OverpassElement) -> Option<String>,
+) -> Result<(Vec<AreaFeature>, LayerStats)> {
     // Build node lookup table
     let mut nodes: HashMap<i64, (f64, f64)> = HashMap::new();
     for element in &response.elements {
@@ -296,48 +1092,661 @@ fn parse_area_features(response: &OverpassResponse, feature_type: &str) -> Resul
         }
     }
 
-    // Parse ways into area features
+    // Parse ways into area features. Untyped ways (e.g. untagged multipolygon
+    // members pulled in by `>;`) are not counted as losses.
     let mut features = Vec::new();
+    let mut stats = LayerStats {
+        osm_timestamp: response.osm_timestamp(),
+        ..Default::default()
+    };
     for element in &response.elements {
-        if element.element_type == "way" {
+        if element.element_type == "relation" {
+            stats.skipped_relations += 1;
+        } else if element.element_type == "way" {
             if let Some(node_ids) = &element.nodes {
+                let Some(feature_type) = feature_type_of(element) else {
+                    continue;
+                };
                 let points: Vec<(f64, f64)> = node_ids
                     .iter()
                     .filter_map(|id| nodes.get(id).copied())
                     .collect();
 
-                if points.len() >= 3 {
-                    features.push(AreaFeature {
-                        points,
-                        feature_type: feature_type.to_string(),
-                    });
+                if points.len() < 3 {
+                    stats.dropped_ways += 1;
+                    continue;
                 }
+                if points.len() < node_ids.len() {
+                    stats.partial_ways += 1;
+                }
+                features.push(AreaFeature {
+                    id: element.id,
+                    points,
+                    feature_type,
+                });
             }
         }
     }
 
-    Ok(features)
+    stats.kept = features.len();
+    Ok((features, stats))
+}
+
+/// Parse tagged nodes into point features
+fn parse_point_features(response: &OverpassResponse) -> (Vec<PointFeature>, LayerStats) {
+    let features: Vec<PointFeature> = response
+        .elements
+        .iter()
+        .filter(|element| element.element_type == "node")
+        .filter_map(|element| {
+            let tags = element.tags.as_ref()?;
+            let kind = if tags.get("natural").map(String::as_str) == Some("peak") {
+                PointKind::Peak
+            } else if tags.get("place").is_some_and(|place| PLACE_TYPES.contains(&place.as_str())) {
+                PointKind::Place
+            } else {
+                return None;
+            };
+            Some(PointFeature {
+                lat: element.lat?,
+                lon: element.lon?,
+                name: tags.get("name").cloned(),
+                kind,
+            })
+        })
+        .collect();
+
+    let stats = LayerStats {
+        kept: features.len(),
+        osm_timestamp: response.osm_timestamp(),
+        ..Default::default()
+    };
+    (features, stats)
+}
+
+/// Mean meters per degree of latitude
+pub const METERS_PER_DEGREE_LAT: f64 = 111_320.0;
+
+/// Furthest latitude a map may reach
+///
+/// The projection scales longitude by `cos(lat)`, which collapses towards the poles, and
+/// a box reaching past ±90° has no sensible shape at all.
+pub const MAX_MAP_LATITUDE: f64 = 85.0;
+
+/// Reject maps whose box would reach beyond `MAX_MAP_LATITUDE`
+pub fn check_map_extent(center: (f64, f64), distance: f64) -> Result<()> {
+    let (lat, _) = center;
+    let lat_delta = distance / METERS_PER_DEGREE_LAT;
+    if lat.abs() + lat_delta > MAX_MAP_LATITUDE {
+        return Err(AppError::InvalidRequest(format!(
+            "A {}m map around latitude {:.4} reaches past {}° {}; locations this close to the pole can't be rendered",
+            distance,
+            lat,
+            MAX_MAP_LATITUDE,
+            if lat >= 0.0 { "N" } else { "S" }
+        )));
+    }
+    Ok(())
+}
+
+/// Signed longitude difference `lon - from`, taking the short way around the antimeridian
+///
+/// The result is in [-180, 180), so 179.9 and -179.9 are 0.2° apart rather than 359.8°.
+pub fn longitude_delta(lon: f64, from: f64) -> f64 {
+    (lon - from + 180.0).rem_euclid(360.0) - 180.0
 }
 
+/// Mean Earth radius in meters
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Great-circle distance in meters between two (lat, lon) points (haversine formula)
+pub fn geodesic_distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = longitude_delta(b.1, a.1).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * h.sqrt().min(1.0).asin()
+}
+
+/// Bounding box covering `distance` meters in every direction from a center point
+///
+/// Longitude degrees shrink with `cos(lat)`, so the box is wider in degrees away from
+/// the equator but spans the same ground distance everywhere. Near the antimeridian the
+/// box's longitudes run past ±180 rather than wrapping, so `min_lon < max_lon` always holds.
+pub fn distance_bounds(center: (f64, f64), distance: f64) -> ((f64, f64), (f64, f64)) {
+    let (lat, lon) = center;
+    let lat_delta = distance / METERS_PER_DEGREE_LAT;
+    // Guard against the poles where cos(lat) approaches zero
+    let lon_scale = lat.to_radians().cos().max(1e-6);
+    let lon_delta = distance / (METERS_PER_DEGREE_LAT * lon_scale);
+
+    ((lat - lat_delta, lon - lon_delta), (lat + lat_delta, lon + lon_delta))
+}
+
+/// Smallest extent of `calculate_bounds` along either axis, in meters
+pub const MIN_BOUNDS_SPAN_METERS: f64 = 1000.0;
+
 /// Calculate bounding box from road segments
+///
+/// Roads straddling the antimeridian get a box that runs past ±180° (e.g. 179.5 to 180.5)
+/// instead of one spanning the whole globe. A box narrower than `MIN_BOUNDS_SPAN_METERS`
+/// (a single point, or one straight road in a tiny village) is widened to that span around
+/// its center, so it can't be scaled up without limit.
 pub fn calculate_bounds(segments: &[RoadSegment]) -> Option<((f64, f64), (f64, f64))> {
-    if segments.is_empty() {
-        return None;
-    }
+    let (first_lat, first_lon) = *segments.iter().flat_map(|s| s.points.first()).next()?;
 
-    let mut min_lat = f64::MAX;
-    let mut max_lat = f64::MIN;
-    let mut min_lon = f64::MAX;
-    let mut max_lon = f64::MIN;
+    let mut min_lat = first_lat;
+    let mut max_lat = first_lat;
+    // Longitudes are measured from the first point so the box never wraps
+    let mut min_lon: f64 = 0.0;
+    let mut max_lon: f64 = 0.0;
 
     for segment in segments {
         for (lat, lon) in &segment.points {
+            let lon = longitude_delta(*lon, first_lon);
             min_lat = min_lat.min(*lat);
             max_lat = max_lat.max(*lat);
-            min_lon = min_lon.min(*lon);
-            max_lon = max_lon.max(*lon);
+            min_lon = min_lon.min(lon);
+            max_lon = max_lon.max(lon);
+        }
+    }
+
+    let (min_lon, max_lon) = (first_lon + min_lon, first_lon + max_lon);
+    let center = ((min_lat + max_lat) / 2.0, (min_lon + max_lon) / 2.0);
+    let ((floor_min_lat, floor_min_lon), (floor_max_lat, floor_max_lon)) = distance_bounds(center, MIN_BOUNDS_SPAN_METERS / 2.0);
+    Some((
+        (min_lat.min(floor_min_lat), min_lon.min(floor_min_lon)),
+        (max_lat.max(floor_max_lat), max_lon.max(floor_max_lon)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_bounds_accounts_for_latitude() {
+        let ((min_lat, min_lon), (max_lat, max_lon)) = distance_bounds((0.0, 10.0), 1113.2);
+        assert!((max_lat - min_lat - 0.02).abs() < 1e-9);
+        assert!((max_lon - min_lon - 0.02).abs() < 1e-9);
+
+        // At 60 degrees a degree of longitude is half as long, so the box is twice as wide
+        let ((min_lat, min_lon), (max_lat, max_lon)) = distance_bounds((60.0, 10.0), 1113.2);
+        assert!((max_lat - min_lat - 0.02).abs() < 1e-9);
+        assert!((max_lon - min_lon - 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bounds_across_the_antimeridian() {
+        assert!((longitude_delta(-179.9, 179.9) - 0.2).abs() < 1e-9);
+        assert!((longitude_delta(179.9, -179.9) + 0.2).abs() < 1e-9);
+        assert!((longitude_delta(10.0, 20.0) + 10.0).abs() < 1e-9);
+
+        // Taveuni, Fiji: roads on both sides of 180°
+        let road = |points: Vec<(f64, f64)>| RoadSegment {
+            id: 1,
+            points,
+            highway_type: HighwayType::Residential,
+            oneway: false,
+            lanes: None,
+            maxspeed: None,
+        };
+        let segments = vec![
+            road(vec![(-16.80, 179.95), (-16.81, 179.99)]),
+            road(vec![(-16.82, -179.98), (-16.83, -179.90)]),
+        ];
+        let ((min_lat, min_lon), (max_lat, max_lon)) = calculate_bounds(&segments).unwrap();
+        assert!((min_lat + 16.83).abs() < 1e-9 && (max_lat + 16.80).abs() < 1e-9);
+        assert!((min_lon - 179.95).abs() < 1e-9);
+        assert!((max_lon - 180.10).abs() < 1e-9);
+
+        // The distance box keeps its longitudes in order past 180°
+        let ((_, min_lon), (_, max_lon)) = distance_bounds((-16.8, 179.99), 5000.0);
+        assert!(min_lon < 179.99 && max_lon > 180.0);
+    }
+
+    #[test]
+    fn test_degenerate_bounds_get_a_minimum_span() {
+        let road = |points: Vec<(f64, f64)>| RoadSegment {
+            id: 1,
+            points,
+            highway_type: HighwayType::Residential,
+            oneway: false,
+            lanes: None,
+            maxspeed: None,
+        };
+        let min_lat_span = MIN_BOUNDS_SPAN_METERS / METERS_PER_DEGREE_LAT;
+
+        // One east-west road: no latitude extent at all
+        let ((min_lat, min_lon), (max_lat, max_lon)) = calculate_bounds(&[road(vec![(48.0, 2.0), (48.0, 2.001)])]).unwrap();
+        assert!((max_lat - min_lat - min_lat_span).abs() < 1e-9);
+        assert!(((min_lat + max_lat) / 2.0 - 48.0).abs() < 1e-9);
+        // Longitude is widened too, since 0.001° is far less than the minimum
+        let lon_span = min_lat_span / 48f64.to_radians().cos();
+        assert!((max_lon - min_lon - lon_span).abs() < 1e-9);
+
+        // A single point still gives a usable map transform
+        let bounds = calculate_bounds(&[road(vec![(10.0, 20.0)])]).unwrap();
+        let mut canvas = crate::rendering::canvas::Canvas::new(100, 100).unwrap();
+        canvas.set_geo_transform(bounds);
+        let (x, y) = canvas.geo_to_screen(10.0, 20.0);
+        assert!((x - 50.0).abs() < 1e-3 && (y - 50.0).abs() < 1e-3);
+        assert!(canvas.geo_to_screen(10.001, 20.001).0.is_finite());
+    }
+
+    #[test]
+    fn test_geodesic_distance() {
+        // One degree of latitude, and of longitude on the equator
+        assert!((geodesic_distance((0.0, 0.0), (1.0, 0.0)) - 111_195.0).abs() < 1.0);
+        assert!((geodesic_distance((0.0, 179.5), (0.0, -179.5)) - 111_195.0).abs() < 1.0);
+        // Paris to London, about 344 km
+        assert!((geodesic_distance((48.8566, 2.3522), (51.5074, -0.1278)) / 1000.0 - 343.6).abs() < 1.0);
+        assert_eq!(geodesic_distance((45.0, 7.0), (45.0, 7.0)), 0.0);
+    }
+
+    #[test]
+    fn test_map_extent_near_the_poles() {
+        // Longyearbyen, Svalbard at 78°N is fine, as is McMurdo Station at 77.8°S
+        assert!(check_map_extent((78.22, 15.65), 20_000.0).is_ok());
+        assert!(check_map_extent((-77.85, 166.67), 20_000.0).is_ok());
+
+        // Alert, Nunavut at 82.5°N with a 300 km radius reaches past 85°N
+        let err = check_map_extent((82.5, -62.35), 300_000.0).unwrap_err();
+        assert!(matches!(err, AppError::InvalidRequest(_)));
+        assert!(err.to_string().contains("85° N"));
+        assert!(check_map_extent((-89.99, 0.0), 1_000.0).unwrap_err().to_string().contains("85° S"));
+    }
+
+    #[test]
+    fn test_offline_file_name() {
+        assert_eq!(
+            offline_file_name("water", (45.4372, 12.3346), 20000),
+            "water_45.2575_12.0786_45.6169_12.5906.json"
+        );
+    }
+
+    #[test]
+    fn test_download_percent_reports_whole_percent_steps() {
+        let mut reported = DownloadPercent::default();
+        assert_eq!(reported.advance(5, 1000), None);
+        assert_eq!(reported.advance(10, 1000), Some(0.01));
+        assert_eq!(reported.advance(19, 1000), None);
+        assert_eq!(reported.advance(500, 1000), Some(0.5));
+        // A retry on another mirror starts over, but is only reported once it gets further
+        assert_eq!(reported.advance(200, 2000), None);
+        assert_eq!(reported.advance(1000, 2000), None);
+        assert_eq!(reported.advance(1200, 2000), Some(0.6));
+        // Servers can send more than they announced; never report past 100%
+        assert_eq!(reported.advance(1200, 1000), Some(1.0));
+        assert_eq!(reported.advance(1300, 1000), None);
+    }
+
+    #[test]
+    fn test_parse_counts_dropped_and_partial_ways() {
+        let response: OverpassResponse = serde_json::from_value(serde_json::json!({
+            "elements": [
+                {"type": "node", "id": 1, "lat": 0.0, "lon": 0.0},
+                {"type": "node", "id": 2, "lat": 0.0, "lon": 1.0},
+                {"type": "node", "id": 3, "lat": 1.0, "lon": 1.0},
+                {"type": "way", "id": 10, "nodes": [1, 2, 3, 1], "tags": {"highway": "primary"}},
+                {"type": "way", "id": 11, "nodes": [1, 2, 99], "tags": {"highway": "primary"}},
+                {"type": "way", "id": 12, "nodes": [1, 98, 99], "tags": {"highway": "primary"}},
+                {"type": "relation", "id": 20, "tags": {"natural": "water"}}
+            ],
+            "osm3s": {"timestamp_osm_base": "2024-05-17T13:45:00Z", "copyright": "The data included in this document is from www.openstreetmap.org."}
+        }))
+        .unwrap();
+
+        let (roads, stats) = parse_road_segments(&response).unwrap();
+        assert_eq!(roads.len(), 2);
+        assert_eq!(
+            stats,
+            LayerStats { kept: 2, dropped_ways: 1, partial_ways: 1, skipped_relations: 1, fetch_failed: false, osm_timestamp: "2024-05-17T13:45:00Z".parse().ok() }
+        );
+
+        // Areas need three resolved points, so the partial way is dropped too
+        let (areas, stats) = parse_area_features(&response, "water").unwrap();
+        assert_eq!(areas.len(), 1);
+        assert_eq!(stats.dropped_ways, 2);
+        assert_eq!(stats.partial_ways, 0);
+        assert_eq!(stats.skipped_relations, 1);
+    }
+
+    #[test]
+    fn test_parse_lanes_and_maxspeed() {
+        assert_eq!(parse_lanes("4"), Some(4));
+        assert_eq!(parse_lanes("2; 3"), Some(2));
+        assert_eq!(parse_lanes("0"), None);
+        assert_eq!(parse_lanes("many"), None);
+
+        assert_eq!(parse_maxspeed("50"), Some(50));
+        assert_eq!(parse_maxspeed("30 mph"), Some(48));
+        assert_eq!(parse_maxspeed("70mph"), Some(113));
+        assert_eq!(parse_maxspeed("50;30"), Some(50));
+        for symbolic in ["none", "walk", "RU:urban", "signals", ""] {
+            assert_eq!(parse_maxspeed(symbolic), None, "{:?}", symbolic);
+        }
+    }
+
+    #[test]
+    fn test_parse_point_features() {
+        let response: OverpassResponse = serde_json::from_value(serde_json::json!({
+            "elements": [
+                {"type": "node", "id": 1, "lat": 45.83, "lon": 6.86, "tags": {"natural": "peak", "name": "Mont Blanc", "ele": "4806"}},
+                {"type": "node", "id": 2, "lat": 45.92, "lon": 6.87, "tags": {"place": "town", "name": "Chamonix"}},
+                {"type": "node", "id": 3, "lat": 45.90, "lon": 6.90, "tags": {"natural": "peak"}},
+                {"type": "node", "id": 4, "lat": 45.91, "lon": 6.91, "tags": {"place": "city", "name": "Geneva"}},
+                {"type": "node", "id": 5, "lat": 45.91, "lon": 6.91}
+            ]
+        }))
+        .unwrap();
+
+        let (points, stats) = parse_point_features(&response);
+        assert_eq!(stats.kept, 3);
+        assert_eq!(
+            points[0],
+            PointFeature { lat: 45.83, lon: 6.86, name: Some("Mont Blanc".to_string()), kind: PointKind::Peak }
+        );
+        assert_eq!((points[1].kind, points[1].name.as_deref()), (PointKind::Place, Some("Chamonix")));
+        // Unnamed peaks still get a marker
+        assert_eq!((points[2].kind, points[2].name.as_deref()), (PointKind::Peak, None));
+    }
+
+    /// Response to a radius query, containing matching ways and near misses
+    const AREA_FIXTURE: &str = include_str!("../../tests/fixtures/overpass_areas.json");
+
+    /// The water and park queries as sent before `.searchArea`: one `around:` per tag
+    fn legacy_area_query(
+        center: (f64, f64),
+        distance: u32,
+        way_tags: &[(&str, &str)],
+        relation_tags: &[(&str, &str)],
+    ) -> String {
+        let around = format!("(around:{},{},{})", distance, center.0, center.1);
+        let mut query = String::from("[out:json][timeout:60];\n(\n");
+        for (key, value) in way_tags {
+            query.push_str(&format!("  way[\"{}\"=\"{}\"]{};\n", key, value, around));
+        }
+        for (key, value) in relation_tags {
+            query.push_str(&format!("  relation[\"{}\"=\"{}\"]{};\n", key, value, around));
+        }
+        query.push_str(");\nout body;\n>;\nout skel qt;");
+        query
+    }
+
+    /// Run the subset of Overpass QL the area queries use against recorded elements
+    ///
+    /// Understands `way`/`relation` statements with `["k"="v"]`, `[~"^(k|..)$"~"^(v|..)$"]`
+    /// and `(around:d,lat,lon)` filters, reading from and saving to named sets, inside one
+    /// union followed by `out body; >; out skel qt;`. A way is around the center when one
+    /// of its nodes is; the fixture's relations carry no geometry, so they always are.
+    fn run_area_query(query: &str, recorded: &OverpassResponse) -> OverpassResponse {
+        let nodes: HashMap<i64, (f64, f64)> =
+            recorded.elements.iter().filter_map(|e| Some((e.id, (e.lat?, e.lon?)))).collect();
+        let has_tag = |element: &OverpassElement, keys: &[&str], values: &[&str]| {
+            element.tags.iter().flatten().any(|(k, v)| keys.contains(&k.as_str()) && values.contains(&v.as_str()))
+        };
+        let is_around = |element: &OverpassElement, args: &str| {
+            let args: Vec<f64> = args.split(',').map(|n| n.parse().unwrap()).collect();
+            element.element_type == "relation"
+                || element
+                    .nodes
+                    .iter()
+                    .flatten()
+                    .filter_map(|id| nodes.get(id))
+                    .any(|&node| geodesic_distance(node, (args[1], args[2])) <= args[0])
+        };
+        // `^(a|b)$` -> ["a", "b"]
+        let alternatives = |pattern: &str| -> Vec<String> {
+            let pattern = pattern.trim_matches('"').strip_prefix("^(").unwrap().strip_suffix(")$").unwrap();
+            pattern.split('|').map(str::to_string).collect()
+        };
+
+        let mut sets: HashMap<&str, Vec<&OverpassElement>> = HashMap::new();
+        let mut union: Vec<&OverpassElement> = Vec::new();
+        for statement in query.lines().map(str::trim) {
+            if statement.starts_with("[out:") || matches!(statement, "(" | ");" | "out body;" | ">;" | "out skel qt;") {
+                continue;
+            }
+            let statement = statement.strip_suffix(';').unwrap();
+            let (statement, into) = match statement.split_once("->.") {
+                Some((statement, set)) => (statement, Some(set)),
+                None => (statement, None),
+            };
+            let (source, mut filters) = statement.split_at(statement.find(['[', '(']).unwrap());
+            let (element_type, from) = match source.split_once('.') {
+                Some((element_type, set)) => (element_type, Some(set)),
+                None => (source, None),
+            };
+
+            let mut selected: Vec<&OverpassElement> = match from {
+                Some(set) => sets[set].clone(),
+                None => recorded.elements.iter().filter(|e| e.element_type == element_type).collect(),
+            };
+            while !filters.is_empty() {
+                let end = filters.find(if filters.starts_with('[') { ']' } else { ')' }).unwrap();
+                let filter = &filters[1..end];
+                filters = &filters[end + 1..];
+
+                if let Some(args) = filter.strip_prefix("around:") {
+                    selected.retain(|e| is_around(e, args));
+                } else if let Some((keys, values)) = filter.strip_prefix('~').and_then(|f| f.split_once('~')) {
+                    let (keys, values) = (alternatives(keys), alternatives(values));
+                    let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+                    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+                    selected.retain(|e| has_tag(e, &keys, &values));
+                } else {
+                    let (key, value) = filter.split_once('=').unwrap();
+                    selected.retain(|e| has_tag(e, &[key.trim_matches('"')], &[value.trim_matches('"')]));
+                }
+            }
+
+            match into {
+                Some(set) => {
+                    sets.insert(set, selected);
+                }
+                None => union.extend(selected),
+            }
+        }
+
+        // `out body` prints the union by type and id, then `>` adds the ways' nodes
+        let order = |e: &OverpassElement| (e.element_type != "way", e.id);
+        union.sort_by_key(|e| order(e));
+        union.dedup_by_key(|e| order(e));
+        let node_ids: Vec<i64> = union.iter().flat_map(|e| e.nodes.iter().flatten().copied()).collect();
+        let members = recorded.elements.iter().filter(|e| e.element_type == "node" && node_ids.contains(&e.id));
+
+        let elements = union
+            .into_iter()
+            .chain(members)
+            .map(|e| OverpassElement {
+                element_type: e.element_type.clone(),
+                id: e.id,
+                lat: e.lat,
+                lon: e.lon,
+                nodes: e.nodes.clone(),
+                tags: e.tags.clone(),
+            })
+            .collect();
+        OverpassResponse { elements, osm3s: None }
+    }
+
+    #[test]
+    fn test_area_query_matches_legacy_results() {
+        let recorded: OverpassResponse = serde_json::from_str(AREA_FIXTURE).unwrap();
+        let center = (45.43, 12.33);
+        let element_ids = |response: &OverpassResponse| {
+            response.elements.iter().map(|e| (e.element_type.clone(), e.id)).collect::<Vec<_>>()
+        };
+
+        for (way_tags, relation_tags, feature_type) in
+            [(WATER_WAY_TAGS, WATER_RELATION_TAGS, "water"), (PARK_WAY_TAGS, PARK_RELATION_TAGS, "park")]
+        {
+            let legacy = run_area_query(&legacy_area_query(center, 3000, way_tags, relation_tags), &recorded);
+            let current = run_area_query(&area_query(center, 3000, way_tags, relation_tags), &recorded);
+            assert_eq!(element_ids(&current), element_ids(&legacy), "{} results differ", feature_type);
+
+            let (legacy, legacy_stats) = parse_area_features(&legacy, feature_type).unwrap();
+            let (current, current_stats) = parse_area_features(&current, feature_type).unwrap();
+            let ids = |features: &[AreaFeature]| features.iter().map(|f| f.id).collect::<Vec<_>>();
+            assert_eq!(ids(&current), ids(&legacy), "{} features differ", feature_type);
+            assert_eq!(current_stats, legacy_stats);
+        }
+
+        // Lakes outside the radius and ways that only pass the regex prefilter
+        // (waterway=water) are left out
+        let water = run_area_query(&area_query(center, 3000, WATER_WAY_TAGS, WATER_RELATION_TAGS), &recorded);
+        let found: Vec<(String, i64)> = element_ids(&water).into_iter().filter(|(t, _)| t != "node").collect();
+        let expected = [("way", 100), ("way", 101), ("way", 109), ("relation", 200)];
+        assert_eq!(found, expected.map(|(t, id)| (t.to_string(), id)));
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_reads_recorded_responses() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-offline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Settings::defaults();
+        config.offline_data_dir = Some(dir.clone());
+        config.offline_mode = true;
+
+        let center = (45.43, 12.33);
+        std::fs::write(dir.join(offline_file_name("water", center, 3000)), AREA_FIXTURE).unwrap();
+        let (water, stats) = fetch_water(center, 3000, None, None, &config).await.unwrap();
+        assert!(!water.is_empty());
+        assert_eq!(stats.kept, water.len());
+
+        // A missing file fails without falling back to the network
+        let err = fetch_parks(center, 3000, None, None, &config).await.unwrap_err().to_string();
+        assert!(err.contains("Offline mode") && err.contains("parks_"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_area_query_evaluates_radius_once_per_set() {
+        let query = area_query((45.43, 12.33), 3000, WATER_WAY_TAGS, WATER_RELATION_TAGS);
+        let expected = r#"[out:json][timeout:60];
+way[~"^(natural|waterway)$"~"^(riverbank|water)$"](around:3000,45.43,12.33)->.searchArea;
+(
+  way.searchArea["natural"="water"];
+  way.searchArea["waterway"="riverbank"];
+  relation["natural"="water"](around:3000,45.43,12.33);
+);
+out body;
+>;
+out skel qt;"#;
+        assert_eq!(query, expected);
+        assert_eq!(area_query((0.0, 0.0), 1, PARK_WAY_TAGS, PARK_RELATION_TAGS).matches("around:").count(), 2);
+    }
+
+    #[test]
+    fn test_feature_tags() {
+        // The built-in filters build the same queries as before they were configurable
+        let defaults = FeatureTags::default();
+        assert_eq!(defaults.water.query((45.43, 12.33), 3000), area_query((45.43, 12.33), 3000, WATER_WAY_TAGS, WATER_RELATION_TAGS));
+        assert_eq!(defaults.parks.query((45.43, 12.33), 3000), area_query((45.43, 12.33), 3000, PARK_WAY_TAGS, PARK_RELATION_TAGS));
+
+        let tags = FeatureTags::from_json(r#"{"parks": ["leisure=park", "way:leisure=garden", "relation:boundary=national_park"]}"#).unwrap();
+        assert_eq!(tags.water, defaults.water);
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+        assert_eq!(tags.parks.ways, vec![pair("leisure", "park"), pair("leisure", "garden")]);
+        assert_eq!(tags.parks.relations, vec![pair("leisure", "park"), pair("boundary", "national_park")]);
+        let query = tags.parks.query((0.0, 0.0), 1000);
+        assert!(query.contains(r#"way.searchArea["leisure"="garden"];"#), "{}", query);
+        assert!(query.contains(r#"relation["boundary"="national_park"](around:1000,0,0);"#), "{}", query);
+
+        // Relation-only categories skip the way search entirely
+        let relations_only = FeatureTags::from_json(r#"{"water": ["relation:natural=water"]}"#).unwrap();
+        assert!(!relations_only.water.query((0.0, 0.0), 1000).contains("searchArea"));
+
+        // Request filters are added to the configured ones, each only once
+        let extra = AreaTags::parse(&["natural=wetland".to_string(), "way:natural=water".to_string()]).unwrap();
+        let merged = defaults.water.merged(&extra);
+        assert_eq!(merged.ways, vec![pair("natural", "water"), pair("waterway", "riverbank"), pair("natural", "wetland")]);
+        assert_eq!(merged.relations, vec![pair("natural", "water"), pair("natural", "wetland")]);
+        let (query, layer) = area_layer_query(&defaults.water, Some(&extra), "water", (0.0, 0.0), 1000);
+        assert!(query.contains(r#"way.searchArea["natural"="wetland"];"#), "{}", query);
+        assert_eq!(layer, "water-natural-water-natural-wetland");
+        assert_eq!(area_layer_query(&defaults.water, None, "water", (0.0, 0.0), 1000).1, "water");
+
+        // Detail areas are configurable categories too
+        let tags = FeatureTags::from_json(r#"{"pitch": ["leisure=pitch", "leisure=track"]}"#).unwrap();
+        assert_eq!(tags.parking, defaults.parking);
+        assert_eq!(tags.detail(DetailArea::Pitch).ways, vec![pair("leisure", "pitch"), pair("leisure", "track")]);
+        let playground: HashMap<String, String> = [("leisure".to_string(), "playground".to_string())].into();
+        assert!(defaults.pitch.matches_way(&playground));
+        assert!(!defaults.parking.matches_way(&playground));
+
+        // A filter shared by two categories is queried once, wherever it appears in their lists
+        let shared = FeatureTags::from_json(r#"{"parking": ["leisure=pitch", "amenity=parking"], "pitch": ["leisure=pitch"]}"#).unwrap();
+        let query = detail_area_query(&shared, &[DetailArea::Parking, DetailArea::Pitch], (0.0, 0.0), 1000);
+        assert_eq!(query.matches(r#"way.searchArea["leisure"="pitch"];"#).count(), 1, "{}", query);
+
+        for (json, message) in [
+            (r#"{"forest": ["natural=wood"]}"#, "unknown category 'forest'"),
+            (r#"{"parks": []}"#, "at least one filter"),
+            (r#"{"parks": ["leisure"]}"#, "invalid filter 'leisure'"),
+            (r#"{"parks": ["leisure=park\"];out;"]}"#, "invalid filter"),
+            (r#"["leisure=park"]"#, "invalid type"),
+        ] {
+            let err = FeatureTags::from_json(json).unwrap_err();
+            assert!(err.contains(message), "{}: {}", json, err);
         }
     }
 
-    Some(((min_lat, min_lon), (max_lat, max_lon)))
+    #[test]
+    fn test_circuit_breaker() {
+        let cooldown = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new();
+
+        assert!(!breaker.record(false, 3, cooldown, start));
+        assert!(!breaker.record(false, 3, cooldown, start));
+        assert_eq!(breaker.retry_after(start), None);
+        assert!(breaker.record(false, 3, cooldown, start));
+        assert_eq!(breaker.retry_after(start + Duration::from_secs(20)), Some(Duration::from_secs(40)));
+
+        // After the cooldown exactly one trial query is let through, and its failure
+        // trips the breaker again
+        let half_open = start + cooldown;
+        assert_eq!(breaker.retry_after(half_open), None);
+        assert_eq!(breaker.acquire(cooldown, half_open), Ok(()));
+        assert_eq!(breaker.acquire(cooldown, half_open + Duration::from_secs(1)), Err(Duration::from_secs(59)));
+        assert!(breaker.record(false, 3, cooldown, start + cooldown));
+        assert!(breaker.retry_after(start + cooldown).is_some());
+
+        // A trial that never reports back frees the slot after another cooldown
+        let next_trial = half_open + cooldown;
+        assert_eq!(breaker.acquire(cooldown, next_trial), Ok(()));
+        assert!(breaker.acquire(cooldown, next_trial).is_err());
+        assert_eq!(breaker.acquire(cooldown, next_trial + cooldown), Ok(()));
+
+        // A success closes it
+        assert!(!breaker.record(true, 3, cooldown, start + cooldown));
+        assert_eq!(breaker.retry_after(start + cooldown), None);
+        assert!(!breaker.record(false, 3, cooldown, start + cooldown));
+
+        // Only overload and server errors are outages, not rejected queries
+        assert!(is_outage_status(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_outage_status(reqwest::StatusCode::GATEWAY_TIMEOUT));
+        assert!(!is_outage_status(reqwest::StatusCode::BAD_REQUEST));
+
+        // A threshold of 0 never trips
+        let mut disabled = CircuitBreaker::new();
+        for _ in 0..10 {
+            assert!(!disabled.record(false, 0, cooldown, start));
+        }
+        assert_eq!(disabled.retry_after(start), None);
+    }
+
+    #[test]
+    fn test_highway_regex() {
+        assert_eq!(
+            highway_regex(FETCHED_HIGHWAY_TYPES),
+            "motorway|motorway_link|trunk|primary|primary_link|secondary|secondary_link|tertiary|tertiary_link|residential|living_street|service|unclassified"
+        );
+        assert_eq!(highway_regex(&[HighwayType::Motorway, HighwayType::Primary]), "motorway|primary");
+        assert_eq!(HighwayType::from_tag("living_street"), Some(HighwayType::LivingStreet));
+        assert_eq!(HighwayType::from_tag("road"), None);
+    }
 }
@@ -1,15 +1,35 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tiny_skia::{Pixmap, PixmapPaint, Transform};
+use tokio::io::AsyncWriteExt;
 
-use crate::core::geocoding::{format_coordinates, geocode};
-use crate::core::osm_client::{calculate_bounds, fetch_parks, fetch_streets, fetch_water, AreaFeature, RoadSegment};
+use crate::config::Settings;
+use crate::core::geocoding::{fetch_country_outline, format_coordinates, geocode, CoordinateFormat};
+use crate::core::osm_client::{
+    calculate_bounds, check_map_extent, distance_bounds, fetch_detail_areas, fetch_landuse, fetch_parks, fetch_points,
+    fetch_streets, fetch_water, AreaFeature, AreaTags, DetailArea, HighwayType, LayerStats, PointFeature, PointKind, RoadSegment,
+    LANDUSE_TYPES,
+};
 use crate::core::progress::{GenerationProgress, ProgressCallback};
 use crate::error::{AppError, Result};
-use crate::rendering::canvas::Canvas;
-use crate::rendering::gradients::apply_gradient_fades;
-use crate::rendering::typography::{render_poster_typography, FontSet};
-use crate::themes::loader::get_theme_color;
+use crate::rendering::canvas::{Canvas, FitMode, PolygonOutline, Shoreline, POSTER_HEIGHT, POSTER_WIDTH};
+use crate::rendering::blend::BlendMode;
+use crate::rendering::gradients::{apply_gradient_fades, apply_transparent_fades, apply_vignette, Vignette};
+use crate::rendering::locator::{draw_locator, unwrap_antimeridian, LocatorCorner, LocatorStyle};
+use crate::rendering::palette::{flatten_palette, theme_palette};
+use crate::rendering::png_text::encode_png_with_text;
+use crate::rendering::resample::downscale_lanczos;
+use crate::rendering::road_styles::{width_multiplier, WidthBy, WidthCurve};
+use crate::rendering::typography::{render_poster_typography, render_text, FontSet, TextPlate, TypographyLayout};
+use crate::themes::loader::{get_theme_bool, get_theme_color, get_theme_number, mix_hex_colors, theme_asset_path};
+
+/// Default point marker size in pixels on a full-size poster (theme key `point_size`)
+pub const DEFAULT_POINT_SIZE: f32 = 18.0;
+
+/// Point label font size as a multiple of the marker size
+const POINT_LABEL_SCALE: f32 = 2.0;
 
 /// Cached map data returned from generation for re-rendering
 #[derive(Debug, Clone)]
@@ -22,6 +42,60 @@ pub struct MapData {
     pub streets: Vec<RoadSegment>,
     pub water: Vec<AreaFeature>,
     pub parks: Vec<AreaFeature>,
+    pub landuse: Vec<AreaFeature>,
+    /// Parking and pitch areas, typed by `DetailArea` name (empty unless requested)
+    pub details: Vec<AreaFeature>,
+    /// Peaks and places drawn as markers (empty unless requested)
+    pub points: Vec<PointFeature>,
+    /// Simplified country outline for the locator inset (empty if not fetched)
+    pub country_outline: Vec<Vec<(f64, f64)>>,
+    /// Per-layer counts of features kept and skipped while parsing
+    pub stats: DataStats,
+}
+
+/// Dropped and partial feature counts for each fetched layer
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DataStats {
+    pub streets: LayerStats,
+    pub water: LayerStats,
+    pub parks: LayerStats,
+    pub landuse: LayerStats,
+    pub details: LayerStats,
+    pub points: LayerStats,
+}
+
+impl DataStats {
+    /// Oldest OSM data timestamp across the layers, i.e. how current the whole map is at least
+    pub fn osm_timestamp(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        [self.streets, self.water, self.parks, self.landuse, self.details, self.points]
+            .iter()
+            .filter_map(|stats| stats.osm_timestamp)
+            .min()
+    }
+
+    /// Log a warning for every layer that lost features
+    pub fn log_losses(&self) {
+        for (layer, stats) in [
+            ("streets", &self.streets),
+            ("water", &self.water),
+            ("parks", &self.parks),
+            ("landuse", &self.landuse),
+            ("details", &self.details),
+            ("points", &self.points),
+        ] {
+            if stats.has_losses() {
+                tracing::warn!(
+                    "{}: kept {}, dropped {} ways, {} partial ways, skipped {} relations{}",
+                    layer,
+                    stats.kept,
+                    stats.dropped_ways,
+                    stats.partial_ways,
+                    stats.skipped_relations,
+                    if stats.fetch_failed { " (fetch failed)" } else { "" }
+                );
+            }
+        }
+    }
 }
 
 /// Request for poster generation
@@ -29,9 +103,193 @@ pub struct MapData {
 pub struct PosterRequest {
     pub city: String,
     pub country: String,
-    pub theme_name: String,
     pub distance: u32,
-    pub dpi: u32,
+    /// Optional country code filter for geocoding
+    pub country_code: Option<String>,
+    /// Fail instead of rendering without water/parks when their fetch fails
+    pub strict: bool,
+    /// Frame a web-map zoom level instead of `distance`
+    pub zoom: Option<ZoomLevel>,
+    /// Filters added to the configured water query for this poster only
+    pub extra_water_tags: Option<AreaTags>,
+    /// Filters added to the configured park query for this poster only
+    pub extra_park_tags: Option<AreaTags>,
+    pub options: RenderOptions,
+}
+
+/// Lowest accepted web-map zoom level (a whole region)
+pub const MIN_ZOOM: f64 = 10.0;
+/// Highest accepted web-map zoom level (a few blocks)
+pub const MAX_ZOOM: f64 = 16.0;
+
+/// Meters per pixel at zoom 0 on the equator for 256px web-map tiles
+const EQUATOR_METERS_PER_PIXEL: f64 = 156_543.034;
+
+/// Screen resolution a zoom level is matched at: the poster shows what a 96 DPI screen of
+/// the same width would
+const SCREEN_DPI: f64 = 96.0;
+
+/// Web-map zoom level, turned into a distance once the location's latitude is known
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomLevel {
+    pub level: f64,
+    /// Trimmed poster width in inches
+    pub poster_width_in: f64,
+}
+
+impl ZoomLevel {
+    /// Distance in meters (half the poster width) covering what a web map at this zoom shows
+    ///
+    /// Web maps use Web Mercator, whose meters per pixel shrink with the cosine of the latitude.
+    pub fn distance(&self, lat: f64) -> u32 {
+        let meters_per_pixel = EQUATOR_METERS_PER_PIXEL * lat.to_radians().cos() / 2f64.powf(self.level);
+        (meters_per_pixel * self.poster_width_in * SCREEN_DPI / 2.0).round() as u32
+    }
+}
+
+/// Rendering options that are independent of the theme
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Trimmed poster width in pixels
+    pub width: u32,
+    /// Trimmed poster height in pixels
+    pub height: u32,
+    /// Print bleed added to every side of the canvas, in pixels
+    pub bleed: u32,
+    /// How the map area is framed on the canvas
+    pub framing: Framing,
+    /// Letterbox the framed area into the canvas or crop it to fill the canvas
+    pub fit_mode: FitMode,
+    /// Corner for the country locator inset (off when None)
+    pub locator: Option<LocatorCorner>,
+    /// Road width curve, overriding the theme's `width_curve`
+    pub width_curve: Option<WidthCurve>,
+    /// What sets each road's width: its type, or its `lanes` or `maxspeed` tag
+    pub width_by: WidthBy,
+    /// How the coordinates line under the city name is written
+    pub coordinate_format: CoordinateFormat,
+    /// Size of the saved image when it differs from the canvas (downscaled with Lanczos)
+    pub output_size: Option<(u32, u32)>,
+    /// Highway types to fetch and draw (all fetched types when None)
+    pub road_types: Option<Vec<HighwayType>>,
+    /// Point features to fetch and draw as markers (none when empty)
+    pub points: Vec<PointKind>,
+    /// Parking and pitch areas to fetch and draw (none when empty)
+    pub detail_areas: Vec<DetailArea>,
+    /// Snap every pixel of the saved image to the nearest theme color
+    pub flatten_palette: bool,
+    /// Skip the background fill and fade the map's edges to transparency instead of `gradient_color`
+    pub transparent_background: bool,
+    /// Write the poster's parameters into PNG text chunks
+    pub embed_metadata: bool,
+    /// Leave out roads lying wholly within this many meters of the center
+    pub center_clear_radius: Option<f64>,
+    /// Template the map is composited into, replacing the built-in typography
+    pub template: Option<PosterTemplate>,
+}
+
+impl RenderOptions {
+    /// Turn full-size options into a preview at `preview_dpi`
+    ///
+    /// The canvas is rendered at `preview_scale` of the full size (never below the preview
+    /// itself) and filtered down, trading render time against smoother thin roads.
+    pub fn into_preview(mut self, output_dpi: u32, preview_dpi: u32, preview_scale: f32) -> Self {
+        let scale_to = |full: u32, factor: f64| ((full as f64 * factor).round() as u32).max(1);
+        let output_factor = preview_dpi as f64 / output_dpi.max(1) as f64;
+        let preview = (scale_to(self.width, output_factor), scale_to(self.height, output_factor));
+        let preview_bleed = (self.bleed as f64 * output_factor).round() as u32;
+
+        let full_width = self.width;
+        self.width = scale_to(self.width, preview_scale as f64).max(preview.0);
+        self.height = scale_to(self.height, preview_scale as f64).max(preview.1);
+        self.bleed = (self.bleed as f64 * self.width as f64 / full_width as f64).round() as u32;
+        self.output_size = Some((preview.0 + 2 * preview_bleed, preview.1 + 2 * preview_bleed));
+        self
+    }
+
+    /// Canvas size including the bleed on both sides
+    pub fn canvas_size(&self) -> (u32, u32) {
+        (self.width + 2 * self.bleed, self.height + 2 * self.bleed)
+    }
+
+    /// Where the poster is trimmed within the saved image, when it has a bleed
+    ///
+    /// Previews are downscaled, so their box is rounded to the nearest pixel.
+    pub fn trim_box(&self) -> Option<TrimBox> {
+        if self.bleed == 0 {
+            return None;
+        }
+        let (canvas_width, canvas_height) = self.canvas_size();
+        let (out_width, out_height) = self.output_size.unwrap_or((canvas_width, canvas_height));
+        let bleed_x = (self.bleed as f64 * out_width as f64 / canvas_width as f64).round() as u32;
+        let bleed_y = (self.bleed as f64 * out_height as f64 / canvas_height as f64).round() as u32;
+
+        Some(TrimBox {
+            x: bleed_x,
+            y: bleed_y,
+            width: out_width.saturating_sub(2 * bleed_x),
+            height: out_height.saturating_sub(2 * bleed_y),
+        })
+    }
+}
+
+/// A PNG layout the map is drawn into, e.g. a branded frame with logos and margins
+///
+/// The rectangle is in template pixels; the full-size map canvas is exactly its size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosterTemplate {
+    pub path: PathBuf,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Trim line of a poster with bleed, in pixels of the saved image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TrimBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: POSTER_WIDTH,
+            height: POSTER_HEIGHT,
+            bleed: 0,
+            framing: Framing::default(),
+            fit_mode: FitMode::default(),
+            locator: None,
+            width_curve: None,
+            width_by: WidthBy::Type,
+            coordinate_format: CoordinateFormat::default(),
+            output_size: None,
+            road_types: None,
+            points: Vec::new(),
+            detail_areas: Vec::new(),
+            flatten_palette: false,
+            transparent_background: false,
+            embed_metadata: false,
+            center_clear_radius: None,
+            template: None,
+        }
+    }
+}
+
+/// How the visible map area is chosen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// Fit the extent of the fetched roads (plus padding); coverage varies with road layout
+    #[default]
+    Roads,
+    /// Frame exactly the requested distance around the center, corrected for latitude
+    Distance,
+    /// Frame like `Distance`, but draw the map only inside the circle of `distance` meters
+    Circle,
 }
 
 impl Default for PosterRequest {
@@ -39,9 +297,13 @@ impl Default for PosterRequest {
         Self {
             city: String::new(),
             country: String::new(),
-            theme_name: "feature_based".to_string(),
             distance: 15000,
-            dpi: 300,
+            country_code: None,
+            strict: false,
+            zoom: None,
+            extra_water_tags: None,
+            extra_park_tags: None,
+            options: RenderOptions::default(),
         }
     }
 }
@@ -50,50 +312,93 @@ impl Default for PosterRequest {
 pub struct PosterGenerator {
     theme: Value,
     fonts: FontSet,
-    nominatim_timeout: f64,
-    osm_timeout: f64,
+    config: Settings,
 }
 
 impl PosterGenerator {
-    /// Create a new poster generator
-    pub fn new(
-        theme: Value,
-        fonts_dir: &Path,
-        nominatim_timeout: f64,
-        osm_timeout: f64,
-    ) -> Result<Self> {
-        let fonts = FontSet::load(fonts_dir)?;
-
-        Ok(Self {
+    /// Create a poster generator from already loaded default fonts, adding the theme's own
+    pub fn with_fonts(theme: Value, mut fonts: FontSet, config: &Settings) -> Self {
+        fonts.load_theme_fonts(&config.fonts_dir, &theme);
+
+        Self {
             theme,
             fonts,
-            nominatim_timeout,
-            osm_timeout,
-        })
+            config: config.clone(),
+        }
     }
 
-    /// Generate a poster and save it to the specified path
-    pub async fn generate(
+    /// Generate a poster and return the map data for caching
+    pub async fn generate_with_cache(
         &self,
         request: &PosterRequest,
         output_path: &Path,
         progress_callback: Option<ProgressCallback>,
-    ) -> Result<()> {
+    ) -> Result<MapData> {
+        let map_data = self.fetch_map_data(request, progress_callback.as_ref()).await?;
+
+        // Render the poster (pass progress_callback for remaining steps)
+        self.render_from_data(
+            &map_data,
+            output_path,
+            &request.options,
+            progress_callback,
+        )
+        .await?;
+
+        Ok(map_data)
+    }
+
+    /// Geocode the location and fetch all map layers (network only, no rendering)
+    pub async fn fetch_map_data(
+        &self,
+        request: &PosterRequest,
+        progress_callback: Option<&ProgressCallback>,
+    ) -> Result<MapData> {
         let report = |progress: GenerationProgress| {
-            if let Some(ref cb) = progress_callback {
+            if let Some(cb) = progress_callback {
                 cb(progress);
             }
         };
 
         // Step 1: Geocode the location
         report(GenerationProgress::geocoding());
-        let (lat, lon) = geocode(&request.city, &request.country, self.nominatim_timeout).await?;
-        let coordinates = format_coordinates(lat, lon);
+        let (lat, lon) = geocode(
+            &request.city,
+            &request.country,
+            request.country_code.as_deref(),
+            &self.config,
+        ).await?;
         tracing::info!("Geocoded {}, {} to ({}, {})", request.city, request.country, lat, lon);
 
+        // A zoom level becomes a distance now that the latitude is known
+        let distance = match request.zoom {
+            Some(zoom) => {
+                let distance = zoom.distance(lat);
+                self.config.validate_distance(distance).map_err(|e| {
+                    AppError::InvalidDistance(format!("Zoom {} at latitude {:.2}: {}", zoom.level, lat, e))
+                })?;
+                tracing::info!("Zoom {} at latitude {:.2} frames {}m", zoom.level, lat, distance);
+                distance
+            }
+            None => request.distance,
+        };
+        check_map_extent((lat, lon), distance as f64)?;
+
+        // Each fetch step reports its download towards the progress of the step after it
+        let download_progress = |step: fn() -> GenerationProgress, next: fn() -> GenerationProgress| {
+            move |fraction: f32| report(GenerationProgress::downloading(step(), &next(), fraction))
+        };
+
         // Step 2: Fetch street network
         report(GenerationProgress::fetching_streets());
-        let streets = fetch_streets((lat, lon), request.distance, self.osm_timeout).await?;
+        let (streets, streets_stats) = fetch_streets(
+            (lat, lon),
+            distance,
+            request.options.road_types.as_deref(),
+            Some(&download_progress(GenerationProgress::fetching_streets, GenerationProgress::fetching_water)),
+            &self.config,
+        )
+        .await?;
         tracing::info!("Fetched {} road segments", streets.len());
 
         if streets.is_empty() {
@@ -102,234 +407,949 @@ impl PosterGenerator {
             ));
         }
 
-        // Step 3: Fetch water features (non-fatal if missing)
+        // Step 3: Fetch water features (non-fatal if missing, unless strict)
         report(GenerationProgress::fetching_water());
-        let water = match fetch_water((lat, lon), request.distance, self.osm_timeout).await {
-            Ok(w) => {
+        let (water, water_stats) = match fetch_water(
+            (lat, lon),
+            distance,
+            request.extra_water_tags.as_ref(),
+            Some(&download_progress(GenerationProgress::fetching_water, GenerationProgress::fetching_parks)),
+            &self.config,
+        )
+        .await
+        {
+            Ok((w, stats)) => {
                 tracing::info!("Fetched {} water features", w.len());
-                w
+                (w, stats)
+            }
+            Err(e) if request.strict => {
+                return Err(AppError::DataFetch(format!("Could not fetch water features: {}", e)));
             }
             Err(e) => {
                 tracing::warn!("Could not fetch water features: {}", e);
-                Vec::new()
+                (Vec::new(), LayerStats::failed())
             }
         };
 
-        // Step 4: Fetch park features (non-fatal if missing)
+        // Step 4: Fetch park features (non-fatal if missing, unless strict)
         report(GenerationProgress::fetching_parks());
-        let parks = match fetch_parks((lat, lon), request.distance, self.osm_timeout).await {
-            Ok(p) => {
+        let (parks, parks_stats) = match fetch_parks(
+            (lat, lon),
+            distance,
+            request.extra_park_tags.as_ref(),
+            Some(&download_progress(GenerationProgress::fetching_parks, GenerationProgress::fetching_landuse)),
+            &self.config,
+        )
+        .await
+        {
+            Ok((p, stats)) => {
                 tracing::info!("Fetched {} park features", p.len());
-                p
+                (p, stats)
+            }
+            Err(e) if request.strict => {
+                return Err(AppError::DataFetch(format!("Could not fetch park features: {}", e)));
             }
             Err(e) => {
                 tracing::warn!("Could not fetch park features: {}", e);
-                Vec::new()
+                (Vec::new(), LayerStats::failed())
             }
         };
 
-        // Step 5: Create canvas and set up coordinate transform
-        report(GenerationProgress::rendering_background());
-        let mut canvas = Canvas::poster()?;
-
-        // Fill background
-        let bg_color = get_theme_color(&self.theme, "bg", "#FFFFFF");
-        canvas.fill_background(&bg_color);
-
-        // Calculate bounds and set transform
-        let bounds = calculate_bounds(&streets)
-            .ok_or_else(|| AppError::Rendering("Could not calculate map bounds".to_string()))?;
-        canvas.set_geo_transform(bounds);
-
-        // Step 6: Render water features
-        report(GenerationProgress::rendering_water());
-        if !water.is_empty() {
-            let water_color = get_theme_color(&self.theme, "water", "#C0C0C0");
-            canvas.draw_polygons(&water, &water_color);
-        }
-
-        // Step 7: Render park features
-        report(GenerationProgress::rendering_parks());
-        if !parks.is_empty() {
-            let parks_color = get_theme_color(&self.theme, "parks", "#F0F0F0");
-            canvas.draw_polygons(&parks, &parks_color);
-        }
-
-        // Step 8: Render roads
-        report(GenerationProgress::rendering_roads());
-        // Calculate base width multiplier based on distance (larger distance = thinner lines)
-        let base_width = 2.0 * (15000.0 / request.distance as f32).sqrt();
-        canvas.draw_roads(&streets, &self.theme, base_width);
-
-        // Step 9: Apply gradient fades
-        report(GenerationProgress::rendering_gradients());
-        let gradient_color = get_theme_color(&self.theme, "gradient_color", &bg_color);
-        apply_gradient_fades(&mut canvas.pixmap, &gradient_color);
-
-        // Step 10: Render typography
-        report(GenerationProgress::rendering_text());
-        let text_color = get_theme_color(&self.theme, "text", "#000000");
-        render_poster_typography(
-            &mut canvas.pixmap,
-            &self.fonts,
-            &request.city,
-            &request.country,
-            &coordinates,
-            &text_color,
-        );
-
-        // Step 11: Save the poster
-        report(GenerationProgress::saving());
-        canvas.save_png(output_path)?;
-        tracing::info!("Saved poster to {:?}", output_path);
-
-        report(GenerationProgress::completed());
-        Ok(())
-    }
-
-    /// Generate a poster and return the map data for caching
-    pub async fn generate_with_cache(
-        &self,
-        request: &PosterRequest,
-        output_path: &Path,
-        progress_callback: Option<ProgressCallback>,
-    ) -> Result<MapData> {
-        let report = |progress: GenerationProgress| {
-            if let Some(ref cb) = progress_callback {
-                cb(progress);
+        // Step 5: Fetch landuse areas (non-fatal if missing)
+        report(GenerationProgress::fetching_landuse());
+        let (landuse, landuse_stats) = match fetch_landuse(
+            (lat, lon),
+            distance,
+            Some(&download_progress(GenerationProgress::fetching_landuse, GenerationProgress::rendering_background)),
+            &self.config,
+        )
+        .await
+        {
+            Ok((l, stats)) => {
+                tracing::info!("Fetched {} landuse features", l.len());
+                (l, stats)
+            }
+            Err(e) => {
+                tracing::warn!("Could not fetch landuse features: {}", e);
+                (Vec::new(), LayerStats::failed())
             }
         };
 
-        // Step 1: Geocode the location
-        report(GenerationProgress::geocoding());
-        let (lat, lon) = geocode(&request.city, &request.country, self.nominatim_timeout).await?;
-        let coordinates = format_coordinates(lat, lon);
-        tracing::info!("Geocoded {}, {} to ({}, {})", request.city, request.country, lat, lon);
-
-        // Step 2: Fetch street network
-        report(GenerationProgress::fetching_streets());
-        let streets = fetch_streets((lat, lon), request.distance, self.osm_timeout).await?;
-        tracing::info!("Fetched {} road segments", streets.len());
-
-        if streets.is_empty() {
-            return Err(AppError::DataFetch(
-                "No street data found for this location".to_string(),
-            ));
-        }
-
-        // Step 3: Fetch water features (non-fatal if missing)
-        report(GenerationProgress::fetching_water());
-        let water = match fetch_water((lat, lon), request.distance, self.osm_timeout).await {
-            Ok(w) => {
-                tracing::info!("Fetched {} water features", w.len());
-                w
-            }
-            Err(e) => {
-                tracing::warn!("Could not fetch water features: {}", e);
-                Vec::new()
+        // Step 6: Parking and pitch areas, only when requested (non-fatal)
+        let (details, details_stats) = if request.options.detail_areas.is_empty() {
+            (Vec::new(), LayerStats::default())
+        } else {
+            report(GenerationProgress::fetching_details());
+            match fetch_detail_areas(
+                (lat, lon),
+                distance,
+                &request.options.detail_areas,
+                Some(&download_progress(GenerationProgress::fetching_details, GenerationProgress::rendering_background)),
+                &self.config,
+            )
+            .await
+            {
+                Ok((d, stats)) => {
+                    tracing::info!("Fetched {} parking and pitch areas", d.len());
+                    (d, stats)
+                }
+                Err(e) => {
+                    tracing::warn!("Could not fetch parking and pitch areas: {}", e);
+                    (Vec::new(), LayerStats::failed())
+                }
             }
         };
 
-        // Step 4: Fetch park features (non-fatal if missing)
-        report(GenerationProgress::fetching_parks());
-        let parks = match fetch_parks((lat, lon), request.distance, self.osm_timeout).await {
-            Ok(p) => {
-                tracing::info!("Fetched {} park features", p.len());
-                p
+        // Step 7: Peaks and places, only when requested (non-fatal)
+        let (points, points_stats) = if request.options.points.is_empty() {
+            (Vec::new(), LayerStats::default())
+        } else {
+            report(GenerationProgress::fetching_points());
+            match fetch_points(
+                (lat, lon),
+                distance,
+                &request.options.points,
+                Some(&download_progress(GenerationProgress::fetching_points, GenerationProgress::rendering_background)),
+                &self.config,
+            )
+            .await
+            {
+                Ok((p, stats)) => {
+                    tracing::info!("Fetched {} point features", p.len());
+                    (p, stats)
+                }
+                Err(e) => {
+                    tracing::warn!("Could not fetch point features: {}", e);
+                    (Vec::new(), LayerStats::failed())
+                }
             }
-            Err(e) => {
-                tracing::warn!("Could not fetch park features: {}", e);
-                Vec::new()
+        };
+
+        // Step 8: Country outline for the locator inset (only when requested, non-fatal)
+        let country_outline = if request.options.locator.is_some() {
+            match fetch_country_outline(&request.country, &self.config).await {
+                Ok(rings) => rings,
+                Err(e) => {
+                    tracing::warn!("Could not fetch country outline, skipping locator: {}", e);
+                    Vec::new()
+                }
             }
+        } else {
+            Vec::new()
+        };
+
+        let stats = DataStats {
+            streets: streets_stats,
+            water: water_stats,
+            parks: parks_stats,
+            landuse: landuse_stats,
+            details: details_stats,
+            points: points_stats,
         };
+        stats.log_losses();
 
-        // Create map data for caching
-        let map_data = MapData {
+        Ok(MapData {
             city: request.city.clone(),
             country: request.country.clone(),
             lat,
             lon,
-            distance: request.distance,
+            distance,
             streets,
             water,
             parks,
-        };
-
-        // Render the poster (pass progress_callback for remaining steps)
-        self.render_from_data(&map_data, &coordinates, output_path, progress_callback)?;
-
-        Ok(map_data)
+            landuse,
+            details,
+            points,
+            country_outline,
+            stats,
+        })
     }
 
-    /// Render a poster from cached map data (no network requests)
-    pub fn render_from_data(
+    /// Render a poster from cached map data (no network requests) and save it
+    ///
+    /// Rendering runs via `block_in_place` so other tasks move off this worker, and the
+    /// file is flushed to disk before this returns.
+    pub async fn render_from_data(
         &self,
         data: &MapData,
-        coordinates: &str,
         output_path: &Path,
+        options: &RenderOptions,
         progress_callback: Option<ProgressCallback>,
     ) -> Result<()> {
+        let png = tokio::task::block_in_place(|| {
+            self.render_png(data, options, progress_callback.as_ref())
+        })?;
+        write_poster(output_path, &png).await?;
+        tracing::info!("Saved poster to {:?}", output_path);
+
+        if let Some(cb) = &progress_callback {
+            cb(GenerationProgress::completed());
+        }
+        Ok(())
+    }
+
+    /// Render a poster to PNG bytes (CPU only, no network or disk access)
+    pub fn render_png(
+        &self,
+        data: &MapData,
+        options: &RenderOptions,
+        progress_callback: Option<&ProgressCallback>,
+    ) -> Result<Vec<u8>> {
+        let output = self.render_pixmap(data, options, progress_callback)?;
+        if options.embed_metadata {
+            return encode_png_with_text(&output, &self.poster_metadata(data));
+        }
+        output
+            .encode_png()
+            .map_err(|e| AppError::Rendering(format!("Failed to encode PNG: {}", e)))
+    }
+
+    /// Render a poster to its final image, ready to encode
+    pub fn render_pixmap(
+        &self,
+        data: &MapData,
+        options: &RenderOptions,
+        progress_callback: Option<&ProgressCallback>,
+    ) -> Result<Pixmap> {
         let report = |progress: GenerationProgress| {
-            if let Some(ref cb) = progress_callback {
+            if let Some(cb) = progress_callback {
                 cb(progress);
             }
         };
 
         // Create canvas and set up coordinate transform
         report(GenerationProgress::rendering_background());
-        let mut canvas = Canvas::poster()?;
+        let (canvas_width, canvas_height) = options.canvas_size();
+        let mut canvas = Canvas::new(canvas_width, canvas_height)?;
+        canvas.set_min_polygon_area(self.config.min_polygon_area_px);
+        canvas.set_bleed(options.bleed);
+        canvas.set_anti_alias(get_theme_bool(&self.theme, "anti_alias", true));
+        canvas.set_fit_mode(options.fit_mode);
+        if let Some(radius) = options.center_clear_radius {
+            canvas.set_center_clear_zone((data.lat, data.lon), radius);
+        }
 
-        // Fill background
+        // Fill background (image or gradient if the theme sets one, else flat color)
         let bg_color = get_theme_color(&self.theme, "bg", "#FFFFFF");
-        canvas.fill_background(&bg_color);
+        let water_color = get_theme_color(&self.theme, "water", "#C0C0C0");
+
+        // With `water_base` the canvas starts as sea and land shows wherever landuse and
+        // parks cover it, so islands get their surrounding water without coastline data
+        let water_base = get_theme_bool(&self.theme, "water_base", false);
+        if options.transparent_background {
+            // Left transparent for compositing over other artwork
+        } else if water_base && options.framing != Framing::Circle {
+            canvas.fill_background(&water_color);
+        } else {
+            self.fill_theme_background(&mut canvas, &bg_color);
+        }
+        let base_color = if water_base { &water_color } else { &bg_color };
 
         // Calculate bounds and set transform
-        let bounds = calculate_bounds(&data.streets)
-            .ok_or_else(|| AppError::Rendering("Could not calculate map bounds".to_string()))?;
-        canvas.set_geo_transform(bounds);
-
-        // Render water features
-        report(GenerationProgress::rendering_water());
-        if !data.water.is_empty() {
-            let water_color = get_theme_color(&self.theme, "water", "#C0C0C0");
-            canvas.draw_polygons(&data.water, &water_color);
+        match options.framing {
+            Framing::Roads => {
+                let bounds = calculate_bounds(&data.streets)
+                    .ok_or_else(|| AppError::Rendering("Could not calculate map bounds".to_string()))?;
+                canvas.set_geo_transform(bounds);
+            }
+            Framing::Distance | Framing::Circle => {
+                let bounds = distance_bounds((data.lat, data.lon), data.distance as f64);
+                canvas.set_geo_transform_with_padding(bounds, 0.0);
+            }
+        }
+        if options.framing == Framing::Circle {
+            canvas.clip_to_radius((data.lat, data.lon), data.distance as f64);
+            // The sea only fills the circle; outside it the poster keeps its background
+            if water_base && !options.transparent_background {
+                canvas.fill_clip(&water_color);
+            }
         }
 
-        // Render park features
-        report(GenerationProgress::rendering_parks());
-        if !data.parks.is_empty() {
-            let parks_color = get_theme_color(&self.theme, "parks", "#F0F0F0");
-            canvas.draw_polygons(&data.parks, &parks_color);
+        // Render map layers in the theme's configured order
+        for layer in theme_layer_order(&self.theme) {
+            match layer {
+                MapLayer::Landuse => {
+                    report(GenerationProgress::rendering_landuse());
+                    if !data.landuse.is_empty() {
+                        self.draw_landuse(&mut canvas, &data.landuse, &bg_color);
+                    }
+                }
+                MapLayer::Water => {
+                    report(GenerationProgress::rendering_water());
+                    if !data.water.is_empty() {
+                        let outline = PolygonOutline::from_theme(&self.theme, "water");
+                        let shoreline = Shoreline::from_theme(&self.theme, &bg_color);
+                        let opacity = get_theme_number(&self.theme, "water_opacity", 1.0);
+                        canvas.draw_layer(opacity, |canvas| {
+                            canvas.draw_polygons(&data.water, &water_color, outline.as_ref());
+                            if let Some(shoreline) = &shoreline {
+                                canvas.draw_shoreline(&data.water, shoreline);
+                            }
+                        });
+                    }
+                }
+                MapLayer::Parks => {
+                    report(GenerationProgress::rendering_parks());
+                    if !data.parks.is_empty() {
+                        let parks_color = get_theme_color(&self.theme, "parks", "#F0F0F0");
+                        let outline = PolygonOutline::from_theme(&self.theme, "parks");
+                        let opacity = get_theme_number(&self.theme, "parks_opacity", 1.0);
+                        canvas.draw_layer(opacity, |canvas| {
+                            canvas.draw_polygons(&data.parks, &parks_color, outline.as_ref())
+                        });
+                    }
+                }
+                MapLayer::Details => {
+                    if !data.details.is_empty() {
+                        report(GenerationProgress::rendering_details());
+                        self.draw_detail_areas(&mut canvas, &data.details, &bg_color);
+                    }
+                }
+                MapLayer::Roads => {
+                    report(GenerationProgress::rendering_roads());
+                    let curve = options.width_curve.unwrap_or_else(|| theme_width_curve(&self.theme));
+                    let base_width = width_multiplier(data.distance, curve);
+                    let opacity = get_theme_number(&self.theme, "roads_opacity", 1.0);
+                    canvas.draw_layer(opacity, |canvas| {
+                        canvas.draw_roads(&data.streets, &self.theme, base_width, options.road_types.as_deref(), options.width_by)
+                    });
+                }
+            }
         }
 
-        // Render roads
-        report(GenerationProgress::rendering_roads());
-        let base_width = 2.0 * (15000.0 / data.distance as f32).sqrt();
-        canvas.draw_roads(&data.streets, &self.theme, base_width);
+        let text_color = get_theme_color(&self.theme, "text", "#000000");
+        let blend_mode = BlendMode::from_gamma_correct(self.config.gamma_correct);
+
+        // Vignette over the map layers, skipped when the background is left transparent
+        if let Some(vignette) = Vignette::from_theme(&self.theme, base_color) {
+            if !options.transparent_background {
+                apply_vignette(&mut canvas.pixmap, &vignette, blend_mode);
+            }
+        }
+
+        // Peak and place markers sit above every map layer
+        if !data.points.is_empty() {
+            report(GenerationProgress::rendering_points());
+            self.draw_points(&mut canvas, &data.points, &text_color, blend_mode);
+        }
 
         // Apply gradient fades
         report(GenerationProgress::rendering_gradients());
-        let gradient_color = get_theme_color(&self.theme, "gradient_color", &bg_color);
-        apply_gradient_fades(&mut canvas.pixmap, &gradient_color);
+        if options.transparent_background {
+            apply_transparent_fades(&mut canvas.pixmap);
+        } else {
+            let gradient_color = get_theme_color(&self.theme, "gradient_color", base_color);
+            apply_gradient_fades(&mut canvas.pixmap, &gradient_color, blend_mode);
+        }
 
-        // Render typography
-        report(GenerationProgress::rendering_text());
-        let text_color = get_theme_color(&self.theme, "text", "#000000");
-        render_poster_typography(
+        // Locator inset, skipped if the outline couldn't be fetched
+        if let Some(corner) = options.locator {
+            self.draw_locator_inset(&mut canvas, data, corner, &bg_color, &text_color);
+        }
+
+        // Render typography, unless a template provides the layout
+        if options.template.is_none() {
+            report(GenerationProgress::rendering_text());
+            let trim = canvas.trim_box();
+            let coordinates = format_coordinates(data.lat, data.lon, options.coordinate_format);
+            render_poster_typography(
+                &mut canvas.pixmap,
+                trim,
+                &self.fonts,
+                &data.city,
+                &data.country,
+                &coordinates,
+                &text_color,
+                &TypographyLayout::from_theme(&self.theme),
+                TextPlate::from_theme(&self.theme).as_ref(),
+                blend_mode,
+            );
+        }
+
+        // Encode the poster, filtering previews down to their output size
+        report(GenerationProgress::saving());
+        let mut output = match options.output_size {
+            Some((width, height)) if (width, height) != (canvas.width, canvas.height) => {
+                downscale_lanczos(&canvas.pixmap, width, height)?
+            }
+            _ => canvas.pixmap,
+        };
+        // Flattened last, so downscaling can't bring in-between shades back
+        if options.flatten_palette {
+            flatten_palette(&mut output, &theme_palette(&self.theme));
+        }
+        if let Some(template) = &options.template {
+            output = composite_into_template(&output, template)?;
+        }
+        Ok(output)
+    }
+
+    /// PNG text chunks describing how a poster was made, readable with tools like `exiftool`
+    fn poster_metadata(&self, data: &MapData) -> Vec<(&'static str, String)> {
+        let theme = self.theme.get("name").and_then(|v| v.as_str()).unwrap_or("Custom");
+        vec![
+            ("Title", format!("{}, {}", data.city, data.country)),
+            ("Software", format!("maptoposter-rs {}", env!("CARGO_PKG_VERSION"))),
+            ("Creation Time", chrono::Utc::now().to_rfc2822()),
+            ("City", data.city.clone()),
+            ("Country", data.country.clone()),
+            ("Coordinates", format!("{:.6}, {:.6}", data.lat, data.lon)),
+            ("Theme", theme.to_string()),
+            ("Distance", format!("{} m", data.distance)),
+        ]
+    }
+
+    /// Fill the background from `bg_image`, then `bg_gradient`, falling back to the flat `bg` color
+    fn fill_theme_background(&self, canvas: &mut Canvas, bg_color: &str) {
+        // Fill first so transparent areas of an image show the theme color
+        canvas.fill_background(bg_color);
+
+        if let Some(name) = self.theme.get("bg_image").and_then(|v| v.as_str()) {
+            // Image paths are relative to the themes directory and can't leave it
+            match theme_asset_path(&self.config.themes_dir, name) {
+                Some(path) => match Pixmap::load_png(&path) {
+                    Ok(image) => {
+                        canvas.fill_background_image(&image);
+                        return;
+                    }
+                    Err(e) => tracing::warn!("Could not load background image {:?}: {}", path, e),
+                },
+                None => tracing::warn!("Ignoring bg_image {:?}, it must be a relative path inside the themes directory", name),
+            }
+        }
+
+        if let Some(stops) = self.theme.get("bg_gradient").and_then(|v| v.as_array()) {
+            let colors: Vec<&str> = stops.iter().filter_map(|v| v.as_str()).collect();
+            if let [top, bottom] = colors[..] {
+                if canvas.fill_vertical_gradient(top, bottom) {
+                    return;
+                }
+            }
+            tracing::warn!("Ignoring invalid bg_gradient, expected two hex colors");
+        }
+    }
+
+    /// Draw the country outline inset with a marker at the map center
+    fn draw_locator_inset(
+        &self,
+        canvas: &mut Canvas,
+        data: &MapData,
+        corner: LocatorCorner,
+        bg_color: &str,
+        text_color: &str,
+    ) {
+        if data.country_outline.is_empty() {
+            return;
+        }
+
+        let mut rings = data.country_outline.clone();
+        let mut location = (data.lat, data.lon);
+        unwrap_antimeridian(&mut rings, &mut location);
+
+        let default_outline = mix_hex_colors(bg_color, text_color, 0.25).unwrap_or_else(|| text_color.to_string());
+        let outline_color = get_theme_color(&self.theme, "locator_fill", &default_outline);
+        let marker_color = get_theme_color(&self.theme, "locator_marker", text_color);
+
+        let trim = canvas.trim_box();
+        draw_locator(
             &mut canvas.pixmap,
-            &self.fonts,
-            &data.city,
-            &data.country,
-            coordinates,
-            &text_color,
+            trim,
+            &rings,
+            location,
+            corner,
+            &LocatorStyle {
+                outline_color: &outline_color,
+                marker_color: &marker_color,
+            },
         );
+    }
 
-        // Save the poster
-        report(GenerationProgress::saving());
-        canvas.save_png(output_path)?;
-        tracing::info!("Saved poster to {:?}", output_path);
+    /// Draw point markers, labelled with their names unless the theme sets `point_labels: false`
+    ///
+    /// Sizes are in pixels of a full-size poster and scale with the trim height.
+    fn draw_points(&self, canvas: &mut Canvas, points: &[PointFeature], text_color: &str, mode: BlendMode) {
+        let color = get_theme_color(&self.theme, "point_color", text_color);
+        let scale = canvas.trim_box().height() / POSTER_HEIGHT as f32;
+        let size = get_theme_number(&self.theme, "point_size", DEFAULT_POINT_SIZE).max(0.0) * scale;
+        if size <= 0.0 {
+            return;
+        }
+        canvas.draw_point_markers(points, &color, size);
 
-        report(GenerationProgress::completed());
-        Ok(())
+        if !get_theme_bool(&self.theme, "point_labels", true) {
+            return;
+        }
+        let label_size = size * POINT_LABEL_SCALE;
+        for point in points {
+            let Some(name) = point.name.as_deref() else {
+                continue;
+            };
+            // Label to the right of the marker, vertically centered on it
+            let (x, y) = canvas.geo_to_screen(point.lat, point.lon);
+            if !canvas.clip_contains(x, y) {
+                continue;
+            }
+            render_text(
+                &mut canvas.pixmap,
+                name,
+                self.fonts.regular(),
+                label_size,
+                &color,
+                x + size * 0.8,
+                y + label_size * 0.35,
+                false,
+                0.0,
+                mode,
+            );
+        }
+    }
+
+    /// Draw landuse areas, one subtle fill per landuse type enabled by the theme
+    fn draw_landuse(&self, canvas: &mut Canvas, landuse: &[AreaFeature], bg_color: &str) {
+        let text_color = get_theme_color(&self.theme, "text", "#000000");
+
+        for landuse_type in theme_landuse_types(&self.theme) {
+            let features: Vec<AreaFeature> = landuse
+                .iter()
+                .filter(|f| f.feature_type == landuse_type)
+                .cloned()
+                .collect();
+            if features.is_empty() {
+                continue;
+            }
+
+            // Default to a shade just off the background so landuse stays subtle
+            let default_color = mix_hex_colors(bg_color, &text_color, landuse_default_mix(&landuse_type))
+                .unwrap_or_else(|| bg_color.to_string());
+            let color = get_theme_color(&self.theme, &format!("landuse_{}", landuse_type), &default_color);
+            canvas.draw_polygons(&features, &color, None);
+        }
+    }
+
+    /// Draw parking and pitch areas, each category in its theme color (`parking`, `pitch`)
+    fn draw_detail_areas(&self, canvas: &mut Canvas, details: &[AreaFeature], bg_color: &str) {
+        let text_color = get_theme_color(&self.theme, "text", "#000000");
+        let parks_color = get_theme_color(&self.theme, "parks", "#F0F0F0");
+
+        for kind in [DetailArea::Parking, DetailArea::Pitch] {
+            let features: Vec<AreaFeature> = details
+                .iter()
+                .filter(|f| f.feature_type == kind.as_str())
+                .cloned()
+                .collect();
+            if features.is_empty() {
+                continue;
+            }
+
+            // Parking defaults to a shade off the background, pitches to a shade off the parks
+            let default_color = match kind {
+                DetailArea::Parking => mix_hex_colors(bg_color, &text_color, 0.1),
+                DetailArea::Pitch => mix_hex_colors(&parks_color, &text_color, 0.1),
+            }
+            .unwrap_or_else(|| bg_color.to_string());
+            let color = get_theme_color(&self.theme, kind.as_str(), &default_color);
+            canvas.draw_polygons(&features, &color, None);
+        }
+    }
+}
+
+/// Draw a rendered map over its template's rectangle
+///
+/// A preview map is smaller than the rectangle, so the template is scaled down to match.
+fn composite_into_template(map: &Pixmap, template: &PosterTemplate) -> Result<Pixmap> {
+    let image = Pixmap::load_png(&template.path)
+        .map_err(|e| AppError::Rendering(format!("Failed to load template {:?}: {}", template.path, e)))?;
+
+    let factor = map.width() as f64 / template.width.max(1) as f64;
+    let scale = |value: u32| (value as f64 * factor).round() as u32;
+    let mut output = if map.width() == template.width {
+        image
+    } else {
+        downscale_lanczos(&image, scale(image.width()).max(1), scale(image.height()).max(1))?
+    };
+    output.draw_pixmap(
+        scale(template.x) as i32,
+        scale(template.y) as i32,
+        map.as_ref(),
+        &PixmapPaint::default(),
+        Transform::identity(),
+        None,
+    );
+    Ok(output)
+}
+
+/// Suffix of posters and theme files still being written; they are renamed into place once complete
+pub const PARTIAL_SUFFIX: &str = ".part";
+
+/// Write an encoded poster and flush it to disk
+///
+/// A crash mid-write never leaves a truncated poster under the final name.
+pub async fn write_poster(path: &Path, png: &[u8]) -> Result<()> {
+    write_file_atomically(path, png).await
+}
+
+/// Write a file through a temporary file next to `path` that is renamed over it once flushed
+pub async fn write_file_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(".{}{}", uuid::Uuid::new_v4().simple(), PARTIAL_SUFFIX));
+    let partial = PathBuf::from(partial);
+
+    let written = async {
+        let mut file = tokio::fs::File::create(&partial).await?;
+        file.write_all(bytes).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&partial, path).await
+    };
+    if let Err(e) = written.await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// PNG files start with this signature
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Every PNG ends with this empty `IEND` chunk (length, type and CRC)
+const PNG_END: [u8; 12] = [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82];
+
+/// Whether the file at `path` is a whole PNG, judged by its signature and final `IEND` chunk
+///
+/// Cheap enough to run before every download: a write cut short loses the trailer, and
+/// only the first and last few bytes are read.
+pub fn png_is_complete(path: &Path) -> bool {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let check = || -> std::io::Result<bool> {
+        let mut file = std::fs::File::open(path)?;
+        let mut start = [0u8; 8];
+        file.read_exact(&mut start)?;
+        file.seek(SeekFrom::End(-(PNG_END.len() as i64)))?;
+        let mut end = [0u8; 12];
+        file.read_exact(&mut end)?;
+        Ok(start == PNG_SIGNATURE && end == PNG_END)
+    };
+    check().unwrap_or(false)
+}
+
+/// Width and height of the PNG at `path`, read from its `IHDR` chunk without decoding it
+///
+/// None if the file can't be read or doesn't start with a PNG signature and `IHDR`.
+pub fn png_dimensions(path: &Path) -> Option<(u32, u32)> {
+    use std::io::Read;
+
+    // Signature, then the IHDR chunk's length and type, width and height
+    let mut header = [0u8; 24];
+    std::fs::File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if header[..8] != PNG_SIGNATURE || &header[12..16] != b"IHDR" {
+        return None;
+    }
+    let read_u32 = |at: usize| u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+    Some((read_u32(16), read_u32(20)))
+}
+
+/// Delete leftover partial writes and truncated posters from the output directory
+///
+/// Run at startup, so files from a crash mid-write aren't served through `/static`.
+/// Returns the number of files removed.
+pub fn remove_incomplete_posters(dir: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let incomplete = name.ends_with(PARTIAL_SUFFIX) || (name.ends_with(".png") && !png_is_complete(&path));
+        if incomplete && path.is_file() {
+            match std::fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) => tracing::warn!("Could not remove incomplete poster {:?}: {}", path, e),
+            }
+        }
+    }
+    removed
+}
+
+/// Road width curve set by the theme's `width_curve`, defaulting to `sqrt`
+fn theme_width_curve(theme: &Value) -> WidthCurve {
+    match theme.get("width_curve").and_then(|v| v.as_str()) {
+        Some(name) => WidthCurve::from_name(name).unwrap_or_else(|| {
+            tracing::warn!("Ignoring unknown width_curve '{}' in theme", name);
+            WidthCurve::default()
+        }),
+        None => WidthCurve::default(),
+    }
+}
+
+/// A drawable map layer, orderable via the theme's `layer_order`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MapLayer {
+    Landuse,
+    Water,
+    Parks,
+    Details,
+    Roads,
+}
+
+impl MapLayer {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "landuse" => Some(MapLayer::Landuse),
+            "water" => Some(MapLayer::Water),
+            "parks" => Some(MapLayer::Parks),
+            "details" => Some(MapLayer::Details),
+            "roads" => Some(MapLayer::Roads),
+            _ => None,
+        }
+    }
+}
+
+/// Draw order used when a theme doesn't set `layer_order`
+const DEFAULT_LAYER_ORDER: [MapLayer; 5] =
+    [MapLayer::Landuse, MapLayer::Water, MapLayer::Parks, MapLayer::Details, MapLayer::Roads];
+
+/// Layer draw order for a theme (`layer_order` array, bottom first)
+///
+/// Unknown and duplicate names are ignored; layers left out of the list are not drawn.
+/// A missing or entirely invalid list falls back to the default order.
+fn theme_layer_order(theme: &Value) -> Vec<MapLayer> {
+    let Some(names) = theme.get("layer_order").and_then(|v| v.as_array()) else {
+        return DEFAULT_LAYER_ORDER.to_vec();
+    };
+
+    let mut order = Vec::new();
+    for name in names.iter().filter_map(|v| v.as_str()) {
+        match MapLayer::from_name(name) {
+            Some(layer) if !order.contains(&layer) => order.push(layer),
+            Some(_) => {}
+            None => tracing::warn!("Ignoring unknown layer '{}' in theme layer_order", name),
+        }
+    }
+
+    if order.is_empty() {
+        DEFAULT_LAYER_ORDER.to_vec()
+    } else {
+        order
+    }
+}
+
+/// Landuse types to render for a theme (`landuse_types` array, defaults to all fetched types)
+fn theme_landuse_types(theme: &Value) -> Vec<String> {
+    match theme.get("landuse_types").and_then(|v| v.as_array()) {
+        Some(types) => types
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(String::from)
+            .collect(),
+        None => LANDUSE_TYPES.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+/// How far the default landuse color moves from the background towards the text color
+fn landuse_default_mix(landuse_type: &str) -> f32 {
+    match landuse_type {
+        "industrial" => 0.08,
+        "commercial" | "retail" => 0.06,
+        _ => 0.04,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_poster_flushes_file() {
+        let path = std::env::temp_dir().join(format!("maptoposter-write-{}.png", std::process::id()));
+        let png = Canvas::new(4, 4).unwrap().pixmap.encode_png().unwrap();
+
+        write_poster(&path, &png).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), png);
+        assert!(png_is_complete(&path));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_incomplete_posters() {
+        let dir = std::env::temp_dir().join(format!("maptoposter-incomplete-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = Canvas::new(4, 4).unwrap().pixmap.encode_png().unwrap();
+        std::fs::write(dir.join("whole.png"), &png).unwrap();
+        std::fs::write(dir.join("truncated.png"), &png[..png.len() - 5]).unwrap();
+        std::fs::write(dir.join("empty.png"), b"").unwrap();
+        std::fs::write(dir.join("whole.png.0123.part"), &png).unwrap();
+        std::fs::write(dir.join("notes.txt"), b"kept").unwrap();
+
+        assert!(png_is_complete(&dir.join("whole.png")));
+        assert!(!png_is_complete(&dir.join("truncated.png")));
+        assert!(!png_is_complete(&dir.join("missing.png")));
+        assert_eq!(png_dimensions(&dir.join("whole.png")), Some((4, 4)));
+        assert_eq!(png_dimensions(&dir.join("notes.txt")), None);
+        assert_eq!(png_dimensions(&dir.join("empty.png")), None);
+
+        assert_eq!(remove_incomplete_posters(&dir), 3);
+        let mut left: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["notes.txt", "whole.png"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_water_base_fills_canvas_with_water() {
+        let mut config = Settings::defaults();
+        config.fonts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fonts");
+        let theme = serde_json::json!({ "bg": "#FFFFFF", "water": "#1040A0", "water_base": true });
+        let generator = PosterGenerator::with_fonts(theme, FontSet::load(&config.fonts_dir).unwrap(), &config);
+        let data = MapData {
+            city: "Malta".to_string(),
+            country: "Malta".to_string(),
+            lat: 35.9,
+            lon: 14.5,
+            distance: 4000,
+            streets: Vec::new(),
+            water: Vec::new(),
+            parks: Vec::new(),
+            landuse: Vec::new(),
+            details: Vec::new(),
+            points: Vec::new(),
+            country_outline: Vec::new(),
+            stats: DataStats::default(),
+        };
+        let options = RenderOptions {
+            width: 60,
+            height: 80,
+            framing: Framing::Distance,
+            ..Default::default()
+        };
+
+        let png = generator.render_png(&data, &options, None).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        let center = pixmap.pixel(30, 40).unwrap();
+        assert_eq!((center.red(), center.green(), center.blue()), (0x10, 0x40, 0xA0));
+        // Fades default to the water color too, so the edges don't wash out to `bg`
+        let top = pixmap.pixel(30, 0).unwrap();
+        assert_eq!((top.red(), top.green(), top.blue()), (0x10, 0x40, 0xA0));
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let mut config = Settings::defaults();
+        config.fonts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fonts");
+        let theme = serde_json::json!({ "bg": "#FFFFFF", "parks": "#20A040", "gradient_color": "#FFFFFF" });
+        let generator = PosterGenerator::with_fonts(theme, FontSet::load(&config.fonts_dir).unwrap(), &config);
+        let park = AreaFeature {
+            id: 1,
+            points: vec![(35.89, 14.49), (35.89, 14.51), (35.91, 14.51), (35.91, 14.49)],
+            feature_type: "park".to_string(),
+        };
+        let data = MapData {
+            city: "Malta".to_string(),
+            country: "Malta".to_string(),
+            lat: 35.9,
+            lon: 14.5,
+            distance: 4000,
+            streets: Vec::new(),
+            water: Vec::new(),
+            parks: vec![park],
+            landuse: Vec::new(),
+            details: Vec::new(),
+            points: Vec::new(),
+            country_outline: Vec::new(),
+            stats: DataStats::default(),
+        };
+        let options = RenderOptions {
+            width: 60,
+            height: 80,
+            framing: Framing::Distance,
+            transparent_background: true,
+            ..Default::default()
+        };
+
+        // The PNG keeps its alpha channel: the park is opaque, the rest of the map and the
+        // faded edges are see-through, with no `gradient_color` band
+        let png = generator.render_png(&data, &options, None).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        let center = pixmap.pixel(30, 40).unwrap();
+        assert_eq!((center.red(), center.green(), center.blue(), center.alpha()), (0x20, 0xA0, 0x40, 255));
+        assert_eq!(pixmap.pixel(3, 40).unwrap().alpha(), 0);
+        assert_eq!(pixmap.pixel(30, 0).unwrap().alpha(), 0);
+    }
+
+    #[test]
+    fn test_composite_into_template() {
+        let path = std::env::temp_dir().join(format!("maptoposter-template-{}.png", std::process::id()));
+        let mut image = Pixmap::new(20, 10).unwrap();
+        image.fill(tiny_skia::Color::WHITE);
+        image.save_png(&path).unwrap();
+        let template = PosterTemplate { path: path.clone(), x: 4, y: 2, width: 8, height: 6 };
+        let rgb = |pixmap: &Pixmap, x, y| {
+            let p = pixmap.pixel(x, y).unwrap();
+            (p.red(), p.green(), p.blue())
+        };
+
+        let mut map = Pixmap::new(8, 6).unwrap();
+        map.fill(tiny_skia::Color::from_rgba8(255, 0, 0, 255));
+        let output = composite_into_template(&map, &template).unwrap();
+        assert_eq!((output.width(), output.height()), (20, 10));
+        assert_eq!(rgb(&output, 4, 2), (255, 0, 0));
+        assert_eq!(rgb(&output, 11, 7), (255, 0, 0));
+        assert_eq!(rgb(&output, 3, 2), (255, 255, 255));
+        assert_eq!(rgb(&output, 12, 8), (255, 255, 255));
+
+        // A half-size preview map scales the template and rectangle with it
+        let mut preview = Pixmap::new(4, 3).unwrap();
+        preview.fill(tiny_skia::Color::from_rgba8(255, 0, 0, 255));
+        let output = composite_into_template(&preview, &template).unwrap();
+        assert_eq!((output.width(), output.height()), (10, 5));
+        assert_eq!(rgb(&output, 2, 1), (255, 0, 0));
+        assert_eq!(rgb(&output, 7, 1), (255, 255, 255));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_zoom_level_distance() {
+        let zoom = ZoomLevel { level: 14.0, poster_width_in: 12.0 };
+        assert_eq!(zoom.distance(0.0), 5503);
+        // Web Mercator pixels cover less ground away from the equator
+        assert_eq!(zoom.distance(60.0), 2752);
+        // Each zoom level halves the distance
+        assert_eq!(ZoomLevel { level: 13.0, ..zoom }.distance(0.0), 11007);
+    }
+
+    #[test]
+    fn test_into_preview_sizes() {
+        // Full 12x16" poster at 300 DPI previewed at 72 DPI, rendered at full size
+        let options = RenderOptions::default().into_preview(300, 72, 1.0);
+        assert_eq!((options.width, options.height), (POSTER_WIDTH, POSTER_HEIGHT));
+        assert_eq!(options.output_size, Some((864, 1152)));
+
+        // A small scale renders faster but never below the preview resolution
+        let options = RenderOptions::default().into_preview(300, 72, 0.5);
+        assert_eq!((options.width, options.height), (1800, 2400));
+        let options = RenderOptions::default().into_preview(300, 72, 0.1);
+        assert_eq!((options.width, options.height), (864, 1152));
+    }
+
+    #[test]
+    fn test_bleed_trim_box() {
+        assert_eq!(RenderOptions::default().trim_box(), None);
+
+        // 3mm at 300 DPI on a full poster
+        let options = RenderOptions { bleed: 35, ..Default::default() };
+        assert_eq!(options.canvas_size(), (POSTER_WIDTH + 70, POSTER_HEIGHT + 70));
+        assert_eq!(
+            options.trim_box(),
+            Some(TrimBox { x: 35, y: 35, width: POSTER_WIDTH, height: POSTER_HEIGHT })
+        );
+
+        // Previews scale the bleed with the rest of the image
+        let preview = options.into_preview(300, 72, 0.5);
+        assert_eq!((preview.width, preview.bleed), (1800, 18));
+        assert_eq!(preview.output_size, Some((864 + 16, 1152 + 16)));
+        assert_eq!(preview.trim_box(), Some(TrimBox { x: 9, y: 9, width: 862, height: 1150 }));
     }
 }
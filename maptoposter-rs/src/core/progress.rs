@@ -3,10 +3,16 @@ pub const STEP_GEOCODING: &str = "geocoding";
 pub const STEP_FETCHING_STREETS: &str = "fetching_streets";
 pub const STEP_FETCHING_WATER: &str = "fetching_water";
 pub const STEP_FETCHING_PARKS: &str = "fetching_parks";
+pub const STEP_FETCHING_LANDUSE: &str = "fetching_landuse";
+pub const STEP_FETCHING_DETAILS: &str = "fetching_details";
+pub const STEP_FETCHING_POINTS: &str = "fetching_points";
 pub const STEP_RENDERING_BACKGROUND: &str = "rendering_background";
+pub const STEP_RENDERING_LANDUSE: &str = "rendering_landuse";
 pub const STEP_RENDERING_WATER: &str = "rendering_water";
 pub const STEP_RENDERING_PARKS: &str = "rendering_parks";
+pub const STEP_RENDERING_DETAILS: &str = "rendering_details";
 pub const STEP_RENDERING_ROADS: &str = "rendering_roads";
+pub const STEP_RENDERING_POINTS: &str = "rendering_points";
 pub const STEP_RENDERING_GRADIENTS: &str = "rendering_gradients";
 pub const STEP_RENDERING_TEXT: &str = "rendering_text";
 pub const STEP_SAVING: &str = "saving";
@@ -45,10 +51,36 @@ impl GenerationProgress {
         Self::new(STEP_FETCHING_PARKS, 0.40, "Fetching park features...")
     }
 
+    pub fn fetching_landuse() -> Self {
+        Self::new(STEP_FETCHING_LANDUSE, 0.45, "Fetching landuse areas...")
+    }
+
+    pub fn fetching_details() -> Self {
+        Self::new(STEP_FETCHING_DETAILS, 0.47, "Fetching parking and pitch areas...")
+    }
+
+    pub fn fetching_points() -> Self {
+        Self::new(STEP_FETCHING_POINTS, 0.48, "Fetching peaks and places...")
+    }
+
+    /// Partway through a fetch step's download, interpolated towards the next step's progress
+    pub fn downloading(step: Self, next: &Self, fraction: f32) -> Self {
+        let fraction = fraction.clamp(0.0, 1.0);
+        Self {
+            progress: step.progress + (next.progress - step.progress) * fraction,
+            message: format!("{} {}%", step.message, (fraction * 100.0).round() as u32),
+            step: step.step,
+        }
+    }
+
     pub fn rendering_background() -> Self {
         Self::new(STEP_RENDERING_BACKGROUND, 0.50, "Rendering background...")
     }
 
+    pub fn rendering_landuse() -> Self {
+        Self::new(STEP_RENDERING_LANDUSE, 0.52, "Rendering landuse areas...")
+    }
+
     pub fn rendering_water() -> Self {
         Self::new(STEP_RENDERING_WATER, 0.55, "Rendering water features...")
     }
@@ -57,10 +89,18 @@ impl GenerationProgress {
         Self::new(STEP_RENDERING_PARKS, 0.60, "Rendering park features...")
     }
 
+    pub fn rendering_details() -> Self {
+        Self::new(STEP_RENDERING_DETAILS, 0.65, "Rendering parking and pitch areas...")
+    }
+
     pub fn rendering_roads() -> Self {
         Self::new(STEP_RENDERING_ROADS, 0.70, "Rendering road network...")
     }
 
+    pub fn rendering_points() -> Self {
+        Self::new(STEP_RENDERING_POINTS, 0.80, "Rendering peaks and places...")
+    }
+
     pub fn rendering_gradients() -> Self {
         Self::new(STEP_RENDERING_GRADIENTS, 0.85, "Applying gradient fades...")
     }
@@ -80,3 +120,20 @@ impl GenerationProgress {
 
 /// Progress callback type
 pub type ProgressCallback = Box<dyn Fn(GenerationProgress) + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downloading_interpolates_between_steps() {
+        let halfway = GenerationProgress::downloading(
+            GenerationProgress::fetching_streets(),
+            &GenerationProgress::fetching_water(),
+            0.5,
+        );
+        assert_eq!(halfway.step, STEP_FETCHING_STREETS);
+        assert!((halfway.progress - 0.225).abs() < 1e-6);
+        assert_eq!(halfway.message, "Fetching street network... 50%");
+    }
+}
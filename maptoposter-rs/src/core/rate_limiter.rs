@@ -1,8 +1,10 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use parking_lot::Mutex;
+use serde::Serialize;
 use tokio::time::sleep;
 
 /// A simple rate limiter that ensures minimum delay between requests
@@ -24,7 +26,7 @@ impl RateLimiter {
     pub async fn wait(&self, key: &str) {
         let now = Instant::now();
         let wait_duration = {
-            let mut last = self.last_request.lock();
+            let last = self.last_request.lock();
             if let Some(last_time) = last.get(key) {
                 let elapsed = now.duration_since(*last_time);
                 if elapsed < self.min_delay {
@@ -48,11 +50,50 @@ impl RateLimiter {
     }
 }
 
+/// Hit and miss counters for a cache
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Record the outcome of one lookup
+    pub fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Snapshot the counters for a cache currently holding `entries` entries
+    pub fn stats(&self, entries: usize) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheStats {
+            entries,
+            hits,
+            misses,
+            hit_ratio: if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 },
+        }
+    }
+}
+
+/// Point-in-time cache statistics
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits divided by lookups (0 before the first lookup)
+    pub hit_ratio: f64,
+}
+
 /// Simple in-memory cache with TTL
 pub struct Cache<V> {
     entries: Mutex<HashMap<String, CacheEntry<V>>>,
     ttl: Duration,
     max_entries: usize,
+    counters: CacheCounters,
 }
 
 struct CacheEntry<V> {
@@ -67,6 +108,7 @@ impl<V: Clone> Cache<V> {
             entries: Mutex::new(HashMap::new()),
             ttl: Duration::from_secs(ttl_secs),
             max_entries,
+            counters: CacheCounters::default(),
         }
     }
 
@@ -74,15 +116,17 @@ impl<V: Clone> Cache<V> {
     pub fn get(&self, key: &str) -> Option<V> {
         let mut entries = self.entries.lock();
 
-        if let Some(entry) = entries.get(key) {
-            if entry.inserted_at.elapsed() < self.ttl {
-                return Some(entry.value.clone());
-            } else {
+        let value = match entries.get(key) {
+            Some(entry) if entry.inserted_at.elapsed() < self.ttl => Some(entry.value.clone()),
+            Some(_) => {
                 // Entry expired, remove it
                 entries.remove(key);
+                None
             }
-        }
-        None
+            None => None,
+        };
+        self.counters.record(value.is_some());
+        value
     }
 
     /// Insert a value into the cache
@@ -125,32 +169,128 @@ impl<V: Clone> Cache<V> {
         }
     }
 
-    /// Clear all entries from the cache
-    pub fn clear(&self) {
-        self.entries.lock().clear();
+    /// Clear all entries from the cache, returning how many were removed
+    ///
+    /// Hit and miss counters are kept so lifetime statistics survive a flush.
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock();
+        let removed = entries.len();
+        entries.clear();
+        removed
+    }
+
+    /// Current entry count and lifetime hit/miss counters
+    pub fn stats(&self) -> CacheStats {
+        self.counters.stats(self.len())
     }
 
     /// Get the number of entries in the cache
     pub fn len(&self) -> usize {
         self.entries.lock().len()
     }
+}
+
+/// In-memory least-recently-used cache of byte blobs, bounded by their total size
+pub struct ByteCache {
+    inner: Mutex<ByteCacheInner>,
+    max_bytes: usize,
+    counters: CacheCounters,
+}
+
+#[derive(Default)]
+struct ByteCacheInner {
+    entries: HashMap<String, ByteCacheEntry>,
+    total_bytes: usize,
+    /// Incremented on every access; an entry's `last_used` is the tick it was last touched
+    tick: u64,
+}
+
+struct ByteCacheEntry {
+    bytes: Arc<[u8]>,
+    last_used: u64,
+}
+
+impl ByteCache {
+    /// Create a cache holding at most `max_bytes` in total (0 disables it)
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(ByteCacheInner::default()),
+            max_bytes,
+            counters: CacheCounters::default(),
+        }
+    }
+
+    /// Get a blob, marking it as the most recently used
+    pub fn get(&self, key: &str) -> Option<Arc<[u8]>> {
+        let mut inner = self.inner.lock();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let value = inner.entries.get_mut(key).map(|entry| {
+            entry.last_used = tick;
+            entry.bytes.clone()
+        });
+        self.counters.record(value.is_some());
+        value
+    }
+
+    /// Insert a blob, evicting the least recently used ones until it fits
+    ///
+    /// Blobs larger than the whole cache are not stored.
+    pub fn insert(&self, key: String, bytes: impl Into<Arc<[u8]>>) {
+        let bytes = bytes.into();
+        if bytes.len() > self.max_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock();
+        if let Some(old) = inner.entries.remove(&key) {
+            inner.total_bytes -= old.bytes.len();
+        }
+        while inner.total_bytes + bytes.len() > self.max_bytes {
+            let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                inner.total_bytes -= evicted.bytes.len();
+            }
+        }
+
+        inner.tick += 1;
+        let last_used = inner.tick;
+        inner.total_bytes += bytes.len();
+        inner.entries.insert(key, ByteCacheEntry { bytes, last_used });
+    }
+
+    /// Clear all entries, returning how many were removed (counters are kept)
+    pub fn clear(&self) -> usize {
+        let mut inner = self.inner.lock();
+        let removed = inner.entries.len();
+        inner.entries.clear();
+        inner.total_bytes = 0;
+        removed
+    }
 
-    /// Check if the cache is empty
-    pub fn is_empty(&self) -> bool {
-        self.entries.lock().is_empty()
+    /// Snapshot of entry count and hit/miss counters
+    pub fn stats(&self) -> CacheStats {
+        self.counters.stats(self.inner.lock().entries.len())
     }
 }
 
 /// Global rate limiters for external APIs
+///
+/// Nominatim requests are spaced by the geocoding client itself, see `send_nominatim`.
 pub struct ApiRateLimiters {
-    pub nominatim: RateLimiter,
     pub overpass: RateLimiter,
 }
 
 impl ApiRateLimiters {
-    pub fn new(nominatim_delay: f64, overpass_delay: f64) -> Self {
+    pub fn new(overpass_delay: f64) -> Self {
         Self {
-            nominatim: RateLimiter::new(nominatim_delay),
             overpass: RateLimiter::new(overpass_delay),
         }
     }
@@ -158,7 +298,7 @@ impl ApiRateLimiters {
 
 impl Default for ApiRateLimiters {
     fn default() -> Self {
-        Self::new(1.0, 0.5) // Nominatim: 1 req/sec, Overpass: 2 req/sec
+        Self::new(0.5) // Overpass: 2 req/sec
     }
 }
 
@@ -186,4 +326,47 @@ mod tests {
         // Should have evicted oldest entry
         assert!(cache.len() <= 2);
     }
+
+    #[test]
+    fn test_byte_cache_evicts_least_recently_used() {
+        let cache = ByteCache::new(10);
+
+        cache.insert("a".to_string(), vec![0; 4]);
+        cache.insert("b".to_string(), vec![0; 4]);
+        // Touching "a" leaves "b" as the least recently used
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), vec![0; 4]);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.inner.lock().total_bytes, 8);
+
+        // Replacing an entry doesn't count its old size twice
+        cache.insert("c".to_string(), vec![0; 6]);
+        assert_eq!(cache.inner.lock().total_bytes, 10);
+
+        // Too big for the whole cache
+        cache.insert("d".to_string(), vec![0; 11]);
+        assert!(cache.get("d").is_none());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn test_cache_stats() {
+        let cache: Cache<i32> = Cache::new(60, 10);
+        assert_eq!(cache.stats().hit_ratio, 0.0);
+
+        cache.insert("key1".to_string(), 1);
+        cache.get("key1");
+        cache.get("key1");
+        cache.get("missing");
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 2, 1));
+        assert!((stats.hit_ratio - 2.0 / 3.0).abs() < 1e-9);
+
+        assert_eq!(cache.clear(), 1);
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().hits, 2);
+    }
 }
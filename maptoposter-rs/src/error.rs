@@ -12,21 +12,57 @@ pub enum AppError {
     #[error("Geocoding failed: {0}")]
     Geocoding(String),
 
+    /// Nominatim is throttling us; the request can be retried later
+    #[error("Geocoding rate limited: {0}")]
+    GeocodingRateLimited(String),
+
     #[error("Theme not found: {0}")]
     ThemeNotFound(String),
 
     #[error("Invalid distance: {0}")]
     InvalidDistance(String),
 
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
     #[error("Data fetch failed: {0}")]
     DataFetch(String),
 
+    /// Overpass is failing everywhere and new fetches are refused until it recovers
+    #[error("Map service temporarily unavailable: {0}")]
+    MapServiceUnavailable(String),
+
     #[error("Rendering failed: {0}")]
     Rendering(String),
 
     #[error("Job not found: {0}")]
     JobNotFound(String),
 
+    #[error("Batch not found: {0}")]
+    BatchNotFound(String),
+
+    /// The job is still queued or running, so its poster isn't there yet
+    #[error("Job not completed: {0}")]
+    JobNotCompleted(String),
+
+    /// The job failed, so there is no poster to serve
+    #[error("Poster not found: {0}")]
+    PosterNotFound(String),
+
+    /// The job's cached map data was evicted or expired
+    #[error("Map data expired: {0}")]
+    MapDataExpired(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
+    /// Too many requests are in flight; shed before reaching a handler
+    #[error("Server overloaded")]
+    Overloaded,
+
     #[error("Internal error: {0}")]
     Internal(String),
 
@@ -55,6 +91,9 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_type, message) = match &self {
             AppError::Geocoding(msg) => (StatusCode::BAD_REQUEST, "geocoding_error", msg.clone()),
+            AppError::GeocodingRateLimited(msg) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "geocoding_rate_limited", msg.clone())
+            }
             AppError::ThemeNotFound(name) => (
                 StatusCode::NOT_FOUND,
                 "theme_not_found",
@@ -63,9 +102,17 @@ impl IntoResponse for AppError {
             AppError::InvalidDistance(msg) => {
                 (StatusCode::BAD_REQUEST, "invalid_distance", msg.clone())
             }
+            AppError::InvalidRequest(msg) => {
+                (StatusCode::BAD_REQUEST, "invalid_request", msg.clone())
+            }
             AppError::DataFetch(msg) => {
                 (StatusCode::SERVICE_UNAVAILABLE, "data_fetch_error", msg.clone())
             }
+            AppError::MapServiceUnavailable(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "map_service_unavailable",
+                format!("Map service temporarily unavailable: {}", msg),
+            ),
             AppError::Rendering(msg) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "rendering_error", msg.clone())
             }
@@ -74,6 +121,27 @@ impl IntoResponse for AppError {
                 "job_not_found",
                 format!("Job '{}' not found", id),
             ),
+            AppError::BatchNotFound(id) => (
+                StatusCode::NOT_FOUND,
+                "batch_not_found",
+                format!("Batch '{}' not found", id),
+            ),
+            AppError::JobNotCompleted(msg) => (StatusCode::CONFLICT, "job_not_completed", msg.clone()),
+            AppError::PosterNotFound(msg) => (StatusCode::NOT_FOUND, "poster_not_found", msg.clone()),
+            AppError::MapDataExpired(id) => (
+                StatusCode::GONE,
+                "map_data_expired",
+                format!("Map data for job '{}' is no longer cached, create the poster again", id),
+            ),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, "forbidden", msg.clone()),
+            AppError::Overloaded => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "overloaded",
+                "Server is busy, please retry shortly".to_string(),
+            ),
+            AppError::TooManyRequests(msg) => {
+                (StatusCode::TOO_MANY_REQUESTS, "too_many_requests", msg.clone())
+            }
             AppError::Internal(msg) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", msg.clone())
             }
@@ -1,12 +1,18 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
+    middleware,
     routing::{get, post},
     Router,
 };
+use tokio::sync::Semaphore;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
+use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -17,6 +23,7 @@ mod error;
 mod rendering;
 mod themes;
 
+use api::scheduler::Schedule;
 use api::state::AppState;
 
 #[tokio::main]
@@ -30,13 +37,41 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Load configuration
-    let config = config::Settings::from_env();
+    // Load configuration (.env and config file, overridden by the environment)
+    let config_path =
+        std::env::var("CONFIG_FILE").unwrap_or_else(|_| config::DEFAULT_CONFIG_FILE.to_string());
+    let config = match config::Settings::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = config.validate_contact() {
+        tracing::error!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    }
     tracing::info!("Starting MapToPoster Rust server");
     tracing::info!("Loaded {} themes", themes::loader::load_themes(&config.themes_dir).len());
+    let removed = core::poster_generator::remove_incomplete_posters(&config.static_dir);
+    if removed > 0 {
+        tracing::warn!("Removed {} incomplete poster file(s) from {:?}", removed, config.static_dir);
+    }
+
+    let schedule = match config.schedule_file.as_deref().map(|path| Schedule::load(path, &config)).transpose() {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Create application state
     let state = Arc::new(AppState::new(config.clone()));
+    if let Some(schedule) = schedule {
+        tokio::spawn(api::scheduler::run_schedule(state.clone(), schedule));
+    }
+    tokio::spawn(expire_jobs(state.clone()));
 
     // Build CORS layer
     let cors = CorsLayer::new()
@@ -44,26 +79,73 @@ async fn main() {
         .allow_methods(Any)
         .allow_headers(Any);
 
-    // Build the router
-    let app = Router::new()
-        // Health endpoints
+    // Compress JSON responses; PNGs (and ZIPs of them) are already compressed and SSE
+    // must stream unbuffered
+    let compression = CompressionLayer::new().compress_when(
+        SizeAbove::new(256)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::const_new("application/zip"))
+            .and(NotForContentType::SSE)
+            .and(NotForContentType::GRPC),
+    );
+
+    // Health endpoints are exempt from load shedding so probes keep working under load
+    let health_routes = Router::new()
         .route("/health", get(api::handlers::health::health_check))
         .route("/health/ready", get(api::handlers::health::readiness_check))
+        .route("/health/storage", get(api::handlers::health::storage_check));
+
+    let request_slots = Arc::new(Semaphore::new(config.max_inflight_requests.max(1)));
+
+    // Build the router
+    let api_routes = Router::new()
         // API routes
+        .route("/api/version", get(api::handlers::health::version_info))
+        .route("/api/stats", get(api::handlers::health::job_stats))
+        .route("/api/warmup", post(api::handlers::health::warmup))
+        .route("/api/config", get(api::handlers::health::effective_config))
+        .route("/api/schedule", get(api::handlers::health::schedule_status))
+        .route("/api/cache/stats", get(api::handlers::cache::cache_stats))
+        .route("/api/cache/clear", post(api::handlers::cache::clear_caches))
         .route("/api/themes", get(api::handlers::themes::list_themes))
         .route("/api/themes/:name", get(api::handlers::themes::get_theme))
+        .route("/api/themes/:name/raw", get(api::handlers::themes::get_theme_raw))
+        .route("/api/themes/:name/swatch", get(api::handlers::themes::get_theme_swatch))
+        .route("/api/themes/:name/normalize", post(api::handlers::themes::normalize_theme))
         .route("/api/locations/search", get(api::handlers::locations::search_locations))
+        .route("/api/locations/reverse", post(api::handlers::locations::reverse_geocode_location))
+        .route("/api/locations/batch", post(api::handlers::locations::batch_geocode_locations))
         .route("/api/posters", post(api::handlers::posters::create_poster))
+        .route("/api/posters/multi-theme", post(api::handlers::posters::create_multi_theme_posters))
+        .route("/api/posters/batch/:batch_id/download", get(api::handlers::posters::download_batch))
         .route("/api/posters/:job_id", get(api::handlers::posters::get_poster_status))
         .route("/api/posters/:job_id/download", get(api::handlers::posters::download_poster))
+        .route("/api/posters/:job_id/thumbnail", get(api::handlers::posters::get_poster_thumbnail))
         .route("/api/posters/:job_id/rerender", post(api::handlers::posters::rerender_poster))
-        .route("/api/posters/:job_id/stream", get(api::handlers::jobs::stream_progress))
-        // Also support /api/jobs path for frontend compatibility
-        .route("/api/jobs/:job_id/stream", get(api::handlers::jobs::stream_progress))
+        .route("/api/posters/:job_id/compare", post(api::handlers::posters::compare_themes))
+        .route("/api/posters/:job_id/confirm", post(api::handlers::posters::confirm_preview))
+        .route("/api/posters/:job_id/geometry", get(api::handlers::geometry::export_geometry))
         // Static files for generated posters
         .nest_service("/static", ServeDir::new(&config.static_dir))
         // Serve frontend
-        .nest_service("/", ServeDir::new(&config.frontend_dir).append_index_html_on_directories(true))
+        .nest_service("/", ServeDir::new(&config.frontend_dir).append_index_html_on_directories(true));
+    let api_routes = match config.request_timeout() {
+        Some(timeout) => api_routes.layer(TimeoutLayer::new(timeout)),
+        None => api_routes,
+    };
+
+    // Progress streams stay open for as long as the job runs, so they are exempt from the
+    // request timeout
+    let stream_routes = Router::new()
+        .route("/api/posters/:job_id/stream", get(api::handlers::jobs::stream_progress))
+        // Also support /api/jobs path for frontend compatibility
+        .route("/api/jobs/:job_id/stream", get(api::handlers::jobs::stream_progress));
+
+    let app = api_routes
+        .merge(stream_routes)
+        .layer(middleware::from_fn_with_state(request_slots, api::middleware::shed_load))
+        .merge(health_routes)
+        .layer(compression)
         .layer(cors)
         .layer(TraceLayer::new_for_http())
         .with_state(state);
@@ -72,6 +154,38 @@ async fn main() {
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     tracing::info!("Server listening on {}", addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let listener = bind_listener(addr, config.tcp_keepalive()).unwrap();
     axum::serve(listener, app).await.unwrap();
 }
+
+/// Listening socket with TCP keep-alive enabled, if configured
+///
+/// Accepted connections inherit the keep-alive settings, so clients that vanish without
+/// closing their connection (a dropped mobile link, a crashed browser) are eventually
+/// detected and their connections released.
+fn bind_listener(addr: SocketAddr, keepalive: Option<Duration>) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = socket2::Socket::new(socket2::Domain::for_address(addr), socket2::Type::STREAM, None)?;
+    // As tokio's own bind does, so a restart doesn't wait for old connections to time out
+    if cfg!(unix) {
+        socket.set_reuse_address(true)?;
+    }
+    if let Some(idle) = keepalive {
+        socket.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(idle))?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+/// How often jobs past `JOB_TTL_HOURS` are removed
+const JOB_EXPIRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Periodically remove jobs older than `JOB_TTL_HOURS`, with their cached data and files
+async fn expire_jobs(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(JOB_EXPIRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        state.cleanup_old_jobs();
+    }
+}
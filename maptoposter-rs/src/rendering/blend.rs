@@ -0,0 +1,174 @@
+use std::sync::OnceLock;
+
+use tiny_skia::PremultipliedColorU8;
+
+/// How overlay colors are composited onto existing pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Blend 8-bit sRGB values directly (fast, slightly dark edges)
+    #[default]
+    Srgb,
+    /// Convert to linear light, blend, and convert back (gamma-correct)
+    Linear,
+}
+
+impl BlendMode {
+    /// Blend mode for the `GAMMA_CORRECT` setting
+    pub fn from_gamma_correct(gamma_correct: bool) -> Self {
+        if gamma_correct {
+            BlendMode::Linear
+        } else {
+            BlendMode::Srgb
+        }
+    }
+}
+
+/// Composite a straight (non-premultiplied) color with coverage `alpha` over a pixel
+pub fn blend_over(pixel: &mut PremultipliedColorU8, r: u8, g: u8, b: u8, alpha: u8, mode: BlendMode) {
+    if alpha == 0 {
+        return;
+    }
+
+    match mode {
+        BlendMode::Srgb => blend_srgb(pixel, r, g, b, alpha),
+        BlendMode::Linear => blend_linear(pixel, r, g, b, alpha),
+    }
+}
+
+fn blend_srgb(pixel: &mut PremultipliedColorU8, r: u8, g: u8, b: u8, alpha: u8) {
+    let a = alpha as f32 / 255.0;
+    let inv_a = 1.0 - a;
+
+    // Demultiply the existing pixel
+    let existing_a = pixel.alpha();
+    if existing_a == 0 {
+        // Fully transparent, just set the new color
+        *pixel = PremultipliedColorU8::from_rgba(
+            (r as f32 * a) as u8,
+            (g as f32 * a) as u8,
+            (b as f32 * a) as u8,
+            alpha,
+        )
+        .unwrap();
+        return;
+    }
+
+    let existing_r = pixel.red() as f32 / existing_a as f32 * 255.0;
+    let existing_g = pixel.green() as f32 / existing_a as f32 * 255.0;
+    let existing_b = pixel.blue() as f32 / existing_a as f32 * 255.0;
+
+    // Blend, weighting the existing color by its coverage so partly transparent
+    // pixels (a transparent background's anti-aliased edges) don't darken
+    let existing_weight = existing_a as f32 / 255.0 * inv_a;
+    let out_a = a + existing_weight;
+    let new_r = ((r as f32 * a + existing_r * existing_weight) / out_a).min(255.0) as u8;
+    let new_g = ((g as f32 * a + existing_g * existing_weight) / out_a).min(255.0) as u8;
+    let new_b = ((b as f32 * a + existing_b * existing_weight) / out_a).min(255.0) as u8;
+    let new_a = (alpha as f32 + existing_a as f32 * inv_a).min(255.0) as u8;
+
+    // Pre-multiply and set
+    *pixel = PremultipliedColorU8::from_rgba(
+        (new_r as f32 * new_a as f32 / 255.0) as u8,
+        (new_g as f32 * new_a as f32 / 255.0) as u8,
+        (new_b as f32 * new_a as f32 / 255.0) as u8,
+        new_a,
+    )
+    .unwrap();
+}
+
+fn blend_linear(pixel: &mut PremultipliedColorU8, r: u8, g: u8, b: u8, alpha: u8) {
+    let a = alpha as f32 / 255.0;
+    let existing_a = pixel.alpha() as f32 / 255.0;
+
+    // Porter-Duff "over" on straight linear-light colors
+    let out_a = a + existing_a * (1.0 - a);
+    let channel = |src: u8, dst_premultiplied: u8| -> u8 {
+        let dst = if existing_a > 0.0 {
+            let straight = (dst_premultiplied as f32 / pixel.alpha() as f32 * 255.0).min(255.0);
+            srgb_to_linear(straight.round() as u8)
+        } else {
+            0.0
+        };
+        let linear = (srgb_to_linear(src) * a + dst * existing_a * (1.0 - a)) / out_a;
+        // Premultiply in sRGB space, as the pixmap stores it
+        (linear_to_srgb(linear) as f32 * out_a).round() as u8
+    };
+
+    let new_r = channel(r, pixel.red());
+    let new_g = channel(g, pixel.green());
+    let new_b = channel(b, pixel.blue());
+    let new_a = (out_a * 255.0).round() as u8;
+
+    *pixel = PremultipliedColorU8::from_rgba(new_r.min(new_a), new_g.min(new_a), new_b.min(new_a), new_a).unwrap();
+}
+
+/// Convert an 8-bit sRGB channel to linear light (0.0-1.0)
+pub fn srgb_to_linear(value: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0.0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *entry = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        table
+    })[value as usize]
+}
+
+/// Convert linear light (0.0-1.0) to an 8-bit sRGB channel
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let c = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> PremultipliedColorU8 {
+        PremultipliedColorU8::from_rgba(255, 255, 255, 255).unwrap()
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for v in [0u8, 1, 10, 128, 200, 255] {
+            assert_eq!(linear_to_srgb(srgb_to_linear(v)), v);
+        }
+    }
+
+    #[test]
+    fn test_half_black_over_white() {
+        // Naive sRGB blending lands near 127; linear light gives ~188, i.e. half the light
+        let mut srgb = white();
+        blend_over(&mut srgb, 0, 0, 0, 128, BlendMode::Srgb);
+        assert!((126..=128).contains(&srgb.red()), "got {}", srgb.red());
+
+        let mut linear = white();
+        blend_over(&mut linear, 0, 0, 0, 128, BlendMode::Linear);
+        assert!((186..=189).contains(&linear.red()), "got {}", linear.red());
+        assert_eq!(linear.alpha(), 255);
+
+        // Onto a transparent pixel both modes give the source color at that coverage
+        let mut empty = PremultipliedColorU8::from_rgba(0, 0, 0, 0).unwrap();
+        blend_over(&mut empty, 255, 0, 0, 255, BlendMode::Linear);
+        assert_eq!((empty.red(), empty.alpha()), (255, 255));
+
+        // Half-covered red under half-covered blue: straight over, not darkened
+        for mode in [BlendMode::Srgb, BlendMode::Linear] {
+            let mut edge = PremultipliedColorU8::from_rgba(128, 0, 0, 128).unwrap();
+            blend_over(&mut edge, 0, 0, 255, 128, mode);
+            let color = edge.demultiply();
+            assert!((190..=192).contains(&edge.alpha()), "{:?} alpha {}", mode, edge.alpha());
+            assert!(color.red() > 70 && color.blue() > 150, "{:?} {:?}", mode, color);
+        }
+    }
+}
@@ -1,15 +1,230 @@
-use std::path::Path;
+use rayon::prelude::*;
+use tiny_skia::{
+    Color, FillRule, FilterQuality, GradientStop, LineCap, LineJoin, LinearGradient, Mask, Paint, Path as SkPath,
+    PathBuilder, Pixmap, PixmapPaint, Point, Rect, SpreadMode, Stroke, StrokeDash, Transform,
+};
+use serde::Deserialize;
+use serde_json::Value;
 
-use tiny_skia::{Color, FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Stroke, Transform};
-
-use crate::core::osm_client::{AreaFeature, HighwayType, RoadSegment};
+use crate::core::osm_client::{geodesic_distance, longitude_delta, METERS_PER_DEGREE_LAT, AreaFeature, HighwayType, PointFeature, PointKind, RoadSegment};
 use crate::error::{AppError, Result};
-use crate::themes::loader::{get_theme_color, parse_hex_color};
+use crate::rendering::road_styles::{road_color, RoadRamp, WidthBy};
+use crate::themes::loader::{get_theme_bool, get_theme_number, parse_hex_color};
 
 /// Canvas dimensions for poster (12x16 inches at 300 DPI)
 pub const POSTER_WIDTH: u32 = 3600;
 pub const POSTER_HEIGHT: u32 = 4800;
 
+/// Default minimum rendered road stroke width in pixels (theme key `road_min_width`)
+pub const DEFAULT_MIN_ROAD_WIDTH: f32 = 0.2;
+
+/// Default spacing between one-way chevrons in pixels (theme key `oneway_spacing`)
+pub const DEFAULT_ONEWAY_SPACING: f32 = 120.0;
+
+/// Default extra width of a road casing over its road in pixels (theme key `road_casing_width`)
+pub const DEFAULT_CASING_WIDTH: f32 = 1.5;
+
+/// Default outline width in pixels when a layer sets `<layer>_outline_color` alone
+pub const DEFAULT_OUTLINE_WIDTH: f32 = 1.0;
+
+/// Default width of the water shoreline band in pixels (theme key `water_shoreline_width`)
+pub const DEFAULT_SHORELINE_WIDTH: f32 = 24.0;
+
+/// Default opacity of the shoreline band at the water's edge (theme key `water_shoreline_strength`)
+pub const DEFAULT_SHORELINE_STRENGTH: f32 = 0.35;
+
+/// Number of nested strokes the shoreline gradient is built from
+const SHORELINE_STEPS: u32 = 8;
+
+/// Polygon count above which paths are built in parallel (below it rayon overhead dominates)
+const PARALLEL_PATH_THRESHOLD: usize = 256;
+
+/// Largest canvas side we are willing to allocate, in pixels
+pub const MAX_CANVAS_DIMENSION: u32 = 8000;
+
+/// Drawing order of a highway type (roads with a higher value are drawn on top)
+fn road_draw_priority(highway_type: HighwayType) -> u8 {
+    match highway_type {
+        HighwayType::Motorway | HighwayType::MotorwayLink => 10,
+        HighwayType::Trunk | HighwayType::Primary | HighwayType::PrimaryLink => 8,
+        HighwayType::Secondary | HighwayType::SecondaryLink => 6,
+        HighwayType::Tertiary | HighwayType::TertiaryLink => 4,
+        _ => 2,
+    }
+}
+
+/// Round-capped road stroke of the given width
+fn road_stroke(width: f32) -> Stroke {
+    Stroke {
+        width,
+        line_cap: LineCap::Round,
+        line_join: LineJoin::Round,
+        ..Default::default()
+    }
+}
+
+/// Optional edge stroked around a polygon layer after it is filled
+///
+/// Configured per layer with `<layer>_outline_color`, `<layer>_outline_width` and
+/// `<layer>_outline_dash` (an `[on, off]` pair of pixel lengths).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolygonOutline {
+    pub color: (u8, u8, u8),
+    pub width: f32,
+    pub dash: Option<(f32, f32)>,
+}
+
+impl PolygonOutline {
+    /// Outline for `layer` (e.g. "parks" or "water"), or None unless its color is a valid hex
+    pub fn from_theme(theme: &Value, layer: &str) -> Option<Self> {
+        let color = theme
+            .get(format!("{}_outline_color", layer))
+            .and_then(|v| v.as_str())
+            .and_then(parse_hex_color)?;
+        let width = get_theme_number(theme, &format!("{}_outline_width", layer), DEFAULT_OUTLINE_WIDTH);
+        if width <= 0.0 {
+            return None;
+        }
+        let dash = theme
+            .get(format!("{}_outline_dash", layer))
+            .and_then(|v| v.as_array())
+            .filter(|pair| pair.len() == 2)
+            .and_then(|pair| Some((pair[0].as_f64()? as f32, pair[1].as_f64()? as f32)))
+            .filter(|&(on, off)| on > 0.0 && off > 0.0);
+        Some(Self { color, width, dash })
+    }
+
+    fn stroke(&self) -> Stroke {
+        Stroke {
+            width: self.width,
+            line_join: LineJoin::Round,
+            dash: self.dash.and_then(|(on, off)| StrokeDash::new(vec![on, off], 0.0)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Soft band along the inside of water polygons, fading from `color` at the edge to nothing
+///
+/// Enabled with the `water_shoreline` theme flag and configured with `water_shoreline_color`
+/// (default: the land color, so shallows look lighter on a light theme), `water_shoreline_width`
+/// and `water_shoreline_strength`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shoreline {
+    pub color: (u8, u8, u8),
+    pub width: f32,
+    pub strength: f32,
+}
+
+impl Shoreline {
+    /// Shoreline from the theme, or None unless `water_shoreline` is set and the band is visible
+    pub fn from_theme(theme: &Value, land_color: &str) -> Option<Self> {
+        if !get_theme_bool(theme, "water_shoreline", false) {
+            return None;
+        }
+        let color = theme
+            .get("water_shoreline_color")
+            .and_then(|v| v.as_str())
+            .and_then(parse_hex_color)
+            .or_else(|| parse_hex_color(land_color))?;
+        let width = get_theme_number(theme, "water_shoreline_width", DEFAULT_SHORELINE_WIDTH);
+        let strength = get_theme_number(theme, "water_shoreline_strength", DEFAULT_SHORELINE_STRENGTH).clamp(0.0, 1.0);
+        (width > 0.0 && strength > 0.0).then_some(Self { color, width, strength })
+    }
+}
+
+/// A road segment projected to screen space with its resolved style
+struct StyledRoad {
+    priority: u8,
+    path: SkPath,
+    screen_points: Vec<(f32, f32)>,
+    paint: Paint<'static>,
+    line_width: f32,
+    oneway: bool,
+}
+
+/// Unsigned area of a closed polygon via the shoelace formula
+pub fn shoelace_area(points: &[(f32, f32)]) -> f32 {
+    let twice_area: f64 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.0 as f64 * b.1 as f64 - b.0 as f64 * a.1 as f64)
+        .sum();
+    (twice_area / 2.0).abs() as f32
+}
+
+/// Stroke width for a road of unscaled `line_width`, never thinner than `min_width`
+pub fn road_stroke_width(line_width: f32, base_width_multiplier: f32, min_width: f32) -> f32 {
+    (line_width * base_width_multiplier).max(min_width)
+}
+
+/// Evenly spaced chevron anchors along a screen-space polyline
+///
+/// Returns the position and unit direction of each chevron. The count grows with the
+/// polyline length, so roads shorter than `spacing` get none.
+pub fn chevron_positions(points: &[(f32, f32)], spacing: f32) -> Vec<((f32, f32), (f32, f32))> {
+    let lengths: Vec<f32> = points
+        .windows(2)
+        .map(|w| ((w[1].0 - w[0].0).powi(2) + (w[1].1 - w[0].1).powi(2)).sqrt())
+        .collect();
+    let total: f32 = lengths.iter().sum();
+    if spacing <= 0.0 || total < spacing {
+        return Vec::new();
+    }
+
+    // Center the chevrons within the polyline
+    let count = (total / spacing).floor() as usize;
+    let step = total / count as f32;
+    let mut targets = (0..count).map(|i| (i as f32 + 0.5) * step).peekable();
+
+    let mut positions = Vec::with_capacity(count);
+    let mut walked = 0.0;
+    for (w, &len) in points.windows(2).zip(&lengths) {
+        while let Some(&target) = targets.peek() {
+            if target > walked + len || len == 0.0 {
+                break;
+            }
+            let t = (target - walked) / len;
+            let dir = ((w[1].0 - w[0].0) / len, (w[1].1 - w[0].1) / len);
+            let pos = (w[0].0 + dir.0 * len * t, w[0].1 + dir.1 * len * t);
+            positions.push((pos, dir));
+            targets.next();
+        }
+        walked += len;
+    }
+
+    positions
+}
+
+/// Resolve a named paper size to canvas dimensions at the given DPI
+pub fn paper_size_pixels(name: &str, dpi: u32) -> Option<(u32, u32)> {
+    let (width_in, height_in) = match name.to_lowercase().as_str() {
+        "12x16" | "poster" => (12.0, 16.0),
+        "8x10" => (8.0, 10.0),
+        "11x14" => (11.0, 14.0),
+        "12x12" | "square" => (12.0, 12.0),
+        "a4" => (8.27, 11.69),
+        "a3" => (11.69, 16.54),
+        _ => return None,
+    };
+
+    Some((
+        (width_in * dpi as f32).round() as u32,
+        (height_in * dpi as f32).round() as u32,
+    ))
+}
+
+/// How geographic bounds are fitted to a canvas of a different aspect ratio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Show all of the bounds, leaving margins along the shorter axis
+    #[default]
+    Contain,
+    /// Fill the canvas, trimming the bounds along the longer axis
+    Cover,
+}
+
 /// Canvas for rendering the poster
 pub struct Canvas {
     pub pixmap: Pixmap,
@@ -20,6 +235,18 @@ pub struct Canvas {
     geo_scale: f64,
     /// Latitude correction factor for proper projection (cos of center latitude)
     lat_correction: f64,
+    /// Polygons with a smaller screen-space area (square pixels) are not drawn
+    min_polygon_area: f32,
+    /// Print bleed in pixels on every side; the map is fitted to the trim box inside it
+    bleed: u32,
+    /// Smooth polygon and road edges (off for hard, pixel-art edges)
+    anti_alias: bool,
+    /// Whether geo bounds are letterboxed into the trim box or cropped to fill it
+    fit_mode: FitMode,
+    /// Map layers are only drawn inside this mask, when set
+    clip: Option<Mask>,
+    /// Roads lying wholly within this many meters of this (lat, lon) are not drawn
+    center_clear_zone: Option<((f64, f64), f64)>,
 }
 
 impl Canvas {
@@ -35,12 +262,87 @@ impl Canvas {
             geo_center: (0.0, 0.0),
             geo_scale: 1.0,
             lat_correction: 1.0,
+            min_polygon_area: 0.0,
+            bleed: 0,
+            anti_alias: true,
+            fit_mode: FitMode::Contain,
+            clip: None,
+            center_clear_zone: None,
         })
     }
 
-    /// Create a poster-sized canvas
-    pub fn poster() -> Result<Self> {
-        Self::new(POSTER_WIDTH, POSTER_HEIGHT)
+    /// Reserve `bleed` pixels on every side beyond the trim line (clamped to leave a 1px trim box)
+    pub fn set_bleed(&mut self, bleed: u32) {
+        self.bleed = bleed.min((self.width.min(self.height).saturating_sub(1)) / 2);
+    }
+
+    /// The area inside the bleed, where the poster is trimmed
+    pub fn trim_box(&self) -> Rect {
+        let bleed = self.bleed as f32;
+        Rect::from_xywh(
+            bleed,
+            bleed,
+            (self.width - 2 * self.bleed) as f32,
+            (self.height - 2 * self.bleed) as f32,
+        )
+        .expect("trim box is at least 1px")
+    }
+
+    /// Skip polygons smaller than `area` square pixels once projected (0 draws everything)
+    pub fn set_min_polygon_area(&mut self, area: f32) {
+        self.min_polygon_area = area.max(0.0);
+    }
+
+    /// Leave out roads whose points all lie within `meters` of `center`, e.g. to make room for
+    /// a landmark
+    pub fn set_center_clear_zone(&mut self, center: (f64, f64), meters: f64) {
+        self.center_clear_zone = Some((center, meters));
+    }
+
+    /// Fit geo bounds inside the trim box (the default) or crop them to fill it; set before
+    /// the geo transform
+    pub fn set_fit_mode(&mut self, fit_mode: FitMode) {
+        self.fit_mode = fit_mode;
+    }
+
+    /// Draw polygon and road edges anti-aliased (the default) or hard-edged
+    pub fn set_anti_alias(&mut self, anti_alias: bool) {
+        self.anti_alias = anti_alias;
+    }
+
+    /// Restrict map layers to a circle of `meters` around a point, once the geo transform is set
+    ///
+    /// At poster distances the projection is locally conformal, so the geodesic circle
+    /// projects to a circle on screen.
+    pub fn clip_to_radius(&mut self, center: (f64, f64), meters: f64) {
+        let (x, y) = self.geo_to_screen(center.0, center.1);
+        let radius = (meters / METERS_PER_DEGREE_LAT * self.geo_scale) as f32;
+        let (Some(mut mask), Some(circle)) = (Mask::new(self.width, self.height), PathBuilder::from_circle(x, y, radius)) else {
+            return;
+        };
+        mask.fill_path(&circle, FillRule::Winding, self.anti_alias, Transform::identity());
+        self.clip = Some(mask);
+    }
+
+    /// Whether a screen point lies inside the clip (everywhere, without one)
+    pub fn clip_contains(&self, x: f32, y: f32) -> bool {
+        let Some(mask) = &self.clip else {
+            return true;
+        };
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return false;
+        }
+        mask.data()[y as usize * self.width as usize + x as usize] > 0
+    }
+
+    /// Fill the clipped area with a color (the whole canvas without a clip)
+    pub fn fill_clip(&mut self, hex_color: &str) {
+        if let Some((r, g, b)) = parse_hex_color(hex_color) {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, 255);
+            let rect = Rect::from_xywh(0.0, 0.0, self.width as f32, self.height as f32).expect("canvas is not empty");
+            self.pixmap.fill_rect(rect, &paint, Transform::identity(), self.clip.as_ref());
+        }
     }
 
     /// Fill the entire canvas with a color
@@ -51,14 +353,71 @@ impl Canvas {
         }
     }
 
+    /// Fill a rectangle with a solid color
+    pub fn fill_rect(&mut self, rect: Rect, hex_color: &str) {
+        if let Some((r, g, b)) = parse_hex_color(hex_color) {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, 255);
+            self.pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+    }
+
+    /// Fill the canvas with a top-to-bottom two-color gradient
+    ///
+    /// Returns false (leaving the canvas untouched) if either color is invalid.
+    pub fn fill_vertical_gradient(&mut self, top_hex: &str, bottom_hex: &str) -> bool {
+        let (Some((tr, tg, tb)), Some((br, bg, bb))) = (parse_hex_color(top_hex), parse_hex_color(bottom_hex)) else {
+            return false;
+        };
+
+        let shader = LinearGradient::new(
+            Point::from_xy(0.0, 0.0),
+            Point::from_xy(0.0, self.height as f32),
+            vec![
+                GradientStop::new(0.0, Color::from_rgba8(tr, tg, tb, 255)),
+                GradientStop::new(1.0, Color::from_rgba8(br, bg, bb, 255)),
+            ],
+            SpreadMode::Pad,
+            Transform::identity(),
+        );
+        let (Some(shader), Some(rect)) = (shader, Rect::from_xywh(0.0, 0.0, self.width as f32, self.height as f32))
+        else {
+            return false;
+        };
+
+        let paint = Paint {
+            shader,
+            ..Default::default()
+        };
+        self.pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        true
+    }
+
+    /// Fill the canvas with an image, scaled to cover it and centered (overflow is cropped)
+    pub fn fill_background_image(&mut self, image: &Pixmap) {
+        let scale = (self.width as f32 / image.width() as f32).max(self.height as f32 / image.height() as f32);
+        let offset_x = (self.width as f32 - image.width() as f32 * scale) / 2.0;
+        let offset_y = (self.height as f32 - image.height() as f32 * scale) / 2.0;
+
+        let paint = PixmapPaint {
+            quality: FilterQuality::Bicubic,
+            ..Default::default()
+        };
+        let transform = Transform::from_row(scale, 0.0, 0.0, scale, offset_x, offset_y);
+        self.pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+    }
+
     /// Set the coordinate transform based on geographic bounds
     pub fn set_geo_transform(&mut self, bounds: ((f64, f64), (f64, f64))) {
+        self.set_geo_transform_with_padding(bounds, 0.05); // 5% padding
+    }
+
+    /// Set the coordinate transform so the bounds fit the trim box with fractional padding
+    pub fn set_geo_transform_with_padding(&mut self, bounds: ((f64, f64), (f64, f64)), padding: f64) {
         let ((min_lat, min_lon), (max_lat, max_lon)) = bounds;
 
-        // Add some padding
         let lat_range = max_lat - min_lat;
         let lon_range = max_lon - min_lon;
-        let padding = 0.05; // 5% padding
 
         let min_lat = min_lat - lat_range * padding;
         let max_lat = max_lat + lat_range * padding;
@@ -81,9 +440,13 @@ impl Canvas {
         let adjusted_lon_range = lon_range * lat_correction;
 
         // Calculate scale to fit the poster while maintaining aspect ratio
-        let scale_x = self.width as f64 / adjusted_lon_range;
-        let scale_y = self.height as f64 / lat_range;
-        let scale = scale_x.min(scale_y);
+        // Fit to the trim box; the bleed shows whatever lies just beyond it
+        let scale_x = (self.width - 2 * self.bleed) as f64 / adjusted_lon_range;
+        let scale_y = (self.height - 2 * self.bleed) as f64 / lat_range;
+        let scale = match self.fit_mode {
+            FitMode::Contain => scale_x.min(scale_y),
+            FitMode::Cover => scale_x.max(scale_y),
+        };
 
         // Store transform parameters
         self.geo_center = (center_lat, center_lon);
@@ -97,7 +460,8 @@ impl Canvas {
 
         // Convert lon to x (lon increases = x increases)
         // Apply latitude correction to account for convergence of meridians
-        let x = (lon - center_lon) * self.lat_correction * self.geo_scale + (self.width as f64 / 2.0);
+        // Measure the short way round so maps straddling the antimeridian stay contiguous
+        let x = longitude_delta(lon, center_lon) * self.lat_correction * self.geo_scale + (self.width as f64 / 2.0);
 
         // Convert lat to y (lat increases = y decreases, since screen y goes down)
         let y = (center_lat - lat) * self.geo_scale + (self.height as f64 / 2.0);
@@ -105,8 +469,28 @@ impl Canvas {
         (x as f32, y as f32)
     }
 
+    /// Run `draw` on a transparent layer, then composite the layer onto the canvas at `opacity`
+    ///
+    /// Drawing the whole layer first keeps overlapping shapes from stacking up darker, as
+    /// they would with per-paint alpha. Fully opaque layers are drawn directly.
+    pub fn draw_layer(&mut self, opacity: f32, draw: impl FnOnce(&mut Self)) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        if opacity >= 1.0 {
+            draw(self);
+            return;
+        }
+        let Some(layer) = Pixmap::new(self.width, self.height) else {
+            return;
+        };
+        let below = std::mem::replace(&mut self.pixmap, layer);
+        draw(self);
+        let layer = std::mem::replace(&mut self.pixmap, below);
+        let paint = PixmapPaint { opacity, ..PixmapPaint::default() };
+        self.pixmap.draw_pixmap(0, 0, layer.as_ref(), &paint, Transform::identity(), None);
+    }
+
     /// Draw filled polygons (for water, parks)
-    pub fn draw_polygons(&mut self, features: &[AreaFeature], hex_color: &str) {
+    pub fn draw_polygons(&mut self, features: &[AreaFeature], hex_color: &str, outline: Option<&PolygonOutline>) {
         let (r, g, b) = match parse_hex_color(hex_color) {
             Some(c) => c,
             None => return,
@@ -114,31 +498,134 @@ impl Canvas {
 
         let mut paint = Paint::default();
         paint.set_color_rgba8(r, g, b, 255);
-        paint.anti_alias = true;
+        paint.anti_alias = self.anti_alias;
 
-        for feature in features {
-            if feature.points.len() < 3 {
-                continue;
+        // Build paths in parallel, then fill serially in the original order
+        // (the pixmap can only be mutated from one thread)
+        let paths: Vec<SkPath> = if features.len() >= PARALLEL_PATH_THRESHOLD {
+            features.par_iter().filter_map(|f| self.polygon_path(f)).collect()
+        } else {
+            features.iter().filter_map(|f| self.polygon_path(f)).collect()
+        };
+
+        for path in &paths {
+            self.pixmap.fill_path(
+                path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                self.clip.as_ref(),
+            );
+        }
+
+        // Outlines go on after every fill so a neighbour can't cover half an edge
+        if let Some(outline) = outline {
+            let (r, g, b) = outline.color;
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, 255);
+            paint.anti_alias = self.anti_alias;
+            let stroke = outline.stroke();
+            for path in &paths {
+                self.pixmap.stroke_path(path, &paint, &stroke, Transform::identity(), self.clip.as_ref());
             }
+        }
+    }
 
-            let mut pb = PathBuilder::new();
-            let (x, y) = self.geo_to_screen(feature.points[0].0, feature.points[0].1);
-            pb.move_to(x, y);
+    /// Draw a shoreline gradient inside filled polygons (for water)
+    ///
+    /// The boundary is stroked at widths shrinking from twice the band width to nothing, each
+    /// stroke adding a slice of the opacity, and masked to the polygons' interior. Pixels at
+    /// the edge are covered by every stroke and those deeper in by fewer, so the band fades
+    /// out linearly towards the middle of the water.
+    pub fn draw_shoreline(&mut self, features: &[AreaFeature], shoreline: &Shoreline) {
+        let paths: Vec<SkPath> = features.iter().filter_map(|f| self.polygon_path(f)).collect();
+        if paths.is_empty() {
+            return;
+        }
+        let Some(mut mask) = Mask::new(self.width, self.height) else {
+            return;
+        };
+        for path in &paths {
+            mask.fill_path(path, FillRule::Winding, self.anti_alias, Transform::identity());
+        }
+        if let Some(clip) = &self.clip {
+            for (value, clip_value) in mask.data_mut().iter_mut().zip(clip.data()) {
+                *value = ((*value as u16 * *clip_value as u16) / 255) as u8;
+            }
+        }
 
-            for (lat, lon) in &feature.points[1..] {
-                let (x, y) = self.geo_to_screen(*lat, *lon);
-                pb.line_to(x, y);
+        let (r, g, b) = shoreline.color;
+        let alpha = (shoreline.strength / SHORELINE_STEPS as f32 * 255.0).round().max(1.0) as u8;
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(r, g, b, alpha);
+        paint.anti_alias = self.anti_alias;
+        for step in 0..SHORELINE_STEPS {
+            let stroke = Stroke {
+                width: 2.0 * shoreline.width * (SHORELINE_STEPS - step) as f32 / SHORELINE_STEPS as f32,
+                line_join: LineJoin::Round,
+                ..Default::default()
+            };
+            for path in &paths {
+                self.pixmap.stroke_path(path, &paint, &stroke, Transform::identity(), Some(&mask));
             }
-            pb.close();
+        }
+    }
 
-            if let Some(path) = pb.finish() {
-                self.pixmap.fill_path(
-                    &path,
-                    &paint,
-                    FillRule::Winding,
-                    Transform::identity(),
-                    None,
-                );
+    /// Build the closed screen-space path for a polygon feature
+    ///
+    /// Returns None for polygons below the minimum polygon area, which would not be
+    /// visible at poster scale anyway.
+    fn polygon_path(&self, feature: &AreaFeature) -> Option<SkPath> {
+        if feature.points.len() < 3 {
+            return None;
+        }
+
+        let screen: Vec<(f32, f32)> = feature
+            .points
+            .iter()
+            .map(|(lat, lon)| self.geo_to_screen(*lat, *lon))
+            .collect();
+        if self.min_polygon_area > 0.0 && shoelace_area(&screen) < self.min_polygon_area {
+            return None;
+        }
+
+        let mut pb = PathBuilder::new();
+        pb.move_to(screen[0].0, screen[0].1);
+        for (x, y) in &screen[1..] {
+            pb.line_to(*x, *y);
+        }
+        pb.close();
+
+        pb.finish()
+    }
+
+    /// Draw a marker of `size` pixels for each point: a triangle for peaks, a dot for places
+    pub fn draw_point_markers(&mut self, points: &[PointFeature], hex_color: &str, size: f32) {
+        let Some(paint) = parse_hex_color(hex_color).map(|(r, g, b)| {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, 255);
+            paint.anti_alias = self.anti_alias;
+            paint
+        }) else {
+            return;
+        };
+
+        let half = size / 2.0;
+        for point in points {
+            let (x, y) = self.geo_to_screen(point.lat, point.lon);
+            let path = match point.kind {
+                PointKind::Peak => {
+                    let mut pb = PathBuilder::new();
+                    pb.move_to(x, y - half);
+                    pb.line_to(x + half, y + half);
+                    pb.line_to(x - half, y + half);
+                    pb.close();
+                    pb.finish()
+                }
+                PointKind::Place => PathBuilder::from_circle(x, y, half * 0.7),
+            };
+            if let Some(path) = path {
+                self.pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), self.clip.as_ref());
             }
         }
     }
@@ -149,24 +636,54 @@ impl Canvas {
         segments: &[RoadSegment],
         theme: &serde_json::Value,
         base_width_multiplier: f32,
+        road_types: Option<&[HighwayType]>,
+        width_by: WidthBy,
     ) {
-        // Sort segments by highway type priority (draw minor roads first)
-        let mut sorted_segments: Vec<&RoadSegment> = segments.iter().collect();
-        sorted_segments.sort_by_key(|s| match s.highway_type {
-            HighwayType::Motorway | HighwayType::MotorwayLink => 10,
-            HighwayType::Trunk | HighwayType::Primary | HighwayType::PrimaryLink => 8,
-            HighwayType::Secondary | HighwayType::SecondaryLink => 6,
-            HighwayType::Tertiary | HighwayType::TertiaryLink => 4,
-            _ => 2,
-        });
+        // Sort segments by highway type priority (draw minor roads first), skipping
+        // types outside the requested subset
+        let mut sorted_segments: Vec<&RoadSegment> = segments
+            .iter()
+            .filter(|s| match road_types {
+                Some(types) => types.contains(&s.highway_type),
+                None => true,
+            })
+            .filter(|s| match self.center_clear_zone {
+                Some((center, meters)) => !s.points.iter().all(|&p| geodesic_distance(center, p) <= meters),
+                None => true,
+            })
+            .collect();
+        sorted_segments.sort_by_key(|s| road_draw_priority(s.highway_type));
+
+        // Keep thin roads visible at large distances
+        let min_width = get_theme_number(theme, "road_min_width", DEFAULT_MIN_ROAD_WIDTH);
 
+        // One-way chevrons are opt-in since they add visual noise
+        let show_oneway = get_theme_bool(theme, "show_oneway", false);
+        let oneway_spacing = get_theme_number(theme, "oneway_spacing", DEFAULT_ONEWAY_SPACING);
+
+        // Casings are drawn only when the theme sets a casing color
+        let casing_paint = theme
+            .get("road_casing")
+            .and_then(|v| v.as_str())
+            .and_then(parse_hex_color)
+            .map(|(r, g, b)| {
+                let mut paint = Paint::default();
+                paint.set_color_rgba8(r, g, b, 255);
+                paint.anti_alias = self.anti_alias;
+                paint
+            });
+        let casing_delta = get_theme_number(theme, "road_casing_width", DEFAULT_CASING_WIDTH).max(0.0);
+
+        // A `road_ramp` replaces the per-type road colors
+        let ramp = RoadRamp::from_theme(theme);
+
+        let mut roads = Vec::with_capacity(sorted_segments.len());
         for segment in sorted_segments {
             if segment.points.len() < 2 {
                 continue;
             }
 
-            let color_key = segment.highway_type.theme_key();
-            let hex_color = get_theme_color(theme, color_key, "#3A3A3A");
+            let hex_color = road_color(theme, ramp.as_ref(), segment.highway_type);
             let (r, g, b) = match parse_hex_color(&hex_color) {
                 Some(c) => c,
                 None => continue,
@@ -174,49 +691,455 @@ impl Canvas {
 
             let mut paint = Paint::default();
             paint.set_color_rgba8(r, g, b, 255);
-            paint.anti_alias = true;
+            paint.anti_alias = self.anti_alias;
 
-            let line_width = segment.highway_type.line_width() * base_width_multiplier;
+            let line_width = road_stroke_width(width_by.line_width(segment), base_width_multiplier, min_width);
 
-            let stroke = Stroke {
-                width: line_width,
-                line_cap: LineCap::Round,
-                line_join: LineJoin::Round,
-                ..Default::default()
-            };
+            let screen_points: Vec<(f32, f32)> = segment
+                .points
+                .iter()
+                .map(|(lat, lon)| self.geo_to_screen(*lat, *lon))
+                .collect();
 
             let mut pb = PathBuilder::new();
-            let (x, y) = self.geo_to_screen(segment.points[0].0, segment.points[0].1);
-            pb.move_to(x, y);
+            pb.move_to(screen_points[0].0, screen_points[0].1);
 
-            for (lat, lon) in &segment.points[1..] {
-                let (x, y) = self.geo_to_screen(*lat, *lon);
-                pb.line_to(x, y);
+            for (x, y) in &screen_points[1..] {
+                pb.line_to(*x, *y);
             }
 
             if let Some(path) = pb.finish() {
-                self.pixmap.stroke_path(
-                    &path,
-                    &paint,
-                    &stroke,
-                    Transform::identity(),
-                    None,
-                );
+                roads.push(StyledRoad {
+                    priority: road_draw_priority(segment.highway_type),
+                    path,
+                    screen_points,
+                    paint,
+                    line_width,
+                    oneway: segment.oneway,
+                });
             }
         }
+
+        // Draw one priority tier at a time: casings first, then the roads on top.
+        // A tier's casings cover lower tiers but never the roads of its own or higher tiers.
+        let mut tier_start = 0;
+        while tier_start < roads.len() {
+            let priority = roads[tier_start].priority;
+            let tier_end = roads[tier_start..]
+                .iter()
+                .position(|road| road.priority != priority)
+                .map_or(roads.len(), |offset| tier_start + offset);
+            let tier = &roads[tier_start..tier_end];
+
+            if let Some(casing_paint) = &casing_paint {
+                for road in tier {
+                    let stroke = road_stroke(road.line_width + casing_delta);
+                    self.pixmap
+                        .stroke_path(&road.path, casing_paint, &stroke, Transform::identity(), self.clip.as_ref());
+                }
+            }
+
+            for road in tier {
+                let stroke = road_stroke(road.line_width);
+                self.pixmap
+                    .stroke_path(&road.path, &road.paint, &stroke, Transform::identity(), self.clip.as_ref());
+
+                if show_oneway && road.oneway {
+                    self.draw_chevrons(&road.screen_points, oneway_spacing, road.line_width, &road.paint);
+                }
+            }
+
+            tier_start = tier_end;
+        }
+    }
+
+    /// Draw direction chevrons along a road, sized to stick out past the road stroke
+    fn draw_chevrons(&mut self, screen_points: &[(f32, f32)], spacing: f32, line_width: f32, paint: &Paint) {
+        let size = line_width * 2.5 + 2.0;
+        let stroke = Stroke {
+            width: (line_width * 0.5).max(0.75),
+            line_cap: LineCap::Round,
+            line_join: LineJoin::Round,
+            ..Default::default()
+        };
+
+        for ((x, y), (dx, dy)) in chevron_positions(screen_points, spacing) {
+            let half = size / 2.0;
+            // Perpendicular to the direction of travel
+            let (px, py) = (-dy, dx);
+
+            let mut pb = PathBuilder::new();
+            pb.move_to(x - dx * half + px * half, y - dy * half + py * half);
+            pb.line_to(x + dx * half, y + dy * half);
+            pb.line_to(x - dx * half - px * half, y - dy * half - py * half);
+
+            if let Some(path) = pb.finish() {
+                self.pixmap.stroke_path(&path, paint, &stroke, Transform::identity(), self.clip.as_ref());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::road_styles::{width_multiplier, WidthCurve};
+
+    #[test]
+    fn test_paper_size_pixels() {
+        assert_eq!(paper_size_pixels("12x16", 300), Some((POSTER_WIDTH, POSTER_HEIGHT)));
+        assert_eq!(paper_size_pixels("Square", 300), Some((3600, 3600)));
+        assert_eq!(paper_size_pixels("a4", 100), Some((827, 1169)));
+        assert_eq!(paper_size_pixels("tabloid", 300), None);
     }
 
-    /// Save the canvas to a PNG file
-    pub fn save_png(&self, path: &Path) -> Result<()> {
-        self.pixmap
-            .save_png(path)
-            .map_err(|e| AppError::Rendering(format!("Failed to save PNG: {}", e)))
+    #[test]
+    fn test_residential_roads_visible_at_max_distance() {
+        // Base width multiplier for a 50km poster on the thinnest curve
+        let base_width = width_multiplier(50000, WidthCurve::Linear);
+        let min_width = 0.2;
+        assert!(road_stroke_width(HighwayType::Residential.line_width(), base_width, min_width) >= min_width);
+        assert_eq!(road_stroke_width(HighwayType::Service.line_width(), 0.01, min_width), min_width);
+
+        let mut canvas = Canvas::new(100, 100).unwrap();
+        canvas.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        let segment = RoadSegment {
+            id: 1,
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+            highway_type: HighwayType::Residential,
+            oneway: false,
+            lanes: None,
+            maxspeed: None,
+        };
+        let theme = serde_json::json!({ "road_residential": "#FF0000" });
+        canvas.draw_roads(&[segment], &theme, 0.01, None, WidthBy::Type);
+
+        assert!(canvas.pixmap.pixels().iter().any(|p| p.alpha() > 0));
     }
 
-    /// Get PNG data as bytes
-    pub fn to_png_bytes(&self) -> Result<Vec<u8>> {
-        self.pixmap
-            .encode_png()
-            .map_err(|e| AppError::Rendering(format!("Failed to encode PNG: {}", e)))
+    #[test]
+    fn test_center_clear_zone_skips_roads_inside_it() {
+        let road = |points: Vec<(f64, f64)>| RoadSegment {
+            id: 1,
+            points,
+            highway_type: HighwayType::Primary,
+            oneway: false,
+            lanes: None,
+            maxspeed: None,
+        };
+        let theme = serde_json::json!({ "road_primary": "#FF0000" });
+        let drawn = |segment: RoadSegment| {
+            let mut canvas = Canvas::new(100, 100).unwrap();
+            canvas.set_geo_transform(((0.0, 0.0), (0.1, 0.1)));
+            // About 2.2km around the middle of the map
+            canvas.set_center_clear_zone((0.05, 0.05), 2200.0);
+            canvas.draw_roads(&[segment], &theme, 1.0, None, WidthBy::Type);
+            canvas.pixmap.pixels().iter().any(|p| p.alpha() > 0)
+        };
+
+        assert!(!drawn(road(vec![(0.04, 0.05), (0.06, 0.05)])));
+        // A road leaving the zone is drawn whole, including the part inside it
+        assert!(drawn(road(vec![(0.05, 0.05), (0.05, 0.09)])));
+        assert!(drawn(road(vec![(0.0, 0.0), (0.01, 0.01)])));
+    }
+
+    #[test]
+    fn test_road_casings_stay_below_higher_priority_roads() {
+        let mut canvas = Canvas::new(100, 100).unwrap();
+        canvas.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        let road = |id, points, highway_type| RoadSegment { id, points, highway_type, oneway: false, lanes: None, maxspeed: None };
+        // The motorway comes first in the input but must still end up on top
+        let segments = [
+            road(1, vec![(0.0, 0.5), (1.0, 0.5)], HighwayType::Motorway),
+            road(2, vec![(0.5, 0.0), (0.5, 1.0)], HighwayType::Residential),
+        ];
+        let theme = serde_json::json!({
+            "road_motorway": "#0000FF",
+            "road_residential": "#00FF00",
+            "road_casing": "#FF0000",
+            "road_casing_width": 6.0,
+        });
+        canvas.draw_roads(&segments, &theme, 5.0, None, WidthBy::Type);
+
+        let pixel = |canvas: &Canvas, (x, y): (f32, f32)| {
+            let p = canvas.pixmap.pixel(x as u32, y as u32).unwrap();
+            (p.red(), p.green(), p.blue())
+        };
+        let (cx, cy) = canvas.geo_to_screen(0.5, 0.5);
+        let (rx, _) = canvas.geo_to_screen(0.5, 0.2);
+
+        assert_eq!(pixel(&canvas, (cx, cy)), (0, 0, 255));
+        assert_eq!(pixel(&canvas, (rx, cy)), (0, 255, 0));
+        // Just outside the residential road but inside its casing
+        let half_road = road_stroke_width(HighwayType::Residential.line_width(), 5.0, 0.2) / 2.0;
+        assert_eq!(pixel(&canvas, (rx, cy + half_road + 1.5)), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_geo_transform_across_the_antimeridian() {
+        let mut canvas = Canvas::new(200, 200).unwrap();
+        canvas.set_geo_transform_with_padding(((-17.0, 179.9), (-16.8, 180.1)), 0.0);
+
+        // Either side of 180° lands either side of the center, not a world apart
+        let (west, _) = canvas.geo_to_screen(-16.9, 179.95);
+        let (east, _) = canvas.geo_to_screen(-16.9, -179.95);
+        let (center, _) = canvas.geo_to_screen(-16.9, 180.0);
+        assert!((center - 100.0).abs() < 0.01);
+        assert!(west > 0.0 && west < center, "{}", west);
+        assert!(((center - west) - (east - center)).abs() < 0.01, "{} {}", west, east);
+    }
+
+    /// Grid of small squares, dense enough to take the parallel path
+    fn dense_polygons(n: usize) -> Vec<AreaFeature> {
+        (0..n * n)
+            .map(|i| {
+                let (lat, lon) = ((i / n) as f64 / n as f64, (i % n) as f64 / n as f64);
+                let size = 0.7 / n as f64;
+                AreaFeature {
+                    id: i as i64,
+                    points: vec![(lat, lon), (lat + size, lon), (lat + size, lon + size), (lat, lon + size)],
+                    feature_type: "park".to_string(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_polygons_match_serial() {
+        let features = dense_polygons(40);
+        assert!(features.len() >= PARALLEL_PATH_THRESHOLD);
+
+        let mut parallel = Canvas::new(400, 400).unwrap();
+        parallel.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        parallel.draw_polygons(&features, "#336699", None);
+
+        // Serial reference: fill one feature at a time (each call stays below the threshold)
+        let mut serial = Canvas::new(400, 400).unwrap();
+        serial.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        for feature in &features {
+            serial.draw_polygons(std::slice::from_ref(feature), "#336699", None);
+        }
+
+        assert_eq!(parallel.pixmap.data(), serial.pixmap.data());
+    }
+
+    #[test]
+    fn test_polygon_outline() {
+        assert_eq!(PolygonOutline::from_theme(&serde_json::json!({"parks_outline_width": 2}), "parks"), None);
+        let theme = serde_json::json!({
+            "parks_outline_color": "#FF0000",
+            "water_outline_color": "#0000FF",
+            "water_outline_width": 3,
+            "water_outline_dash": [4, 2]
+        });
+        let parks = PolygonOutline::from_theme(&theme, "parks").unwrap();
+        assert_eq!(parks, PolygonOutline { color: (255, 0, 0), width: DEFAULT_OUTLINE_WIDTH, dash: None });
+        let water = PolygonOutline::from_theme(&theme, "water").unwrap();
+        assert_eq!((water.width, water.dash), (3.0, Some((4.0, 2.0))));
+
+        // The edge is stroked over the fill, leaving the interior fill-colored
+        let square = vec![AreaFeature {
+            id: 1,
+            points: vec![(0.2, 0.2), (0.8, 0.2), (0.8, 0.8), (0.2, 0.8)],
+            feature_type: "park".to_string(),
+        }];
+        let mut canvas = Canvas::new(100, 100).unwrap();
+        canvas.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        canvas.draw_polygons(&square, "#00FF00", Some(&parks));
+        let pixel = |x: u32, y: u32| {
+            let p = canvas.pixmap.pixel(x, y).unwrap();
+            (p.red(), p.green(), p.blue())
+        };
+        assert_eq!(pixel(50, 50), (0, 255, 0));
+        let edge = (0..100).map(|x| pixel(x, 50)).find(|&(r, _, _)| r > 0).unwrap();
+        assert!(edge.0 > edge.1, "edge pixel {:?} should be mostly outline", edge);
+    }
+
+    #[test]
+    fn test_point_markers() {
+        let point = |lat, lon, kind| PointFeature { lat, lon, name: None, kind };
+        let mut canvas = Canvas::new(40, 40).unwrap();
+        canvas.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        let (peak_x, peak_y) = canvas.geo_to_screen(0.25, 0.25);
+        let (place_x, place_y) = canvas.geo_to_screen(0.75, 0.75);
+        canvas.draw_point_markers(
+            &[point(0.25, 0.25, PointKind::Peak), point(0.75, 0.75, PointKind::Place)],
+            "#FF0000",
+            10.0,
+        );
+
+        let alpha = |x: f32, y: f32| canvas.pixmap.pixel(x as u32, y as u32).unwrap().alpha();
+        assert_eq!(alpha(peak_x, peak_y), 255);
+        assert_eq!(alpha(place_x, place_y), 255);
+        // Peaks point up: the triangle is wide at its base and empty beside its tip
+        assert_eq!(alpha(peak_x - 3.0, peak_y + 3.0), 255);
+        assert_eq!(alpha(peak_x - 4.0, peak_y - 4.0), 0);
+        assert_eq!(alpha(20.0, 20.0), 0);
+    }
+
+    #[test]
+    fn test_anti_alias_toggle() {
+        // A triangle's diagonal edge only gets partial coverage when anti-aliased
+        let triangle = vec![AreaFeature {
+            id: 1,
+            points: vec![(0.1, 0.1), (0.9, 0.1), (0.9, 0.7)],
+            feature_type: "park".to_string(),
+        }];
+        let partial_pixels = |anti_alias: bool| {
+            let mut canvas = Canvas::new(50, 50).unwrap();
+            canvas.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+            canvas.set_anti_alias(anti_alias);
+            canvas.draw_polygons(&triangle, "#336699", None);
+            canvas.pixmap.pixels().iter().filter(|p| p.alpha() != 0 && p.alpha() != 255).count()
+        };
+        assert!(partial_pixels(true) > 0);
+        assert_eq!(partial_pixels(false), 0);
+    }
+
+    #[test]
+    fn test_draw_layer_opacity() {
+        let square = |id| AreaFeature {
+            id,
+            points: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            feature_type: "park".to_string(),
+        };
+        let mut canvas = Canvas::new(10, 10).unwrap();
+        canvas.set_geo_transform_with_padding(((0.0, 0.0), (1.0, 1.0)), 0.0);
+        canvas.fill_background("#FFFFFF");
+        // Two overlapping squares at half opacity blend once, not twice
+        canvas.draw_layer(0.5, |canvas| canvas.draw_polygons(&[square(1), square(2)], "#000000", None));
+        let pixel = canvas.pixmap.pixel(5, 5).unwrap();
+        assert!((126..=129).contains(&pixel.red()), "{}", pixel.red());
+        assert_eq!(pixel.alpha(), 255);
+
+        canvas.draw_layer(1.0, |canvas| canvas.draw_polygons(&[square(1)], "#000000", None));
+        assert_eq!(canvas.pixmap.pixel(5, 5).unwrap().red(), 0);
+    }
+
+    #[test]
+    fn test_draw_shoreline() {
+        let water = vec![AreaFeature {
+            id: 1,
+            points: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            feature_type: "water".to_string(),
+        }];
+        let theme = serde_json::json!({ "water_shoreline": true, "water_shoreline_width": 20 });
+        let shoreline = Shoreline::from_theme(&theme, "#FFFFFF").unwrap();
+        assert!(Shoreline::from_theme(&serde_json::json!({}), "#FFFFFF").is_none());
+
+        let mut canvas = Canvas::new(100, 100).unwrap();
+        canvas.set_geo_transform_with_padding(((0.0, 0.0), (1.0, 1.0)), 0.1);
+        canvas.draw_polygons(&water, "#000000", None);
+        canvas.draw_shoreline(&water, &shoreline);
+
+        // Lightest at the edge, fading to the plain fill in the middle, nothing outside
+        let red = |x| canvas.pixmap.pixel(x, 50).unwrap().red();
+        assert!(red(11) > red(20), "{} <= {}", red(11), red(20));
+        assert!(red(20) > red(40));
+        assert_eq!(red(50), 0);
+        assert_eq!(canvas.pixmap.pixel(5, 50).unwrap().alpha(), 0);
+    }
+
+    #[test]
+    fn test_clip_to_radius() {
+        let square = vec![AreaFeature {
+            id: 1,
+            points: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            feature_type: "park".to_string(),
+        }];
+        let mut canvas = Canvas::new(100, 100).unwrap();
+        canvas.set_geo_transform_with_padding(((0.0, 0.0), (1.0, 1.0)), 0.0);
+        // Half a degree of latitude reaches the canvas edges
+        canvas.clip_to_radius((0.5, 0.5), METERS_PER_DEGREE_LAT * 0.5);
+        canvas.draw_polygons(&square, "#336699", None);
+
+        let alpha = |x: usize, y: usize| canvas.pixmap.pixels()[y * 100 + x].alpha();
+        assert_eq!(alpha(50, 50), 255);
+        assert_eq!(alpha(50, 2), 255);
+        assert_eq!(alpha(2, 2), 0);
+        assert_eq!(alpha(97, 97), 0);
+        assert!(canvas.clip_contains(50.0, 50.0));
+        assert!(!canvas.clip_contains(3.0, 3.0));
+        assert!(!canvas.clip_contains(-1.0, 50.0));
+    }
+
+    #[test]
+    fn test_min_polygon_area_skips_tiny_polygons() {
+        assert_eq!(shoelace_area(&[(0.0, 0.0), (4.0, 0.0), (4.0, 3.0), (0.0, 3.0)]), 12.0);
+        // Winding direction doesn't matter
+        assert_eq!(shoelace_area(&[(0.0, 0.0), (0.0, 3.0), (4.0, 3.0), (4.0, 0.0)]), 12.0);
+
+        // 10x10 canvas: each of the 5x5 squares covers 0.7 * 10 / 5 = 1.4px on a side (~2px²)
+        let features = dense_polygons(5);
+        let mut canvas = Canvas::new(10, 10).unwrap();
+        canvas.set_geo_transform(((0.0, 0.0), (1.0, 1.0)));
+        canvas.set_min_polygon_area(4.0);
+        canvas.draw_polygons(&features, "#336699", None);
+        assert!(canvas.pixmap.data().iter().all(|&b| b == 0));
+
+        canvas.set_min_polygon_area(1.0);
+        canvas.draw_polygons(&features, "#336699", None);
+        assert!(canvas.pixmap.data().iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn test_fill_vertical_gradient() {
+        let mut canvas = Canvas::new(4, 100).unwrap();
+        assert!(!canvas.fill_vertical_gradient("#000000", "nope"));
+        assert!(canvas.fill_vertical_gradient("#000000", "#FFFFFF"));
+
+        let top = canvas.pixmap.pixel(0, 0).unwrap();
+        let bottom = canvas.pixmap.pixel(0, 99).unwrap();
+        assert!(top.red() < 10);
+        assert!(bottom.red() > 245);
+        assert_eq!(bottom.alpha(), 255);
+    }
+
+    #[test]
+    fn test_bleed_fits_map_to_trim_box() {
+        let mut canvas = Canvas::new(120, 220).unwrap();
+        canvas.set_bleed(10);
+        assert_eq!(canvas.trim_box(), Rect::from_xywh(10.0, 10.0, 100.0, 200.0).unwrap());
+
+        // The bounds fill the trim box exactly, leaving the bleed for what lies beyond them
+        canvas.set_geo_transform_with_padding(((0.0, 0.0), (0.002, 0.001)), 0.0);
+        let (left, top) = canvas.geo_to_screen(0.002, 0.0);
+        let (right, bottom) = canvas.geo_to_screen(0.0, 0.001);
+        assert!((left - 10.0).abs() < 0.01 && (right - 110.0).abs() < 0.01);
+        assert!((top - 10.0).abs() < 0.01 && (bottom - 210.0).abs() < 0.01);
+
+        // An oversized bleed still leaves a trim box
+        canvas.set_bleed(500);
+        assert!(canvas.trim_box().width() >= 1.0);
+    }
+
+    #[test]
+    fn test_fit_mode_cover_crops_the_long_axis() {
+        // Square bounds on a portrait canvas
+        let bounds = ((0.0, 0.0), (0.001, 0.001));
+        let mut canvas = Canvas::new(100, 200).unwrap();
+        canvas.set_geo_transform_with_padding(bounds, 0.0);
+        let (left, top) = canvas.geo_to_screen(0.001, 0.0);
+        assert!((left - 0.0).abs() < 0.01 && (top - 50.0).abs() < 0.01, "{} {}", left, top);
+
+        // Cover fills the height and trims the sides instead of letterboxing
+        canvas.set_fit_mode(FitMode::Cover);
+        canvas.set_geo_transform_with_padding(bounds, 0.0);
+        let (left, top) = canvas.geo_to_screen(0.001, 0.0);
+        let (right, bottom) = canvas.geo_to_screen(0.0, 0.001);
+        assert!((top - 0.0).abs() < 0.01 && (bottom - 200.0).abs() < 0.01, "{} {}", top, bottom);
+        assert!((left + 50.0).abs() < 0.01 && (right - 150.0).abs() < 0.01, "{} {}", left, right);
+    }
+
+    #[test]
+    fn test_chevron_positions_scale_with_length() {
+        // Too short for a single chevron
+        assert!(chevron_positions(&[(0.0, 0.0), (50.0, 0.0)], 100.0).is_empty());
+
+        let positions = chevron_positions(&[(0.0, 0.0), (100.0, 0.0), (100.0, 200.0)], 100.0);
+        assert_eq!(positions.len(), 3);
+        assert_eq!(positions[0], ((50.0, 0.0), (1.0, 0.0)));
+        assert_eq!(positions[1], ((100.0, 50.0), (0.0, 1.0)));
+        assert_eq!(positions[2], ((100.0, 150.0), (0.0, 1.0)));
     }
 }
@@ -0,0 +1,83 @@
+use std::io::Cursor;
+
+use tiff::encoder::{colortype, TiffEncoder};
+use tiny_skia::Pixmap;
+
+use crate::error::{AppError, Result};
+
+/// Convert one sRGB pixel to CMYK using the naive device-independent formula
+///
+/// No ICC profile is applied, so printed colors will only roughly match the screen;
+/// proper profile-based conversion could replace this later.
+pub fn rgb_to_cmyk(r: u8, g: u8, b: u8) -> [u8; 4] {
+    let max = r.max(g).max(b);
+    if max == 0 {
+        return [0, 0, 0, 255];
+    }
+
+    let max = max as u32;
+    let channel = |v: u8| ((max - v as u32) * 255 / max) as u8;
+    [channel(r), channel(g), channel(b), (255 - max) as u8]
+}
+
+/// Convert a pixmap to interleaved CMYK bytes, flattening transparency onto white paper
+pub fn pixmap_to_cmyk(pixmap: &Pixmap) -> Vec<u8> {
+    let mut data = Vec::with_capacity(pixmap.pixels().len() * 4);
+    for pixel in pixmap.pixels() {
+        // Pixels are premultiplied, so compositing over white just adds the uncovered part
+        let paper = 255 - pixel.alpha();
+        data.extend_from_slice(&rgb_to_cmyk(
+            pixel.red() + paper,
+            pixel.green() + paper,
+            pixel.blue() + paper,
+        ));
+    }
+    data
+}
+
+/// Encode a pixmap as an uncompressed CMYK TIFF
+pub fn encode_cmyk_tiff(pixmap: &Pixmap) -> Result<Vec<u8>> {
+    let data = pixmap_to_cmyk(pixmap);
+    let mut buffer = Cursor::new(Vec::new());
+    TiffEncoder::new(&mut buffer)
+        .and_then(|mut encoder| {
+            encoder.write_image::<colortype::CMYK8>(pixmap.width(), pixmap.height(), &data)
+        })
+        .map_err(|e| AppError::Rendering(format!("Failed to encode TIFF: {}", e)))?;
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiny_skia::Color;
+
+    #[test]
+    fn test_rgb_to_cmyk() {
+        assert_eq!(rgb_to_cmyk(255, 255, 255), [0, 0, 0, 0]);
+        assert_eq!(rgb_to_cmyk(0, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(rgb_to_cmyk(255, 0, 0), [0, 255, 255, 0]);
+        assert_eq!(rgb_to_cmyk(0, 0, 128), [255, 255, 0, 127]);
+        assert_eq!(rgb_to_cmyk(128, 128, 128), [0, 0, 0, 127]);
+    }
+
+    #[test]
+    fn test_encode_cmyk_tiff_round_trip() {
+        let mut pixmap = Pixmap::new(4, 2).unwrap();
+        pixmap.fill(Color::from_rgba8(0, 255, 255, 255));
+        // Leave one pixel transparent; it should come out as blank paper
+        pixmap.pixels_mut()[0] = tiny_skia::PremultipliedColorU8::TRANSPARENT;
+
+        let bytes = encode_cmyk_tiff(&pixmap).unwrap();
+        let mut decoder = Decoder::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (4, 2));
+        assert_eq!(decoder.colortype().unwrap(), tiff::ColorType::CMYK(8));
+
+        let DecodingResult::U8(data) = decoder.read_image().unwrap() else {
+            panic!("expected 8-bit samples");
+        };
+        assert_eq!(&data[0..4], &[0, 0, 0, 0]);
+        assert_eq!(&data[4..8], &[255, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,62 @@
+use tiny_skia::{Paint, Pixmap, PixmapPaint, Rect, Transform};
+
+use crate::error::{AppError, Result};
+
+/// Join the left half of one poster to the right half of another, split by a divider line
+///
+/// Both posters must be the same size. The divider scales with the image width so it stays
+/// visible on thumbnails and full-size posters alike.
+pub fn split_compare(left: &Pixmap, right: &Pixmap, divider: (u8, u8, u8)) -> Result<Pixmap> {
+    if (left.width(), left.height()) != (right.width(), right.height()) {
+        return Err(AppError::Rendering(format!(
+            "Cannot compare a {}x{} poster with a {}x{} one",
+            left.width(),
+            left.height(),
+            right.width(),
+            right.height()
+        )));
+    }
+
+    let (width, height) = (left.width(), left.height());
+    let half = width / 2;
+    let mut output = left.clone();
+    let right_half = Rect::from_xywh(half as f32, 0.0, (width - half) as f32, height as f32)
+        .and_then(|rect| right.clone_rect(rect.round()?));
+    if let Some(right_half) = right_half {
+        output.draw_pixmap(half as i32, 0, right_half.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+    }
+
+    let line_width = (width as f32 / 400.0).max(2.0);
+    if let Some(line) = Rect::from_xywh(half as f32 - line_width / 2.0, 0.0, line_width, height as f32) {
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(divider.0, divider.1, divider.2, 255);
+        output.fill_rect(line, &paint, Transform::identity(), None);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    fn filled(color: Color) -> Pixmap {
+        let mut pixmap = Pixmap::new(100, 10).unwrap();
+        pixmap.fill(color);
+        pixmap
+    }
+
+    #[test]
+    fn test_split_compare_halves_and_divider() {
+        let output = split_compare(&filled(Color::BLACK), &filled(Color::from_rgba8(255, 0, 0, 255)), (255, 255, 255)).unwrap();
+        let rgb = |x: u32| {
+            let p = output.pixel(x, 5).unwrap();
+            (p.red(), p.green(), p.blue())
+        };
+        assert_eq!(rgb(10), (0, 0, 0));
+        assert_eq!(rgb(90), (255, 0, 0));
+        assert_eq!(rgb(50), (255, 255, 255));
+
+        assert!(split_compare(&filled(Color::BLACK), &Pixmap::new(50, 10).unwrap(), (255, 255, 255)).is_err());
+    }
+}
@@ -1,6 +1,11 @@
-use tiny_skia::Pixmap;
+use serde_json::Value;
+use tiny_skia::{Pixmap, PremultipliedColorU8};
 
-use crate::themes::loader::parse_hex_color;
+use crate::rendering::blend::{blend_over, BlendMode};
+use crate::themes::loader::{get_theme_color, get_theme_number, parse_hex_color};
+
+/// Default distance from the center where a vignette starts darkening (theme key `vignette_radius`)
+pub const DEFAULT_VIGNETTE_RADIUS: f32 = 0.6;
 
 /// Location for gradient fade
 #[derive(Debug, Clone, Copy)]
@@ -10,7 +15,7 @@ pub enum GradientLocation {
 }
 
 /// Apply a gradient fade overlay to the pixmap
-pub fn apply_gradient_fade(pixmap: &mut Pixmap, hex_color: &str, location: GradientLocation) {
+pub fn apply_gradient_fade(pixmap: &mut Pixmap, hex_color: &str, location: GradientLocation, mode: BlendMode) {
     let (r, g, b) = match parse_hex_color(hex_color) {
         Some(c) => c,
         None => return,
@@ -33,7 +38,7 @@ pub fn apply_gradient_fade(pixmap: &mut Pixmap, hex_color: &str, location: Gradi
 
                 for x in 0..width {
                     let idx = (y * width + x) as usize;
-                    blend_pixel(&mut pixels[idx], r, g, b, alpha);
+                    blend_over(&mut pixels[idx], r, g, b, alpha, mode);
                 }
             }
         }
@@ -46,58 +51,112 @@ pub fn apply_gradient_fade(pixmap: &mut Pixmap, hex_color: &str, location: Gradi
 
                 for x in 0..width {
                     let idx = ((start_y + y) * width + x) as usize;
-                    blend_pixel(&mut pixels[idx], r, g, b, alpha);
+                    blend_over(&mut pixels[idx], r, g, b, alpha, mode);
                 }
             }
         }
     }
 }
 
-/// Blend a color onto an existing pixel using alpha compositing
-fn blend_pixel(pixel: &mut tiny_skia::PremultipliedColorU8, r: u8, g: u8, b: u8, alpha: u8) {
-    if alpha == 0 {
+/// Fade the top and bottom of the pixmap out to transparency, over the same bands
+/// `apply_gradient_fades` covers with color
+pub fn apply_transparent_fades(pixmap: &mut Pixmap) {
+    let height = pixmap.height();
+    let width = pixmap.width() as usize;
+    let gradient_height = (height as f32 * 0.25) as u32;
+    if gradient_height == 0 {
         return;
     }
 
-    let a = alpha as f32 / 255.0;
-    let inv_a = 1.0 - a;
-
-    // Demultiply the existing pixel
-    let existing_a = pixel.alpha();
-    if existing_a == 0 {
-        // Fully transparent, just set the new color
-        *pixel = tiny_skia::PremultipliedColorU8::from_rgba(
-            (r as f32 * a) as u8,
-            (g as f32 * a) as u8,
-            (b as f32 * a) as u8,
-            alpha,
-        )
-        .unwrap();
-        return;
+    let pixels = pixmap.pixels_mut();
+    for y in 0..height {
+        // 0 at either edge up to 1 where each band ends
+        let from_edge = y.min(height - 1 - y);
+        if from_edge >= gradient_height {
+            continue;
+        }
+        let keep = from_edge as f32 / gradient_height as f32;
+        let row = y as usize * width;
+        for pixel in &mut pixels[row..row + width] {
+            let scale = |c: u8| (c as f32 * keep) as u8;
+            *pixel = PremultipliedColorU8::from_rgba(scale(pixel.red()), scale(pixel.green()), scale(pixel.blue()), scale(pixel.alpha()))
+                .unwrap_or(PremultipliedColorU8::TRANSPARENT);
+        }
     }
-
-    let existing_r = pixel.red() as f32 / existing_a as f32 * 255.0;
-    let existing_g = pixel.green() as f32 / existing_a as f32 * 255.0;
-    let existing_b = pixel.blue() as f32 / existing_a as f32 * 255.0;
-
-    // Blend
-    let new_r = (r as f32 * a + existing_r * inv_a).min(255.0) as u8;
-    let new_g = (g as f32 * a + existing_g * inv_a).min(255.0) as u8;
-    let new_b = (b as f32 * a + existing_b * inv_a).min(255.0) as u8;
-    let new_a = ((alpha as f32 + existing_a as f32 * inv_a)).min(255.0) as u8;
-
-    // Pre-multiply and set
-    *pixel = tiny_skia::PremultipliedColorU8::from_rgba(
-        (new_r as f32 * new_a as f32 / 255.0) as u8,
-        (new_g as f32 * new_a as f32 / 255.0) as u8,
-        (new_b as f32 * new_a as f32 / 255.0) as u8,
-        new_a,
-    )
-    .unwrap();
 }
 
 /// Apply both top and bottom gradient fades
-pub fn apply_gradient_fades(pixmap: &mut Pixmap, hex_color: &str) {
-    apply_gradient_fade(pixmap, hex_color, GradientLocation::Bottom);
-    apply_gradient_fade(pixmap, hex_color, GradientLocation::Top);
+pub fn apply_gradient_fades(pixmap: &mut Pixmap, hex_color: &str, mode: BlendMode) {
+    apply_gradient_fade(pixmap, hex_color, GradientLocation::Bottom, mode);
+    apply_gradient_fade(pixmap, hex_color, GradientLocation::Top, mode);
+}
+
+/// Radial vignette shading the poster's corners, enabled by the theme's `vignette` strength
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    pub color: (u8, u8, u8),
+    /// Opacity reached in the corners, from 0.0 (none) to 1.0 (solid)
+    pub strength: f32,
+    /// Where the falloff starts, as a fraction of the center-to-corner distance
+    pub radius: f32,
+}
+
+impl Vignette {
+    /// Vignette settings from `vignette`, `vignette_color` and `vignette_radius`
+    ///
+    /// None (no vignette) unless the theme sets a positive `vignette` strength. The color
+    /// defaults to `bg_color`.
+    pub fn from_theme(theme: &Value, bg_color: &str) -> Option<Self> {
+        let strength = get_theme_number(theme, "vignette", 0.0).clamp(0.0, 1.0);
+        if strength <= 0.0 {
+            return None;
+        }
+        let color = parse_hex_color(&get_theme_color(theme, "vignette_color", bg_color))?;
+        Some(Self {
+            color,
+            strength,
+            radius: get_theme_number(theme, "vignette_radius", DEFAULT_VIGNETTE_RADIUS).clamp(0.0, 0.99),
+        })
+    }
+}
+
+/// Blend the vignette color over the pixmap, rising smoothly from nothing at `radius` to
+/// `strength` in the corners
+pub fn apply_vignette(pixmap: &mut Pixmap, vignette: &Vignette, mode: BlendMode) {
+    let width = pixmap.width() as usize;
+    let (cx, cy) = (pixmap.width() as f32 / 2.0, pixmap.height() as f32 / 2.0);
+    let corner = (cx * cx + cy * cy).sqrt().max(1.0);
+    let (r, g, b) = vignette.color;
+
+    for (idx, pixel) in pixmap.pixels_mut().iter_mut().enumerate() {
+        let (dx, dy) = ((idx % width) as f32 + 0.5 - cx, (idx / width) as f32 + 0.5 - cy);
+        let distance = (dx * dx + dy * dy).sqrt() / corner;
+        let t = ((distance - vignette.radius) / (1.0 - vignette.radius)).clamp(0.0, 1.0);
+        // Smoothstep, so the edge of the falloff doesn't show as a ring
+        let alpha = (t * t * (3.0 - 2.0 * t) * vignette.strength * 255.0).round() as u8;
+        blend_over(pixel, r, g, b, alpha, mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    #[test]
+    fn test_vignette_darkens_corners_only() {
+        assert_eq!(Vignette::from_theme(&serde_json::json!({}), "#FFFFFF"), None);
+        let theme = serde_json::json!({ "vignette": 0.8, "vignette_radius": 0.5 });
+        let vignette = Vignette::from_theme(&theme, "#000000").unwrap();
+        assert_eq!(vignette.color, (0, 0, 0));
+
+        let mut pixmap = Pixmap::new(100, 100).unwrap();
+        pixmap.fill(Color::WHITE);
+        apply_vignette(&mut pixmap, &vignette, BlendMode::Srgb);
+        let red = |x: u32, y: u32| pixmap.pixel(x, y).unwrap().red();
+        assert_eq!(red(50, 50), 255);
+        assert_eq!(red(70, 50), 255);
+        assert!(red(0, 0) < 80, "{}", red(0, 0));
+        assert!(red(0, 0) < red(10, 10) && red(10, 10) < red(25, 25));
+    }
 }
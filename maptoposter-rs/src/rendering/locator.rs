@@ -0,0 +1,196 @@
+use serde::Deserialize;
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+use crate::themes::loader::parse_hex_color;
+
+/// Inset side length as a fraction of the trim width
+const INSET_SIZE: f32 = 0.16;
+/// Gap between the inset and the trim edges as a fraction of the trim width
+const INSET_MARGIN: f32 = 0.04;
+
+/// Canvas corner for the locator inset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocatorCorner {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Colors for the locator inset
+pub struct LocatorStyle<'a> {
+    pub outline_color: &'a str,
+    pub marker_color: &'a str,
+}
+
+/// Draw a small country outline with a marker at the poster location
+///
+/// `rings` are (lat, lon) polygon rings and the inset is placed in a corner of `trim`
+/// (the whole canvas unless there is a print bleed). Does nothing if there is no outline to draw.
+pub fn draw_locator(
+    pixmap: &mut Pixmap,
+    trim: Rect,
+    rings: &[Vec<(f64, f64)>],
+    location: (f64, f64),
+    corner: LocatorCorner,
+    style: &LocatorStyle,
+) {
+    let Some(bounds) = outline_bounds(rings) else {
+        return;
+    };
+
+    let size = trim.width() * INSET_SIZE;
+    let margin = trim.width() * INSET_MARGIN;
+    let (left, top) = match corner {
+        LocatorCorner::TopLeft => (trim.left() + margin, trim.top() + margin),
+        LocatorCorner::TopRight => (trim.right() - margin - size, trim.top() + margin),
+        LocatorCorner::BottomLeft => (trim.left() + margin, trim.bottom() - margin - size),
+        LocatorCorner::BottomRight => (trim.right() - margin - size, trim.bottom() - margin - size),
+    };
+    let Some(inset) = Rect::from_xywh(left, top, size, size) else {
+        return;
+    };
+
+    let project = inset_projection(bounds, inset);
+
+    // Country outline
+    if let Some(paint) = solid_paint(style.outline_color) {
+        let mut pb = PathBuilder::new();
+        for ring in rings.iter().filter(|r| r.len() >= 3) {
+            let (x, y) = project(ring[0]);
+            pb.move_to(x, y);
+            for point in &ring[1..] {
+                let (x, y) = project(*point);
+                pb.line_to(x, y);
+            }
+            pb.close();
+        }
+        if let Some(path) = pb.finish() {
+            pixmap.fill_path(&path, &paint, FillRule::EvenOdd, Transform::identity(), None);
+        }
+    }
+
+    // Location marker with a ring so it stays visible on the outline
+    if let Some(paint) = solid_paint(style.marker_color) {
+        let (x, y) = project(location);
+        let radius = (size * 0.03).max(2.0);
+        if let Some(dot) = PathBuilder::from_circle(x, y, radius) {
+            pixmap.fill_path(&dot, &paint, FillRule::Winding, Transform::identity(), None);
+        }
+        if let Some(ring) = PathBuilder::from_circle(x, y, radius * 2.0) {
+            let stroke = Stroke {
+                width: (radius * 0.4).max(1.0),
+                ..Default::default()
+            };
+            pixmap.stroke_path(&ring, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+}
+
+/// Opaque anti-aliased paint for a hex color
+fn solid_paint(hex_color: &str) -> Option<Paint<'static>> {
+    let (r, g, b) = parse_hex_color(hex_color)?;
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, 255);
+    paint.anti_alias = true;
+    Some(paint)
+}
+
+/// Bounds ((min_lat, min_lon), (max_lat, max_lon)) of all rings, if any
+fn outline_bounds(rings: &[Vec<(f64, f64)>]) -> Option<((f64, f64), (f64, f64))> {
+    let mut points = rings.iter().flatten().peekable();
+    points.peek()?;
+
+    let mut bounds = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+    for &(lat, lon) in points {
+        bounds.0 .0 = bounds.0 .0.min(lat);
+        bounds.0 .1 = bounds.0 .1.min(lon);
+        bounds.1 .0 = bounds.1 .0.max(lat);
+        bounds.1 .1 = bounds.1 .1.max(lon);
+    }
+    Some(bounds)
+}
+
+/// Map (lat, lon) into the inset, preserving aspect ratio (equirectangular, cos(lat) corrected)
+fn inset_projection(bounds: ((f64, f64), (f64, f64)), inset: Rect) -> impl Fn((f64, f64)) -> (f32, f32) {
+    let ((min_lat, min_lon), (max_lat, max_lon)) = bounds;
+    let lon_scale = ((min_lat + max_lat) / 2.0).to_radians().cos().max(0.1);
+
+    let geo_width = ((max_lon - min_lon) * lon_scale).max(1e-9);
+    let geo_height = (max_lat - min_lat).max(1e-9);
+
+    // Leave 10% padding inside the inset
+    let usable = inset.width() as f64 * 0.9;
+    let scale = (usable / geo_width).min(usable / geo_height);
+    let center = ((min_lat + max_lat) / 2.0, (min_lon + max_lon) / 2.0);
+    let (cx, cy) = (
+        (inset.left() + inset.width() / 2.0) as f64,
+        (inset.top() + inset.height() / 2.0) as f64,
+    );
+
+    move |(lat, lon)| {
+        let x = cx + (lon - center.1) * lon_scale * scale;
+        let y = cy - (lat - center.0) * scale;
+        (x as f32, y as f32)
+    }
+}
+
+/// Shift longitudes so outlines crossing the antimeridian stay contiguous
+///
+/// If the outline spans more than half the globe, western-hemisphere points are moved
+/// past 180° (e.g. -179° becomes 181°). `location` is shifted the same way.
+pub fn unwrap_antimeridian(rings: &mut [Vec<(f64, f64)>], location: &mut (f64, f64)) {
+    let Some(((_, min_lon), (_, max_lon))) = outline_bounds(rings) else {
+        return;
+    };
+    if max_lon - min_lon <= 180.0 {
+        return;
+    }
+
+    for point in rings.iter_mut().flatten() {
+        if point.1 < 0.0 {
+            point.1 += 360.0;
+        }
+    }
+    if location.1 < 0.0 {
+        location.1 += 360.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap_antimeridian() {
+        // An outline straddling 180° (like Fiji) becomes contiguous
+        let mut rings = vec![vec![(-17.0, 178.0), (-16.0, 179.5), (-17.5, -179.5)]];
+        let mut location = (-17.0, -179.8);
+        unwrap_antimeridian(&mut rings, &mut location);
+        assert_eq!(rings[0][2], (-17.5, 180.5));
+        assert!((location.1 - 180.2).abs() < 1e-9);
+
+        // Ordinary outlines are left alone
+        let mut rings = vec![vec![(45.0, 10.0), (46.0, 12.0), (44.0, 11.0)]];
+        let mut location = (45.0, 11.0);
+        unwrap_antimeridian(&mut rings, &mut location);
+        assert_eq!(rings[0][1], (46.0, 12.0));
+        assert_eq!(location, (45.0, 11.0));
+    }
+
+    #[test]
+    fn test_inset_projection_fits_bounds() {
+        let inset = Rect::from_xywh(100.0, 100.0, 200.0, 200.0).unwrap();
+        let project = inset_projection(((0.0, 0.0), (10.0, 10.0)), inset);
+
+        let (x, y) = project((5.0, 5.0));
+        assert!((x - 200.0).abs() < 0.01 && (y - 200.0).abs() < 0.01);
+
+        // North-west corner lands inside the inset, north is up
+        let (x, y) = project((10.0, 0.0));
+        assert!((100.0..200.0).contains(&x));
+        assert!((100.0..200.0).contains(&y));
+    }
+}
@@ -1,4 +1,12 @@
+pub mod blend;
 pub mod canvas;
+pub mod cmyk;
+pub mod compare;
 pub mod gradients;
+pub mod locator;
+pub mod palette;
+pub mod png_text;
+pub mod resample;
 pub mod road_styles;
+pub mod swatch;
 pub mod typography;
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use tiny_skia::{Pixmap, PremultipliedColorU8};
+
+use crate::themes::loader::parse_hex_color;
+
+/// Every color a theme names: its hex string values, including those inside arrays such as
+/// `bg_gradient` and `road_ramp`, without duplicates and in a stable order
+pub fn theme_palette(theme: &Value) -> Vec<(u8, u8, u8)> {
+    let Some(keys) = theme.as_object() else {
+        return Vec::new();
+    };
+
+    let mut palette = Vec::new();
+    for value in keys.values() {
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for color in values.into_iter().filter_map(|v| v.as_str()).filter_map(parse_hex_color) {
+            if !palette.contains(&color) {
+                palette.push(color);
+            }
+        }
+    }
+    palette
+}
+
+/// Replace every pixel with the nearest palette color (by RGB distance), fully opaque
+///
+/// Removes anti-aliased edges, blended fades and any other in-between shade, so each
+/// color in the output can be printed with its own screen. Pixels less than half opaque
+/// (only found on transparent backgrounds) become fully transparent instead. Does nothing
+/// for an empty palette.
+pub fn flatten_palette(pixmap: &mut Pixmap, palette: &[(u8, u8, u8)]) {
+    if palette.is_empty() {
+        return;
+    }
+
+    // Posters repeat a handful of shades millions of times, so remember each mapping
+    let mut nearest: HashMap<(u8, u8, u8, u8), PremultipliedColorU8> = HashMap::new();
+    for pixel in pixmap.pixels_mut() {
+        let key = (pixel.red(), pixel.green(), pixel.blue(), pixel.alpha());
+        *pixel = *nearest.entry(key).or_insert_with(|| {
+            if pixel.alpha() < 128 {
+                return PremultipliedColorU8::TRANSPARENT;
+            }
+            let color = pixel.demultiply();
+            let (r, g, b) = nearest_color(palette, (color.red(), color.green(), color.blue()));
+            PremultipliedColorU8::from_rgba(r, g, b, 255).expect("opaque colors are always valid")
+        });
+    }
+}
+
+fn nearest_color(palette: &[(u8, u8, u8)], (r, g, b): (u8, u8, u8)) -> (u8, u8, u8) {
+    let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(pr, r) + d(pg, g) + d(pb, b)
+    };
+    *palette.iter().min_by_key(|c| distance(c)).expect("palette is not empty")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_palette() {
+        let theme = serde_json::json!({
+            "name": "Test",
+            "bg": "#FFFFFF",
+            "text": "#000000",
+            "water": "#ffffff",
+            "road_ramp": ["#FF0000", "#0000FF"],
+            "road_min_width": 0.5
+        });
+        let mut palette = theme_palette(&theme);
+        palette.sort_unstable();
+        assert_eq!(palette, vec![(0, 0, 0), (0, 0, 255), (255, 0, 0), (255, 255, 255)]);
+    }
+
+    #[test]
+    fn test_flatten_palette_snaps_to_nearest_color() {
+        let mut pixmap = Pixmap::new(4, 1).unwrap();
+        let pixels = pixmap.pixels_mut();
+        pixels[0] = PremultipliedColorU8::from_rgba(100, 100, 100, 255).unwrap();
+        pixels[1] = PremultipliedColorU8::from_rgba(200, 30, 20, 255).unwrap();
+        // Half-transparent white demultiplies to white
+        pixels[2] = PremultipliedColorU8::from_rgba(128, 128, 128, 128).unwrap();
+        // Mostly transparent edges drop out
+        pixels[3] = PremultipliedColorU8::from_rgba(40, 0, 0, 40).unwrap();
+
+        flatten_palette(&mut pixmap, &[(0, 0, 0), (255, 255, 255), (255, 0, 0)]);
+        let rgba: Vec<_> = pixmap.pixels().iter().map(|p| (p.red(), p.green(), p.blue(), p.alpha())).collect();
+        assert_eq!(rgba, vec![(0, 0, 0, 255), (255, 0, 0, 255), (255, 255, 255, 255), (0, 0, 0, 0)]);
+    }
+}
@@ -0,0 +1,62 @@
+use tiny_skia::Pixmap;
+
+use crate::error::{AppError, Result};
+
+/// Encode a pixmap as a PNG carrying one text chunk per `(keyword, text)` pair
+///
+/// Text that fits in Latin-1 is stored in plain `tEXt` chunks, anything else (a city name
+/// in Cyrillic, say) in UTF-8 `iTXt` chunks. Pixels are written exactly as tiny-skia's own
+/// `encode_png` writes them.
+pub fn encode_png_with_text(pixmap: &Pixmap, entries: &[(&str, String)]) -> Result<Vec<u8>> {
+    let encode_error = |e: png::EncodingError| AppError::Rendering(format!("Failed to encode PNG: {}", e));
+
+    let mut data = Vec::with_capacity(pixmap.data().len());
+    for pixel in pixmap.pixels() {
+        let color = pixel.demultiply();
+        data.extend_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, pixmap.width(), pixmap.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in entries {
+        if text.chars().all(|c| (c as u32) < 256) {
+            encoder.add_text_chunk(keyword.to_string(), text.clone())
+        } else {
+            encoder.add_itxt_chunk(keyword.to_string(), text.clone())
+        }
+        .map_err(encode_error)?;
+    }
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    writer.write_image_data(&data).map_err(encode_error)?;
+    writer.finish().map_err(encode_error)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::Color;
+
+    #[test]
+    fn test_text_chunks_round_trip() {
+        let mut pixmap = Pixmap::new(3, 2).unwrap();
+        pixmap.fill(Color::from_rgba8(200, 100, 50, 128));
+        let entries = [("City", "Zürich".to_string()), ("Country", "Россия".to_string())];
+        let png = encode_png_with_text(&pixmap, &entries).unwrap();
+
+        let reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.uncompressed_latin1_text.len(), 1);
+        assert_eq!(info.uncompressed_latin1_text[0].keyword, "City");
+        assert_eq!(info.uncompressed_latin1_text[0].text, "Zürich");
+        assert_eq!(info.utf8_text.len(), 1);
+        assert_eq!(info.utf8_text[0].keyword, "Country");
+        assert_eq!(info.utf8_text[0].get_text().unwrap(), "Россия");
+
+        // Same pixels as the plain encoder
+        let plain = Pixmap::decode_png(&pixmap.encode_png().unwrap()).unwrap();
+        assert_eq!(Pixmap::decode_png(&png).unwrap().data(), plain.data());
+    }
+}
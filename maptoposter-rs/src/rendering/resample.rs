@@ -0,0 +1,58 @@
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use tiny_skia::{IntSize, Pixmap};
+
+use crate::error::{AppError, Result};
+
+/// Resize a pixmap with a Lanczos filter
+///
+/// Used for previews: rendering large and filtering down keeps thin roads smooth,
+/// where rendering natively small would alias them.
+pub fn downscale_lanczos(pixmap: &Pixmap, width: u32, height: u32) -> Result<Pixmap> {
+    let source = RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixmap.data().to_vec())
+        .ok_or_else(|| AppError::Rendering("Pixmap buffer has an unexpected size".to_string()))?;
+
+    // Filtering premultiplied pixels avoids dark fringes at transparent edges
+    let mut data = imageops::resize(&source, width, height, FilterType::Lanczos3).into_raw();
+
+    // Lanczos can ring past the alpha value; keep the pixels validly premultiplied
+    for pixel in data.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        for channel in &mut pixel[..3] {
+            *channel = (*channel).min(alpha);
+        }
+    }
+
+    let size = IntSize::from_wh(width, height)
+        .ok_or_else(|| AppError::Rendering(format!("Invalid preview size {}x{}", width, height)))?;
+    Pixmap::from_vec(data, size)
+        .ok_or_else(|| AppError::Rendering("Failed to build downscaled pixmap".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_skia::{Color, Paint, PathBuilder, Stroke, Transform};
+
+    #[test]
+    fn test_downscale_lanczos_smooths_thin_lines() {
+        let mut pixmap = Pixmap::new(400, 400).unwrap();
+        pixmap.fill(Color::WHITE);
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(0, 0, 0, 255);
+        let mut pb = PathBuilder::new();
+        pb.move_to(0.0, 201.0);
+        pb.line_to(400.0, 201.0);
+        let stroke = Stroke { width: 2.0, ..Default::default() };
+        pixmap.stroke_path(&pb.finish().unwrap(), &paint, &stroke, Transform::identity(), None);
+
+        let small = downscale_lanczos(&pixmap, 100, 100).unwrap();
+        assert_eq!((small.width(), small.height()), (100, 100));
+
+        // The 2px line survives as a partially covered grey row instead of vanishing
+        let row: Vec<u8> = (45..55).map(|y| small.pixel(50, y).unwrap().red()).collect();
+        let darkest = *row.iter().min().unwrap();
+        assert!(darkest > 50 && darkest < 200, "row {:?}", row);
+        assert_eq!(small.pixel(50, 10).unwrap().red(), 255);
+    }
+}
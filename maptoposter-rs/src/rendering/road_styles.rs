@@ -1,55 +1,100 @@
-use crate::core::osm_client::HighwayType;
-
-/// Road style configuration
-#[derive(Debug, Clone)]
-pub struct RoadStyle {
-    pub width: f32,
-    pub color_key: &'static str,
-    pub default_color: &'static str,
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::core::osm_client::{HighwayType, RoadSegment};
+use crate::themes::loader::{get_theme_color, mix_hex_colors, parse_hex_color, DEFAULT_ROAD_COLOR};
+
+/// Distance at which every width curve yields the reference multiplier
+const REFERENCE_DISTANCE: f32 = 15000.0;
+
+/// Road width multiplier at the reference distance
+const REFERENCE_MULTIPLIER: f32 = 2.0;
+
+/// How road widths scale with the poster's distance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WidthCurve {
+    /// Widths scale with the square root of the zoom factor
+    #[default]
+    Sqrt,
+    /// Widths scale directly with the zoom factor (strongest contrast)
+    Linear,
+    /// Widths change only gently with distance
+    Log,
+}
+
+impl WidthCurve {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sqrt" => Some(Self::Sqrt),
+            "linear" => Some(Self::Linear),
+            "log" => Some(Self::Log),
+            _ => None,
+        }
+    }
+}
+
+/// Unscaled width of the thinnest and widest roads (service roads and motorways)
+const MIN_LINE_WIDTH: f32 = 0.3;
+const MAX_LINE_WIDTH: f32 = 1.2;
+
+/// Lane counts mapped onto the road width range; more lanes are drawn at the widest
+const LANES_RANGE: (f32, f32) = (1.0, 6.0);
+
+/// Speed limits (km/h) mapped onto the road width range
+const MAXSPEED_RANGE: (f32, f32) = (20.0, 130.0);
+
+/// What sets each road's stroke width
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WidthBy {
+    /// A fixed width per highway type
+    #[default]
+    Type,
+    /// The `lanes` tag
+    Lanes,
+    /// The `maxspeed` tag
+    Maxspeed,
 }
 
-impl RoadStyle {
-    pub fn for_highway(highway_type: HighwayType) -> Self {
-        match highway_type {
-            HighwayType::Motorway | HighwayType::MotorwayLink => Self {
-                width: 1.2,
-                color_key: "road_motorway",
-                default_color: "#0A0A0A",
-            },
-            HighwayType::Trunk | HighwayType::Primary | HighwayType::PrimaryLink => Self {
-                width: 1.0,
-                color_key: "road_primary",
-                default_color: "#1A1A1A",
-            },
-            HighwayType::Secondary | HighwayType::SecondaryLink => Self {
-                width: 0.8,
-                color_key: "road_secondary",
-                default_color: "#2A2A2A",
-            },
-            HighwayType::Tertiary | HighwayType::TertiaryLink => Self {
-                width: 0.6,
-                color_key: "road_tertiary",
-                default_color: "#3A3A3A",
-            },
-            HighwayType::Residential | HighwayType::LivingStreet => Self {
-                width: 0.4,
-                color_key: "road_residential",
-                default_color: "#4A4A4A",
-            },
-            HighwayType::Service | HighwayType::Unclassified => Self {
-                width: 0.3,
-                color_key: "road_residential",
-                default_color: "#4A4A4A",
-            },
-            HighwayType::Default => Self {
-                width: 0.4,
-                color_key: "road_default",
-                default_color: "#3A3A3A",
-            },
+impl WidthBy {
+    /// Unscaled stroke width of a road
+    ///
+    /// Tag values are normalized over a fixed range onto the span of the per-type widths,
+    /// so the same tag gives the same width on every poster. Roads without the tag keep
+    /// their type's width.
+    pub fn line_width(self, segment: &RoadSegment) -> f32 {
+        let (value, (low, high)) = match self {
+            Self::Type => return segment.highway_type.line_width(),
+            Self::Lanes => (segment.lanes, LANES_RANGE),
+            Self::Maxspeed => (segment.maxspeed, MAXSPEED_RANGE),
+        };
+        match value {
+            Some(value) => {
+                let t = ((value as f32 - low) / (high - low)).clamp(0.0, 1.0);
+                MIN_LINE_WIDTH + t * (MAX_LINE_WIDTH - MIN_LINE_WIDTH)
+            }
+            None => segment.highway_type.line_width(),
         }
     }
 }
 
+/// Base road width multiplier for a poster covering `distance` meters
+///
+/// All curves pass through 2.0 at 15km and shrink as the distance grows.
+pub fn width_multiplier(distance: u32, curve: WidthCurve) -> f32 {
+    // Keep ln(distance) well above zero for the log curve
+    let distance = (distance as f32).max(2.0);
+    let ratio = REFERENCE_DISTANCE / distance;
+    REFERENCE_MULTIPLIER
+        * match curve {
+            WidthCurve::Sqrt => ratio.sqrt(),
+            WidthCurve::Linear => ratio,
+            WidthCurve::Log => REFERENCE_DISTANCE.ln() / distance.ln(),
+        }
+}
+
 /// Get the drawing priority for a highway type (higher = drawn later = on top)
 pub fn highway_priority(highway_type: HighwayType) -> u8 {
     match highway_type {
@@ -62,3 +107,115 @@ pub fn highway_priority(highway_type: HighwayType) -> u8 {
         HighwayType::Service | HighwayType::Unclassified | HighwayType::Default => 1,
     }
 }
+
+/// Two-color ramp interpolated across the highway hierarchy, from the theme's `road_ramp`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoadRamp {
+    /// Color of the least important roads (service, unclassified)
+    pub low: String,
+    /// Color of motorways
+    pub high: String,
+}
+
+impl RoadRamp {
+    /// Ramp from `road_ramp: ["#low", "#high"]`, or None if absent or not two hex colors
+    pub fn from_theme(theme: &Value) -> Option<Self> {
+        match theme.get("road_ramp")?.as_array()?.as_slice() {
+            [low, high] => {
+                let low = low.as_str().filter(|c| parse_hex_color(c).is_some())?;
+                let high = high.as_str().filter(|c| parse_hex_color(c).is_some())?;
+                Some(Self {
+                    low: low.to_string(),
+                    high: high.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Color for a highway type, placed on the ramp by its `highway_priority`
+    pub fn color(&self, highway_type: HighwayType) -> String {
+        let (min, max) = (
+            highway_priority(HighwayType::Default) as f32,
+            highway_priority(HighwayType::Motorway) as f32,
+        );
+        let t = (highway_priority(highway_type) as f32 - min) / (max - min);
+        mix_hex_colors(&self.low, &self.high, t).unwrap_or_else(|| self.high.clone())
+    }
+}
+
+/// Road color for a highway type: from the ramp when the theme has one, else its `road_*` key
+pub fn road_color(theme: &Value, ramp: Option<&RoadRamp>, highway_type: HighwayType) -> String {
+    match ramp {
+        Some(ramp) => ramp.color(highway_type),
+        None => get_theme_color(theme, highway_type.theme_key(), DEFAULT_ROAD_COLOR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_width_by_tags() {
+        let road = |lanes, maxspeed| RoadSegment {
+            id: 1,
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+            highway_type: HighwayType::Residential,
+            oneway: false,
+            lanes,
+            maxspeed,
+        };
+        let residential = HighwayType::Residential.line_width();
+        assert_eq!(WidthBy::Type.line_width(&road(Some(6), Some(130))), residential);
+
+        assert_eq!(WidthBy::Lanes.line_width(&road(Some(1), None)), MIN_LINE_WIDTH);
+        assert_eq!(WidthBy::Lanes.line_width(&road(Some(10), None)), MAX_LINE_WIDTH);
+        assert!(WidthBy::Lanes.line_width(&road(Some(3), None)) < WidthBy::Lanes.line_width(&road(Some(4), None)));
+        assert_eq!(WidthBy::Maxspeed.line_width(&road(None, Some(130))), MAX_LINE_WIDTH);
+
+        // Untagged roads keep their type's width
+        assert_eq!(WidthBy::Lanes.line_width(&road(None, Some(50))), residential);
+        assert_eq!(WidthBy::Maxspeed.line_width(&road(Some(2), None)), residential);
+    }
+
+    #[test]
+    fn test_width_multiplier_curves() {
+        let curves = [WidthCurve::Sqrt, WidthCurve::Linear, WidthCurve::Log];
+        for curve in curves {
+            assert!((width_multiplier(15000, curve) - 2.0).abs() < 1e-5);
+
+            // Strictly decreasing and positive across the supported distance range
+            let mut previous = f32::INFINITY;
+            for distance in (1000..=50000).step_by(500) {
+                let width = width_multiplier(distance, curve);
+                assert!(width > 0.0 && width < previous, "{:?} at {}m", curve, distance);
+                previous = width;
+            }
+        }
+
+        // The previous fixed formula is the default curve
+        assert_eq!(WidthCurve::default(), WidthCurve::Sqrt);
+        assert_eq!(width_multiplier(50000, WidthCurve::Sqrt), 2.0 * (15000.0 / 50000.0f32).sqrt());
+        assert_eq!(WidthCurve::from_name("LOG"), Some(WidthCurve::Log));
+        assert_eq!(WidthCurve::from_name("stepped"), None);
+    }
+
+    #[test]
+    fn test_road_ramp() {
+        assert_eq!(RoadRamp::from_theme(&serde_json::json!({})), None);
+        assert_eq!(RoadRamp::from_theme(&serde_json::json!({ "road_ramp": ["#111111"] })), None);
+        assert_eq!(RoadRamp::from_theme(&serde_json::json!({ "road_ramp": ["#111111", "grey"] })), None);
+
+        let theme = serde_json::json!({ "road_ramp": ["#111111", "#AAAAAA"], "road_motorway": "#FF0000" });
+        let ramp = RoadRamp::from_theme(&theme).unwrap();
+        // The ramp overrides explicit keys and spans the whole hierarchy
+        assert_eq!(road_color(&theme, Some(&ramp), HighwayType::Motorway), "#AAAAAA");
+        assert_eq!(road_color(&theme, Some(&ramp), HighwayType::Service), "#111111");
+        assert_eq!(ramp.color(HighwayType::Residential), "#222222");
+
+        // Without a ramp the per-type keys apply
+        assert_eq!(road_color(&theme, None, HighwayType::Motorway), "#FF0000");
+        assert_eq!(road_color(&theme, None, HighwayType::Primary), DEFAULT_ROAD_COLOR);
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::Value;
+use tiny_skia::{Pixmap, Rect};
+
+use crate::error::Result;
+use crate::rendering::blend::BlendMode;
+use crate::rendering::canvas::Canvas;
+use crate::rendering::typography::{render_text, FontSet};
+use crate::themes::loader::{get_theme_color, parse_hex_color};
+
+/// Theme keys shown in a swatch strip, left to right
+pub const SWATCH_KEYS: [&str; 10] = [
+    "bg",
+    "water",
+    "parks",
+    "road_motorway",
+    "road_primary",
+    "road_secondary",
+    "road_tertiary",
+    "road_residential",
+    "road_default",
+    "text",
+];
+
+/// Width and height of each color block
+const SWATCH_SIZE: u32 = 64;
+
+/// Height of the label area under each block (key name and hex value)
+const LABEL_HEIGHT: u32 = 30;
+
+const LABEL_FONT_SIZE: f32 = 10.0;
+
+/// A horizontal strip of the theme's colors, each labelled with its key and hex value
+///
+/// Keys the theme doesn't set, or sets to something other than a hex color, are left
+/// out. Labels use the theme's `text` color on its `bg`.
+pub fn render_swatch(theme: &Value, fonts: &FontSet) -> Result<Pixmap> {
+    let colors: Vec<(&str, String)> = SWATCH_KEYS
+        .iter()
+        .filter_map(|key| theme.get(*key).and_then(|v| v.as_str()).map(|hex| (*key, hex)))
+        .filter(|(_, hex)| parse_hex_color(hex).is_some())
+        .map(|(key, hex)| (key, hex.to_uppercase()))
+        .collect();
+
+    let bg = get_theme_color(theme, "bg", "#FFFFFF");
+    let text = get_theme_color(theme, "text", "#000000");
+    let mut canvas = Canvas::new(SWATCH_SIZE * colors.len().max(1) as u32, SWATCH_SIZE + LABEL_HEIGHT)?;
+    canvas.fill_background(&bg);
+
+    for (i, (key, hex)) in colors.iter().enumerate() {
+        let left = (i as u32 * SWATCH_SIZE) as f32;
+        let block = Rect::from_xywh(left, 0.0, SWATCH_SIZE as f32, SWATCH_SIZE as f32).expect("swatch block is not empty");
+        canvas.fill_rect(block, hex);
+
+        let center = left + SWATCH_SIZE as f32 / 2.0;
+        let label = key.strip_prefix("road_").unwrap_or(key);
+        let baseline = SWATCH_SIZE as f32 + LABEL_FONT_SIZE + 3.0;
+        render_text(&mut canvas.pixmap, label, fonts.regular(), LABEL_FONT_SIZE, &text, center, baseline, true, 0.0, BlendMode::Srgb);
+        render_text(&mut canvas.pixmap, hex, fonts.light(), LABEL_FONT_SIZE, &text, center, baseline + LABEL_FONT_SIZE + 2.0, true, 0.0, BlendMode::Srgb);
+    }
+
+    Ok(canvas.pixmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_swatch() {
+        let fonts = FontSet::load(&std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../fonts"))).unwrap();
+        let theme = serde_json::json!({
+            "bg": "#000000",
+            "text": "#FFFFFF",
+            "water": "#0000ff",
+            "road_motorway": "#FF0000",
+            "parks": "not a color"
+        });
+        let pixmap = render_swatch(&theme, &fonts).unwrap();
+
+        // bg, water, motorway and text; parks is skipped
+        assert_eq!((pixmap.width(), pixmap.height()), (4 * SWATCH_SIZE, SWATCH_SIZE + LABEL_HEIGHT));
+        let rgb = |x: u32, y: u32| {
+            let p = pixmap.pixel(x, y).unwrap();
+            (p.red(), p.green(), p.blue())
+        };
+        let middle = SWATCH_SIZE / 2;
+        assert_eq!(rgb(middle, middle), (0, 0, 0));
+        assert_eq!(rgb(SWATCH_SIZE + middle, middle), (0, 0, 255));
+        assert_eq!(rgb(2 * SWATCH_SIZE + middle, middle), (255, 0, 0));
+        assert_eq!(rgb(3 * SWATCH_SIZE + middle, middle), (255, 255, 255));
+
+        // Every swatch has a label drawn in the text color under it
+        for i in 0..4 {
+            let labelled = (i * SWATCH_SIZE..(i + 1) * SWATCH_SIZE)
+                .flat_map(|x| (SWATCH_SIZE..SWATCH_SIZE + LABEL_HEIGHT).map(move |y| (x, y)))
+                .any(|(x, y)| rgb(x, y).0 > 128);
+            assert!(labelled, "swatch {} has no label", i);
+        }
+    }
+}
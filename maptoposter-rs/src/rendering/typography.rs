@@ -1,44 +1,152 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use fontdue::{Font, FontSettings};
-use tiny_skia::Pixmap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Rect, Transform};
 
 use crate::error::{AppError, Result};
-use crate::themes::loader::parse_hex_color;
+use crate::rendering::blend::{blend_over, BlendMode};
+use crate::themes::loader::{get_theme_color, get_theme_number, parse_hex_color};
+
+/// Default opacity of text plates (theme key `text_plate_opacity`)
+pub const DEFAULT_PLATE_OPACITY: f32 = 0.6;
+
+/// Default plate padding as a fraction of the block's largest font size (theme key `text_plate_padding`)
+pub const DEFAULT_PLATE_PADDING: f32 = 0.5;
+
+/// Rasterizer settings every font is loaded with, pinned rather than taken from `FontSettings::default()`
+///
+/// fontdue applies no hinting, so glyph coverage depends only on the outline and `scale`,
+/// the px-per-em size the outline geometry is flattened for (40 is fontdue 0.9's default).
+/// Glyphs are rasterized one character at a time, so GSUB substitutions are never used.
+/// Changing any of this shifts anti-aliasing and requires regenerating the golden image
+/// checked by `test_golden_text_rendering`.
+fn font_settings() -> FontSettings {
+    FontSettings {
+        collection_index: 0,
+        scale: 40.0,
+        load_substitutions: false,
+    }
+}
 
-/// Font collection for text rendering
+/// Weights every font set has, and the files they are loaded from
+pub const DEFAULT_WEIGHTS: [(&str, &str); 3] = [
+    ("bold", "Roboto-Bold.ttf"),
+    ("regular", "Roboto-Regular.ttf"),
+    ("light", "Roboto-Light.ttf"),
+];
+
+/// Font collection for text rendering, by weight name
+#[derive(Clone)]
 pub struct FontSet {
-    pub bold: Font,
-    pub regular: Font,
-    pub light: Font,
+    weights: HashMap<String, Font>,
 }
 
 impl FontSet {
-    /// Load fonts from a directory
+    /// Load fonts from a directory: the default weights, then any other font file in it
+    ///
+    /// Extra files are named by the part of the file name after the family, in snake case,
+    /// so `Roboto-Thin.ttf` is `thin` and `Roboto-MediumItalic.ttf` is `medium_italic`.
+    /// An extra file that fails to load is skipped with a warning.
     pub fn load(fonts_dir: &Path) -> Result<Self> {
-        let bold = load_font(fonts_dir.join("Roboto-Bold.ttf"))?;
-        let regular = load_font(fonts_dir.join("Roboto-Regular.ttf"))?;
-        let light = load_font(fonts_dir.join("Roboto-Light.ttf"))?;
-
-        Ok(Self {
-            bold,
-            regular,
-            light,
+        let mut weights = HashMap::new();
+        for (name, file) in DEFAULT_WEIGHTS {
+            weights.insert(name.to_string(), load_font(fonts_dir.join(file))?);
+        }
+
+        let mut extra: Vec<PathBuf> = std::fs::read_dir(fonts_dir)
+            .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        extra.retain(|path| {
+            let is_font = path.extension().and_then(|e| e.to_str()).is_some_and(|e| matches!(e.to_lowercase().as_str(), "ttf" | "otf"));
+            let is_default = DEFAULT_WEIGHTS.iter().any(|(_, file)| path.file_name().is_some_and(|name| name == *file));
+            is_font && !is_default
+        });
+        extra.sort();
+        for path in extra {
+            let Some(name) = weight_name(&path) else {
+                continue;
+            };
+            if weights.contains_key(&name) {
+                continue;
+            }
+            match load_font(&path) {
+                Ok(font) => {
+                    weights.insert(name, font);
+                }
+                Err(e) => tracing::warn!("Skipping font: {}", e),
+            }
+        }
+
+        Ok(Self { weights })
+    }
+
+    /// Add or replace weights named by a theme's `fonts` object (weight name to a file in `fonts_dir`)
+    ///
+    /// Files outside the fonts directory, or that fail to load, are skipped with a warning.
+    pub fn load_theme_fonts(&mut self, fonts_dir: &Path, theme: &Value) {
+        let Some(fonts) = theme.get("fonts").and_then(|v| v.as_object()) else {
+            return;
+        };
+        for (name, file) in fonts {
+            let Some(file) = file.as_str().filter(|f| Path::new(f).file_name().is_some_and(|n| n == *f)) else {
+                tracing::warn!("Ignoring theme font '{}': expected a file name in the fonts directory", name);
+                continue;
+            };
+            match load_font(fonts_dir.join(file)) {
+                Ok(font) => {
+                    self.weights.insert(name.clone(), font);
+                }
+                Err(e) => tracing::warn!("Ignoring theme font '{}': {}", name, e),
+            }
+        }
+    }
+
+    pub fn regular(&self) -> &Font {
+        self.weight("regular")
+    }
+
+    pub fn light(&self) -> &Font {
+        self.weight("light")
+    }
+
+    /// Font for a weight name, or `regular` (with a warning) if there is no such weight
+    pub fn weight(&self, name: &str) -> &Font {
+        self.weights.get(name).unwrap_or_else(|| {
+            tracing::warn!("Unknown font weight '{}', using regular", name);
+            &self.weights["regular"]
         })
     }
 }
 
+/// Weight name for a font file: the snake-cased part of its stem after the family name
+fn weight_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let style = stem.split_once('-').map_or(stem, |(_, style)| style);
+    let mut name = String::new();
+    for (i, c) in style.chars().filter(|c| c.is_alphanumeric()).enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.extend(c.to_lowercase());
+    }
+    (!name.is_empty()).then_some(name)
+}
+
 fn load_font(path: impl AsRef<Path>) -> Result<Font> {
     let data = std::fs::read(path.as_ref()).map_err(|e| {
         AppError::Rendering(format!("Failed to read font {:?}: {}", path.as_ref(), e))
     })?;
 
-    Font::from_bytes(data, FontSettings::default()).map_err(|e| {
+    Font::from_bytes(data, font_settings()).map_err(|e| {
         AppError::Rendering(format!("Failed to load font {:?}: {}", path.as_ref(), e))
     })
 }
 
 /// Render text onto a pixmap
+#[allow(clippy::too_many_arguments)]
 pub fn render_text(
     pixmap: &mut Pixmap,
     text: &str,
@@ -49,6 +157,7 @@ pub fn render_text(
     y: f32,
     centered: bool,
     letter_spacing: f32,
+    mode: BlendMode,
 ) {
     let (r, g, b) = match parse_hex_color(hex_color) {
         Some(c) => c,
@@ -95,7 +204,7 @@ pub fn render_text(
 
                 if px < width && py < height {
                     let idx = py * width + px;
-                    blend_text_pixel(&mut pixels[idx], r, g, b, alpha);
+                    blend_over(&mut pixels[idx], r, g, b, alpha, mode);
                 }
             }
         }
@@ -104,43 +213,112 @@ pub fn render_text(
     }
 }
 
-/// Blend text pixel onto existing pixel
-fn blend_text_pixel(pixel: &mut tiny_skia::PremultipliedColorU8, r: u8, g: u8, b: u8, alpha: u8) {
-    if alpha == 0 {
-        return;
-    }
+/// Screen-space box around some text (left, top, right, bottom)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextBounds {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
 
-    let a = alpha as f32 / 255.0;
-    let inv_a = 1.0 - a;
+impl TextBounds {
+    /// Smallest box containing both
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+}
 
-    let existing_a = pixel.alpha();
-    if existing_a == 0 {
-        *pixel = tiny_skia::PremultipliedColorU8::from_rgba(
-            (r as f32 * a) as u8,
-            (g as f32 * a) as u8,
-            (b as f32 * a) as u8,
-            alpha,
-        )
-        .unwrap();
-        return;
+/// Box covering the glyphs `render_text` would draw with the same arguments
+pub fn text_bounds(
+    text: &str,
+    font: &Font,
+    size: f32,
+    x: f32,
+    y: f32,
+    centered: bool,
+    letter_spacing: f32,
+) -> Option<TextBounds> {
+    let mut total_width = 0.0;
+    let mut top = f32::MAX;
+    let mut bottom = f32::MIN;
+    for c in text.chars() {
+        let metrics = font.metrics(c, size);
+        total_width += metrics.advance_width + letter_spacing;
+        if metrics.height > 0 {
+            top = top.min(y - metrics.ymin as f32 - metrics.height as f32);
+            bottom = bottom.max(y - metrics.ymin as f32);
+        }
+    }
+    if top > bottom {
+        return None;
     }
 
-    let existing_r = pixel.red() as f32 / existing_a as f32 * 255.0;
-    let existing_g = pixel.green() as f32 / existing_a as f32 * 255.0;
-    let existing_b = pixel.blue() as f32 / existing_a as f32 * 255.0;
+    let left = if centered { x - total_width / 2.0 } else { x };
+    Some(TextBounds {
+        left,
+        top,
+        right: left + total_width,
+        bottom,
+    })
+}
 
-    let new_r = (r as f32 * a + existing_r * inv_a).min(255.0) as u8;
-    let new_g = (g as f32 * a + existing_g * inv_a).min(255.0) as u8;
-    let new_b = (b as f32 * a + existing_b * inv_a).min(255.0) as u8;
-    let new_a = ((alpha as f32 + existing_a as f32 * inv_a)).min(255.0) as u8;
+/// Semi-transparent rounded plate drawn behind text blocks, enabled by the theme's `text_plate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextPlate {
+    pub color: (u8, u8, u8),
+    /// Opacity from 0.0 (invisible) to 1.0 (solid)
+    pub opacity: f32,
+    /// Padding around the text as a fraction of the block's largest font size
+    pub padding: f32,
+}
 
-    *pixel = tiny_skia::PremultipliedColorU8::from_rgba(
-        (new_r as f32 * new_a as f32 / 255.0) as u8,
-        (new_g as f32 * new_a as f32 / 255.0) as u8,
-        (new_b as f32 * new_a as f32 / 255.0) as u8,
-        new_a,
-    )
-    .unwrap();
+impl TextPlate {
+    /// Plate settings from `text_plate`, `text_plate_opacity` and `text_plate_padding`
+    ///
+    /// None (no plates) unless the theme sets a valid `text_plate` color.
+    pub fn from_theme(theme: &Value) -> Option<Self> {
+        let color = parse_hex_color(&get_theme_color(theme, "text_plate", ""))?;
+        Some(Self {
+            color,
+            opacity: get_theme_number(theme, "text_plate_opacity", DEFAULT_PLATE_OPACITY).clamp(0.0, 1.0),
+            padding: get_theme_number(theme, "text_plate_padding", DEFAULT_PLATE_PADDING).max(0.0),
+        })
+    }
+
+    /// Fill a rounded rectangle around `bounds`, padded relative to `font_size`
+    pub fn draw(&self, pixmap: &mut Pixmap, bounds: TextBounds, font_size: f32) {
+        let pad = self.padding * font_size;
+        let (left, top) = (bounds.left - pad, bounds.top - pad);
+        let (right, bottom) = (bounds.right + pad, bounds.bottom + pad);
+        let radius = pad.min((right - left) / 2.0).min((bottom - top) / 2.0);
+
+        let mut pb = PathBuilder::new();
+        pb.move_to(left + radius, top);
+        pb.line_to(right - radius, top);
+        pb.quad_to(right, top, right, top + radius);
+        pb.line_to(right, bottom - radius);
+        pb.quad_to(right, bottom, right - radius, bottom);
+        pb.line_to(left + radius, bottom);
+        pb.quad_to(left, bottom, left, bottom - radius);
+        pb.line_to(left, top + radius);
+        pb.quad_to(left, top, left + radius, top);
+        pb.close();
+        let Some(path) = pb.finish() else {
+            return;
+        };
+
+        let (r, g, b) = self.color;
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(r, g, b, (self.opacity * 255.0).round() as u8);
+        paint.anti_alias = true;
+        pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+    }
 }
 
 /// Render a decorative line
@@ -173,81 +351,358 @@ pub fn render_line(pixmap: &mut Pixmap, hex_color: &str, y: f32, width_ratio: f3
     }
 }
 
-/// Render all poster typography (city, country, coordinates, attribution)
-pub fn render_poster_typography(
+/// Position, size and weight of one line of poster text, as fractions of the canvas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextStyle {
+    /// Baseline position as a fraction of the canvas height
+    pub y: f32,
+    /// Font size as a fraction of the canvas height
+    pub size: f32,
+    /// Letter spacing as a fraction of the font size
+    pub letter_spacing: f32,
+    /// Font weight name from the `FontSet` (e.g. `bold`, `light`, `thin`)
+    pub weight: String,
+}
+
+/// Layout of the poster text block, loadable from a theme's `typography` object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypographyLayout {
+    pub city: TextStyle,
+    pub country: TextStyle,
+    pub coordinates: TextStyle,
+    /// Decorative line position as a fraction of the canvas height
+    pub line_y: f32,
+    /// Decorative line length as a fraction of the canvas width
+    pub line_width: f32,
+    /// Decorative line thickness in pixels
+    pub line_thickness: f32,
+}
+
+impl Default for TypographyLayout {
+    fn default() -> Self {
+        Self {
+            city: TextStyle {
+                y: 0.86,
+                size: 0.04,
+                letter_spacing: 0.3,
+                weight: "bold".to_string(),
+            },
+            country: TextStyle {
+                y: 0.90,
+                size: 0.015,
+                letter_spacing: 0.2,
+                weight: "regular".to_string(),
+            },
+            coordinates: TextStyle {
+                y: 0.93,
+                size: 0.01,
+                letter_spacing: 0.0,
+                weight: "light".to_string(),
+            },
+            line_y: 0.875,
+            line_width: 0.2,
+            line_thickness: 2.0,
+        }
+    }
+}
+
+impl TypographyLayout {
+    /// Load the layout from a theme's `typography` object
+    ///
+    /// Keys may be given partially (e.g. only `city.y`); anything missing keeps its default.
+    pub fn from_theme(theme: &Value) -> Self {
+        let Some(overrides) = theme.get("typography") else {
+            return Self::default();
+        };
+
+        let mut layout = serde_json::to_value(Self::default()).unwrap_or(Value::Null);
+        merge_json(&mut layout, overrides);
+
+        serde_json::from_value(layout).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid theme typography: {}", e);
+            Self::default()
+        })
+    }
+}
+
+/// Recursively overlay `overrides` onto `base`, object keys only
+fn merge_json(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// Render a line of text centered horizontally according to its style
+fn render_styled_text(
     pixmap: &mut Pixmap,
-    fonts: &FontSet,
-    city: &str,
-    country: &str,
-    coordinates: &str,
+    trim: Rect,
+    text: &str,
+    font: &Font,
+    style: &TextStyle,
     text_color: &str,
+    mode: BlendMode,
 ) {
-    let width = pixmap.width() as f32;
-    let height = pixmap.height() as f32;
-    let center_x = width / 2.0;
-
-    // City name (with letter spacing) - y=0.14
-    let city_y = height * 0.86;
-    let city_size = height * 0.04; // Larger font for city
+    let height = trim.height();
+    let center_x = trim.left() + trim.width() / 2.0;
+    let size = height * style.size;
     render_text(
         pixmap,
-        &city.to_uppercase(),
-        &fonts.bold,
-        city_size,
+        text,
+        font,
+        size,
         text_color,
         center_x,
-        city_y,
+        trim.top() + height * style.y,
         true,
-        city_size * 0.3, // Letter spacing
+        size * style.letter_spacing,
+        mode,
     );
+}
 
-    // Decorative line - y=0.125
-    let line_y = height * 0.875;
-    render_line(pixmap, text_color, line_y, 0.2, 2.0);
+/// Bounds of a line `render_styled_text` would draw in the given trim box
+fn styled_text_bounds(text: &str, font: &Font, style: &TextStyle, trim: Rect) -> Option<TextBounds> {
+    let size = trim.height() * style.size;
+    let center_x = trim.left() + trim.width() / 2.0;
+    text_bounds(text, font, size, center_x, trim.top() + trim.height() * style.y, true, size * style.letter_spacing)
+}
 
-    // Country name - y=0.10
-    let country_y = height * 0.90;
-    let country_size = height * 0.015;
-    render_text(
-        pixmap,
-        &country.to_uppercase(),
-        &fonts.regular,
-        country_size,
-        text_color,
-        center_x,
-        country_y,
-        true,
-        country_size * 0.2,
-    );
+/// Attribution text, drawn right-aligned at the bottom
+const ATTRIBUTION: &str = "Map data © OpenStreetMap";
 
-    // Coordinates - y=0.07
-    let coords_y = height * 0.93;
-    let coords_size = height * 0.01;
-    render_text(
+/// Render all poster typography (city, country, coordinates, attribution)
+///
+/// Positions are relative to `trim`, the whole pixmap unless the poster has a print bleed.
+#[allow(clippy::too_many_arguments)]
+pub fn render_poster_typography(
+    pixmap: &mut Pixmap,
+    trim: Rect,
+    fonts: &FontSet,
+    city: &str,
+    country: &str,
+    coordinates: &str,
+    text_color: &str,
+    layout: &TypographyLayout,
+    plate: Option<&TextPlate>,
+    mode: BlendMode,
+) {
+    let (left, top) = (trim.left(), trim.top());
+    let width = trim.width();
+    let height = trim.height();
+    let city = city.to_uppercase();
+    let country = country.to_uppercase();
+    let city_font = fonts.weight(&layout.city.weight);
+    let country_font = fonts.weight(&layout.country.weight);
+    let coordinates_font = fonts.weight(&layout.coordinates.weight);
+
+    let attr_y = top + height * 0.98;
+    let attr_size = height * 0.006;
+    let attr_x = left + width * 0.98;
+
+    // Plates go under the text: one for the city/country/coordinates block, one for the attribution
+    if let Some(plate) = plate {
+        let line_width = width * layout.line_width;
+        let line = TextBounds {
+            left: left + (width - line_width) / 2.0,
+            top: top + height * layout.line_y,
+            right: left + (width + line_width) / 2.0,
+            bottom: top + height * layout.line_y + layout.line_thickness,
+        };
+        let block = [
+            styled_text_bounds(&city, city_font, &layout.city, trim),
+            styled_text_bounds(&country, country_font, &layout.country, trim),
+            styled_text_bounds(coordinates, coordinates_font, &layout.coordinates, trim),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(line, TextBounds::union);
+        let largest = layout.city.size.max(layout.country.size).max(layout.coordinates.size) * height;
+        plate.draw(pixmap, block, largest);
+
+        if let Some(bounds) = text_bounds(ATTRIBUTION, fonts.light(), attr_size, attr_x, attr_y, false, 0.0) {
+            plate.draw(pixmap, bounds, attr_size);
+        }
+    }
+
+    // City name (with letter spacing)
+    render_styled_text(pixmap, trim, &city, city_font, &layout.city, text_color, mode);
+
+    // Decorative line, centered like the trim box (its width is a fraction of the trim width)
+    render_line(
         pixmap,
-        coordinates,
-        &fonts.light,
-        coords_size,
         text_color,
-        center_x,
-        coords_y,
-        true,
-        0.0,
+        top + height * layout.line_y,
+        layout.line_width * width / pixmap.width() as f32,
+        layout.line_thickness,
     );
 
+    // Country name
+    render_styled_text(pixmap, trim, &country, country_font, &layout.country, text_color, mode);
+
+    // Coordinates
+    render_styled_text(pixmap, trim, coordinates, coordinates_font, &layout.coordinates, text_color, mode);
+
     // Attribution - bottom right
-    let attr_y = height * 0.98;
-    let attr_size = height * 0.006;
-    let attr_x = width * 0.98;
     render_text(
         pixmap,
-        "Map data © OpenStreetMap",
-        &fonts.light,
+        ATTRIBUTION,
+        fonts.light(),
         attr_size,
         text_color,
         attr_x,
         attr_y,
         false,
         0.0,
+        mode,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference rendering for `test_golden_text_rendering`
+    const GOLDEN_TEXT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/golden_text.png");
+
+    /// Render a fixed string with each weight, black on white
+    fn golden_text_pixmap() -> Pixmap {
+        let fonts = FontSet::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../fonts")).unwrap();
+        let mut pixmap = Pixmap::new(320, 120).unwrap();
+        pixmap.fill(tiny_skia::Color::WHITE);
+        for (i, font) in [fonts.weight("bold"), fonts.regular(), fonts.light()].into_iter().enumerate() {
+            let y = 34.0 + 36.0 * i as f32;
+            render_text(&mut pixmap, "P A R I S · 48.8566° N", font, 24.0, "#000000", 160.0, y, true, 0.0, BlendMode::Srgb);
+        }
+        pixmap
+    }
+
+    /// Glyph rasterization must match the committed reference; run with `UPDATE_GOLDEN=1` to
+    /// rewrite it after an intended change
+    #[test]
+    fn test_golden_text_rendering() {
+        let pixmap = golden_text_pixmap();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            pixmap.save_png(GOLDEN_TEXT).unwrap();
+        }
+        let golden = Pixmap::load_png(GOLDEN_TEXT).unwrap();
+        assert_eq!((golden.width(), golden.height()), (pixmap.width(), pixmap.height()));
+
+        // Allow off-by-a-few coverage differences on a handful of edge pixels
+        let differing = pixmap
+            .pixels()
+            .iter()
+            .zip(golden.pixels())
+            .filter(|(a, b)| a.red().abs_diff(b.red()) > 8 || a.alpha() != b.alpha())
+            .count();
+        assert!(
+            differing * 1000 <= pixmap.pixels().len(),
+            "{} pixels differ from {}",
+            differing,
+            GOLDEN_TEXT
+        );
+        // Guard against a blank reference passing trivially
+        assert!(golden.pixels().iter().filter(|p| p.red() < 128).count() > 500);
+    }
+
+    #[test]
+    fn test_typography_layout_from_theme() {
+        assert_eq!(TypographyLayout::from_theme(&serde_json::json!({})), TypographyLayout::default());
+
+        // Partial overrides keep the remaining defaults
+        let theme = serde_json::json!({
+            "typography": { "city": { "y": 0.12 }, "line_y": 0.135 }
+        });
+        let layout = TypographyLayout::from_theme(&theme);
+        let defaults = TypographyLayout::default();
+        assert_eq!(layout.city.y, 0.12);
+        assert_eq!(layout.city.size, defaults.city.size);
+        assert_eq!(layout.line_y, 0.135);
+        assert_eq!(layout.country, defaults.country);
+
+        // Invalid values fall back to the default layout
+        let theme = serde_json::json!({ "typography": { "city": { "y": "top" } } });
+        assert_eq!(TypographyLayout::from_theme(&theme), defaults);
+
+        let theme = serde_json::json!({ "typography": { "country": { "weight": "medium_italic" } } });
+        let layout = TypographyLayout::from_theme(&theme);
+        assert_eq!(layout.country.weight, "medium_italic");
+        assert_eq!(layout.city.weight, "bold");
+    }
+
+    #[test]
+    fn test_font_weights() {
+        assert_eq!(weight_name(Path::new("Roboto-Thin.ttf")).as_deref(), Some("thin"));
+        assert_eq!(weight_name(Path::new("Roboto-MediumItalic.ttf")).as_deref(), Some("medium_italic"));
+        assert_eq!(weight_name(Path::new("Oswald.otf")).as_deref(), Some("oswald"));
+
+        // Extra files in the fonts directory and theme-named files join the defaults
+        let fonts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../fonts");
+        let dir = std::env::temp_dir().join(format!("maptoposter-fonts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (_, file) in DEFAULT_WEIGHTS {
+            std::fs::copy(fonts_dir.join(file), dir.join(file)).unwrap();
+        }
+        std::fs::copy(fonts_dir.join("Roboto-Bold.ttf"), dir.join("Roboto-BlackItalic.ttf")).unwrap();
+        std::fs::copy(fonts_dir.join("Roboto-Light.ttf"), dir.join("Display.ttf")).unwrap();
+        std::fs::write(dir.join("Broken-Thin.ttf"), b"not a font").unwrap();
+
+        let names = |fonts: &FontSet| {
+            let mut names: Vec<String> = fonts.weights.keys().cloned().collect();
+            names.sort();
+            names
+        };
+        let mut fonts = FontSet::load(&dir).unwrap();
+        assert_eq!(names(&fonts), ["black_italic", "bold", "display", "light", "regular"]);
+
+        let theme = serde_json::json!({ "fonts": { "headline": "Display.ttf", "escape": "../Display.ttf", "thin": "Missing.ttf" } });
+        fonts.load_theme_fonts(&dir, &theme);
+        assert_eq!(names(&fonts), ["black_italic", "bold", "display", "headline", "light", "regular"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Unknown weights fall back to regular
+        let (unknown, _) = fonts.weight("heavy").rasterize('A', 20.0);
+        let (regular, _) = fonts.regular().rasterize('A', 20.0);
+        assert_eq!(unknown.width, regular.width);
+    }
+
+    #[test]
+    fn test_text_plate_from_theme() {
+        assert_eq!(TextPlate::from_theme(&serde_json::json!({})), None);
+        assert_eq!(TextPlate::from_theme(&serde_json::json!({ "text_plate": "white" })), None);
+
+        let plate = TextPlate::from_theme(&serde_json::json!({ "text_plate": "#102030" })).unwrap();
+        assert_eq!(plate.color, (0x10, 0x20, 0x30));
+        assert_eq!(plate.opacity, DEFAULT_PLATE_OPACITY);
+        assert_eq!(plate.padding, DEFAULT_PLATE_PADDING);
+
+        let theme = serde_json::json!({ "text_plate": "#102030", "text_plate_opacity": 3.0, "text_plate_padding": -1.0 });
+        let plate = TextPlate::from_theme(&theme).unwrap();
+        assert_eq!(plate.opacity, 1.0);
+        assert_eq!(plate.padding, 0.0);
+    }
+
+    #[test]
+    fn test_text_plate_draws_padded_rounded_box() {
+        let mut pixmap = Pixmap::new(100, 60).unwrap();
+        let plate = TextPlate { color: (255, 0, 0), opacity: 0.5, padding: 0.5 };
+        let bounds = TextBounds { left: 30.0, top: 20.0, right: 70.0, bottom: 40.0 };
+        plate.draw(&mut pixmap, bounds, 20.0);
+
+        // Padding of 10px around the text, translucent inside and rounded off at the corners
+        let alpha = |x: u32, y: u32| pixmap.pixel(x, y).unwrap().alpha();
+        assert_eq!(alpha(50, 30), 128);
+        assert_eq!(alpha(50, 11), 128);
+        assert_eq!(alpha(50, 8), 0);
+        assert_eq!(alpha(21, 11), 0);
+        assert_eq!(alpha(85, 30), 0);
+    }
+}
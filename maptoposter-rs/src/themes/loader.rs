@@ -1,9 +1,19 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
 
+use parking_lot::RwLock;
 use serde_json::Value;
 
+use crate::core::osm_client::ROAD_THEME_KEYS;
+use crate::core::poster_generator::PARTIAL_SUFFIX;
+use crate::error::AppError;
+use crate::rendering::road_styles::RoadRamp;
+
+/// Color used for any road type whose theme key is missing
+pub const DEFAULT_ROAD_COLOR: &str = "#3A3A3A";
+
 /// Load all themes from the themes directory
 pub fn load_themes(themes_dir: &Path) -> HashMap<String, Value> {
     let mut themes = HashMap::new();
@@ -22,6 +32,7 @@ pub fn load_themes(themes_dir: &Path) -> HashMap<String, Value> {
             if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
                 match load_theme_file(&path) {
                     Ok(theme) => {
+                        log_road_color_warnings(name, &theme);
                         themes.insert(name.to_string(), theme);
                     }
                     Err(e) => {
@@ -35,19 +46,274 @@ pub fn load_themes(themes_dir: &Path) -> HashMap<String, Value> {
     themes
 }
 
-/// Load a specific theme by name
+/// Whether a theme name is a plain file stem that stays inside the themes directory
+///
+/// Rejects path separators, `..`, drive prefixes and NUL bytes.
+pub fn is_valid_theme_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains("..")
+        && !name.chars().any(|c| matches!(c, '/' | '\\' | ':' | '\0'))
+}
+
+/// Path of a file a theme refers to, such as its `bg_image`, inside `themes_dir`
+///
+/// None unless `name` is a plain relative path: no root, drive prefix or `..` component,
+/// so a theme can't read files elsewhere on the server.
+pub fn theme_asset_path(themes_dir: &Path, name: &str) -> Option<PathBuf> {
+    let relative = Path::new(name);
+    let plain = relative.components().any(|c| matches!(c, Component::Normal(_)))
+        && relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    plain.then(|| themes_dir.join(relative))
+}
+
+/// Reject a theme name that could escape the themes directory (400 Bad Request)
+pub fn validate_theme_name(name: &str) -> crate::error::Result<()> {
+    if is_valid_theme_name(name) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidRequest(format!("Invalid theme name: {:?}", name)))
+    }
+}
+
+/// Load a specific theme by name (None for names rejected by `is_valid_theme_name`)
 pub fn load_theme(themes_dir: &Path, name: &str) -> Option<Value> {
+    if !is_valid_theme_name(name) {
+        return None;
+    }
     let path = themes_dir.join(format!("{}.json", name));
-    load_theme_file(&path).ok()
+    let theme = load_theme_file(&path).ok()?;
+    log_road_color_warnings(name, &theme);
+    Some(theme)
+}
+
+/// Parsed themes kept in memory, each read again once its file's modification time changes
+#[derive(Default)]
+pub struct ThemeCache {
+    themes: RwLock<HashMap<String, (SystemTime, Value)>>,
+}
+
+impl ThemeCache {
+    /// Load a theme like `load_theme`, from memory unless its file changed since it was read
+    pub fn load(&self, themes_dir: &Path, name: &str) -> Option<Value> {
+        if !is_valid_theme_name(name) {
+            return None;
+        }
+        let modified = fs::metadata(themes_dir.join(format!("{}.json", name)))
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let Some(modified) = modified else {
+            self.themes.write().remove(name);
+            return None;
+        };
+        if let Some((read_at, theme)) = self.themes.read().get(name) {
+            if *read_at == modified {
+                return Some(theme.clone());
+            }
+        }
+
+        let theme = load_theme(themes_dir, name)?;
+        self.themes.write().insert(name.to_string(), (modified, theme.clone()));
+        Some(theme)
+    }
+
+    /// Parse every theme in the directory into the cache, returning how many loaded
+    pub fn warm(&self, themes_dir: &Path) -> usize {
+        let names: Vec<String> = fs::read_dir(themes_dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|e| e == "json"))
+                    .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.iter().filter(|name| self.load(themes_dir, name).is_some()).count()
+    }
+}
+
+/// Check that a theme gives roads a visible hierarchy.
+///
+/// Mirrors how `draw_roads` resolves colors: a missing key falls back to
+/// `DEFAULT_ROAD_COLOR` and an unparseable one hides that road type entirely.
+pub fn road_color_warnings(theme: &Value) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    // A valid ramp replaces the per-type keys entirely
+    if let Some(ramp) = RoadRamp::from_theme(theme) {
+        if parse_hex_color(&ramp.low) == parse_hex_color(&ramp.high) {
+            warnings.push("road_ramp starts and ends on the same color, the road hierarchy will be flat".to_string());
+        }
+        return warnings;
+    }
+    if theme.get("road_ramp").is_some() {
+        warnings.push("road_ramp must be two #RRGGBB colors, using the road_* keys instead".to_string());
+    }
+
+    let missing: Vec<&str> = ROAD_THEME_KEYS
+        .iter()
+        .copied()
+        .filter(|key| theme.get(*key).and_then(|v| v.as_str()).is_none())
+        .collect();
+    if !missing.is_empty() {
+        warnings.push(format!(
+            "missing road colors {} (falling back to {})",
+            missing.join(", "),
+            DEFAULT_ROAD_COLOR
+        ));
+    }
+
+    let mut resolved = Vec::new();
+    for key in ROAD_THEME_KEYS {
+        let hex = get_theme_color(theme, key, DEFAULT_ROAD_COLOR);
+        match parse_hex_color(&hex) {
+            Some(rgb) => resolved.push(rgb),
+            None => warnings.push(format!("{} is not a #RRGGBB color, those roads will not be drawn", key)),
+        }
+    }
+
+    resolved.sort_unstable();
+    resolved.dedup();
+    if resolved.len() == 1 {
+        warnings.push("all road types resolve to the same color, the road hierarchy will be flat".to_string());
+    }
+
+    warnings
+}
+
+fn log_road_color_warnings(name: &str, theme: &Value) {
+    for warning in road_color_warnings(theme) {
+        tracing::warn!("Theme '{}': {}", name, warning);
+    }
 }
 
-/// Load a theme from a file path
+/// Load a theme from a file path, with its colors normalized
 fn load_theme_file(path: &Path) -> Result<Value, Box<dyn std::error::Error>> {
+    Ok(normalize_theme(&read_theme_file(path)?))
+}
+
+/// Read a theme file exactly as written
+fn read_theme_file(path: &Path) -> Result<Value, Box<dyn std::error::Error>> {
     let content = fs::read_to_string(path)?;
     let theme: Value = serde_json::from_str(&content)?;
     Ok(theme)
 }
 
+/// Theme keys holding free text or file names rather than colors
+const NON_COLOR_THEME_KEYS: [&str; 4] = ["name", "description", "bg_image", "fonts"];
+
+/// Theme keys holding a color, besides `color`, `*_color`, `road_*` and `landuse_*` keys
+const COLOR_THEME_KEYS: [&str; 9] = [
+    "bg",
+    "text",
+    "water",
+    "parks",
+    "parking",
+    "pitch",
+    "text_plate",
+    "locator_fill",
+    "locator_marker",
+];
+
+/// Whether a theme key holds colors, so a bare `RRGGBB` value under it is read as one
+fn is_color_theme_key(key: &str) -> bool {
+    COLOR_THEME_KEYS.contains(&key)
+        || key == "color"
+        || key.ends_with("_color")
+        || key.starts_with("road_")
+        || key.starts_with("landuse_")
+}
+
+/// Canonical uppercase `#RRGGBB` form of a color written as `#rrggbb` or `#RGB`
+pub fn normalize_hex_color(value: &str) -> Option<String> {
+    let short_or_long = value.trim().strip_prefix('#')?;
+    let digits: String = match short_or_long.len() {
+        3 => short_or_long.chars().flat_map(|c| [c, c]).collect(),
+        _ => short_or_long.to_string(),
+    };
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("#{}", digits.to_ascii_uppercase()))
+}
+
+/// Rewrite every color in a theme as uppercase `#RRGGBB`, so API responses and validation
+/// see one format whichever way the file spells them
+///
+/// Colors without a `#` are only recognized as `RRGGBB` under color keys, so a word like
+/// "facade" elsewhere stays a word. The text keys in `NON_COLOR_THEME_KEYS` are left unchanged.
+pub fn normalize_theme(value: &Value) -> Value {
+    normalize_theme_value(value, false)
+}
+
+fn normalize_theme_value(value: &Value, color_key: bool) -> Value {
+    match value {
+        Value::String(s) => {
+            let bare = s.trim();
+            let color = if color_key && bare.len() == 6 {
+                normalize_hex_color(&format!("#{}", bare))
+            } else {
+                normalize_hex_color(s)
+            };
+            Value::String(color.unwrap_or_else(|| s.clone()))
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|v| normalize_theme_value(v, color_key)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, v)| {
+                    let v = if NON_COLOR_THEME_KEYS.contains(&key.as_str()) {
+                        v.clone()
+                    } else {
+                        normalize_theme_value(v, is_color_theme_key(key))
+                    };
+                    (key.clone(), v)
+                })
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Rewrite a theme file with its colors normalized, returning the top-level keys that changed
+///
+/// The file is left untouched when it is already canonical.
+pub fn normalize_theme_file(themes_dir: &Path, name: &str) -> crate::error::Result<Vec<String>> {
+    validate_theme_name(name)?;
+    let path = themes_dir.join(format!("{}.json", name));
+    let theme = read_theme_file(&path).map_err(|_| AppError::ThemeNotFound(name.to_string()))?;
+    let normalized = normalize_theme(&theme);
+
+    let changed: Vec<String> = match (theme.as_object(), normalized.as_object()) {
+        (Some(before), Some(after)) => after
+            .iter()
+            .filter(|(key, v)| before.get(key.as_str()) != Some(v))
+            .map(|(key, _)| key.clone())
+            .collect(),
+        _ => Vec::new(),
+    };
+    if !changed.is_empty() {
+        let mut content = serde_json::to_string_pretty(&normalized)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize theme: {}", e)))?;
+        content.push('\n');
+        write_theme_file(&path, &content)?;
+    }
+    Ok(changed)
+}
+
+/// Replace a theme file through a temporary file renamed over it, so a concurrent
+/// `load_theme` sees either the old or the new theme, never a half-written one
+fn write_theme_file(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(".{}{}", uuid::Uuid::new_v4().simple(), PARTIAL_SUFFIX));
+    let partial = PathBuf::from(partial);
+
+    let written = fs::write(&partial, content).and_then(|()| fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
 /// Get a color from a theme, with a fallback default
 pub fn get_theme_color(theme: &Value, key: &str, default: &str) -> String {
     theme
@@ -57,6 +323,20 @@ pub fn get_theme_color(theme: &Value, key: &str, default: &str) -> String {
         .to_string()
 }
 
+/// Get a numeric value from a theme, with a fallback default
+pub fn get_theme_number(theme: &Value, key: &str, default: f32) -> f32 {
+    theme
+        .get(key)
+        .and_then(|v| v.as_f64())
+        .map(|v| v as f32)
+        .unwrap_or(default)
+}
+
+/// Get a boolean flag from a theme, with a fallback default
+pub fn get_theme_bool(theme: &Value, key: &str, default: bool) -> bool {
+    theme.get(key).and_then(|v| v.as_bool()).unwrap_or(default)
+}
+
 /// Parse a hex color string to RGB components
 pub fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
     let hex = hex.trim_start_matches('#');
@@ -71,10 +351,84 @@ pub fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
     Some((r, g, b))
 }
 
-/// Parse a hex color string to RGBA with alpha
-pub fn parse_hex_color_rgba(hex: &str, alpha: u8) -> Option<(u8, u8, u8, u8)> {
+/// Mix two hex colors, moving `t` (0.0-1.0) of the way from `from` towards `to`
+pub fn mix_hex_colors(from: &str, to: &str, t: f32) -> Option<String> {
+    let (r1, g1, b1) = parse_hex_color(from)?;
+    let (r2, g2, b2) = parse_hex_color(to)?;
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+    Some(format!(
+        "#{:02X}{:02X}{:02X}",
+        mix(r1, r2),
+        mix(g1, g2),
+        mix(b1, b2)
+    ))
+}
+
+/// Dark counterpart of a theme: every `#RRGGBB` color has its HSL lightness inverted
+///
+/// Hue and saturation are kept, so a white background with navy roads becomes a black
+/// background with light blue roads. Non-color values are copied unchanged.
+pub fn invert_theme(value: &Value) -> Value {
+    match value {
+        Value::String(s) if s.starts_with('#') => match invert_hex_lightness(s) {
+            Some(inverted) => Value::String(inverted),
+            None => value.clone(),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(invert_theme).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter().map(|(key, v)| (key.clone(), invert_theme(v))).collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Invert the HSL lightness of a hex color, keeping its hue and saturation
+fn invert_hex_lightness(hex: &str) -> Option<String> {
     let (r, g, b) = parse_hex_color(hex)?;
-    Some((r, g, b, alpha))
+    let (h, s, l) = rgb_to_hsl(r, g, b);
+    let (r, g, b) = hsl_to_rgb(h, s, 1.0 - l);
+    Some(format!("#{:02X}{:02X}{:02X}", r, g, b))
+}
+
+/// RGB to hue (0-360), saturation and lightness (0-1)
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, l);
+    }
+
+    let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (h, s, l)
+}
+
+/// Hue (0-360), saturation and lightness (0-1) to RGB
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let channel = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (channel(r), channel(g), channel(b))
 }
 
 #[cfg(test)]
@@ -89,4 +443,219 @@ mod tests {
         assert_eq!(parse_hex_color("FFFFFF"), Some((255, 255, 255)));
         assert_eq!(parse_hex_color("#FFF"), None); // Invalid length
     }
+
+    #[test]
+    fn test_road_color_warnings() {
+        let complete = serde_json::json!({
+            "road_motorway": "#FFFFFF",
+            "road_primary": "#E0E0E0",
+            "road_secondary": "#B0B0B0",
+            "road_tertiary": "#808080",
+            "road_residential": "#505050",
+            "road_default": "#808080"
+        });
+        assert!(road_color_warnings(&complete).is_empty());
+
+        let warnings = road_color_warnings(&serde_json::json!({ "road_motorway": "#FFFFFF" }));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("road_primary") && !warnings[0].contains("road_motorway"));
+
+        let warnings = road_color_warnings(&serde_json::json!({}));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].contains("same color"));
+
+        let mut bad = complete.clone();
+        bad["road_default"] = serde_json::json!("gray");
+        assert_eq!(road_color_warnings(&bad), vec!["road_default is not a #RRGGBB color, those roads will not be drawn"]);
+
+        // A ramp stands in for the per-type keys
+        assert!(road_color_warnings(&serde_json::json!({ "road_ramp": ["#111111", "#AAAAAA"] })).is_empty());
+        let warnings = road_color_warnings(&serde_json::json!({ "road_ramp": ["#111111", "#111111"] }));
+        assert!(warnings.len() == 1 && warnings[0].contains("flat"));
+        let mut bad_ramp = complete.clone();
+        bad_ramp["road_ramp"] = serde_json::json!(["#111111"]);
+        assert_eq!(road_color_warnings(&bad_ramp).len(), 1);
+    }
+
+    #[test]
+    fn test_theme_names_cannot_escape_themes_dir() {
+        let root = std::env::temp_dir().join(format!("maptoposter-themes-{}", std::process::id()));
+        let themes_dir = root.join("themes");
+        fs::create_dir_all(&themes_dir).unwrap();
+        fs::write(root.join("secret.json"), r##"{"bg": "#000000"}"##).unwrap();
+        fs::write(themes_dir.join("plain.json"), r##"{"bg": "#FFFFFF"}"##).unwrap();
+
+        assert!(load_theme(&themes_dir, "plain").is_some());
+        let secret = root.join("secret");
+        let malicious = [
+            "../secret",
+            "..\\secret",
+            "plain/../../secret",
+            "..",
+            "",
+            "sub/plain",
+            "plain\0",
+            secret.to_str().unwrap(),
+        ];
+        for name in malicious {
+            assert!(load_theme(&themes_dir, name).is_none(), "{:?} was loaded", name);
+            assert!(matches!(validate_theme_name(name), Err(AppError::InvalidRequest(_))), "{:?} was accepted", name);
+        }
+        assert!(validate_theme_name("noir").is_ok());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_theme_assets_stay_inside_themes_dir() {
+        let themes_dir = Path::new("/srv/themes");
+        assert_eq!(theme_asset_path(themes_dir, "paper.png"), Some(themes_dir.join("paper.png")));
+        assert_eq!(theme_asset_path(themes_dir, "images/paper.png"), Some(themes_dir.join("images/paper.png")));
+        assert_eq!(theme_asset_path(themes_dir, "./paper.png"), Some(themes_dir.join("./paper.png")));
+
+        for name in ["", ".", "/etc/passwd", "../secret.png", "images/../../secret.png", "images/.."] {
+            assert_eq!(theme_asset_path(themes_dir, name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_bundled_themes_have_road_hierarchy() {
+        let themes_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../themes");
+        for (name, theme) in load_themes(&themes_dir) {
+            assert!(road_color_warnings(&theme).is_empty(), "theme {} has road color warnings", name);
+        }
+    }
+
+    #[test]
+    fn test_invert_theme() {
+        let theme = serde_json::json!({
+            "name": "Light",
+            "bg": "#FFFFFF",
+            "text": "#000000",
+            "road_primary": "#FF0000",
+            "water": "#ADD8E6",
+            "parks": "#1A3D1A",
+            "layer_order": ["roads"],
+            "road_casing_width": 2.0,
+            "broken": "#12"
+        });
+        let dark = invert_theme(&theme);
+
+        assert_eq!(dark["bg"], "#000000");
+        assert_eq!(dark["text"], "#FFFFFF");
+        // Fully saturated mid-lightness colors are their own inverse
+        assert_eq!(dark["road_primary"], "#FF0000");
+        // Light blue becomes a dark blue of the same hue
+        assert_eq!(dark["water"], "#194452");
+        assert_eq!(dark["parks"], "#C2E5C2");
+        // Everything that isn't a color is left alone
+        assert_eq!(dark["name"], "Light");
+        assert_eq!(dark["layer_order"], serde_json::json!(["roads"]));
+        assert_eq!(dark["road_casing_width"], 2.0);
+        assert_eq!(dark["broken"], "#12");
+
+        // Inverting twice gets back (approximately) to the original
+        let round_trip = invert_theme(&dark);
+        let (r, g, b) = parse_hex_color(round_trip["water"].as_str().unwrap()).unwrap();
+        assert!(r.abs_diff(0xAD) <= 1 && g.abs_diff(0xD8) <= 1 && b.abs_diff(0xE6) <= 1);
+    }
+
+    #[test]
+    fn test_normalize_hex_color() {
+        assert_eq!(normalize_hex_color("#FFFFFF"), Some("#FFFFFF".to_string()));
+        assert_eq!(normalize_hex_color("#ffffff"), Some("#FFFFFF".to_string()));
+        // Bare digits are only taken as a color under a color key (see `normalize_theme`)
+        assert_eq!(normalize_hex_color("a0b1c2"), None);
+        assert_eq!(normalize_hex_color(" #AbCdEf "), Some("#ABCDEF".to_string()));
+        assert_eq!(normalize_hex_color("#f0a"), Some("#FF00AA".to_string()));
+        // Short forms need the `#`, so short words aren't mistaken for colors
+        assert_eq!(normalize_hex_color("add"), None);
+        assert_eq!(normalize_hex_color("#12345"), None);
+        assert_eq!(normalize_hex_color("#GGGGGG"), None);
+        assert_eq!(normalize_hex_color("roads"), None);
+    }
+
+    #[test]
+    fn test_normalize_theme() {
+        let theme = serde_json::json!({
+            "name": "facade",
+            "description": "Muted",
+            "bg": "#ffffff",
+            "text": "1a1a1a",
+            "water": "#abc",
+            "road_ramp": ["#111111", "aaaaaa"],
+            "fonts": { "headline": "BADDAD" },
+            "typography": { "city": { "color": "#ff0000", "weight": "bold" } },
+            "layer_order": ["water", "roads"],
+            "road_casing_width": 2.0,
+            "road_motorway": "a0b1c2",
+            "style": "decade"
+        });
+        let normalized = normalize_theme(&theme);
+
+        assert_eq!(normalized["bg"], "#FFFFFF");
+        assert_eq!(normalized["text"], "#1A1A1A");
+        assert_eq!(normalized["water"], "#AABBCC");
+        assert_eq!(normalized["road_ramp"], serde_json::json!(["#111111", "#AAAAAA"]));
+        assert_eq!(normalized["typography"]["city"]["color"], "#FF0000");
+        assert_eq!(normalized["typography"]["city"]["weight"], "bold");
+        // Text keys keep their values even when they look like hex
+        assert_eq!(normalized["name"], "facade");
+        assert_eq!(normalized["fonts"]["headline"], "BADDAD");
+        assert_eq!(normalized["layer_order"], theme["layer_order"]);
+        assert_eq!(normalized["road_casing_width"], 2.0);
+        assert_eq!(normalized["road_motorway"], "#A0B1C2");
+        // Bare hex-looking words outside color keys aren't colors
+        assert_eq!(normalized["style"], "decade");
+        assert_eq!(normalize_theme(&normalized), normalized);
+    }
+
+    #[test]
+    fn test_theme_cache_rereads_changed_files() {
+        let themes_dir = std::env::temp_dir().join(format!("maptoposter-theme-cache-{}", std::process::id()));
+        fs::create_dir_all(&themes_dir).unwrap();
+        let path = themes_dir.join("plain.json");
+        fs::write(&path, r##"{"name": "Plain", "bg": "#ffffff"}"##).unwrap();
+
+        let cache = ThemeCache::default();
+        assert_eq!(cache.warm(&themes_dir), 1);
+        assert_eq!(cache.load(&themes_dir, "plain").unwrap()["bg"], "#FFFFFF");
+
+        fs::write(&path, r##"{"name": "Plain", "bg": "#000000"}"##).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert_eq!(cache.load(&themes_dir, "plain").unwrap()["bg"], "#000000");
+
+        fs::remove_file(&path).unwrap();
+        assert!(cache.load(&themes_dir, "plain").is_none());
+        assert!(cache.load(&themes_dir, "../plain").is_none());
+        fs::remove_dir_all(&themes_dir).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_theme_file() {
+        let themes_dir = std::env::temp_dir().join(format!("maptoposter-normalize-{}", std::process::id()));
+        fs::create_dir_all(&themes_dir).unwrap();
+        fs::write(themes_dir.join("mixed.json"), r##"{"name": "Mixed", "bg": "#ffffff", "text": "000000", "water": "#C0C0C0"}"##).unwrap();
+
+        // Loading normalizes without touching the file
+        assert_eq!(load_theme(&themes_dir, "mixed").unwrap()["bg"], "#FFFFFF");
+        assert_eq!(normalize_theme_file(&themes_dir, "mixed").unwrap(), vec!["bg", "text"]);
+        let rewritten = read_theme_file(&themes_dir.join("mixed.json")).unwrap();
+        assert_eq!(rewritten["text"], "#000000");
+        assert!(normalize_theme_file(&themes_dir, "mixed").unwrap().is_empty());
+
+        assert!(matches!(normalize_theme_file(&themes_dir, "missing"), Err(AppError::ThemeNotFound(_))));
+        assert!(matches!(normalize_theme_file(&themes_dir, "../mixed"), Err(AppError::InvalidRequest(_))));
+
+        fs::remove_dir_all(&themes_dir).unwrap();
+    }
+
+    #[test]
+    fn test_mix_hex_colors() {
+        assert_eq!(mix_hex_colors("#000000", "#FFFFFF", 0.0), Some("#000000".to_string()));
+        assert_eq!(mix_hex_colors("#000000", "#FFFFFF", 1.0), Some("#FFFFFF".to_string()));
+        assert_eq!(mix_hex_colors("#FFFFFF", "#000000", 0.5), Some("#808080".to_string()));
+        assert_eq!(mix_hex_colors("#FFF", "#000000", 0.5), None);
+    }
 }